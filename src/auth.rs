@@ -119,6 +119,11 @@ impl UserRole {
         true // All roles can use/consume batches
     }
 
+    /// QA-решение по партии на карантине (выпуск / отклонение)
+    pub fn can_release_batches(&self) -> bool {
+        matches!(self, UserRole::Admin)
+    }
+
     // ======== EQUIPMENT PERMISSIONS ========
     pub fn can_manage_equipment(&self) -> bool {
         matches!(self, UserRole::Admin | UserRole::Researcher)
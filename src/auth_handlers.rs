@@ -11,7 +11,7 @@ use crate::handlers::ApiResponse;
 use crate::audit::ChangeSet;
 use crate::auth::{
     AuthService, User, LoginRequest, RegisterRequest, ChangePasswordRequest,
-    LoginResponse, UserInfo, UserRole, get_current_user, check_permission
+    LoginResponse, UserInfo, UserRole, Claims, get_current_user, check_permission
 };
 use crate::error::{ApiError, ApiResult};
 use crate::AppState;
@@ -89,6 +89,7 @@ pub enum Permission {
     DeleteBatch,
     ViewBatch,
     UseBatch,
+    ReleaseBatch,
     
     // Equipment permissions
     CreateEquipment,
@@ -136,6 +137,7 @@ impl Permission {
             Permission::DeleteBatch => "delete_batch",
             Permission::ViewBatch => "view_batch",
             Permission::UseBatch => "use_batch",
+            Permission::ReleaseBatch => "release_batch",
             Permission::CreateEquipment => "create_equipment",
             Permission::EditEquipment => "edit_equipment",
             Permission::DeleteEquipment => "delete_equipment",
@@ -175,6 +177,7 @@ pub fn get_role_permissions(role: &UserRole) -> Vec<Permission> {
             Permission::DeleteBatch,
             Permission::ViewBatch,
             Permission::UseBatch,
+            Permission::ReleaseBatch,
             Permission::CreateEquipment,
             Permission::EditEquipment,
             Permission::DeleteEquipment,
@@ -906,6 +909,7 @@ pub fn check_batch_permission(
         BatchAction::Create => check_permission(&claims, |role| role.can_create_batches()),
         BatchAction::Edit => check_permission(&claims, |role| role.can_edit_batches()),
         BatchAction::Delete => check_permission(&claims, |role| role.can_delete_batches()),
+        BatchAction::Release => check_permission(&claims, |role| role.can_release_batches()),
         BatchAction::View => Ok(()), // All can view
     }
 }
@@ -928,6 +932,7 @@ pub async fn check_batch_permission_async(
         BatchAction::Create => "create_batch",
         BatchAction::Edit => "edit_batch",
         BatchAction::Delete => "delete_batch",
+        BatchAction::Release => "release_batch",
         BatchAction::View => return Ok(()),
     };
 
@@ -967,6 +972,7 @@ pub async fn check_batch_permission_async(
         BatchAction::Create => claims.role.can_create_batches(),
         BatchAction::Edit => claims.role.can_edit_batches(),
         BatchAction::Delete => claims.role.can_delete_batches(),
+        BatchAction::Release => claims.role.can_release_batches(),
         BatchAction::View => true,
     };
 
@@ -1205,6 +1211,31 @@ pub async fn check_room_permission(
     Err(ApiError::Forbidden("Insufficient permissions".to_string()))
 }
 
+/// Non-failing view check for cross-module features (global search).
+/// Custom permissions are the source of truth when present, otherwise role defaults apply.
+pub async fn has_view_permission(
+    claims: &Claims,
+    permission: Permission,
+    pool: &sqlx::SqlitePool,
+) -> bool {
+    let key = permission.as_str();
+    let custom: Option<(String,)> = sqlx::query_as(
+        "SELECT permissions FROM user_permissions WHERE user_id = ?"
+    )
+    .bind(&claims.sub)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or(None);
+
+    if let Some((perms_json,)) = custom {
+        if let Ok(perms) = serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
+            return perms.get(key).copied().unwrap_or(false);
+        }
+    }
+
+    get_role_permissions(&claims.role).iter().any(|p| p.as_str() == key)
+}
+
 // ======== ACTION ENUMS ========
 
 #[derive(Debug)]
@@ -1221,6 +1252,8 @@ pub enum BatchAction {
     Edit,
     Delete,
     View,
+    /// Выпуск/отклонение партии после входного контроля
+    Release,
 }
 
 #[derive(Debug)]
@@ -1349,7 +1382,7 @@ fn get_default_permissions_for_role(role: &str) -> std::collections::HashMap<Str
         "admin" => {
             for perm in &[
                 "create_reagent", "edit_reagent", "delete_reagent", "view_reagent",
-                "create_batch", "edit_batch", "delete_batch", "view_batch", "use_batch",
+                "create_batch", "edit_batch", "delete_batch", "view_batch", "use_batch", "release_batch",
                 "create_equipment", "edit_equipment", "delete_equipment", "view_equipment", "manage_maintenance",
                 "create_experiment", "edit_experiment", "delete_experiment", "view_experiment",
                 "create_room", "edit_room", "delete_room", "view_room",
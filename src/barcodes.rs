@@ -0,0 +1,494 @@
+// src/barcodes.rs
+//! Штрихкоды для этикеток и сканеров.
+//!
+//! - Короткие коды: `C000123` — контейнер, `B000045` — партия, `P000007` — позиция хранения.
+//!   Выдаются триггерами БД из `scan_code_sequences` и не переиспользуются.
+//! - Генерация Code128 (набор B) и QR в SVG/PNG.
+//! - Разбор GS1 (GS1-128 / GS1 DataMatrix / QR) с флаконов производителя:
+//!   GTIN, лот, срок годности, серийный и каталожный номер.
+
+use chrono::NaiveDate;
+use image::{GrayImage, ImageFormat, Luma};
+use qrcode::{Color, QrCode};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+use crate::error::{ApiError, ApiResult};
+
+// ==================== SHORT CODES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEntity {
+    Container,
+    Batch,
+    Position,
+}
+
+impl ScanEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanEntity::Container => "container",
+            ScanEntity::Batch => "batch",
+            ScanEntity::Position => "position",
+        }
+    }
+}
+
+/// Короткий код → тип объекта и нормализованный код (регистр и пробелы не важны)
+pub fn parse_short_code(code: &str) -> Option<(ScanEntity, String)> {
+    let code = code.trim().to_ascii_uppercase();
+    let mut chars = code.chars();
+    let entity = match chars.next()? {
+        'C' => ScanEntity::Container,
+        'B' => ScanEntity::Batch,
+        'P' => ScanEntity::Position,
+        _ => return None,
+    };
+    let digits = chars.as_str();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((entity, code))
+}
+
+// ==================== RENDERING ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbology {
+    #[default]
+    Code128,
+    Qr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BarcodeFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BarcodeQuery {
+    pub symbology: Option<Symbology>,
+    pub format: Option<BarcodeFormat>,
+    /// Пикселей на модуль, 1-20
+    pub scale: Option<u32>,
+}
+
+const MAX_SCALE: u32 = 20;
+/// Высота штрихов Code128 в модулях
+const CODE128_BAR_HEIGHT: usize = 40;
+const CODE128_QUIET_ZONE: usize = 10;
+const QR_QUIET_ZONE: usize = 4;
+
+/// Code128: ширины элементов (штрих, пробел, …) для значений 0..=106; 106 — STOP
+const CODE128_PATTERNS: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+const CODE128_START_B: u8 = 104;
+const CODE128_STOP: u8 = 106;
+
+/// Кодовые значения Code128-B: START, данные, контрольный символ, STOP
+pub fn code128_values(data: &str) -> ApiResult<Vec<u8>> {
+    if data.is_empty() || data.len() > 80 {
+        return Err(ApiError::bad_request("Code128 data must be 1-80 characters"));
+    }
+    let mut values = vec![CODE128_START_B];
+    for c in data.chars() {
+        if !(' '..='~').contains(&c) {
+            return Err(ApiError::bad_request("Code128 supports printable ASCII only"));
+        }
+        values.push(c as u8 - b' ');
+    }
+    let checksum = values.iter().enumerate()
+        .map(|(i, &v)| i.max(1) as u32 * v as u32)
+        .sum::<u32>() % 103;
+    values.push(checksum as u8);
+    values.push(CODE128_STOP);
+    Ok(values)
+}
+
+/// Двумерная матрица модулей (для Code128 — одна строка, растягиваемая по высоте)
+pub struct Matrix {
+    pub width: usize,
+    pub rows: Vec<Vec<bool>>,
+    pub row_height: usize,
+    pub quiet_zone: usize,
+}
+
+/// Матрица модулей для отрисовки в других форматах (этикетки PDF)
+pub fn barcode_matrix(data: &str, symbology: Symbology) -> ApiResult<Matrix> {
+    match symbology {
+        Symbology::Code128 => code128_matrix(data),
+        Symbology::Qr => qr_matrix(data),
+    }
+}
+
+fn code128_matrix(data: &str) -> ApiResult<Matrix> {
+    let mut modules = Vec::new();
+    for value in code128_values(data)? {
+        let pattern = CODE128_PATTERNS[value as usize];
+        for (i, width) in pattern.bytes().enumerate() {
+            let dark = i % 2 == 0;
+            modules.extend(std::iter::repeat_n(dark, (width - b'0') as usize));
+        }
+    }
+    Ok(Matrix { width: modules.len(), rows: vec![modules], row_height: CODE128_BAR_HEIGHT, quiet_zone: CODE128_QUIET_ZONE })
+}
+
+fn qr_matrix(data: &str) -> ApiResult<Matrix> {
+    let code = QrCode::new(data.as_bytes())
+        .map_err(|e| ApiError::bad_request(&format!("Cannot encode QR code: {}", e)))?;
+    let width = code.width();
+    let colors = code.to_colors();
+    let rows = colors.chunks(width)
+        .map(|row| row.iter().map(|c| *c == Color::Dark).collect())
+        .collect();
+    Ok(Matrix { width, rows, row_height: 1, quiet_zone: QR_QUIET_ZONE })
+}
+
+impl Matrix {
+    /// Ширина и высота в модулях вместе с тихой зоной
+    pub fn module_size(&self) -> (usize, usize) {
+        (self.width + 2 * self.quiet_zone, self.rows.len() * self.row_height + 2 * self.quiet_zone)
+    }
+
+    fn pixel_size(&self, scale: u32) -> (u32, u32) {
+        let (w, h) = self.module_size();
+        ((w * scale as usize) as u32, (h * scale as usize) as u32)
+    }
+
+    /// Горизонтальные отрезки тёмных модулей: (строка, начало, длина)
+    pub fn dark_runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] {
+                    let start = x;
+                    while x < row.len() && row[x] {
+                        x += 1;
+                    }
+                    runs.push((y, start, x - start));
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        runs
+    }
+
+    fn to_svg(&self, scale: u32, caption: Option<&str>) -> String {
+        let s = scale as usize;
+        let (w, h) = self.pixel_size(scale);
+        let text_height = if caption.is_some() { 12 * s } else { 0 };
+        let mut svg = format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{total}" viewBox="0 0 {w} {total}"><rect width="{w}" height="{total}" fill="#ffffff"/><g fill="#000000">"##,
+            w = w, total = h as usize + text_height,
+        );
+        for (y, x, len) in self.dark_runs() {
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+                (self.quiet_zone + x) * s,
+                (self.quiet_zone + y * self.row_height) * s,
+                len * s,
+                self.row_height * s,
+            ));
+        }
+        svg.push_str("</g>");
+        if let Some(text) = caption {
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" font-family="monospace" font-size="{}" text-anchor="middle">{}</text>"#,
+                w / 2,
+                h as usize + 8 * s,
+                10 * s,
+                text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+            ));
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    fn to_png(&self, scale: u32) -> ApiResult<Vec<u8>> {
+        let s = scale as usize;
+        let (w, h) = self.pixel_size(scale);
+        let mut img = GrayImage::from_pixel(w, h, Luma([255]));
+        for (y, x, len) in self.dark_runs() {
+            let top = (self.quiet_zone + y * self.row_height) * s;
+            let left = (self.quiet_zone + x) * s;
+            for py in top..top + self.row_height * s {
+                for px in left..left + len * s {
+                    img.put_pixel(px as u32, py as u32, Luma([0]));
+                }
+            }
+        }
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .map_err(|e| ApiError::internal_error(format!("PNG encoding failed: {}", e)))?;
+        Ok(out)
+    }
+}
+
+/// Изображение штрихкода и его MIME-тип. Подпись (сам код) — только у Code128 в SVG.
+pub fn render_barcode(data: &str, query: &BarcodeQuery) -> ApiResult<(Vec<u8>, &'static str)> {
+    let symbology = query.symbology.unwrap_or_default();
+    let default_scale = match symbology {
+        Symbology::Code128 => 2,
+        Symbology::Qr => 8,
+    };
+    let scale = query.scale.unwrap_or(default_scale);
+    if scale == 0 || scale > MAX_SCALE {
+        return Err(ApiError::bad_request("Scale must be 1-20"));
+    }
+    let matrix = barcode_matrix(data, symbology)?;
+    match query.format.unwrap_or_default() {
+        BarcodeFormat::Svg => {
+            let caption = (symbology == Symbology::Code128).then_some(data);
+            Ok((matrix.to_svg(scale, caption).into_bytes(), "image/svg+xml"))
+        }
+        BarcodeFormat::Png => Ok((matrix.to_png(scale)?, "image/png")),
+    }
+}
+
+// ==================== GS1 ====================
+
+/// Разобранный GS1-штрихкод производителя
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct Gs1Data {
+    pub gtin: Option<String>,
+    pub lot_number: Option<String>,
+    pub serial_number: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    pub production_date: Option<NaiveDate>,
+    /// AI 240 — дополнительный идентификатор, у поставщиков реактивов обычно каталожный номер
+    pub catalog_number: Option<String>,
+    /// AI 400 — номер заказа покупателя
+    pub order_number: Option<String>,
+    pub net_quantity: Option<f64>,
+    pub net_unit: Option<String>,
+    pub elements: Vec<Gs1Element>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Gs1Element {
+    pub ai: String,
+    pub value: String,
+}
+
+/// Поддерживаемые AI: (префикс, длина AI, фиксированная длина значения, максимальная длина)
+const GS1_AIS: &[(&str, usize, Option<usize>, usize)] = &[
+    ("00", 2, Some(18), 18),
+    ("01", 2, Some(14), 14),
+    ("02", 2, Some(14), 14),
+    ("10", 2, None, 20),
+    ("11", 2, Some(6), 6),
+    ("13", 2, Some(6), 6),
+    ("15", 2, Some(6), 6),
+    ("17", 2, Some(6), 6),
+    ("21", 2, None, 20),
+    ("240", 3, None, 30),
+    ("241", 3, None, 30),
+    ("30", 2, None, 8),
+    ("37", 2, None, 8),
+    ("310", 4, Some(6), 6),
+    ("315", 4, Some(6), 6),
+    ("400", 3, None, 30),
+];
+
+/// Разделитель FNC1 в данных сканера
+const GS: char = '\u{1d}';
+/// Идентификаторы символики, которые сканер может добавить в начало
+const SYMBOLOGY_IDS: &[&str] = &["]C1", "]d2", "]Q3", "]e0", "]J1"];
+
+/// Похоже ли отсканированное значение на GS1, а не на внутренний код
+pub fn looks_like_gs1(input: &str) -> bool {
+    let input = input.trim();
+    input.starts_with('(')
+        || input.contains(GS)
+        || SYMBOLOGY_IDS.iter().any(|id| input.starts_with(id))
+        || (input.len() >= 16 && input.starts_with("01") && input.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// AI в начале строки → (длина AI, фиксированная длина значения, максимальная длина)
+fn lookup_ai(data: &str) -> Result<(usize, Option<usize>, usize), String> {
+    GS1_AIS.iter()
+        .find(|(prefix, ai_len, _, _)| {
+            data.starts_with(prefix) && data.get(..*ai_len).is_some_and(|ai| ai.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(|&(_, ai_len, fixed, max)| (ai_len, fixed, max))
+        .ok_or_else(|| format!("Unsupported GS1 application identifier at '{}'", data.chars().take(4).collect::<String>()))
+}
+
+/// Разбор строки с AI в скобках — "(01)…(17)…(10)…" — или сырых данных сканера с FNC1
+pub fn parse_gs1(input: &str) -> Result<Gs1Data, String> {
+    let mut data = input.trim();
+    for id in SYMBOLOGY_IDS {
+        if let Some(rest) = data.strip_prefix(id) {
+            data = rest;
+            break;
+        }
+    }
+    let data = data.trim_start_matches(GS);
+
+    let mut elements = Vec::new();
+    if data.starts_with('(') {
+        for part in data.split('(').skip(1) {
+            let (ai, value) = part.split_once(')').ok_or("Unbalanced parentheses in GS1 data")?;
+            let (ai_len, fixed, max) = lookup_ai(ai)?;
+            if ai.len() != ai_len {
+                return Err(format!("Invalid GS1 application identifier '{}'", ai));
+            }
+            let ok = match fixed {
+                Some(n) => value.len() == n,
+                None => !value.is_empty() && value.len() <= max,
+            };
+            if !ok {
+                return Err(format!("Invalid length of GS1 AI ({})", ai));
+            }
+            elements.push(Gs1Element { ai: ai.to_string(), value: value.to_string() });
+        }
+    } else {
+        let mut rest = data;
+        while !rest.is_empty() {
+            let (ai_len, fixed, max) = lookup_ai(rest)?;
+            let ai = &rest[..ai_len];
+            rest = &rest[ai_len..];
+            let value = match fixed {
+                Some(n) => {
+                    if rest.len() < n || !rest.is_char_boundary(n) {
+                        return Err(format!("GS1 AI ({}) is truncated", ai));
+                    }
+                    &rest[..n]
+                }
+                None => {
+                    let end = rest.find(GS).unwrap_or(rest.len());
+                    if end == 0 || end > max {
+                        return Err(format!("Invalid length of GS1 AI ({})", ai));
+                    }
+                    &rest[..end]
+                }
+            };
+            elements.push(Gs1Element { ai: ai.to_string(), value: value.to_string() });
+            rest = rest[value.len()..].trim_start_matches(GS);
+        }
+    }
+    if elements.is_empty() {
+        return Err("Empty GS1 data".to_string());
+    }
+
+    let mut parsed = Gs1Data::default();
+    for el in &elements {
+        let value = el.value.clone();
+        match el.ai.as_str() {
+            "01" | "02" => {
+                if !gtin_check_digit_valid(&value) {
+                    return Err(format!("Invalid GTIN check digit: {}", value));
+                }
+                parsed.gtin = Some(value);
+            }
+            "10" => parsed.lot_number = Some(value),
+            "21" => parsed.serial_number = Some(value),
+            "11" => parsed.production_date = Some(parse_gs1_date(&value)?),
+            "17" => parsed.expiry_date = Some(parse_gs1_date(&value)?),
+            // «Годен до» используем, только если нет срока годности (AI 17)
+            "15" => {
+                let date = parse_gs1_date(&value)?;
+                parsed.expiry_date.get_or_insert(date);
+            }
+            "240" => parsed.catalog_number = Some(value),
+            "400" => parsed.order_number = Some(value),
+            ai if ai.starts_with("310") || ai.starts_with("315") => {
+                let decimals = ai[3..].parse::<i32>().map_err(|_| format!("Invalid GS1 AI ({})", ai))?;
+                let raw: f64 = value.parse().map_err(|_| format!("Invalid GS1 quantity: {}", value))?;
+                parsed.net_quantity = Some(raw / 10f64.powi(decimals));
+                parsed.net_unit = Some(if ai.starts_with("310") { "kg" } else { "L" }.to_string());
+            }
+            _ => {}
+        }
+    }
+    parsed.elements = elements;
+    Ok(parsed)
+}
+
+/// Контрольная цифра GTIN-8/12/13/14 (mod 10, веса 3/1 справа)
+pub fn gtin_check_digit_valid(gtin: &str) -> bool {
+    if !gtin.chars().all(|c| c.is_ascii_digit()) || ![8, 12, 13, 14].contains(&gtin.len()) {
+        return false;
+    }
+    let digits: Vec<u32> = gtin.bytes().map(|b| (b - b'0') as u32).collect();
+    let (body, check) = digits.split_at(digits.len() - 1);
+    let sum: u32 = body.iter().rev().enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+    (10 - sum % 10) % 10 == check[0]
+}
+
+/// YYMMDD; день 00 — последний день месяца
+fn parse_gs1_date(value: &str) -> Result<NaiveDate, String> {
+    let invalid = || format!("Invalid GS1 date: {}", value);
+    if value.len() != 6 || !value.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let year = 2000 + value[0..2].parse::<i32>().map_err(|_| invalid())?;
+    let month = value[2..4].parse::<u32>().map_err(|_| invalid())?;
+    let day = value[4..6].parse::<u32>().map_err(|_| invalid())?;
+    if day == 0 {
+        let (ny, nm) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        return NaiveDate::from_ymd_opt(ny, nm, 1)
+            .and_then(|d| d.pred_opt())
+            .filter(|_| (1..=12).contains(&month))
+            .ok_or_else(invalid);
+    }
+    NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code128_and_short_codes() {
+        assert!(CODE128_PATTERNS[..106].iter().all(|p| p.bytes().map(|b| (b - b'0') as u32).sum::<u32>() == 11));
+        // START B(104) + 'C'(35)×1 + '1'(17)×2 = 173 → 173 mod 103 = 70
+        assert_eq!(code128_values("C1").unwrap(), vec![104, 35, 17, 70, 106]);
+        assert_eq!(code128_matrix("C000123").unwrap().width, 9 * 11 + 13);
+        assert!(code128_values("Ж").is_err());
+
+        assert_eq!(parse_short_code(" c000123 "), Some((ScanEntity::Container, "C000123".to_string())));
+        assert_eq!(parse_short_code("P7").map(|(e, _)| e), Some(ScanEntity::Position));
+        assert!(parse_short_code("B12A").is_none());
+        assert!(parse_short_code("X000001").is_none());
+    }
+
+    #[test]
+    fn test_parse_gs1() {
+        let bracketed = parse_gs1("(01)04012345678901(17)260300(10)MKCD1234(240)A1234-500G").unwrap();
+        assert_eq!(bracketed.gtin.as_deref(), Some("04012345678901"));
+        assert_eq!(bracketed.expiry_date, NaiveDate::from_ymd_opt(2026, 3, 31));
+        assert_eq!(bracketed.lot_number.as_deref(), Some("MKCD1234"));
+        assert_eq!(bracketed.catalog_number.as_deref(), Some("A1234-500G"));
+
+        let raw = parse_gs1("]C101040123456789011726030010MKCD1234").unwrap();
+        assert_eq!(raw.lot_number.as_deref(), Some("MKCD1234"));
+        let with_gs = parse_gs1("010401234567890110LOT7\u{1d}3103001500").unwrap();
+        assert_eq!(with_gs.lot_number.as_deref(), Some("LOT7"));
+        assert_eq!(with_gs.net_quantity, Some(1.5));
+
+        assert!(looks_like_gs1("(01)04012345678901"));
+        assert!(!looks_like_gs1("C000123"));
+        assert!(parse_gs1("(01)04012345678902").is_err());
+        assert!(parse_gs1("(99)X").is_err());
+    }
+}
@@ -26,6 +26,7 @@ pub struct BatchResponse {
     pub lot_number: Option<String>,
     pub batch_number: String,
     pub cat_number: Option<String>,
+    pub short_code: Option<String>,
     pub quantity: f64,
     pub original_quantity: f64,
     pub reserved_quantity: f64,
@@ -73,6 +74,8 @@ pub struct BatchWithReagent {
     pub lot_number: Option<String>,
     pub batch_number: String,
     pub cat_number: Option<String>,
+    #[sqlx(default)]
+    pub short_code: Option<String>,
     pub quantity: f64,
     pub original_quantity: f64,
     pub reserved_quantity: f64,
@@ -91,6 +94,9 @@ pub struct BatchWithReagent {
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
     pub reagent_name: String,
+    /// Ближайший срок годности вскрытого контейнера (только в /batches/expiring)
+    #[sqlx(default)]
+    pub container_expiry_date: Option<DateTime<Utc>>,
 }
 
 /// Расширенный ответ партии с реагентом
@@ -102,6 +108,7 @@ pub struct BatchWithReagentResponse {
     pub lot_number: Option<String>,
     pub batch_number: String,
     pub cat_number: Option<String>,
+    pub short_code: Option<String>,
     pub quantity: f64,
     pub original_quantity: f64,
     pub reserved_quantity: f64,
@@ -119,6 +126,8 @@ pub struct BatchWithReagentResponse {
     pub updated_at: DateTime<Utc>,
     pub expiration_status: String,
     pub days_until_expiration: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_expiry_date: Option<DateTime<Utc>>,
 }
 
 // ==================== PACK COUNT CALCULATION ====================
@@ -315,6 +324,7 @@ pub async fn get_all_batches(
                 lot_number: b.lot_number,
                 batch_number: b.batch_number,
                 cat_number: b.cat_number,
+                short_code: b.short_code,
                 quantity: b.quantity,
                 original_quantity: b.original_quantity,
                 reserved_quantity: b.reserved_quantity,
@@ -396,6 +406,7 @@ pub async fn get_batch(
         lot_number: batch.lot_number,
         batch_number: batch.batch_number,
         cat_number: batch.cat_number,
+        short_code: batch.short_code,
         quantity: batch.quantity,
         original_quantity: batch.original_quantity,
         reserved_quantity: batch.reserved_quantity,
@@ -519,6 +530,7 @@ pub async fn create_batch(
         lot_number: batch.lot_number,
         batch_number: batch.batch_number,
         cat_number: batch.cat_number,
+        short_code: batch.short_code,
         quantity: batch.quantity,
         original_quantity: batch.original_quantity,
         reserved_quantity: batch.reserved_quantity,
@@ -573,6 +585,15 @@ pub async fn update_batch(
         .await
         .map_err(|_| ApiError::not_found("Batch"))?;
 
+    // Карантин снимается только решением QA (goods-in release/reject)
+    if let Some(ref status) = batch_data.status {
+        if *status != existing.status && (crate::goods_in::is_qa_hold(status) || crate::goods_in::is_qa_hold(&existing.status)) {
+            return Err(ApiError::bad_request(
+                "Quarantine and rejected statuses are managed by QA release/reject in goods-in"
+            ));
+        }
+    }
+
     let now = Utc::now();
 
     // Build dynamic SET clause — only include fields present in request
@@ -689,6 +710,7 @@ pub async fn update_batch(
         lot_number: batch.lot_number,
         batch_number: batch.batch_number,
         cat_number: batch.cat_number,
+        short_code: batch.short_code,
         quantity: batch.quantity,
         original_quantity: batch.original_quantity,
         reserved_quantity: batch.reserved_quantity,
@@ -771,17 +793,24 @@ pub async fn get_expiring_batches(
     let expiry_threshold = Utc::now() + chrono::Duration::days(days);
 
     let whitelist = get_batch_join_whitelist();
-    let base_query = "SELECT b.*, r.name as reagent_name FROM batches b JOIN reagents r ON b.reagent_id = r.id";
+    // Срок после вскрытия — по самому раннему из непустых контейнеров партии
+    let base_query = "SELECT b.*, r.name as reagent_name, ce.container_expiry_date \
+                      FROM batches b JOIN reagents r ON b.reagent_id = r.id \
+                      LEFT JOIN (SELECT batch_id, MIN(CASE WHEN status IN ('full', 'partial') AND quantity > 0 \
+                                 THEN open_expiry_date END) AS container_expiry_date \
+                                 FROM batch_containers GROUP BY batch_id) ce ON ce.batch_id = b.id";
     let mut builder = crate::query_builders::SafeQueryBuilder::new(base_query)
         .map_err(|e| ApiError::bad_request(&e))?
         .with_whitelist(&whitelist);
 
     // Исключаем удалённые батчи
     builder.add_condition("b.deleted_at IS NULL", vec![]);
+    builder.add_condition(
+        "((b.expiry_date IS NOT NULL AND b.expiry_date <= ?) OR ce.container_expiry_date <= ?)",
+        vec![expiry_threshold.to_rfc3339(), expiry_threshold.to_rfc3339()],
+    );
 
     builder
-        .add_is_not_null("b.expiry_date")
-        .add_comparison("b.expiry_date", "<=", expiry_threshold.to_rfc3339())
         .add_exact_match("b.status", "available")
         .order_by("b.expiry_date", "ASC");
 
@@ -793,10 +822,14 @@ pub async fn get_expiring_batches(
     }
     let batches: Vec<BatchWithReagent> = select_query.fetch_all(&app_state.db_pool).await?;
 
-    let response: Vec<BatchWithReagentResponse> = batches
+    let mut response: Vec<BatchWithReagentResponse> = batches
         .into_iter()
         .map(|b| {
-            let (expiration_status, days_until_expiration) = calculate_expiration_status(b.expiry_date);
+            let effective_expiry = match (b.expiry_date, b.container_expiry_date) {
+                (Some(batch), Some(container)) => Some(batch.min(container)),
+                (batch, container) => batch.or(container),
+            };
+            let (expiration_status, days_until_expiration) = calculate_expiration_status(effective_expiry);
             let pack_count = calculate_pack_count(b.quantity, b.pack_size);
             BatchWithReagentResponse {
                 id: b.id,
@@ -805,6 +838,7 @@ pub async fn get_expiring_batches(
                 lot_number: b.lot_number,
                 batch_number: b.batch_number,
                 cat_number: b.cat_number,
+                short_code: b.short_code,
                 quantity: b.quantity,
                 original_quantity: b.original_quantity,
                 reserved_quantity: b.reserved_quantity,
@@ -822,9 +856,11 @@ pub async fn get_expiring_batches(
                 updated_at: b.updated_at,
                 expiration_status,
                 days_until_expiration,
+                container_expiry_date: b.container_expiry_date,
             }
         })
         .collect();
+    response.sort_by_key(|b| b.days_until_expiration.unwrap_or(i64::MAX));
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
 }
@@ -870,6 +906,7 @@ pub async fn get_low_stock_batches(
                 lot_number: b.lot_number,
                 batch_number: b.batch_number,
                 cat_number: b.cat_number,
+                short_code: b.short_code,
                 quantity: b.quantity,
                 original_quantity: b.original_quantity,
                 reserved_quantity: b.reserved_quantity,
@@ -887,6 +924,7 @@ pub async fn get_low_stock_batches(
                 updated_at: b.updated_at,
                 expiration_status,
                 days_until_expiration,
+                container_expiry_date: b.container_expiry_date,
             }
         })
         .collect();
@@ -1034,6 +1072,7 @@ pub async fn get_batches_for_reagent(
                 lot_number: b.lot_number,
                 batch_number: b.batch_number,
                 cat_number: b.cat_number,
+                short_code: b.short_code,
                 quantity: b.quantity,
                 original_quantity: b.original_quantity,
                 reserved_quantity: b.reserved_quantity,
@@ -1157,6 +1196,7 @@ pub async fn dispense_units(
         .map_err(|_| ApiError::batch_not_found(&batch_id))?;
 
     // Проверяем статус батча
+    crate::goods_in::ensure_released(&batch.status)?;
     if batch.status != "available" {
         return Err(ApiError::BadRequest(format!(
             "Batch is not available for dispensing. Current status: '{}'", 
@@ -1243,32 +1283,9 @@ pub async fn dispense_units(
     .execute(&mut *tx)
     .await?;
 
-    // Deduct from specific placement if placement_id is provided
+    // Deduct from the container at the given placement (the placement itself stays)
     if let Some(ref placement_id) = request.placement_id {
-        let placement: Option<(String, f64)> = sqlx::query_as(
-            "SELECT id, quantity FROM batch_placements WHERE id = ? AND batch_id = ?"
-        )
-        .bind(placement_id)
-        .bind(&batch_id)
-        .fetch_optional(&mut *tx)
-        .await?;
-
-        if let Some((_pid, p_qty)) = placement {
-            let new_p_qty = p_qty - quantity_to_dispense;
-            if new_p_qty <= 0.001 {
-                sqlx::query("DELETE FROM batch_placements WHERE id = ?")
-                    .bind(placement_id)
-                    .execute(&mut *tx)
-                    .await?;
-            } else {
-                sqlx::query("UPDATE batch_placements SET quantity = ?, updated_at = ? WHERE id = ?")
-                    .bind(new_p_qty.max(0.0))
-                    .bind(&now)
-                    .bind(placement_id)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-        }
+        crate::container_handlers::draw_from_placement(&mut tx, placement_id, &batch_id, quantity_to_dispense).await?;
     }
 
     // Коммитим транзакцию
@@ -0,0 +1,484 @@
+// src/calibration_handlers.rs
+//! Структурированные результаты калибровки (ISO/IEC 17025).
+//!
+//! Результат привязан к записи обслуживания типа 'calibration' и эталону с
+//! сертификатом. Pass/fail вычисляется по точкам (см. `CalibrationPointInput::passes`).
+//! Неудачная калибровка переводит оборудование в 'damaged' и помечает
+//! эксперименты, использовавшие его после последней успешной калибровки.
+//!
+//! Endpoints:
+//!   GET    /api/v1/equipment/calibration-standards
+//!   POST   /api/v1/equipment/calibration-standards
+//!   PUT    /api/v1/equipment/calibration-standards/{id}
+//!   GET    /api/v1/equipment/calibration-flags?resolved=&experiment_id=&equipment_id=
+//!   POST   /api/v1/equipment/calibration-flags/{flag_id}/resolve
+//!   GET    /api/v1/equipment/{id}/calibrations
+//!   POST   /api/v1/equipment/{id}/maintenance/{maintenance_id}/calibration
+//!   GET    /api/v1/experiments/{id}/calibration-flags
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::{info, warn};
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::maintenance_handlers::{on_maintenance_closed, parse_day};
+use crate::models::{
+    CalibrationFlag, CalibrationPoint, CalibrationResult, CalibrationResultDetail, CalibrationStandard,
+    CreateCalibrationStandardRequest, EquipmentMaintenance, RecordCalibrationRequest,
+    ResolveCalibrationFlagRequest, UpdateCalibrationStandardRequest,
+};
+
+const FLAG_SELECT: &str = r#"
+    SELECT f.id, f.experiment_id, x.title as experiment_title, x.status as experiment_status,
+           f.equipment_id, f.calibration_result_id, f.reason, f.created_at,
+           f.resolved_at, f.resolved_by, f.resolution
+    FROM experiment_calibration_flags f
+    JOIN experiments x ON f.experiment_id = x.id
+"#;
+
+// ==================== STANDARDS ====================
+
+pub async fn get_calibration_standards(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let standards: Vec<CalibrationStandard> = sqlx::query_as(
+        "SELECT * FROM calibration_standards ORDER BY name ASC"
+    )
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(standards)))
+}
+
+pub async fn create_calibration_standard(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateCalibrationStandardRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    if let Some(ref date) = body.valid_until {
+        parse_day(date)?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO calibration_standards
+           (id, name, serial_number, certificate_number, certificate_issuer, traceability,
+            valid_until, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&body.name)
+        .bind(&body.serial_number)
+        .bind(&body.certificate_number)
+        .bind(&body.certificate_issuer)
+        .bind(&body.traceability)
+        .bind(&body.valid_until)
+        .bind(&body.notes)
+        .bind(&user_id)
+        .bind(now)
+        .bind(now)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let standard = get_standard_or_404(&app_state.db_pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(standard)))
+}
+
+pub async fn update_calibration_standard(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<UpdateCalibrationStandardRequest>,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let id = path.into_inner();
+    get_standard_or_404(&app_state.db_pool, &id).await?;
+    if let Some(ref date) = body.valid_until {
+        parse_day(date)?;
+    }
+
+    sqlx::query(
+        r#"UPDATE calibration_standards SET
+               name = COALESCE(?, name),
+               certificate_number = COALESCE(?, certificate_number),
+               certificate_issuer = COALESCE(?, certificate_issuer),
+               traceability = COALESCE(?, traceability),
+               valid_until = COALESCE(?, valid_until),
+               notes = COALESCE(?, notes),
+               updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(&body.name)
+        .bind(&body.certificate_number)
+        .bind(&body.certificate_issuer)
+        .bind(&body.traceability)
+        .bind(&body.valid_until)
+        .bind(&body.notes)
+        .bind(Utc::now())
+        .bind(&id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let standard = get_standard_or_404(&app_state.db_pool, &id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(standard)))
+}
+
+async fn get_standard_or_404(pool: &SqlitePool, id: &str) -> ApiResult<CalibrationStandard> {
+    sqlx::query_as::<_, CalibrationStandard>("SELECT * FROM calibration_standards WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Calibration standard"))
+}
+
+// ==================== RESULTS ====================
+
+/// Записать результат калибровки и закрыть запись обслуживания
+pub async fn record_calibration(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<RecordCalibrationRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (equipment_id, maintenance_id) = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let performed_at = match body.performed_at {
+        Some(ref date) => parse_day(date)?,
+        None => Utc::now().date_naive(),
+    };
+    let performed_str = performed_at.format("%Y-%m-%d").to_string();
+
+    let standard = get_standard_or_404(pool, &body.standard_id).await?;
+    if let Some(ref valid_until) = standard.valid_until {
+        if parse_day(valid_until)? < performed_at {
+            return Err(ApiError::bad_request(&format!(
+                "Certificate {} of reference standard '{}' expired on {}",
+                standard.certificate_number, standard.name, valid_until
+            )));
+        }
+    }
+
+    // Проверка на повторный результат и вставка — в одной транзакции
+    let mut tx = pool.begin().await?;
+
+    let record: EquipmentMaintenance = sqlx::query_as(
+        "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
+    )
+        .bind(&maintenance_id)
+        .bind(&equipment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Maintenance record"))?;
+
+    if record.maintenance_type != "calibration" {
+        return Err(ApiError::bad_request("Calibration results can only be recorded for calibration maintenance"));
+    }
+    if record.status == "cancelled" {
+        return Err(ApiError::bad_request("Maintenance record is cancelled"));
+    }
+    let already: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM calibration_results WHERE maintenance_id = ?")
+        .bind(&maintenance_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if already > 0 {
+        return Err(ApiError::bad_request("Calibration result already recorded for this maintenance"));
+    }
+
+    let passed = body.points.iter().all(|p| p.passes());
+    let result_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"INSERT INTO calibration_results
+           (id, maintenance_id, equipment_id, standard_id, performed_at, performed_by, passed, notes, created_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&result_id)
+        .bind(&maintenance_id)
+        .bind(&equipment_id)
+        .bind(&standard.id)
+        .bind(&performed_str)
+        .bind(&body.performed_by)
+        .bind(passed)
+        .bind(&body.notes)
+        .bind(&user_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match &e {
+            // maintenance_id UNIQUE: параллельная запись того же результата
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                ApiError::bad_request("Calibration result already recorded for this maintenance")
+            }
+            _ => e.into(),
+        })?;
+
+    for point in &body.points {
+        sqlx::query(
+            r#"INSERT INTO calibration_points
+               (result_id, nominal_value, measured_value, unit, uncertainty, tolerance, deviation, passed)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(&result_id)
+            .bind(point.nominal_value)
+            .bind(point.measured_value)
+            .bind(&point.unit)
+            .bind(point.uncertainty)
+            .bind(point.tolerance)
+            .bind(point.deviation())
+            .bind(point.passes())
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    if record.status == "scheduled" || record.status == "in_progress" {
+        sqlx::query(
+            r#"UPDATE equipment_maintenance
+               SET status = 'completed', completed_date = ?, performed_by = COALESCE(?, performed_by), updated_at = ?
+               WHERE id = ?"#
+        )
+            .bind(&performed_str)
+            .bind(&body.performed_by)
+            .bind(now)
+            .bind(&maintenance_id)
+            .execute(&mut *tx)
+            .await?;
+        on_maintenance_closed(&mut tx, &record, Some(&performed_str), Some(&user_id)).await?;
+    }
+
+    if !passed {
+        handle_failed_calibration(&mut tx, &equipment_id, &result_id, &performed_str).await?;
+    }
+
+    tx.commit().await?;
+
+    info!("Calibration {} of equipment {} recorded: {}", result_id, equipment_id, if passed { "PASS" } else { "FAIL" });
+    let detail = load_result_detail(pool, &result_id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(detail)))
+}
+
+/// Вывести оборудование из эксплуатации и пометить эксперименты, которые
+/// использовали его после последней успешной калибровки
+async fn handle_failed_calibration(
+    conn: &mut SqliteConnection,
+    equipment_id: &str,
+    result_id: &str,
+    performed_at: &str,
+) -> ApiResult<u64> {
+    sqlx::query("UPDATE equipment SET status = 'damaged', updated_at = ? WHERE id = ? AND status != 'retired'")
+        .bind(Utc::now())
+        .bind(equipment_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let last_pass: Option<String> = sqlx::query_scalar(
+        r#"SELECT MAX(performed_at) FROM calibration_results
+           WHERE equipment_id = ? AND passed = 1 AND id != ? AND performed_at <= ?"#
+    )
+        .bind(equipment_id)
+        .bind(result_id)
+        .bind(performed_at)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let equipment_name: String = sqlx::query_scalar("SELECT name FROM equipment WHERE id = ?")
+        .bind(equipment_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let experiments: Vec<String> = sqlx::query_scalar(r#"
+        SELECT x.id FROM experiments x
+        WHERE x.status IN ('in_progress', 'on_hold', 'completed')
+          AND (
+              x.id IN (SELECT experiment_id FROM experiment_equipment WHERE equipment_id = ?)
+              OR x.id IN (
+                  SELECT e.experiment_id FROM experiment_notebook_entries e
+                  JOIN experiment_notebook_links l ON l.entry_id = e.id
+                  WHERE l.entity_type = 'equipment' AND l.entity_id = ?
+              )
+          )
+          AND date(x.start_date) <= date(?)
+          AND (? IS NULL OR date(COALESCE(x.end_date, x.start_date)) >= date(?))
+    "#)
+        .bind(equipment_id)
+        .bind(equipment_id)
+        .bind(performed_at)
+        .bind(&last_pass)
+        .bind(&last_pass)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let reason = format!(
+        "Equipment '{}' failed calibration on {}; last passing calibration: {}",
+        equipment_name, performed_at, last_pass.as_deref().unwrap_or("none on record")
+    );
+
+    let mut flagged = 0u64;
+    for experiment_id in &experiments {
+        let result = sqlx::query(
+            r#"INSERT OR IGNORE INTO experiment_calibration_flags
+               (id, experiment_id, equipment_id, calibration_result_id, reason, created_at)
+               VALUES (?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(experiment_id)
+            .bind(equipment_id)
+            .bind(result_id)
+            .bind(&reason)
+            .bind(Utc::now())
+            .execute(&mut *conn)
+            .await?;
+        flagged += result.rows_affected();
+    }
+
+    warn!("Equipment {} failed calibration {}: set to 'damaged', {} experiment(s) flagged", equipment_id, result_id, flagged);
+    Ok(flagged)
+}
+
+async fn load_result_detail(pool: &SqlitePool, result_id: &str) -> ApiResult<CalibrationResultDetail> {
+    let result: CalibrationResult = sqlx::query_as("SELECT * FROM calibration_results WHERE id = ?")
+        .bind(result_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Calibration result"))?;
+
+    let standard = sqlx::query_as::<_, CalibrationStandard>("SELECT * FROM calibration_standards WHERE id = ?")
+        .bind(&result.standard_id)
+        .fetch_optional(pool)
+        .await?;
+
+    let points: Vec<CalibrationPoint> = sqlx::query_as(
+        "SELECT * FROM calibration_points WHERE result_id = ? ORDER BY nominal_value ASC, id ASC"
+    )
+        .bind(result_id)
+        .fetch_all(pool)
+        .await?;
+
+    let flagged_experiments: Vec<CalibrationFlag> = sqlx::query_as(
+        &format!("{} WHERE f.calibration_result_id = ? ORDER BY x.start_date DESC", FLAG_SELECT)
+    )
+        .bind(result_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(CalibrationResultDetail { result, standard, points, flagged_experiments })
+}
+
+/// История калибровок оборудования (новые сверху)
+pub async fn get_equipment_calibrations(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)")
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Equipment"));
+    }
+
+    let ids: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM calibration_results WHERE equipment_id = ? ORDER BY performed_at DESC, created_at DESC"
+    )
+        .bind(&equipment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    let mut results = Vec::with_capacity(ids.len());
+    for id in &ids {
+        results.push(load_result_detail(&app_state.db_pool, id).await?);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(results)))
+}
+
+// ==================== FLAGS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct CalibrationFlagQuery {
+    pub resolved: Option<bool>,
+    pub experiment_id: Option<String>,
+    pub equipment_id: Option<String>,
+}
+
+pub async fn get_calibration_flags(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CalibrationFlagQuery>,
+) -> ApiResult<HttpResponse> {
+    let sql = format!(r#"{}
+        WHERE (? IS NULL OR (f.resolved_at IS NOT NULL) = ?)
+          AND (? IS NULL OR f.experiment_id = ?)
+          AND (? IS NULL OR f.equipment_id = ?)
+        ORDER BY f.created_at DESC
+    "#, FLAG_SELECT);
+
+    let flags: Vec<CalibrationFlag> = sqlx::query_as(&sql)
+        .bind(query.resolved)
+        .bind(query.resolved)
+        .bind(&query.experiment_id)
+        .bind(&query.experiment_id)
+        .bind(&query.equipment_id)
+        .bind(&query.equipment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(flags)))
+}
+
+pub async fn resolve_calibration_flag(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<ResolveCalibrationFlagRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let flag_id = path.into_inner();
+
+    let result = sqlx::query(
+        r#"UPDATE experiment_calibration_flags
+           SET resolved_at = ?, resolved_by = ?, resolution = ?
+           WHERE id = ? AND resolved_at IS NULL"#
+    )
+        .bind(Utc::now())
+        .bind(&user_id)
+        .bind(&body.resolution)
+        .bind(&flag_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Calibration flag not found or already resolved"));
+    }
+
+    let flag: CalibrationFlag = sqlx::query_as(&format!("{} WHERE f.id = ?", FLAG_SELECT))
+        .bind(&flag_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(flag)))
+}
+
+/// GET /api/v1/experiments/{id}/calibration-flags
+pub async fn get_experiment_calibration_flags(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let flags: Vec<CalibrationFlag> = sqlx::query_as(
+        &format!("{} WHERE f.experiment_id = ? ORDER BY f.created_at DESC", FLAG_SELECT)
+    )
+        .bind(&experiment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(flags)))
+}
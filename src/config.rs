@@ -15,6 +15,8 @@ pub struct Config {
     pub security: SecurityConfig,
     pub logging: LoggingConfig,
     pub hot_reload: HotReloadConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -64,6 +66,20 @@ pub struct SecurityConfig {
     pub require_https: bool,
 }
 
+/// Хранилище загружаемых файлов (см. `storage`)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// "local" или "s3"
+    pub backend: String,
+    pub local_root: String,
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: String,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct LoggingConfig {
     pub level: String,
@@ -146,6 +162,20 @@ impl Default for LoggingConfig {
     }
 }
 
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: "local".to_string(),
+            local_root: "./uploads/blobs".to_string(),
+            s3_endpoint: None,
+            s3_bucket: None,
+            s3_region: "us-east-1".to_string(),
+            s3_access_key: None,
+            s3_secret_key: None,
+        }
+    }
+}
+
 impl Default for HotReloadConfig {
     fn default() -> Self {
         Self {
@@ -165,6 +195,7 @@ impl Default for Config {
             security: SecurityConfig::default(),
             logging: LoggingConfig::default(),
             hot_reload: HotReloadConfig::default(),
+            storage: StorageConfig::default(),
         }
     }
 }
@@ -350,6 +381,27 @@ fn override_with_env(config: &mut Config) -> Result<()> {
             .filter(|s| !s.is_empty())
             .collect();
     }
+    if let Ok(backend) = env::var("STORAGE_BACKEND") {
+        config.storage.backend = backend.trim().to_lowercase();
+    }
+    if let Ok(root) = env::var("STORAGE_LOCAL_ROOT") {
+        config.storage.local_root = root;
+    }
+    if let Ok(endpoint) = env::var("S3_ENDPOINT") {
+        config.storage.s3_endpoint = Some(endpoint);
+    }
+    if let Ok(bucket) = env::var("S3_BUCKET") {
+        config.storage.s3_bucket = Some(bucket);
+    }
+    if let Ok(region) = env::var("S3_REGION") {
+        config.storage.s3_region = region;
+    }
+    if let Ok(access_key) = env::var("S3_ACCESS_KEY_ID") {
+        config.storage.s3_access_key = Some(access_key);
+    }
+    if let Ok(secret_key) = env::var("S3_SECRET_ACCESS_KEY") {
+        config.storage.s3_secret_key = Some(secret_key);
+    }
     if let Ok(level) = env::var("RUST_LOG") {
         config.logging.level = level;
     }
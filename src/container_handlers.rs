@@ -9,7 +9,24 @@
 //!   PUT    /api/containers/{container_id}/move              — move container to new position
 //!   DELETE /api/containers/{container_id}/unplace           — remove from position (back to unplaced)
 //!   POST   /api/containers/{container_id}/use               — dispense from container
+//!   POST   /api/containers/{container_id}/retest            — record retest result of an opened container
+//!   GET    /api/containers/expiring?days=                   — containers past/near expiry or retest date
 //!   DELETE /api/containers/{container_id}                   — dispose of empty container
+//!          (non-empty stock goes out via POST /api/containers/{container_id}/waste, see waste.rs)
+//!   GET    /api/containers/{container_id}/movements         — movement timeline of a container
+//!   GET    /api/storage/positions/{id}/movements            — movement timeline of a position
+//!   GET    /api/rooms/{room_id}/inventory?as_of=            — room inventory, optionally as of a past date
+//!
+//! Place, move, unplace and dispose append to `container_movements`;
+//! `batch_placements` holds only the current position.
+//!
+//! Placement respects `max_capacity` and is rejected when the position is full,
+//! under maintenance or unavailable. Grid positions (boxes, racks) address
+//! containers by cell ("A1"); without an explicit cell the first free one is taken.
+//!
+//! The first `use` of a container stamps `open_expiry_date` / `retest_date` from the
+//! reagent's shelf life after opening and retest interval; the container expires at
+//! the earlier of that date and the batch expiry.
 
 use actix_web::{web, HttpResponse, HttpRequest};
 use std::sync::Arc;
@@ -18,16 +35,18 @@ use crate::models::*;
 use crate::error::{ApiError, ApiResult};
 use crate::handlers::ApiResponse;
 use crate::auth::get_current_user;
-use chrono::Utc;
+use crate::storage_handlers::{free_grid_cells, grid_cell_label, parse_grid_cell, refresh_position_occupancy};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
 use validator::Validate;
 use serde::{Serialize, Deserialize};
 use log::info;
+use std::collections::HashSet;
 
 // ==================== SQL FRAGMENTS ====================
 
 /// SELECT for ContainerWithLocation: LEFT JOIN to get placement + location
-const CONTAINER_WITH_LOCATION_SELECT: &str = r#"
+pub const CONTAINER_WITH_LOCATION_SELECT: &str = r#"
     SELECT
         bc.id,
         bc.batch_id,
@@ -39,12 +58,18 @@ const CONTAINER_WITH_LOCATION_SELECT: &str = r#"
         bc.opened_by,
         bc.status as container_status,
         bc.notes as container_notes,
+        bc.short_code,
+        bc.open_expiry_date,
+        bc.retest_date,
+        CASE WHEN bc.open_expiry_date IS NOT NULL AND (cb.expiry_date IS NULL OR bc.open_expiry_date < cb.expiry_date)
+             THEN bc.open_expiry_date ELSE cb.expiry_date END as effective_expiry_date,
         bc.created_at as container_created_at,
         bc.updated_at as container_updated_at,
         bp.id as placement_id,
         bp.position_id,
         sp.name as position_name,
         sp.position_label,
+        bp.cell,
         sz.id as zone_id,
         sz.name as zone_name,
         sz.zone_type,
@@ -52,6 +77,7 @@ const CONTAINER_WITH_LOCATION_SELECT: &str = r#"
         rm.name as room_name,
         rm.color as room_color
     FROM batch_containers bc
+    LEFT JOIN batches cb ON cb.id = bc.batch_id
     LEFT JOIN batch_placements bp ON bp.container_id = bc.id
     LEFT JOIN storage_positions sp ON bp.position_id = sp.id
     LEFT JOIN storage_zones sz ON sp.zone_id = sz.id
@@ -93,8 +119,181 @@ async fn validate_position(pool: &sqlx::SqlitePool, position_id: &str) -> ApiRes
     .map_err(|_| ApiError::not_found("Storage position"))
 }
 
+/// Where a container sits: position plus grid cell (grid positions only)
+pub struct Slot {
+    pub position_id: String,
+    pub cell: Option<String>,
+}
+
+/// Append a movement. Room ids are resolved now, so history survives later zone changes.
+pub async fn record_movement(
+    conn: &mut sqlx::SqliteConnection,
+    container_id: &str,
+    action: &str,
+    from: Option<&Slot>,
+    to: Option<&Slot>,
+    user_id: &str,
+    notes: Option<&str>,
+) -> ApiResult<()> {
+    let from_position_id = from.map(|s| s.position_id.as_str());
+    let to_position_id = to.map(|s| s.position_id.as_str());
+    sqlx::query(
+        r#"INSERT INTO container_movements
+           (id, container_id, batch_id, action, from_position_id, from_room_id,
+            to_position_id, to_room_id, from_cell, to_cell, moved_by, moved_at, notes)
+           SELECT ?, bc.id, bc.batch_id, ?, ?,
+                  (SELECT sz.room_id FROM storage_positions sp JOIN storage_zones sz ON sp.zone_id = sz.id WHERE sp.id = ?),
+                  ?,
+                  (SELECT sz.room_id FROM storage_positions sp JOIN storage_zones sz ON sp.zone_id = sz.id WHERE sp.id = ?),
+                  ?, ?, ?, ?, ?
+           FROM batch_containers bc WHERE bc.id = ?"#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(action)
+    .bind(from_position_id)
+    .bind(from_position_id)
+    .bind(to_position_id)
+    .bind(to_position_id)
+    .bind(from.and_then(|s| s.cell.as_deref()))
+    .bind(to.and_then(|s| s.cell.as_deref()))
+    .bind(user_id)
+    .bind(Utc::now())
+    .bind(notes)
+    .bind(container_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Deduct batch-level use given with a `placement_id` from the placed container.
+/// The placement stays: like `use`, an emptied container leaves its position only
+/// when disposed, so `container_movements` never loses track of it.
+/// An unknown placement is ignored (the batch total is already decremented).
+pub async fn draw_from_placement(
+    conn: &mut sqlx::SqliteConnection,
+    placement_id: &str,
+    batch_id: &str,
+    quantity: f64,
+) -> ApiResult<()> {
+    let container: Option<(String, f64, f64)> = sqlx::query_as(
+        r#"SELECT bc.id, bc.quantity, bc.original_quantity
+           FROM batch_placements bp JOIN batch_containers bc ON bc.id = bp.container_id
+           WHERE bp.id = ? AND bc.batch_id = ?"#
+    )
+    .bind(placement_id)
+    .bind(batch_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some((container_id, container_qty, original_qty)) = container {
+        let new_qty = (container_qty - quantity).max(0.0);
+        sqlx::query("UPDATE batch_containers SET quantity = ?, status = ?, updated_at = ? WHERE id = ?")
+            .bind(new_qty)
+            .bind(compute_container_status(new_qty, original_qty))
+            .bind(Utc::now())
+            .bind(&container_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Current slot of a container, if placed
+pub async fn current_slot(conn: &mut sqlx::SqliteConnection, container_id: &str) -> ApiResult<Option<Slot>> {
+    let placement: Option<BatchPlacement> = sqlx::query_as(
+        "SELECT * FROM batch_placements WHERE container_id = ?"
+    )
+    .bind(container_id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(placement.map(|p| Slot { position_id: p.position_id, cell: p.cell }))
+}
+
+/// Check that the position can take the container and pick its cell.
+/// `container_id` is excluded from occupancy, so re-slotting within a position works.
+pub async fn reserve_slot(
+    conn: &mut sqlx::SqliteConnection,
+    position_id: &str,
+    requested_cell: Option<&str>,
+    container_id: &str,
+) -> ApiResult<Slot> {
+    let position: StoragePosition = sqlx::query_as("SELECT * FROM storage_positions WHERE id = ?")
+        .bind(position_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Storage position"))?;
+
+    if matches!(position.status.as_str(), "maintenance" | "unavailable") {
+        return Err(ApiError::bad_request(&format!(
+            "Position {} is {} and does not accept containers", position.name, position.status
+        )));
+    }
+
+    let occupied: Vec<(Option<String>,)> = sqlx::query_as(
+        "SELECT cell FROM batch_placements WHERE position_id = ? AND container_id != ?"
+    )
+    .bind(position_id)
+    .bind(container_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if let Some(cap) = position.max_capacity {
+        if occupied.len() as i64 >= cap as i64 {
+            return Err(ApiError::bad_request(&format!(
+                "Position {} is full ({}/{})", position.name, occupied.len(), cap
+            )));
+        }
+    }
+
+    let cell = match (position.grid_rows, position.grid_columns) {
+        (Some(rows), Some(columns)) => {
+            let taken: HashSet<String> = occupied.into_iter().filter_map(|(c,)| c).collect();
+            let cell = match requested_cell {
+                Some(requested) => {
+                    let (row, column) = parse_grid_cell(requested, rows, columns).ok_or_else(|| {
+                        ApiError::bad_request(&format!(
+                            "Cell '{}' is not in the {}×{} grid of {} (A1…{})",
+                            requested, rows, columns, position.name, grid_cell_label(rows, columns)
+                        ))
+                    })?;
+                    grid_cell_label(row, column)
+                }
+                None => free_grid_cells(rows, columns, &taken).next()
+                    .ok_or_else(|| ApiError::bad_request(&format!("Position {} has no free cells", position.name)))?,
+            };
+            if taken.contains(&cell) {
+                return Err(ApiError::bad_request(&format!("Cell {} of {} is occupied", cell, position.name)));
+            }
+            Some(cell)
+        }
+        _ => {
+            if requested_cell.is_some() {
+                return Err(ApiError::bad_request(&format!(
+                    "Position {} has no grid; omit the cell", position.name
+                )));
+            }
+            None
+        }
+    };
+
+    Ok(Slot { position_id: position.id, cell })
+}
+
+/// as_of: 'YYYY-MM-DD' means the end of that day (UTC)
+fn parse_as_of(value: &str) -> ApiResult<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_milli_opt(23, 59, 59, 999))
+        .map(|dt| dt.and_utc())
+        .ok_or_else(|| ApiError::bad_request("as_of must be YYYY-MM-DD or an RFC 3339 timestamp"))
+}
+
 /// Compute container status from quantity vs original
-fn compute_container_status(quantity: f64, original_quantity: f64) -> &'static str {
+pub fn compute_container_status(quantity: f64, original_quantity: f64) -> &'static str {
     if quantity <= 0.001 {
         "empty"
     } else if (quantity - original_quantity).abs() < 0.001 {
@@ -104,6 +303,18 @@ fn compute_container_status(quantity: f64, original_quantity: f64) -> &'static s
     }
 }
 
+/// Сроки вскрытого контейнера: (opened_at + срок после вскрытия, opened_at + интервал перепроверки)
+pub fn opened_container_dates(
+    opened_at: DateTime<Utc>,
+    shelf_life_after_opening_days: Option<i64>,
+    retest_interval_days: Option<i64>,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let add_days = |days: Option<i64>| days
+        .filter(|d| *d > 0)
+        .map(|d| opened_at + chrono::Duration::days(d));
+    (add_days(shelf_life_after_opening_days), add_days(retest_interval_days))
+}
+
 /// Get next sequence number for batch
 async fn next_sequence(pool: &sqlx::SqlitePool, batch_id: &str) -> ApiResult<i64> {
     let result: (i64,) = sqlx::query_as(
@@ -383,6 +594,7 @@ pub async fn place_container(
     let claims = get_current_user(&http_request)?;
     let now = Utc::now();
 
+    request.validate()?;
     let container = get_container_or_404(&app_state.db_pool, &container_id).await?;
     let position = validate_position(&app_state.db_pool, &request.position_id).await?;
 
@@ -400,10 +612,12 @@ pub async fn place_container(
         ));
     }
 
+    let mut tx = app_state.db_pool.begin().await?;
+    let slot = reserve_slot(&mut tx, &request.position_id, request.cell.as_deref(), &container_id).await?;
     let placement_id = Uuid::new_v4().to_string();
     sqlx::query(
-        r#"INSERT INTO batch_placements (id, container_id, position_id, placed_by, placed_at, notes)
-           VALUES (?, ?, ?, ?, ?, ?)"#
+        r#"INSERT INTO batch_placements (id, container_id, position_id, placed_by, placed_at, notes, cell)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
     )
     .bind(&placement_id)
     .bind(&container_id)
@@ -411,8 +625,12 @@ pub async fn place_container(
     .bind(&claims.sub)
     .bind(&now)
     .bind(&request.notes)
-    .execute(&app_state.db_pool)
+    .bind(&slot.cell)
+    .execute(&mut *tx)
     .await?;
+    record_movement(&mut tx, &container_id, "place", None, Some(&slot), &claims.sub, request.notes.as_deref()).await?;
+    refresh_position_occupancy(&mut tx, &slot.position_id).await?;
+    tx.commit().await?;
 
     let sql = format!("{} WHERE bc.id = ?", CONTAINER_WITH_LOCATION_SELECT);
     let result: ContainerWithLocation = sqlx::query_as(&sql)
@@ -445,16 +663,20 @@ pub async fn place_containers_bulk(
 
         if existing.is_some() { continue; }
 
+        // All-or-nothing: a full position rolls back the whole batch
+        let slot = reserve_slot(&mut tx, &request.position_id, None, cid).await?;
         let id = Uuid::new_v4().to_string();
         sqlx::query(
-            "INSERT INTO batch_placements (id, container_id, position_id, placed_by, placed_at, notes) VALUES (?, ?, ?, ?, ?, NULL)"
+            "INSERT INTO batch_placements (id, container_id, position_id, placed_by, placed_at, notes, cell) VALUES (?, ?, ?, ?, ?, NULL, ?)"
         )
         .bind(&id).bind(cid).bind(&request.position_id)
-        .bind(&claims.sub).bind(&now)
+        .bind(&claims.sub).bind(now).bind(&slot.cell)
         .execute(&mut *tx).await?;
+        record_movement(&mut tx, cid, "place", None, Some(&slot), &claims.sub, None).await?;
         placed += 1;
     }
 
+    refresh_position_occupancy(&mut tx, &request.position_id).await?;
     tx.commit().await?;
     info!("📍 Bulk placed {} containers at position {}", placed, request.position_id);
 
@@ -473,16 +695,28 @@ pub async fn move_containers_bulk(
     let now = Utc::now();
     let mut tx = app_state.db_pool.begin().await?;
     let mut moved = 0i64;
+    let mut vacated: HashSet<String> = HashSet::new();
 
     for cid in &request.container_ids {
-        let result = sqlx::query(
-            "UPDATE batch_placements SET position_id = ?, placed_by = ?, placed_at = ? WHERE container_id = ?"
+        // Unplaced containers and containers already there are skipped
+        let from = match current_slot(&mut tx, cid).await? {
+            Some(slot) if slot.position_id != request.new_position_id => slot,
+            _ => continue,
+        };
+        let to = reserve_slot(&mut tx, &request.new_position_id, None, cid).await?;
+        sqlx::query(
+            "UPDATE batch_placements SET position_id = ?, cell = ?, placed_by = ?, placed_at = ? WHERE container_id = ?"
         )
-        .bind(&request.new_position_id).bind(&claims.sub).bind(&now).bind(cid)
+        .bind(&request.new_position_id).bind(&to.cell).bind(&claims.sub).bind(now).bind(cid)
         .execute(&mut *tx).await?;
-        moved += result.rows_affected() as i64;
+        record_movement(&mut tx, cid, "move", Some(&from), Some(&to), &claims.sub, None).await?;
+        vacated.insert(from.position_id);
+        moved += 1;
     }
 
+    for position_id in vacated.iter().chain(std::iter::once(&request.new_position_id)) {
+        refresh_position_occupancy(&mut tx, position_id).await?;
+    }
     tx.commit().await?;
     info!("📍 Bulk moved {} containers to position {}", moved, request.new_position_id);
 
@@ -507,25 +741,32 @@ pub async fn move_container(
     let container = get_container_or_404(&app_state.db_pool, &container_id).await?;
     let new_position = validate_position(&app_state.db_pool, &request.new_position_id).await?;
 
-    // Find existing placement
-    let placement: BatchPlacement = sqlx::query_as(
-        "SELECT * FROM batch_placements WHERE container_id = ?"
-    )
-    .bind(&container_id)
-    .fetch_one(&app_state.db_pool)
-    .await
-    .map_err(|_| ApiError::bad_request("Container is not placed anywhere. Use place first."))?;
-
-    // Just update position_id — no quantity logic needed
-    sqlx::query(
-        "UPDATE batch_placements SET position_id = ?, placed_by = ?, placed_at = ? WHERE id = ?"
-    )
-    .bind(&request.new_position_id)
-    .bind(&claims.sub)
-    .bind(&now)
-    .bind(&placement.id)
-    .execute(&app_state.db_pool)
-    .await?;
+    let mut tx = app_state.db_pool.begin().await?;
+    let from = current_slot(&mut tx, &container_id).await?
+        .ok_or_else(|| ApiError::bad_request("Container is not placed anywhere. Use place first."))?;
+
+    // Same position without a cell is a no-op; with a cell it re-slots within the grid
+    if from.position_id != request.new_position_id || request.cell.is_some() {
+        let to = reserve_slot(&mut tx, &request.new_position_id, request.cell.as_deref(), &container_id).await?;
+        if to.position_id != from.position_id || to.cell != from.cell {
+            sqlx::query(
+                "UPDATE batch_placements SET position_id = ?, cell = ?, placed_by = ?, placed_at = ? WHERE container_id = ?"
+            )
+            .bind(&to.position_id)
+            .bind(&to.cell)
+            .bind(&claims.sub)
+            .bind(now)
+            .bind(&container_id)
+            .execute(&mut *tx)
+            .await?;
+            record_movement(&mut tx, &container_id, "move", Some(&from), Some(&to), &claims.sub, None).await?;
+            refresh_position_occupancy(&mut tx, &from.position_id).await?;
+            if to.position_id != from.position_id {
+                refresh_position_occupancy(&mut tx, &to.position_id).await?;
+            }
+        }
+    }
+    tx.commit().await?;
 
     let sql = format!("{} WHERE bc.id = ?", CONTAINER_WITH_LOCATION_SELECT);
     let result: ContainerWithLocation = sqlx::query_as(&sql)
@@ -552,16 +793,19 @@ pub async fn unplace_container(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let container_id = path.into_inner();
-    let _claims = get_current_user(&http_request)?;
+    let claims = get_current_user(&http_request)?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let from = current_slot(&mut tx, &container_id).await?
+        .ok_or_else(|| ApiError::bad_request("Container is not placed anywhere."))?;
 
-    let result = sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
+    sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
         .bind(&container_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
-
-    if result.rows_affected() == 0 {
-        return Err(ApiError::bad_request("Container is not placed anywhere."));
-    }
+    record_movement(&mut tx, &container_id, "unplace", Some(&from), None, &claims.sub, None).await?;
+    refresh_position_occupancy(&mut tx, &from.position_id).await?;
+    tx.commit().await?;
 
     info!("📍 Container {} unplaced", container_id);
 
@@ -586,6 +830,7 @@ pub async fn use_from_container(
 
     let container = get_container_or_404(&app_state.db_pool, &container_id).await?;
     let batch = get_batch_or_404(&app_state.db_pool, &container.batch_id).await?;
+    crate::goods_in::ensure_released(&batch.status)?;
 
     // Validate: enough in this container
     if request.quantity > container.quantity + 0.001 {
@@ -599,19 +844,37 @@ pub async fn use_from_container(
 
     // 1. Update container quantity + mark opened
     let new_container_qty = (container.quantity - request.quantity).max(0.0);
-    let new_status = compute_container_status(new_container_qty, container.original_quantity);
+    // Просроченный контейнер остаётся просроченным, пока в нём что-то есть
+    let new_status = if container.status == "expired" && new_container_qty > 0.001 {
+        "expired"
+    } else {
+        compute_container_status(new_container_qty, container.original_quantity)
+    };
+    let mut open_expiry_date = container.open_expiry_date;
+    let mut retest_date = container.retest_date;
 
     if !container.is_opened {
-        // First time opening
+        // First time opening — считаем срок годности после вскрытия и дату перепроверки
+        let (shelf_life, retest_interval): (Option<i64>, Option<i64>) = sqlx::query_as(
+            "SELECT shelf_life_after_opening_days, retest_interval_days FROM reagents WHERE id = ?"
+        )
+        .bind(&batch.reagent_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or((None, None));
+        (open_expiry_date, retest_date) = opened_container_dates(now, shelf_life, retest_interval);
+
         sqlx::query(
             r#"UPDATE batch_containers 
                SET quantity = ?, is_opened = 1, opened_at = ?, opened_by = ?,
-                   status = ?, updated_at = ?
+                   open_expiry_date = ?, retest_date = ?, status = ?, updated_at = ?
                WHERE id = ?"#
         )
         .bind(new_container_qty)
         .bind(&now)
         .bind(&claims.sub)
+        .bind(open_expiry_date)
+        .bind(retest_date)
         .bind(new_status)
         .bind(&now)
         .bind(&container_id)
@@ -633,6 +896,8 @@ pub async fn use_from_container(
     let new_batch_qty = (batch.quantity - request.quantity).max(0.0);
     let batch_status = if new_batch_qty <= 0.0 {
         "depleted"
+    } else if batch.status == "expired" {
+        "expired"
     } else if let Some(ps) = batch.pack_size {
         if new_batch_qty <= ps { "low_stock" } else { "available" }
     } else {
@@ -686,6 +951,8 @@ pub async fn use_from_container(
         container_remaining: f64,
         container_status: String,
         is_opened: bool,
+        open_expiry_date: Option<DateTime<Utc>>,
+        retest_date: Option<DateTime<Utc>>,
         batch_remaining: f64,
         batch_status: String,
         unit: String,
@@ -699,6 +966,8 @@ pub async fn use_from_container(
             container_remaining: new_container_qty,
             container_status: new_status.to_string(),
             is_opened: true,
+            open_expiry_date,
+            retest_date,
             batch_remaining: new_batch_qty,
             batch_status: batch_status.to_string(),
             unit: batch.unit,
@@ -715,30 +984,42 @@ pub async fn dispose_container(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let container_id = path.into_inner();
-    let _claims = get_current_user(&http_request)?;
+    let claims = get_current_user(&http_request)?;
     let now = Utc::now();
 
     let container = get_container_or_404(&app_state.db_pool, &container_id).await?;
 
     if container.quantity > 0.001 {
         return Err(ApiError::bad_request(&format!(
-            "Container still has {:.2} remaining. Use or empty it before disposing.",
+            "Container still has {:.2} remaining. Use it or dispose of the remainder as waste first.",
             container.quantity
         )));
     }
 
+    if container.status == "disposed" {
+        return Err(ApiError::bad_request("Container is already disposed."));
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let from = current_slot(&mut tx, &container_id).await?;
+
     // Remove placement if exists
     sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
         .bind(&container_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
 
     // Mark as disposed (or delete — your choice)
     sqlx::query("UPDATE batch_containers SET status = 'disposed', updated_at = ? WHERE id = ?")
         .bind(&now)
         .bind(&container_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    record_movement(&mut tx, &container_id, "dispose", from.as_ref(), None, &claims.sub, None).await?;
+    if let Some(ref from) = from {
+        refresh_position_occupancy(&mut tx, &from.position_id).await?;
+    }
+    tx.commit().await?;
 
     info!("🗑️ Container #{} disposed", container.sequence_number);
 
@@ -748,11 +1029,307 @@ pub async fn dispose_container(
     )))
 }
 
+// ==================== OPENED SHELF LIFE / RETEST ====================
+
+const DEFAULT_EXPIRING_DAYS: i64 = 30;
+
+/// Срок контейнера = раньшая из дат партии и после вскрытия
+const EFFECTIVE_EXPIRY_SQL: &str = "CASE WHEN bc.open_expiry_date IS NOT NULL AND (b.expiry_date IS NULL OR bc.open_expiry_date < b.expiry_date) \
+     THEN bc.open_expiry_date ELSE b.expiry_date END";
+
+/// Ближайшая из дат контейнера и её причина
+fn nearest_deadline(c: &ExpiringContainer) -> Option<(DateTime<Utc>, &'static str)> {
+    [
+        (c.batch_expiry_date, "batch_expiry"),
+        (c.open_expiry_date, "opened_shelf_life"),
+        (c.retest_date, "retest"),
+    ]
+    .into_iter()
+    .filter_map(|(date, reason)| date.map(|d| (d, reason)))
+    .min_by_key(|(date, _)| *date)
+}
+
+/// GET /containers/expiring?days=30 — контейнеры, у которых истекает срок
+/// (партии или после вскрытия) или подошла дата перепроверки
+pub async fn get_expiring_containers(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ExpiringContainersQuery>,
+) -> ApiResult<HttpResponse> {
+    let days = query.days.unwrap_or(DEFAULT_EXPIRING_DAYS);
+    if !(0..=3650).contains(&days) {
+        return Err(ApiError::bad_request("days must be between 0 and 3650"));
+    }
+    let now = Utc::now();
+    let threshold = now + chrono::Duration::days(days);
+
+    let sql = format!(
+        r#"SELECT bc.id, bc.short_code, bc.sequence_number, bc.batch_id, b.batch_number,
+                  b.reagent_id, r.name as reagent_name, bc.quantity, b.unit,
+                  bc.status as container_status, bc.is_opened, bc.opened_at,
+                  b.expiry_date as batch_expiry_date, bc.open_expiry_date,
+                  {effective} as effective_expiry_date, bc.retest_date
+           FROM batch_containers bc
+           JOIN batches b ON b.id = bc.batch_id
+           JOIN reagents r ON r.id = b.reagent_id
+           WHERE b.deleted_at IS NULL
+             AND bc.status IN ('full', 'partial', 'expired')
+             AND bc.quantity > 0
+             AND ({effective} <= ? OR bc.retest_date <= ?)"#,
+        effective = EFFECTIVE_EXPIRY_SQL,
+    );
+    let mut containers: Vec<ExpiringContainer> = sqlx::query_as(&sql)
+        .bind(threshold)
+        .bind(threshold)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    for c in containers.iter_mut() {
+        if let Some((date, reason)) = nearest_deadline(c) {
+            c.reason = reason.to_string();
+            c.days_left = Some((date - now).num_days());
+        }
+    }
+    containers.sort_by_key(|c| c.days_left.unwrap_or(i64::MAX));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(containers)))
+}
+
+/// Почасовая задача: просрочивает контейнеры, у которых истёк срок партии или срок после
+/// вскрытия, и партии, все оставшиеся контейнеры которых просрочены.
+/// Возвращает (контейнеры, партии).
+pub async fn apply_container_expiry(pool: &sqlx::SqlitePool) -> Result<(u64, u64), sqlx::Error> {
+    let now = Utc::now();
+    let mut containers = 0;
+    loop {
+        let sql = format!(
+            r#"UPDATE batch_containers SET status = 'expired', updated_at = ?
+               WHERE id IN (
+                   SELECT bc.id FROM batch_containers bc
+                   JOIN batches b ON b.id = bc.batch_id
+                   WHERE bc.status IN ('full', 'partial') AND {} < ?
+                   LIMIT 1000
+               )"#,
+            EFFECTIVE_EXPIRY_SQL,
+        );
+        let count = sqlx::query(&sql).bind(now).bind(now).execute(pool).await?.rows_affected();
+        containers += count;
+        if count < 1000 { break; }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+
+    let batches = sqlx::query(
+        r#"UPDATE batches SET status = 'expired', updated_at = ?
+           WHERE status IN ('available', 'low_stock') AND deleted_at IS NULL
+             AND EXISTS (SELECT 1 FROM batch_containers bc WHERE bc.batch_id = batches.id AND bc.status = 'expired')
+             AND NOT EXISTS (SELECT 1 FROM batch_containers bc WHERE bc.batch_id = batches.id AND bc.status IN ('full', 'partial'))"#
+    )
+    .bind(now)
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok((containers, batches))
+}
+
+/// POST /containers/{id}/retest — результат перепроверки вскрытого контейнера.
+/// Годен: retest_date = сейчас + интервал реагента; не годен: контейнер просрочен.
+pub async fn retest_container(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<RetestContainerRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    request.validate()?;
+    let container_id = path.into_inner();
+    let claims = get_current_user(&http_request)?;
+    let now = Utc::now();
+
+    let container = get_container_or_404(&app_state.db_pool, &container_id).await?;
+    if !container.is_opened {
+        return Err(ApiError::bad_request("Container has not been opened yet"));
+    }
+    if matches!(container.status.as_str(), "empty" | "disposed") {
+        return Err(ApiError::bad_request(&format!("Container is {}", container.status)));
+    }
+
+    let (status, retest_date) = if request.passed {
+        let interval: Option<i64> = sqlx::query_scalar(
+            r#"SELECT r.retest_interval_days FROM batches b
+               JOIN reagents r ON r.id = b.reagent_id WHERE b.id = ?"#
+        )
+        .bind(&container.batch_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .flatten();
+        let interval = interval
+            .ok_or_else(|| ApiError::bad_request("Reagent has no retest interval configured"))?;
+        if container.status == "expired" {
+            return Err(ApiError::bad_request("Expired container cannot pass a retest"));
+        }
+        (container.status.clone(), Some(now + chrono::Duration::days(interval)))
+    } else {
+        ("expired".to_string(), container.retest_date)
+    };
+
+    let notes = request.notes.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let notes = match (container.notes.as_deref(), notes) {
+        (Some(old), Some(new)) => Some(format!("{}\n{}", old, new)),
+        (None, Some(new)) => Some(new.to_string()),
+        (old, None) => old.map(str::to_string),
+    };
+
+    sqlx::query("UPDATE batch_containers SET status = ?, retest_date = ?, notes = ?, updated_at = ? WHERE id = ?")
+        .bind(&status)
+        .bind(retest_date)
+        .bind(&notes)
+        .bind(now)
+        .bind(&container_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    info!(
+        "🧪 Container #{} retest {} by {}",
+        container.sequence_number,
+        if request.passed { "passed" } else { "failed" },
+        claims.sub,
+    );
+
+    let updated = get_container_or_404(&app_state.db_pool, &container_id).await?;
+    let message = if request.passed {
+        "Retest passed, next retest date updated"
+    } else {
+        "Retest failed, container marked as expired"
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(updated, message.to_string())))
+}
+
+// ==================== MOVEMENT TIMELINES ====================
+
+/// SELECT for ContainerMovement with resolved names (deleted positions come back as NULL)
+const MOVEMENT_SELECT: &str = r#"
+    SELECT
+        m.id, m.container_id, m.batch_id,
+        bc.sequence_number,
+        b.batch_number,
+        rg.name as reagent_name,
+        m.action,
+        m.from_position_id, fp.name as from_position_name,
+        m.from_room_id, fr.name as from_room_name,
+        m.to_position_id, tp.name as to_position_name,
+        m.to_room_id, tr.name as to_room_name,
+        m.from_cell, m.to_cell,
+        m.moved_by, u.username as moved_by_username,
+        m.moved_at, m.notes
+    FROM container_movements m
+    LEFT JOIN batch_containers bc ON m.container_id = bc.id
+    LEFT JOIN batches b ON m.batch_id = b.id
+    LEFT JOIN reagents rg ON b.reagent_id = rg.id
+    LEFT JOIN storage_positions fp ON m.from_position_id = fp.id
+    LEFT JOIN rooms fr ON m.from_room_id = fr.id
+    LEFT JOIN storage_positions tp ON m.to_position_id = tp.id
+    LEFT JOIN rooms tr ON m.to_room_id = tr.id
+    LEFT JOIN users u ON m.moved_by = u.id
+"#;
+
+const DEFAULT_TIMELINE_LIMIT: i64 = 200;
+
+pub async fn get_container_movements(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<MovementTimelineQuery>,
+) -> ApiResult<HttpResponse> {
+    let container_id = path.into_inner();
+    get_container_or_404(&app_state.db_pool, &container_id).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_TIMELINE_LIMIT).clamp(1, 1000);
+
+    let sql = format!(
+        "{} WHERE m.container_id = ? ORDER BY julianday(m.moved_at) DESC, m.rowid DESC LIMIT ?",
+        MOVEMENT_SELECT
+    );
+    let movements: Vec<ContainerMovement> = sqlx::query_as(&sql)
+        .bind(&container_id)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(movements)))
+}
+
+/// Everything that arrived at or left a position. Works for deleted positions too.
+pub async fn get_position_movements(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<MovementTimelineQuery>,
+) -> ApiResult<HttpResponse> {
+    let position_id = path.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_TIMELINE_LIMIT).clamp(1, 1000);
+
+    let sql = format!(
+        "{} WHERE m.from_position_id = ? OR m.to_position_id = ? ORDER BY julianday(m.moved_at) DESC, m.rowid DESC LIMIT ?",
+        MOVEMENT_SELECT
+    );
+    let movements: Vec<ContainerMovement> = sqlx::query_as(&sql)
+        .bind(&position_id)
+        .bind(&position_id)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    if movements.is_empty() {
+        validate_position(&app_state.db_pool, &position_id).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(movements)))
+}
+
 // ==================== ROOM INVENTORY (updated for containers) ====================
 
+/// Containers in a room at a past moment: the latest movement of each container
+/// up to `as_of` decides where it was. Quantities and statuses are current values.
+const ROOM_INVENTORY_AS_OF: &str = r#"
+    WITH last_move AS (
+        SELECT m.container_id, m.to_position_id, m.to_room_id, m.to_cell,
+               ROW_NUMBER() OVER (
+                   PARTITION BY m.container_id ORDER BY julianday(m.moved_at) DESC, m.rowid DESC
+               ) as rn
+        FROM container_movements m
+        WHERE julianday(m.moved_at) <= julianday(?)
+    )
+    SELECT
+        bc.id as container_id,
+        bc.sequence_number,
+        bc.quantity as container_quantity,
+        bc.is_opened,
+        bc.status as container_status,
+        lm.to_position_id as position_id,
+        COALESCE(sp.name, '(deleted position)') as position_name,
+        lm.to_cell as cell,
+        b.id as batch_id,
+        b.batch_number,
+        b.lot_number,
+        b.unit,
+        b.quantity as total_quantity,
+        b.expiry_date,
+        b.status as batch_status,
+        rg.id as reagent_id,
+        rg.name as reagent_name,
+        rg.formula,
+        rg.cas_number,
+        rg.hazard_pictograms
+    FROM last_move lm
+    JOIN batch_containers bc ON lm.container_id = bc.id
+    JOIN batches b ON bc.batch_id = b.id
+    JOIN reagents rg ON b.reagent_id = rg.id
+    LEFT JOIN storage_positions sp ON lm.to_position_id = sp.id
+    LEFT JOIN storage_zones sz ON sp.zone_id = sz.id
+    WHERE lm.rn = 1 AND lm.to_position_id IS NOT NULL AND lm.to_room_id = ?
+    ORDER BY sz.sort_order, sp.sort_order, rg.name, b.batch_number, bc.sequence_number
+"#;
+
 pub async fn get_room_inventory(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    query: web::Query<RoomInventoryQuery>,
 ) -> ApiResult<HttpResponse> {
     let room_id = path.into_inner();
 
@@ -763,6 +1340,16 @@ pub async fn get_room_inventory(
         .await
         .map_err(|_| ApiError::not_found("Room"))?;
 
+    if let Some(ref as_of) = query.as_of {
+        let as_of = parse_as_of(as_of)?;
+        let inventory: Vec<PositionInventoryItem> = sqlx::query_as(ROOM_INVENTORY_AS_OF)
+            .bind(as_of)
+            .bind(&room_id)
+            .fetch_all(&app_state.db_pool)
+            .await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(inventory)));
+    }
+
     let inventory: Vec<PositionInventoryItem> = sqlx::query_as(
         r#"SELECT
             bc.id as container_id,
@@ -772,6 +1359,7 @@ pub async fn get_room_inventory(
             bc.status as container_status,
             sp.id as position_id,
             sp.name as position_name,
+            bp.cell,
             b.id as batch_id,
             b.batch_number,
             b.lot_number,
@@ -799,3 +1387,30 @@ pub async fn get_room_inventory(
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(inventory)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_as_of() {
+        let end_of_day = parse_as_of("2025-03-10").unwrap();
+        assert_eq!(end_of_day.to_rfc3339(), "2025-03-10T23:59:59.999+00:00");
+
+        let exact = parse_as_of("2025-03-10T08:30:00+03:00").unwrap();
+        assert_eq!(exact.to_rfc3339(), "2025-03-10T05:30:00+00:00");
+
+        assert!(parse_as_of("10.03.2025").is_err());
+        assert!(parse_as_of("").is_err());
+    }
+
+    #[test]
+    fn test_opened_container_dates() {
+        let opened = DateTime::parse_from_rfc3339("2025-03-10T08:00:00Z").unwrap().with_timezone(&Utc);
+        let (expiry, retest) = opened_container_dates(opened, Some(30), Some(7));
+        assert_eq!(expiry.unwrap().to_rfc3339(), "2025-04-09T08:00:00+00:00");
+        assert_eq!(retest.unwrap().to_rfc3339(), "2025-03-17T08:00:00+00:00");
+
+        assert_eq!(opened_container_dates(opened, None, Some(0)), (None, None));
+    }
+}
@@ -5,6 +5,8 @@ use sqlx::SqlitePool;
 use anyhow::Result;
 use log::info;
 
+use crate::query_builders::fts::config::FtsConfig;
+
 pub async fn ensure_performance_indexes(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     info!("Checking and applying performance indexes...");
 
@@ -97,6 +99,9 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             storage_conditions TEXT CHECK(storage_conditions IS NULL OR length(storage_conditions) <= 255),
             appearance TEXT CHECK(appearance IS NULL OR length(appearance) <= 255),
             hazard_pictograms TEXT CHECK(hazard_pictograms IS NULL OR length(hazard_pictograms) <= 100),
+            signal_word TEXT CHECK(signal_word IS NULL OR signal_word IN ('Danger', 'Warning')),
+            shelf_life_after_opening_days INTEGER CHECK(shelf_life_after_opening_days IS NULL OR shelf_life_after_opening_days > 0),
+            retest_interval_days INTEGER CHECK(retest_interval_days IS NULL OR retest_interval_days > 0),
             status TEXT NOT NULL DEFAULT 'active' CHECK(
                 status IN ('active', 'inactive', 'discontinued')
             ),
@@ -136,7 +141,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             manufacturer TEXT CHECK(manufacturer IS NULL OR length(manufacturer) <= 255),
             received_date DATETIME NOT NULL,
             status TEXT NOT NULL DEFAULT 'available' CHECK(
-                status IN ('available', 'in_use', 'expired', 'depleted', 'low_stock')
+                status IN ('available', 'in_use', 'expired', 'depleted', 'low_stock', 'quarantine', 'rejected')
             ),
             location TEXT CHECK(location IS NULL OR length(location) <= 255),
             notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
@@ -145,6 +150,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
             deleted_at DATETIME,
+            short_code TEXT,
             FOREIGN KEY (reagent_id) REFERENCES reagents (id) ON DELETE CASCADE,
             FOREIGN KEY (created_by) REFERENCES users (id),
             FOREIGN KEY (updated_by) REFERENCES users (id),
@@ -155,6 +161,9 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
         .execute(pool)
         .await?;
 
+    // Старые базы: CHECK по статусу без 'quarantine'/'rejected'
+    widen_batch_status_check(pool).await?;
+
 
     // ==================== EQUIPMENT TABLE ====================
     sqlx::query(
@@ -257,7 +266,10 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             position_label TEXT CHECK(position_label IS NULL OR length(position_label) <= 20),
             max_capacity INTEGER CHECK(max_capacity IS NULL OR max_capacity > 0),
             current_count INTEGER NOT NULL DEFAULT 0 CHECK(current_count >= 0),
+            grid_rows INTEGER CHECK(grid_rows IS NULL OR grid_rows BETWEEN 1 AND 26),
+            grid_columns INTEGER CHECK(grid_columns IS NULL OR grid_columns BETWEEN 1 AND 99),
             sort_order INTEGER NOT NULL DEFAULT 0,
+            short_code TEXT,
             description TEXT CHECK(description IS NULL OR length(description) <= 500),
             status TEXT NOT NULL DEFAULT 'available' CHECK(
                 status IN ('available', 'full', 'maintenance', 'unavailable')
@@ -272,6 +284,95 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
         "#,
     ).execute(pool).await?;
 
+    // ==================== STORAGE LOCATION TREE ====================
+    // Комнаты, зоны и позиции — узлы с тем же id; триггеры держат дерево в синхроне с ними
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS storage_locations (
+            id TEXT PRIMARY KEY,
+            parent_id TEXT REFERENCES storage_locations(id),
+            location_type TEXT NOT NULL,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 100),
+            linked_entity TEXT CHECK(linked_entity IS NULL OR linked_entity IN ('room', 'zone', 'position')),
+            path TEXT NOT NULL,
+            depth INTEGER NOT NULL DEFAULT 0 CHECK(depth >= 0),
+            sort_order INTEGER NOT NULL DEFAULT 0,
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            created_by TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    let location_sync_triggers = [
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_room_insert
+           AFTER INSERT ON rooms
+           WHEN NOT EXISTS (SELECT 1 FROM storage_locations WHERE id = NEW.id)
+           BEGIN
+               INSERT INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, created_at, updated_at)
+               VALUES (NEW.id, NULL, 'room', NEW.name, 'room', '/' || NEW.id || '/', 0,
+                       strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), strftime('%Y-%m-%dT%H:%M:%SZ', 'now'));
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_room_rename
+           AFTER UPDATE OF name ON rooms
+           BEGIN
+               UPDATE storage_locations SET name = NEW.name, updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+               WHERE id = NEW.id;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_room_delete
+           AFTER DELETE ON rooms
+           BEGIN
+               DELETE FROM storage_locations WHERE instr(path, '/' || OLD.id || '/') > 0;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_zone_insert
+           AFTER INSERT ON storage_zones
+           WHEN NOT EXISTS (SELECT 1 FROM storage_locations WHERE id = NEW.id)
+           BEGIN
+               INSERT INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, sort_order, description, created_by, created_at, updated_at)
+               SELECT NEW.id, p.id, NEW.zone_type, NEW.name, 'zone', p.path || NEW.id || '/', p.depth + 1,
+                      NEW.sort_order, NEW.description, NEW.created_by,
+                      strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+               FROM storage_locations p WHERE p.id = NEW.room_id;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_zone_update
+           AFTER UPDATE OF name, zone_type ON storage_zones
+           BEGIN
+               UPDATE storage_locations SET name = NEW.name, location_type = NEW.zone_type,
+                      updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+               WHERE id = NEW.id;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_zone_delete
+           AFTER DELETE ON storage_zones
+           BEGIN
+               DELETE FROM storage_locations WHERE instr(path, '/' || OLD.id || '/') > 0;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_position_insert
+           AFTER INSERT ON storage_positions
+           WHEN NOT EXISTS (SELECT 1 FROM storage_locations WHERE id = NEW.id)
+           BEGIN
+               INSERT INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, sort_order, description, created_by, created_at, updated_at)
+               SELECT NEW.id, p.id, 'position', NEW.name, 'position', p.path || NEW.id || '/', p.depth + 1,
+                      NEW.sort_order, NEW.description, NEW.created_by,
+                      strftime('%Y-%m-%dT%H:%M:%SZ', 'now'), strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+               FROM storage_locations p WHERE p.id = NEW.zone_id;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_position_rename
+           AFTER UPDATE OF name ON storage_positions
+           BEGIN
+               UPDATE storage_locations SET name = NEW.name, updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+               WHERE id = NEW.id;
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_location_position_delete
+           AFTER DELETE ON storage_positions
+           BEGIN
+               DELETE FROM storage_locations WHERE id = OLD.id;
+           END"#,
+    ];
+    for query in location_sync_triggers.iter() {
+        sqlx::query(query).execute(pool).await?;
+    }
+
     // ==================== BATCH PLACEMENTS v2 ====================
     sqlx::query(
         r#"
@@ -286,6 +387,9 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             opened_by TEXT,                          
             status TEXT NOT NULL DEFAULT 'full', 
             notes TEXT,
+            short_code TEXT,
+            open_expiry_date TEXT,
+            retest_date TEXT,
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL,
             UNIQUE(batch_id, sequence_number)
@@ -302,10 +406,333 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             placed_by TEXT,
             placed_at TEXT NOT NULL,
             notes TEXT,
+            cell TEXT,
             UNIQUE(container_id)
         )
         "#,
     ).execute(pool).await?;
+    // ==================== SCAN CODES ====================
+    // Счётчики коротких кодов для этикеток: C000123 — контейнер, B… — партия, P… — позиция.
+    // Значения не переиспользуются после удаления, поэтому старая этикетка не укажет на чужой объект.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS scan_code_sequences (
+            prefix TEXT PRIMARY KEY,
+            last_value INTEGER NOT NULL DEFAULT 0
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // ==================== CONTAINER MOVEMENTS ====================
+    // Append-only history of placements; room ids are snapshotted for as-of queries
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS container_movements (
+            id TEXT PRIMARY KEY,
+            container_id TEXT NOT NULL REFERENCES batch_containers(id),
+            batch_id TEXT NOT NULL,
+            action TEXT NOT NULL CHECK(action IN ('place', 'move', 'unplace', 'dispose')),
+            from_position_id TEXT,
+            from_room_id TEXT,
+            to_position_id TEXT,
+            to_room_id TEXT,
+            from_cell TEXT,
+            to_cell TEXT,
+            moved_by TEXT,
+            moved_at TEXT NOT NULL,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500)
+        )
+        "#,
+    ).execute(pool).await?;
+
+    let movement_guards = [
+        r#"CREATE TRIGGER IF NOT EXISTS container_movements_no_update
+           BEFORE UPDATE ON container_movements BEGIN
+               SELECT RAISE(ABORT, 'container movements are append-only');
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS container_movements_no_delete
+           BEFORE DELETE ON container_movements BEGIN
+               SELECT RAISE(ABORT, 'container movements are append-only');
+           END"#,
+    ];
+    for query in movement_guards.iter() {
+        sqlx::query(query).execute(pool).await?;
+    }
+
+    // ==================== STOCKTAKE ====================
+    // Сессия инвентаризации по комнате или зоне: пересчёт → сверка → утверждение корректировок
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stocktake_sessions (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 200),
+            room_id TEXT NOT NULL REFERENCES rooms(id),
+            zone_id TEXT REFERENCES storage_zones(id),
+            status TEXT NOT NULL DEFAULT 'counting' CHECK(status IN ('counting', 'review', 'approved', 'cancelled')),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            started_by TEXT,
+            started_at TEXT NOT NULL,
+            submitted_by TEXT,
+            submitted_at TEXT,
+            approved_by TEXT,
+            approved_at TEXT,
+            approval_reason TEXT,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Один пересчёт контейнера на сессию; повторный скан перезаписывает строку
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stocktake_counts (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL REFERENCES stocktake_sessions(id) ON DELETE CASCADE,
+            container_id TEXT NOT NULL REFERENCES batch_containers(id),
+            position_id TEXT REFERENCES storage_positions(id),
+            cell TEXT,
+            observed_quantity REAL CHECK(observed_quantity IS NULL OR observed_quantity >= 0),
+            method TEXT NOT NULL DEFAULT 'manual' CHECK(method IN ('scan', 'manual')),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500),
+            counted_by TEXT,
+            counted_at TEXT NOT NULL,
+            UNIQUE(session_id, container_id)
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Применённые корректировки — неизменяемый след утверждения
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stocktake_adjustments (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL REFERENCES stocktake_sessions(id),
+            container_id TEXT NOT NULL REFERENCES batch_containers(id),
+            variance_type TEXT NOT NULL CHECK(variance_type IN ('missing', 'unexpected', 'quantity', 'wrong_position')),
+            action TEXT NOT NULL,
+            from_position_id TEXT,
+            to_position_id TEXT,
+            system_quantity REAL,
+            observed_quantity REAL,
+            usage_log_id TEXT REFERENCES usage_logs(id),
+            reason TEXT NOT NULL,
+            applied_by TEXT,
+            applied_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // ==================== HAZARDOUS WASTE ====================
+    // Вывоз отходов: манифест перевозчика на одну или несколько ёмкостей
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS waste_manifests (
+            id TEXT PRIMARY KEY,
+            manifest_number TEXT NOT NULL UNIQUE CHECK(length(manifest_number) > 0 AND length(manifest_number) <= 100),
+            transporter TEXT NOT NULL CHECK(length(transporter) > 0 AND length(transporter) <= 200),
+            disposal_facility TEXT CHECK(disposal_facility IS NULL OR length(disposal_facility) <= 200),
+            status TEXT NOT NULL DEFAULT 'draft' CHECK(status IN ('draft', 'shipped')),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at TEXT NOT NULL,
+            shipped_by TEXT,
+            shipped_at TEXT,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Ёмкость для накопления отходов одного потока в зоне накопления
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS waste_containers (
+            id TEXT PRIMARY KEY,
+            label TEXT NOT NULL UNIQUE CHECK(length(label) > 0 AND length(label) <= 100),
+            waste_category TEXT NOT NULL,
+            room_id TEXT REFERENCES rooms(id),
+            capacity REAL NOT NULL CHECK(capacity > 0),
+            unit TEXT NOT NULL,
+            current_quantity REAL NOT NULL DEFAULT 0 CHECK(current_quantity >= 0),
+            status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open', 'full', 'sealed', 'picked_up')),
+            accumulation_limit_days INTEGER NOT NULL DEFAULT 90 CHECK(accumulation_limit_days > 0),
+            accumulation_started_at TEXT,
+            sealed_by TEXT,
+            sealed_at TEXT,
+            manifest_id TEXT REFERENCES waste_manifests(id),
+            picked_up_at TEXT,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Списание в отходы: сколько, откуда и в какую ёмкость
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS waste_entries (
+            id TEXT PRIMARY KEY,
+            waste_container_id TEXT NOT NULL REFERENCES waste_containers(id),
+            source_container_id TEXT REFERENCES batch_containers(id),
+            batch_id TEXT REFERENCES batches(id),
+            reagent_id TEXT REFERENCES reagents(id),
+            quantity REAL NOT NULL CHECK(quantity > 0),
+            unit TEXT NOT NULL,
+            waste_quantity REAL NOT NULL CHECK(waste_quantity > 0),
+            reason TEXT NOT NULL CHECK(reason IN ('expired', 'contaminated', 'degraded', 'surplus', 'spill', 'other')),
+            usage_log_id TEXT REFERENCES usage_logs(id),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500),
+            disposed_by TEXT,
+            disposed_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // ==================== TEMPERATURE MONITORING ====================
+    // Датчик (логгер холодильника/морозильника) в зоне хранения; ключ API хранится только хешем
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS temperature_sensors (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 200),
+            zone_id TEXT NOT NULL REFERENCES storage_zones(id),
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            api_key_prefix TEXT NOT NULL,
+            api_key_hash TEXT NOT NULL UNIQUE,
+            is_active INTEGER NOT NULL DEFAULT 1 CHECK(is_active IN (0, 1)),
+            last_reading_at TEXT,
+            last_temperature REAL,
+            created_by TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Показания, прореженные до корзин (5 мин / 1 ч): min/max/сумма/число замеров
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS temperature_readings (
+            sensor_id TEXT NOT NULL REFERENCES temperature_sensors(id) ON DELETE CASCADE,
+            resolution INTEGER NOT NULL CHECK(resolution > 0),
+            bucket_start TEXT NOT NULL,
+            min_value REAL NOT NULL,
+            max_value REAL NOT NULL,
+            sum_value REAL NOT NULL,
+            sample_count INTEGER NOT NULL CHECK(sample_count > 0),
+            PRIMARY KEY (sensor_id, resolution, bucket_start)
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Выход температуры зоны за допустимый диапазон
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS temperature_excursions (
+            id TEXT PRIMARY KEY,
+            sensor_id TEXT NOT NULL REFERENCES temperature_sensors(id),
+            zone_id TEXT NOT NULL REFERENCES storage_zones(id),
+            kind TEXT NOT NULL CHECK(kind IN ('high', 'low')),
+            status TEXT NOT NULL DEFAULT 'active' CHECK(status IN ('active', 'resolved')),
+            limit_min REAL,
+            limit_max REAL,
+            started_at TEXT NOT NULL,
+            ended_at TEXT,
+            peak_temperature REAL NOT NULL,
+            last_temperature REAL NOT NULL,
+            reading_count INTEGER NOT NULL DEFAULT 1,
+            affected_container_count INTEGER NOT NULL DEFAULT 0,
+            acknowledged_by TEXT,
+            acknowledged_at TEXT,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Контейнеры, находившиеся в зоне в момент начала отклонения
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS temperature_excursion_containers (
+            excursion_id TEXT NOT NULL REFERENCES temperature_excursions(id) ON DELETE CASCADE,
+            container_id TEXT NOT NULL REFERENCES batch_containers(id),
+            batch_id TEXT NOT NULL,
+            position_id TEXT,
+            cell TEXT,
+            quantity REAL NOT NULL,
+            PRIMARY KEY (excursion_id, container_id)
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // ==================== GOODS-IN / QA ====================
+    // Заказ поставщику и его строки (что и сколько ожидается)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS purchase_orders (
+            id TEXT PRIMARY KEY,
+            po_number TEXT NOT NULL UNIQUE CHECK(length(po_number) > 0 AND length(po_number) <= 100),
+            supplier TEXT NOT NULL CHECK(length(supplier) > 0 AND length(supplier) <= 255),
+            status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open', 'partially_received', 'received', 'cancelled')),
+            order_date TEXT,
+            expected_date TEXT,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS purchase_order_lines (
+            id TEXT PRIMARY KEY,
+            purchase_order_id TEXT NOT NULL REFERENCES purchase_orders(id) ON DELETE CASCADE,
+            reagent_id TEXT NOT NULL REFERENCES reagents(id),
+            ordered_quantity REAL NOT NULL CHECK(ordered_quantity > 0),
+            unit TEXT NOT NULL CHECK(length(unit) > 0 AND length(unit) <= 20),
+            received_quantity REAL NOT NULL DEFAULT 0 CHECK(received_quantity >= 0),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500),
+            created_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
+    // Приёмка поставки: партия на карантине до решения QA
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS goods_receipts (
+            id TEXT PRIMARY KEY,
+            batch_id TEXT NOT NULL UNIQUE REFERENCES batches(id),
+            purchase_order_line_id TEXT REFERENCES purchase_order_lines(id),
+            supplier TEXT NOT NULL CHECK(length(supplier) > 0 AND length(supplier) <= 255),
+            delivery_note TEXT NOT NULL CHECK(length(delivery_note) > 0 AND length(delivery_note) <= 100),
+            ordered_quantity REAL CHECK(ordered_quantity IS NULL OR ordered_quantity > 0),
+            received_quantity REAL NOT NULL CHECK(received_quantity > 0),
+            unit TEXT NOT NULL,
+            coa_number TEXT CHECK(coa_number IS NULL OR length(coa_number) <= 100),
+            coa_filename TEXT,
+            coa_mime_type TEXT,
+            coa_size INTEGER,
+            coa_storage_key TEXT,
+            coa_sha256 TEXT,
+            coa_uploaded_by TEXT,
+            coa_uploaded_at TEXT,
+            inspection_notes TEXT CHECK(inspection_notes IS NULL OR length(inspection_notes) <= 1000),
+            qa_status TEXT NOT NULL DEFAULT 'pending' CHECK(qa_status IN ('pending', 'released', 'rejected')),
+            qa_decided_by TEXT,
+            qa_decided_at TEXT,
+            qa_reason TEXT CHECK(qa_reason IS NULL OR length(qa_reason) <= 1000),
+            received_by TEXT,
+            received_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    ).execute(pool).await?;
+
    // ==================== EXPERIMENTS TABLE ====================
     sqlx::query(
         r#"
@@ -447,6 +874,8 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             ),
             last_replaced TEXT,
             next_replacement TEXT,
+            replacement_interval_days INTEGER CHECK(replacement_interval_days IS NULL OR replacement_interval_days > 0),
+            unit_cost REAL CHECK(unit_cost IS NULL OR unit_cost >= 0),
             notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
             created_by TEXT,
             created_at DATETIME NOT NULL,
@@ -478,10 +907,13 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             cost REAL CHECK(cost IS NULL OR cost >= 0),
             parts_replaced TEXT CHECK(parts_replaced IS NULL OR length(parts_replaced) <= 1000),
             notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            plan_id TEXT,
+            previous_equipment_status TEXT,
             created_by TEXT,
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
             FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (plan_id) REFERENCES equipment_maintenance_plans (id) ON DELETE SET NULL,
             FOREIGN KEY (created_by) REFERENCES users (id)
         )
         "#,
@@ -489,62 +921,681 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
         .execute(pool)
         .await?;
 
-    // ==================== EQUIPMENT FILES TABLE ====================
+    // ==================== EQUIPMENT PART USAGE ====================
+    // Append-only stock movements of spare parts (consumed by maintenance)
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS equipment_files (
+        CREATE TABLE IF NOT EXISTS equipment_part_usage (
             id TEXT PRIMARY KEY,
+            part_id TEXT NOT NULL,
             equipment_id TEXT NOT NULL,
-            part_id TEXT,
-            file_type TEXT NOT NULL DEFAULT 'other' CHECK(
-                file_type IN ('manual', 'certificate', 'photo', 'other')
-            ),
-            original_filename TEXT NOT NULL,
-            stored_filename TEXT NOT NULL,
-            file_path TEXT NOT NULL,
-            file_size INTEGER NOT NULL CHECK(file_size > 0),
-            mime_type TEXT NOT NULL,
-            description TEXT CHECK(description IS NULL OR length(description) <= 500),
-            uploaded_by TEXT,
+            maintenance_id TEXT,
+            quantity INTEGER NOT NULL CHECK(quantity > 0),
+            quantity_after INTEGER NOT NULL CHECK(quantity_after >= 0),
+            used_date TEXT NOT NULL,
+            used_by TEXT,
             created_at DATETIME NOT NULL,
+            FOREIGN KEY (part_id) REFERENCES equipment_parts (id) ON DELETE CASCADE,
             FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
-            FOREIGN KEY (part_id) REFERENCES equipment_parts (id) ON DELETE SET NULL,
-            FOREIGN KEY (uploaded_by) REFERENCES users (id)
+            FOREIGN KEY (maintenance_id) REFERENCES equipment_maintenance (id) ON DELETE SET NULL,
+            FOREIGN KEY (used_by) REFERENCES users (id)
         )
         "#,
     )
         .execute(pool)
         .await?;
 
-    // ==================== RUN ADDITIONAL MIGRATIONS ====================
-    run_additional_migrations(pool).await?;
-    
-
-    // ==================== CREATE BATCH TRIGGERS ====================
-    create_batch_triggers(pool).await?;
-
-    // ==================== CREATE FTS TABLES ====================
-    create_fts_tables(pool).await?;
+    // ==================== EQUIPMENT MAINTENANCE PLANS ====================
+    // One plan per recurring task; completing a planned record schedules the next one
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_maintenance_plans (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 255),
+            maintenance_type TEXT NOT NULL CHECK(
+                maintenance_type IN ('calibration', 'repair', 'inspection', 'cleaning', 'replacement', 'other')
+            ),
+            interval_days INTEGER NOT NULL CHECK(interval_days > 0),
+            interval_runs INTEGER CHECK(interval_runs IS NULL OR interval_runs > 0),
+            auto_status INTEGER NOT NULL DEFAULT 1 CHECK(auto_status IN (0, 1)),
+            is_active INTEGER NOT NULL DEFAULT 1 CHECK(is_active IN (0, 1)),
+            description TEXT CHECK(description IS NULL OR length(description) <= 1000),
+            last_completed_date TEXT,
+            next_due_date TEXT,
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    // ==================== INITIALIZE CACHED FIELDS ====================
-    initialize_reagent_cache(pool).await?;
+    // ==================== CALIBRATION (ISO 17025) ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS calibration_standards (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 255),
+            serial_number TEXT CHECK(serial_number IS NULL OR length(serial_number) <= 100),
+            certificate_number TEXT NOT NULL CHECK(length(certificate_number) > 0 AND length(certificate_number) <= 100),
+            certificate_issuer TEXT CHECK(certificate_issuer IS NULL OR length(certificate_issuer) <= 255),
+            traceability TEXT CHECK(traceability IS NULL OR length(traceability) <= 1000),
+            valid_until TEXT,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    // ==================== PERFORMANCE INDEXES ====================
-    ensure_performance_indexes(pool).await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS calibration_results (
+            id TEXT PRIMARY KEY,
+            maintenance_id TEXT NOT NULL UNIQUE,
+            equipment_id TEXT NOT NULL,
+            standard_id TEXT NOT NULL,
+            performed_at TEXT NOT NULL,
+            performed_by TEXT CHECK(performed_by IS NULL OR length(performed_by) <= 255),
+            passed INTEGER NOT NULL CHECK(passed IN (0, 1)),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (maintenance_id) REFERENCES equipment_maintenance (id) ON DELETE RESTRICT,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE RESTRICT,
+            FOREIGN KEY (standard_id) REFERENCES calibration_standards (id) ON DELETE RESTRICT,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    Ok(())
-}
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS calibration_points (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            result_id TEXT NOT NULL,
+            nominal_value REAL NOT NULL,
+            measured_value REAL NOT NULL,
+            unit TEXT CHECK(unit IS NULL OR length(unit) <= 20),
+            uncertainty REAL NOT NULL CHECK(uncertainty >= 0),
+            tolerance REAL NOT NULL CHECK(tolerance >= 0),
+            deviation REAL NOT NULL,
+            passed INTEGER NOT NULL CHECK(passed IN (0, 1)),
+            FOREIGN KEY (result_id) REFERENCES calibration_results (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-// ==================== BATCH TRIGGERS ====================
-// Automatically update total_quantity and batches_count in reagents
+    // Experiments that used equipment between its last passing calibration and a failure
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_calibration_flags (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            equipment_id TEXT NOT NULL,
+            calibration_result_id TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            resolved_at DATETIME,
+            resolved_by TEXT,
+            resolution TEXT CHECK(resolution IS NULL OR length(resolution) <= 1000),
+            UNIQUE (experiment_id, calibration_result_id),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE RESTRICT,
+            FOREIGN KEY (calibration_result_id) REFERENCES calibration_results (id) ON DELETE RESTRICT,
+            FOREIGN KEY (resolved_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-async fn create_batch_triggers(pool: &SqlitePool) -> Result<()> {
-    info!("Creating batch triggers for reagent cache...");
+    // Записи калибровки — доказательная база: удаление оборудования их не стирает
+    restrict_calibration_deletes(pool).await?;
 
-    // Drop old triggers if exist
-    let drop_triggers = [
-        "DROP TRIGGER IF EXISTS trg_batches_insert",
-        "DROP TRIGGER IF EXISTS trg_batches_update",
+    // ==================== EXPERIMENT EQUIPMENT ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_equipment (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            equipment_id TEXT NOT NULL,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            UNIQUE (experiment_id, equipment_id),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT DOCUMENTS ====================
+    // Protocols, raw data and reports; contents live in the blob storage
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_documents (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            original_filename TEXT NOT NULL CHECK(length(original_filename) > 0 AND length(original_filename) <= 255),
+            mime_type TEXT NOT NULL,
+            file_size INTEGER NOT NULL CHECK(file_size > 0),
+            storage_key TEXT NOT NULL,
+            sha256 TEXT NOT NULL,
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            uploaded_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (uploaded_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EQUIPMENT FILES TABLE ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_files (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            part_id TEXT,
+            contract_id TEXT REFERENCES service_contracts (id) ON DELETE SET NULL,
+            file_type TEXT NOT NULL DEFAULT 'other' CHECK(
+                file_type IN ('manual', 'certificate', 'photo', 'other')
+            ),
+            original_filename TEXT NOT NULL,
+            stored_filename TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            file_size INTEGER NOT NULL CHECK(file_size > 0),
+            mime_type TEXT NOT NULL,
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            storage_key TEXT,
+            sha256 TEXT,
+            image_width INTEGER,
+            image_height INTEGER,
+            orientation TEXT CHECK(orientation IS NULL OR orientation IN ('landscape', 'portrait', 'square')),
+            exif_orientation INTEGER,
+            uploaded_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (part_id) REFERENCES equipment_parts (id) ON DELETE SET NULL,
+            FOREIGN KEY (uploaded_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EQUIPMENT USAGE LOG ====================
+    // Check-out/check-in sessions; ended_at IS NULL marks the open session
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_usage_log (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            experiment_id TEXT,
+            purpose TEXT CHECK(purpose IS NULL OR length(purpose) <= 500),
+            started_at DATETIME NOT NULL,
+            ended_at DATETIME,
+            run_count INTEGER NOT NULL DEFAULT 0 CHECK(run_count >= 0),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            checked_in_by TEXT,
+            created_at DATETIME NOT NULL,
+            CHECK(ended_at IS NULL OR ended_at >= started_at),
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE SET NULL,
+            FOREIGN KEY (checked_in_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== SERVICE CONTRACTS ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS service_contracts (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            vendor TEXT NOT NULL CHECK(length(vendor) > 0 AND length(vendor) <= 255),
+            contract_number TEXT NOT NULL CHECK(length(contract_number) > 0 AND length(contract_number) <= 100),
+            coverage TEXT CHECK(coverage IS NULL OR length(coverage) <= 1000),
+            start_date TEXT NOT NULL CHECK(date(start_date) IS start_date),
+            end_date TEXT NOT NULL CHECK(date(end_date) IS end_date AND end_date >= start_date),
+            cost REAL CHECK(cost IS NULL OR cost >= 0),
+            contact TEXT CHECK(contact IS NULL OR length(contact) <= 255),
+            alert_days INTEGER NOT NULL DEFAULT 30 CHECK(alert_days >= 0 AND alert_days <= 365),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            UNIQUE (vendor, contract_number, equipment_id),
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== NOTIFICATIONS ====================
+    // System-generated alerts visible to all users; dedupe_key keeps the
+    // hourly generator idempotent, reads are tracked per user
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notifications (
+            id TEXT PRIMARY KEY,
+            category TEXT NOT NULL,
+            severity TEXT NOT NULL DEFAULT 'info' CHECK(severity IN ('info', 'warning', 'critical')),
+            title TEXT NOT NULL,
+            message TEXT NOT NULL,
+            entity_type TEXT,
+            entity_id TEXT,
+            dedupe_key TEXT NOT NULL UNIQUE,
+            created_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notification_reads (
+            notification_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            read_at DATETIME NOT NULL,
+            PRIMARY KEY (notification_id, user_id),
+            FOREIGN KEY (notification_id) REFERENCES notifications (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EQUIPMENT HIERARCHY ====================
+    // Parent/child links between equipment (system → modules). Rows are never
+    // updated except to close them: unlinked_at IS NULL marks the current link.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_links (
+            id TEXT PRIMARY KEY,
+            parent_id TEXT NOT NULL,
+            child_id TEXT NOT NULL,
+            slot TEXT CHECK(slot IS NULL OR length(slot) <= 100),
+            linked_at DATETIME NOT NULL,
+            unlinked_at DATETIME,
+            linked_by TEXT,
+            unlinked_by TEXT,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            CHECK(parent_id != child_id),
+            CHECK(unlinked_at IS NULL OR unlinked_at >= linked_at),
+            FOREIGN KEY (parent_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (child_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (linked_by) REFERENCES users (id),
+            FOREIGN KEY (unlinked_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EQUIPMENT FILE THUMBNAILS ====================
+    // Pre-rendered previews of photos, stored in the blob storage
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_file_thumbnails (
+            file_id TEXT NOT NULL,
+            size TEXT NOT NULL CHECK(size IN ('thumb', 'small', 'medium')),
+            storage_key TEXT NOT NULL,
+            sha256 TEXT NOT NULL,
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            mime_type TEXT NOT NULL,
+            file_size INTEGER NOT NULL,
+            created_at DATETIME NOT NULL,
+            PRIMARY KEY (file_id, size),
+            FOREIGN KEY (file_id) REFERENCES equipment_files (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== FILE DOWNLOAD LINKS ====================
+    // Signed, expiring links to equipment files; used_at enforces single-use
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_download_links (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            file_id TEXT NOT NULL,
+            expires_at DATETIME NOT NULL,
+            single_use INTEGER NOT NULL DEFAULT 0 CHECK(single_use IN (0, 1)),
+            used_at DATETIME,
+            created_by TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (file_id) REFERENCES equipment_files (id) ON DELETE CASCADE,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT WORKFLOW TABLES ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_status_transitions (
+            from_status TEXT NOT NULL,
+            to_status TEXT NOT NULL,
+            guards TEXT NOT NULL DEFAULT '',
+            requires_signoff INTEGER NOT NULL DEFAULT 0 CHECK(requires_signoff IN (0, 1)),
+            signoff_role TEXT NOT NULL DEFAULT 'admin' CHECK(
+                signoff_role IN ('admin', 'researcher')
+            ),
+            is_enabled INTEGER NOT NULL DEFAULT 1 CHECK(is_enabled IN (0, 1)),
+            updated_by TEXT,
+            updated_at DATETIME NOT NULL,
+            PRIMARY KEY (from_status, to_status),
+            FOREIGN KEY (updated_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_signoffs (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            from_status TEXT NOT NULL,
+            to_status TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending' CHECK(
+                status IN ('pending', 'approved', 'rejected', 'withdrawn')
+            ),
+            reason TEXT CHECK(reason IS NULL OR length(reason) <= 1000),
+            payload TEXT,
+            requested_by TEXT NOT NULL,
+            requested_at DATETIME NOT NULL,
+            signed_by TEXT,
+            signed_at DATETIME,
+            signature_meaning TEXT CHECK(signature_meaning IS NULL OR length(signature_meaning) <= 255),
+            signature_hash TEXT,
+            comment TEXT CHECK(comment IS NULL OR length(comment) <= 1000),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (requested_by) REFERENCES users (id),
+            FOREIGN KEY (signed_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_status_history (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            from_status TEXT NOT NULL,
+            to_status TEXT NOT NULL,
+            changed_by TEXT,
+            reason TEXT CHECK(reason IS NULL OR length(reason) <= 1000),
+            signoff_id TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (changed_by) REFERENCES users (id),
+            FOREIGN KEY (signoff_id) REFERENCES experiment_signoffs (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT NOTEBOOK (ELN) ====================
+    // Append-only: corrections are new entries pointing at amends_entry_id
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_notebook_entries (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            entry_number INTEGER NOT NULL,
+            title TEXT CHECK(title IS NULL OR length(title) <= 255),
+            content TEXT NOT NULL,
+            author_id TEXT NOT NULL,
+            amends_entry_id TEXT,
+            amendment_reason TEXT CHECK(amendment_reason IS NULL OR length(amendment_reason) <= 1000),
+            created_at DATETIME NOT NULL,
+            UNIQUE (experiment_id, entry_number),
+            CHECK (amends_entry_id IS NULL OR amendment_reason IS NOT NULL),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE RESTRICT,
+            FOREIGN KEY (author_id) REFERENCES users (id),
+            FOREIGN KEY (amends_entry_id) REFERENCES experiment_notebook_entries (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_notebook_links (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL CHECK(entity_type IN ('batch', 'equipment')),
+            entity_id TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            UNIQUE (entry_id, entity_type, entity_id),
+            FOREIGN KEY (entry_id) REFERENCES experiment_notebook_entries (id) ON DELETE RESTRICT
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    let notebook_guards = [
+        r#"CREATE TRIGGER IF NOT EXISTS experiment_notebook_entries_no_update
+           BEFORE UPDATE ON experiment_notebook_entries BEGIN
+               SELECT RAISE(ABORT, 'notebook entries are append-only');
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS experiment_notebook_entries_no_delete
+           BEFORE DELETE ON experiment_notebook_entries BEGIN
+               SELECT RAISE(ABORT, 'notebook entries are append-only');
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS experiment_notebook_links_no_update
+           BEFORE UPDATE ON experiment_notebook_links BEGIN
+               SELECT RAISE(ABORT, 'notebook links are append-only');
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS experiment_notebook_links_no_delete
+           BEFORE DELETE ON experiment_notebook_links BEGIN
+               SELECT RAISE(ABORT, 'notebook links are append-only');
+           END"#,
+    ];
+    for query in notebook_guards.iter() {
+        sqlx::query(query).execute(pool).await?;
+    }
+
+    // ==================== EQUIPMENT STATUS HISTORY ====================
+    // Заполняется триггерами при любой смене статуса; из неё считается простой
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_status_history (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            changed_at DATETIME NOT NULL,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    let status_history_triggers = [
+        r#"CREATE TRIGGER IF NOT EXISTS trg_equipment_status_insert
+           AFTER INSERT ON equipment BEGIN
+               INSERT INTO equipment_status_history (id, equipment_id, from_status, to_status, changed_at)
+               VALUES (lower(hex(randomblob(16))), NEW.id, NULL, NEW.status, datetime('now'));
+           END"#,
+        r#"CREATE TRIGGER IF NOT EXISTS trg_equipment_status_update
+           AFTER UPDATE OF status ON equipment
+           WHEN OLD.status IS NOT NEW.status BEGIN
+               INSERT INTO equipment_status_history (id, equipment_id, from_status, to_status, changed_at)
+               VALUES (lower(hex(randomblob(16))), NEW.id, OLD.status, NEW.status, datetime('now'));
+           END"#,
+    ];
+    for query in status_history_triggers.iter() {
+        sqlx::query(query).execute(pool).await?;
+    }
+
+    // ==================== RUN ADDITIONAL MIGRATIONS ====================
+    run_additional_migrations(pool).await?;
+
+    // ==================== SEED EXPERIMENT WORKFLOW ====================
+    seed_experiment_workflow(pool).await?;
+    
+
+    // ==================== CREATE BATCH TRIGGERS ====================
+    create_batch_triggers(pool).await?;
+
+    // ==================== CREATE FTS TABLES ====================
+    create_fts_tables(pool).await?;
+    create_notebook_fts(pool).await?;
+
+    // ==================== INITIALIZE CACHED FIELDS ====================
+    initialize_reagent_cache(pool).await?;
+
+    // ==================== PERFORMANCE INDEXES ====================
+    ensure_performance_indexes(pool).await?;
+
+    Ok(())
+}
+
+// ==================== BATCH STATUS CONSTRAINT ====================
+// SQLite не умеет менять CHECK через ALTER TABLE, поэтому таблица batches
+// пересоздаётся по её же сохранённому определению с расширенным списком
+// статусов. Индексы и триггеры batches создаются заново дальше в миграциях.
+
+const LEGACY_BATCH_STATUSES: &str = "status IN ('available', 'in_use', 'expired', 'depleted', 'low_stock')";
+const BATCH_STATUSES: &str = "status IN ('available', 'in_use', 'expired', 'depleted', 'low_stock', 'quarantine', 'rejected')";
+
+async fn widen_batch_status_check(pool: &SqlitePool) -> Result<()> {
+    let table_sql: Option<String> = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'batches'"
+    )
+        .fetch_optional(pool)
+        .await?;
+
+    let Some(table_sql) = table_sql else { return Ok(()) };
+    if !table_sql.contains(LEGACY_BATCH_STATUSES) {
+        return Ok(());
+    }
+
+    info!("Rebuilding batches table to allow quarantine/rejected statuses...");
+    let new_sql = table_sql
+        .replacen("CREATE TABLE batches", "CREATE TABLE batches_new", 1)
+        .replacen(LEGACY_BATCH_STATUSES, BATCH_STATUSES, 1);
+
+    rebuild_table(pool, "batches", &new_sql).await
+}
+
+// ==================== CALIBRATION DELETE RESTRICTIONS ====================
+// Ранние версии схемы каскадно удаляли результаты калибровки и флаги
+// вместе с оборудованием. Такие таблицы пересоздаются с ON DELETE RESTRICT.
+
+const CALIBRATION_CASCADES: [(&str, &str); 2] = [
+    (
+        "REFERENCES equipment (id) ON DELETE CASCADE",
+        "REFERENCES equipment (id) ON DELETE RESTRICT",
+    ),
+    (
+        "REFERENCES calibration_results (id) ON DELETE CASCADE",
+        "REFERENCES calibration_results (id) ON DELETE RESTRICT",
+    ),
+];
+
+async fn restrict_calibration_deletes(pool: &SqlitePool) -> Result<()> {
+    for table in ["calibration_results", "experiment_calibration_flags"] {
+        let table_sql: Option<String> = sqlx::query_scalar(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?"
+        )
+            .bind(table)
+            .fetch_optional(pool)
+            .await?;
+
+        let Some(table_sql) = table_sql else { continue };
+        if !CALIBRATION_CASCADES.iter().any(|(cascade, _)| table_sql.contains(cascade)) {
+            continue;
+        }
+
+        info!("Rebuilding {} table to restrict deletes...", table);
+        // После RENAME имя в sqlite_master хранится в кавычках — заголовок собираем заново
+        let columns = table_sql.find('(').map(|i| &table_sql[i..]).unwrap_or_default();
+        let mut new_sql = format!("CREATE TABLE {}_new {}", table, columns);
+        for (cascade, restrict) in CALIBRATION_CASCADES {
+            new_sql = new_sql.replace(cascade, restrict);
+        }
+        rebuild_table(pool, table, &new_sql).await?;
+    }
+    Ok(())
+}
+
+/// Пересоздать таблицу по `new_sql` (создаёт `{table}_new`) с переносом всех строк.
+/// Индексы и триггеры таблицы создаются заново дальше в миграциях.
+async fn rebuild_table(pool: &SqlitePool, table: &str, new_sql: &str) -> Result<()> {
+    // foreign_keys нельзя переключить внутри транзакции; без этого DROP TABLE
+    // каскадно удалил бы строки, ссылающиеся на таблицу
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+
+    let rebuild = async {
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        sqlx::query(&format!("DROP TABLE IF EXISTS {}_new", table)).execute(&mut *tx).await?;
+        sqlx::query(new_sql).execute(&mut *tx).await?;
+        sqlx::query(&format!("INSERT INTO {}_new SELECT * FROM {}", table, table)).execute(&mut *tx).await?;
+        sqlx::query(&format!("DROP TABLE {}", table)).execute(&mut *tx).await?;
+        sqlx::query(&format!("ALTER TABLE {}_new RENAME TO {}", table, table)).execute(&mut *tx).await?;
+        tx.commit().await
+    }
+    .await;
+
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    rebuild?;
+    Ok(())
+}
+
+// ==================== BATCH TRIGGERS ====================
+// Automatically update total_quantity and batches_count in reagents
+
+async fn create_batch_triggers(pool: &SqlitePool) -> Result<()> {
+    info!("Creating batch triggers for reagent cache...");
+
+    // Drop old triggers if exist
+    let drop_triggers = [
+        "DROP TRIGGER IF EXISTS trg_batches_insert",
+        "DROP TRIGGER IF EXISTS trg_batches_update",
         "DROP TRIGGER IF EXISTS trg_batches_delete",
     ];
 
@@ -641,69 +1692,162 @@ async fn create_batch_triggers(pool: &SqlitePool) -> Result<()> {
 
 // ==================== FTS TABLES ====================
 // Full-text search for fast searching across 100k+ records
-// Search fields: name, cas_number, formula
+// Reagents: name, cas_number, formula
+// Experiments, equipment, equipment parts: fields from FtsConfig
 
 async fn create_fts_tables(pool: &SqlitePool) -> Result<()> {
     info!("Creating FTS5 tables for full-text search...");
 
-    // Check if FTS table already exists
+    create_fts_table(pool, "reagents_fts", "reagents", &["name", "cas_number", "formula"]).await?;
+
+    for config in [FtsConfig::for_experiments(), FtsConfig::for_equipment(), FtsConfig::for_equipment_parts()] {
+        create_fts_table(pool, config.fts_table, config.main_table, &config.search_fields).await?;
+    }
+
+    Ok(())
+}
+
+/// External-content FTS5 table synced with its main table by triggers.
+/// A table left over with a different column set is dropped and rebuilt.
+async fn create_fts_table(pool: &SqlitePool, fts_table: &str, main_table: &str, columns: &[&str]) -> Result<()> {
+    let existing = get_table_columns(pool, fts_table).await?;
+    if existing.iter().map(String::as_str).eq(columns.iter().copied()) {
+        info!("FTS table {} already exists, skipping creation.", fts_table);
+        return Ok(());
+    }
+    if !existing.is_empty() {
+        log::warn!("FTS table {} has an outdated schema, recreating", fts_table);
+        drop_fts_table(pool, fts_table).await?;
+    }
+
+    let cols = columns.join(", ");
+    let new_cols = columns.iter().map(|c| format!("NEW.{}", c)).collect::<Vec<_>>().join(", ");
+    let old_cols = columns.iter().map(|c| format!("OLD.{}", c)).collect::<Vec<_>>().join(", ");
+
+    sqlx::query(&format!(r#"
+        CREATE VIRTUAL TABLE {fts} USING fts5(
+            {cols},
+            content='{main}',
+            content_rowid='rowid',
+            tokenize='unicode61 remove_diacritics 1'
+        )
+    "#, fts = fts_table, main = main_table, cols = cols)).execute(pool).await?;
+
+    // INSERT trigger - sync new rows to FTS
+    sqlx::query(&format!(r#"
+        CREATE TRIGGER {fts}_insert AFTER INSERT ON {main} BEGIN
+            INSERT INTO {fts}(rowid, {cols}) VALUES (NEW.rowid, {new_cols});
+        END
+    "#, fts = fts_table, main = main_table, cols = cols, new_cols = new_cols)).execute(pool).await?;
+
+    // DELETE trigger - remove from FTS
+    sqlx::query(&format!(r#"
+        CREATE TRIGGER {fts}_delete AFTER DELETE ON {main} BEGIN
+            INSERT INTO {fts}({fts}, rowid, {cols}) VALUES ('delete', OLD.rowid, {old_cols});
+        END
+    "#, fts = fts_table, main = main_table, cols = cols, old_cols = old_cols)).execute(pool).await?;
+
+    // UPDATE trigger - update FTS index
+    sqlx::query(&format!(r#"
+        CREATE TRIGGER {fts}_update AFTER UPDATE ON {main} BEGIN
+            INSERT INTO {fts}({fts}, rowid, {cols}) VALUES ('delete', OLD.rowid, {old_cols});
+            INSERT INTO {fts}(rowid, {cols}) VALUES (NEW.rowid, {new_cols});
+        END
+    "#, fts = fts_table, main = main_table, cols = cols, old_cols = old_cols, new_cols = new_cols)).execute(pool).await?;
+
+    // Populate FTS with existing data
+    sqlx::query(&format!("INSERT INTO {fts}({fts}) VALUES('rebuild')", fts = fts_table)).execute(pool).await?;
+
+    // Optimize the FTS index
+    let _ = sqlx::query(&format!("INSERT INTO {fts}({fts}) VALUES('optimize')", fts = fts_table)).execute(pool).await;
+
+    info!("FTS5 table {} created and populated.", fts_table);
+    Ok(())
+}
+
+async fn drop_fts_table(pool: &SqlitePool, fts_table: &str) -> Result<()> {
+    for suffix in ["insert", "update", "delete"] {
+        sqlx::query(&format!("DROP TRIGGER IF EXISTS {}_{}", fts_table, suffix)).execute(pool).await?;
+    }
+    sqlx::query(&format!("DROP TABLE IF EXISTS {}", fts_table)).execute(pool).await?;
+    Ok(())
+}
+
+// Notebook entries are never updated or deleted, so only an INSERT trigger is needed
+
+async fn create_notebook_fts(pool: &SqlitePool) -> Result<()> {
     let exists: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='reagents_fts'"
+        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='experiment_notebook_fts'"
     ).fetch_one(pool).await?;
 
     if exists.0 > 0 {
-        info!("FTS table already exists, skipping creation.");
         return Ok(());
     }
 
-    // Create FTS5 virtual table for reagents
     sqlx::query(r#"
-        CREATE VIRTUAL TABLE reagents_fts USING fts5(
-            name,
-            cas_number,
-            formula,
-            content='reagents',
+        CREATE VIRTUAL TABLE experiment_notebook_fts USING fts5(
+            title,
+            content,
+            content='experiment_notebook_entries',
             content_rowid='rowid',
             tokenize='unicode61 remove_diacritics 1'
         )
     "#).execute(pool).await?;
 
-    // INSERT trigger - sync new reagents to FTS
     sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_insert AFTER INSERT ON reagents BEGIN
-            INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-            VALUES (NEW.rowid, NEW.name, NEW.cas_number, NEW.formula);
+        CREATE TRIGGER experiment_notebook_fts_insert AFTER INSERT ON experiment_notebook_entries BEGIN
+            INSERT INTO experiment_notebook_fts(rowid, title, content)
+            VALUES (NEW.rowid, NEW.title, NEW.content);
         END
     "#).execute(pool).await?;
 
-    // DELETE trigger - remove from FTS
     sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_delete AFTER DELETE ON reagents BEGIN
-            INSERT INTO reagents_fts(reagents_fts, rowid, name, cas_number, formula)
-            VALUES ('delete', OLD.rowid, OLD.name, OLD.cas_number, OLD.formula);
-        END
+        INSERT INTO experiment_notebook_fts(rowid, title, content)
+        SELECT rowid, title, content FROM experiment_notebook_entries
     "#).execute(pool).await?;
 
-    // UPDATE trigger - update FTS index
-    sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_update AFTER UPDATE ON reagents BEGIN
-            INSERT INTO reagents_fts(reagents_fts, rowid, name, cas_number, formula)
-            VALUES ('delete', OLD.rowid, OLD.name, OLD.cas_number, OLD.formula);
-            INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-            VALUES (NEW.rowid, NEW.name, NEW.cas_number, NEW.formula);
-        END
-    "#).execute(pool).await?;
+    info!("Notebook FTS5 table created.");
+    Ok(())
+}
 
-    // Populate FTS with existing data
-    sqlx::query(r#"
-        INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-        SELECT rowid, name, cas_number, formula FROM reagents
-    "#).execute(pool).await?;
+// ==================== EXPERIMENT WORKFLOW SEED ====================
+// Default transitions mirror the hand-coded start/complete/cancel rules.
+// INSERT OR IGNORE keeps whatever an admin has reconfigured since.
+// Guards are opt-in: completion without a payload settles at planned_quantity.
+
+async fn seed_experiment_workflow(pool: &SqlitePool) -> Result<()> {
+    let defaults: [(&str, &str, &str); 11] = [
+        ("draft", "planned", ""),
+        ("draft", "cancelled", ""),
+        ("planned", "in_progress", ""),
+        ("planned", "on_hold", ""),
+        ("planned", "cancelled", ""),
+        ("in_progress", "completed", "reagents_settled"),
+        ("in_progress", "on_hold", ""),
+        ("in_progress", "cancelled", ""),
+        ("on_hold", "planned", ""),
+        ("on_hold", "in_progress", ""),
+        ("on_hold", "cancelled", ""),
+    ];
 
-    // Optimize the FTS index
-    let _ = sqlx::query("INSERT INTO reagents_fts(reagents_fts) VALUES('optimize')").execute(pool).await;
+    for (from, to, guards) in defaults {
+        sqlx::query(
+            "INSERT OR IGNORE INTO experiment_status_transitions (from_status, to_status, guards, updated_at) VALUES (?, ?, ?, datetime('now'))"
+        )
+            .bind(from)
+            .bind(to)
+            .bind(guards)
+            .execute(pool)
+            .await?;
+    }
+
+    // Установки, где guard снимался миграцией, а администратор правило не менял
+    sqlx::query(
+        "UPDATE experiment_status_transitions SET guards = 'reagents_settled' WHERE from_status = 'in_progress' AND to_status = 'completed' AND guards = '' AND updated_by IS NULL"
+    )
+        .execute(pool)
+        .await?;
 
-    info!("FTS5 table created and populated.");
     Ok(())
 }
 
@@ -753,6 +1897,9 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         "ALTER TABLE reagents ADD COLUMN total_quantity REAL NOT NULL DEFAULT 0.0",
         "ALTER TABLE reagents ADD COLUMN batches_count INTEGER NOT NULL DEFAULT 0",
         "ALTER TABLE reagents ADD COLUMN primary_unit TEXT",
+        "ALTER TABLE reagents ADD COLUMN signal_word TEXT CHECK(signal_word IS NULL OR signal_word IN ('Danger', 'Warning'))",
+        "ALTER TABLE reagents ADD COLUMN shelf_life_after_opening_days INTEGER CHECK(shelf_life_after_opening_days IS NULL OR shelf_life_after_opening_days > 0)",
+        "ALTER TABLE reagents ADD COLUMN retest_interval_days INTEGER CHECK(retest_interval_days IS NULL OR retest_interval_days > 0)",
         
 
         // ==================== EQUIPMENT ====================
@@ -764,6 +1911,12 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         "ALTER TABLE equipment ADD COLUMN last_maintenance TEXT",
         "ALTER TABLE equipment ADD COLUMN next_maintenance TEXT",
         "ALTER TABLE equipment ADD COLUMN maintenance_interval_days INTEGER DEFAULT 90",
+        // purchase_date: free text → 'YYYY-MM-DD'; unparseable values are kept in the description
+        "UPDATE equipment SET description = substr(COALESCE(description || ' ', '') || '[Purchase date: ' || purchase_date || ']', 1, 1000) WHERE purchase_date IS NOT NULL AND date(substr(purchase_date, 1, 10)) IS NOT substr(purchase_date, 1, 10)",
+        "UPDATE equipment SET purchase_date = CASE WHEN date(substr(purchase_date, 1, 10)) IS substr(purchase_date, 1, 10) THEN substr(purchase_date, 1, 10) END WHERE purchase_date IS NOT NULL AND date(purchase_date) IS NOT purchase_date",
+        // warranty_until: free text → 'YYYY-MM-DD'; unparseable values are kept in the description
+        "UPDATE equipment SET description = substr(COALESCE(description || ' ', '') || '[Warranty until: ' || warranty_until || ']', 1, 1000) WHERE warranty_until IS NOT NULL AND date(substr(warranty_until, 1, 10)) IS NOT substr(warranty_until, 1, 10)",
+        "UPDATE equipment SET warranty_until = CASE WHEN date(substr(warranty_until, 1, 10)) IS substr(warranty_until, 1, 10) THEN substr(warranty_until, 1, 10) END WHERE warranty_until IS NOT NULL AND date(warranty_until) IS NOT warranty_until",
 
         // ==================== USERS ====================
         "ALTER TABLE users ADD COLUMN failed_login_attempts INTEGER NOT NULL DEFAULT 0",
@@ -812,6 +1965,186 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         // ==================== BATCH PLACEMENTS IDEXES ====================
         "CREATE INDEX IF NOT EXISTS idx_placements_container ON batch_placements(container_id)",
 
+        // ==================== EXPERIMENT WORKFLOW ====================
+        "ALTER TABLE experiment_signoffs ADD COLUMN payload TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_status_history_exp ON experiment_status_history(experiment_id, created_at)",
+        "ALTER TABLE equipment_maintenance ADD COLUMN plan_id TEXT REFERENCES equipment_maintenance_plans(id) ON DELETE SET NULL",
+        "ALTER TABLE equipment_maintenance ADD COLUMN previous_equipment_status TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_maintenance_open ON equipment_maintenance(status, scheduled_date)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_maintenance_plan ON equipment_maintenance(plan_id, status)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_maintenance_plans_equipment ON equipment_maintenance_plans(equipment_id)",
+        "ALTER TABLE equipment_parts ADD COLUMN replacement_interval_days INTEGER CHECK(replacement_interval_days IS NULL OR replacement_interval_days > 0)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_parts_next_replacement ON equipment_parts(next_replacement)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_part_usage_equipment ON equipment_part_usage(equipment_id, used_date)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_part_usage_part ON equipment_part_usage(part_id, used_date)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_part_usage_maintenance ON equipment_part_usage(maintenance_id)",
+        "CREATE INDEX IF NOT EXISTS idx_file_download_links_expires ON file_download_links(expires_at)",
+        "ALTER TABLE equipment_files ADD COLUMN storage_key TEXT",
+        "ALTER TABLE equipment_files ADD COLUMN sha256 TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_files_storage_key ON equipment_files(storage_key)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_documents_exp ON experiment_documents(experiment_id, created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_documents_storage_key ON experiment_documents(storage_key)",
+        "ALTER TABLE equipment_files ADD COLUMN image_width INTEGER",
+        "ALTER TABLE equipment_files ADD COLUMN image_height INTEGER",
+        "ALTER TABLE equipment_files ADD COLUMN orientation TEXT CHECK(orientation IS NULL OR orientation IN ('landscape', 'portrait', 'square'))",
+        "ALTER TABLE equipment_files ADD COLUMN exif_orientation INTEGER",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_file_thumbnails_key ON equipment_file_thumbnails(storage_key)",
+        "ALTER TABLE equipment_files ADD COLUMN contract_id TEXT REFERENCES service_contracts(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_files_contract ON equipment_files(contract_id)",
+        "CREATE INDEX IF NOT EXISTS idx_service_contracts_equipment ON service_contracts(equipment_id)",
+        "CREATE INDEX IF NOT EXISTS idx_service_contracts_end ON service_contracts(end_date)",
+        "CREATE INDEX IF NOT EXISTS idx_notifications_created ON notifications(created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_warranty ON equipment(warranty_until)",
+        "CREATE INDEX IF NOT EXISTS idx_container_movements_container ON container_movements(container_id, moved_at)",
+        "CREATE INDEX IF NOT EXISTS idx_container_movements_from ON container_movements(from_position_id, moved_at)",
+        "CREATE INDEX IF NOT EXISTS idx_container_movements_to ON container_movements(to_position_id, moved_at)",
+        "CREATE INDEX IF NOT EXISTS idx_container_movements_room ON container_movements(to_room_id, moved_at)",
+        // Placements made before movement history existed become their initial 'place' event
+        r#"INSERT INTO container_movements (id, container_id, batch_id, action, to_position_id, to_room_id, moved_by, moved_at, notes)
+           SELECT lower(hex(randomblob(16))), bp.container_id, bc.batch_id, 'place', bp.position_id, sz.room_id,
+                  bp.placed_by, bp.placed_at, 'Recorded from existing placement'
+           FROM batch_placements bp
+           JOIN batch_containers bc ON bp.container_id = bc.id
+           LEFT JOIN storage_positions sp ON bp.position_id = sp.id
+           LEFT JOIN storage_zones sz ON sp.zone_id = sz.id
+           WHERE NOT EXISTS (SELECT 1 FROM container_movements m WHERE m.container_id = bp.container_id)"#,
+        "ALTER TABLE equipment_parts ADD COLUMN unit_cost REAL CHECK(unit_cost IS NULL OR unit_cost >= 0)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_status_history_equipment ON equipment_status_history(equipment_id, changed_at)",
+        // Оборудование без истории: текущий статус считаем действующим с последнего изменения записи
+        r#"INSERT INTO equipment_status_history (id, equipment_id, from_status, to_status, changed_at)
+           SELECT lower(hex(randomblob(16))), e.id, NULL, e.status, e.updated_at FROM equipment e
+           WHERE NOT EXISTS (SELECT 1 FROM equipment_status_history h WHERE h.equipment_id = e.id)"#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_equipment_usage_open ON equipment_usage_log(equipment_id) WHERE ended_at IS NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_usage_equipment ON equipment_usage_log(equipment_id, started_at)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_usage_user ON equipment_usage_log(user_id, started_at)",
+        "ALTER TABLE equipment_maintenance_plans ADD COLUMN interval_runs INTEGER CHECK(interval_runs IS NULL OR interval_runs > 0)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_equipment_links_current_child ON equipment_links(child_id) WHERE unlinked_at IS NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_links_parent ON equipment_links(parent_id, unlinked_at)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_links_child ON equipment_links(child_id, linked_at)",
+        "CREATE INDEX IF NOT EXISTS idx_calibration_results_equipment ON calibration_results(equipment_id, performed_at)",
+        "CREATE INDEX IF NOT EXISTS idx_calibration_points_result ON calibration_points(result_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_calibration_flags_exp ON experiment_calibration_flags(experiment_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_equipment_equipment ON experiment_equipment(equipment_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_notebook_entries_exp ON experiment_notebook_entries(experiment_id, created_at)",
+        // У записи не больше одного исправления: цепочка амендментов не ветвится
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_experiment_notebook_entries_amends ON experiment_notebook_entries(amends_entry_id) WHERE amends_entry_id IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_notebook_links_entity ON experiment_notebook_links(entity_type, entity_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_signoffs_exp ON experiment_signoffs(experiment_id, status)",
+        "ALTER TABLE storage_positions ADD COLUMN grid_rows INTEGER CHECK(grid_rows IS NULL OR grid_rows BETWEEN 1 AND 26)",
+        "ALTER TABLE storage_positions ADD COLUMN grid_columns INTEGER CHECK(grid_columns IS NULL OR grid_columns BETWEEN 1 AND 99)",
+        "ALTER TABLE batch_placements ADD COLUMN cell TEXT",
+        "ALTER TABLE container_movements ADD COLUMN from_cell TEXT",
+        "ALTER TABLE container_movements ADD COLUMN to_cell TEXT",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_batch_placements_cell ON batch_placements(position_id, cell) WHERE cell IS NOT NULL",
+        // current_count считает контейнеры (раньше — партии); статус full выставляется по вместимости
+        "UPDATE storage_positions SET current_count = (SELECT COUNT(*) FROM batch_placements bp WHERE bp.position_id = storage_positions.id)",
+        r#"UPDATE storage_positions
+           SET status = CASE WHEN max_capacity IS NOT NULL AND current_count >= max_capacity THEN 'full' ELSE 'available' END
+           WHERE status IN ('available', 'full')"#,
+
+        "CREATE INDEX IF NOT EXISTS idx_storage_locations_parent ON storage_locations(parent_id, sort_order)",
+        "CREATE INDEX IF NOT EXISTS idx_storage_locations_path ON storage_locations(path)",
+        // Существующие комнаты → зоны → позиции становятся узлами дерева глубиной 0/1/2
+        r#"INSERT OR IGNORE INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, created_at, updated_at)
+           SELECT r.id, NULL, 'room', r.name, 'room', '/' || r.id || '/', 0, r.created_at, r.updated_at FROM rooms r"#,
+        r#"INSERT OR IGNORE INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, sort_order, description, created_by, created_at, updated_at)
+           SELECT sz.id, p.id, sz.zone_type, sz.name, 'zone', p.path || sz.id || '/', p.depth + 1,
+                  sz.sort_order, sz.description, sz.created_by, sz.created_at, sz.updated_at
+           FROM storage_zones sz JOIN storage_locations p ON p.id = sz.room_id"#,
+        r#"INSERT OR IGNORE INTO storage_locations (id, parent_id, location_type, name, linked_entity, path, depth, sort_order, description, created_by, created_at, updated_at)
+           SELECT sp.id, p.id, 'position', sp.name, 'position', p.path || sp.id || '/', p.depth + 1,
+                  sp.sort_order, sp.description, sp.created_by, sp.created_at, sp.updated_at
+           FROM storage_positions sp JOIN storage_locations p ON p.id = sp.zone_id"#,
+
+        // ==================== SCAN CODES ====================
+        "INSERT OR IGNORE INTO scan_code_sequences (prefix, last_value) VALUES ('C', 0), ('B', 0), ('P', 0)",
+        "ALTER TABLE batch_containers ADD COLUMN short_code TEXT",
+        "ALTER TABLE batches ADD COLUMN short_code TEXT",
+        "ALTER TABLE storage_positions ADD COLUMN short_code TEXT",
+        // Существующим записям — коды по порядку создания, затем счётчик догоняет максимум
+        r#"UPDATE batch_containers
+           SET short_code = 'C' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'C') + r.rn)
+           FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, id) AS rn FROM batch_containers WHERE short_code IS NULL) r
+           WHERE batch_containers.id = r.id"#,
+        r#"UPDATE scan_code_sequences
+           SET last_value = MAX(last_value, COALESCE((SELECT MAX(CAST(substr(short_code, 2) AS INTEGER)) FROM batch_containers), 0))
+           WHERE prefix = 'C'"#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_batch_containers_short_code ON batch_containers(short_code)",
+        r#"CREATE TRIGGER IF NOT EXISTS trg_batch_containers_short_code
+           AFTER INSERT ON batch_containers
+           WHEN NEW.short_code IS NULL
+           BEGIN
+               UPDATE scan_code_sequences SET last_value = last_value + 1 WHERE prefix = 'C';
+               UPDATE batch_containers
+               SET short_code = 'C' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'C'))
+               WHERE id = NEW.id;
+           END"#,
+        r#"UPDATE batches
+           SET short_code = 'B' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'B') + r.rn)
+           FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, id) AS rn FROM batches WHERE short_code IS NULL) r
+           WHERE batches.id = r.id"#,
+        r#"UPDATE scan_code_sequences
+           SET last_value = MAX(last_value, COALESCE((SELECT MAX(CAST(substr(short_code, 2) AS INTEGER)) FROM batches), 0))
+           WHERE prefix = 'B'"#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_batches_short_code ON batches(short_code)",
+        r#"CREATE TRIGGER IF NOT EXISTS trg_batches_short_code
+           AFTER INSERT ON batches
+           WHEN NEW.short_code IS NULL
+           BEGIN
+               UPDATE scan_code_sequences SET last_value = last_value + 1 WHERE prefix = 'B';
+               UPDATE batches
+               SET short_code = 'B' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'B'))
+               WHERE id = NEW.id;
+           END"#,
+        r#"UPDATE storage_positions
+           SET short_code = 'P' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'P') + r.rn)
+           FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY created_at, id) AS rn FROM storage_positions WHERE short_code IS NULL) r
+           WHERE storage_positions.id = r.id"#,
+        r#"UPDATE scan_code_sequences
+           SET last_value = MAX(last_value, COALESCE((SELECT MAX(CAST(substr(short_code, 2) AS INTEGER)) FROM storage_positions), 0))
+           WHERE prefix = 'P'"#,
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_storage_positions_short_code ON storage_positions(short_code)",
+        r#"CREATE TRIGGER IF NOT EXISTS trg_storage_positions_short_code
+           AFTER INSERT ON storage_positions
+           WHEN NEW.short_code IS NULL
+           BEGIN
+               UPDATE scan_code_sequences SET last_value = last_value + 1 WHERE prefix = 'P';
+               UPDATE storage_positions
+               SET short_code = 'P' || printf('%06d', (SELECT last_value FROM scan_code_sequences WHERE prefix = 'P'))
+               WHERE id = NEW.id;
+           END"#,
+
+        // ==================== STOCKTAKE ====================
+        "CREATE INDEX IF NOT EXISTS idx_stocktake_sessions_room ON stocktake_sessions(room_id, status)",
+        "CREATE INDEX IF NOT EXISTS idx_stocktake_counts_session ON stocktake_counts(session_id)",
+        "CREATE INDEX IF NOT EXISTS idx_stocktake_adjustments_session ON stocktake_adjustments(session_id)",
+        "CREATE INDEX IF NOT EXISTS idx_stocktake_adjustments_container ON stocktake_adjustments(container_id)",
+
+        // ==================== OPENED-CONTAINER SHELF LIFE ====================
+        // Срок после вскрытия и дата перепроверки считаются при первом вскрытии контейнера
+        "ALTER TABLE batch_containers ADD COLUMN open_expiry_date TEXT",
+        "ALTER TABLE batch_containers ADD COLUMN retest_date TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_batch_containers_open_expiry ON batch_containers(open_expiry_date) WHERE open_expiry_date IS NOT NULL",
+
+        // ==================== HAZARDOUS WASTE ====================
+        "CREATE INDEX IF NOT EXISTS idx_waste_containers_status ON waste_containers(status, waste_category)",
+        "CREATE INDEX IF NOT EXISTS idx_waste_containers_manifest ON waste_containers(manifest_id)",
+        "CREATE INDEX IF NOT EXISTS idx_waste_entries_container ON waste_entries(waste_container_id)",
+        "CREATE INDEX IF NOT EXISTS idx_waste_entries_disposed_at ON waste_entries(disposed_at)",
+        "CREATE INDEX IF NOT EXISTS idx_waste_entries_source ON waste_entries(source_container_id)",
+
+        // ==================== TEMPERATURE MONITORING ====================
+        "CREATE INDEX IF NOT EXISTS idx_temperature_sensors_zone ON temperature_sensors(zone_id)",
+        "CREATE INDEX IF NOT EXISTS idx_temperature_readings_bucket ON temperature_readings(resolution, bucket_start)",
+        "CREATE INDEX IF NOT EXISTS idx_temperature_excursions_sensor ON temperature_excursions(sensor_id, status)",
+        "CREATE INDEX IF NOT EXISTS idx_temperature_excursions_zone ON temperature_excursions(zone_id, started_at)",
+
+        // ==================== GOODS-IN / QA ====================
+        "CREATE INDEX IF NOT EXISTS idx_purchase_orders_status ON purchase_orders(status)",
+        "CREATE INDEX IF NOT EXISTS idx_purchase_order_lines_order ON purchase_order_lines(purchase_order_id)",
+        "CREATE INDEX IF NOT EXISTS idx_goods_receipts_qa_status ON goods_receipts(qa_status, received_at)",
+        "CREATE INDEX IF NOT EXISTS idx_goods_receipts_line ON goods_receipts(purchase_order_line_id)",
+
         // ==================== PARTIAL UNIQUE INDEXES FOR SOFT DELETE ====================
         "CREATE UNIQUE INDEX IF NOT EXISTS idx_reagents_name_active ON reagents(name) WHERE deleted_at IS NULL",
         "CREATE UNIQUE INDEX IF NOT EXISTS idx_batches_reagent_batch_active ON batches(reagent_id, batch_number) WHERE deleted_at IS NULL",
@@ -846,14 +2179,48 @@ pub async fn reset_database(pool: &SqlitePool) -> Result<()> {
         "DROP TRIGGER IF EXISTS reagents_fts_insert",
         "DROP TRIGGER IF EXISTS reagents_fts_update",
         "DROP TRIGGER IF EXISTS reagents_fts_delete",
+        "DROP TRIGGER IF EXISTS experiments_fts_insert",
+        "DROP TRIGGER IF EXISTS experiments_fts_update",
+        "DROP TRIGGER IF EXISTS experiments_fts_delete",
+        "DROP TRIGGER IF EXISTS equipment_fts_insert",
+        "DROP TRIGGER IF EXISTS equipment_fts_update",
+        "DROP TRIGGER IF EXISTS equipment_fts_delete",
+        "DROP TRIGGER IF EXISTS equipment_parts_fts_insert",
+        "DROP TRIGGER IF EXISTS equipment_parts_fts_update",
+        "DROP TRIGGER IF EXISTS equipment_parts_fts_delete",
+        "DROP TABLE IF EXISTS experiments_fts",
         "DROP TABLE IF EXISTS equipment_fts",
+        "DROP TABLE IF EXISTS equipment_parts_fts",
         "DROP TABLE IF EXISTS reagents_fts",
+        "DROP TABLE IF EXISTS file_download_links",
+        "DROP TABLE IF EXISTS equipment_file_thumbnails",
+        "DROP TABLE IF EXISTS equipment_links",
+        "DROP TABLE IF EXISTS equipment_usage_log",
+        "DROP TABLE IF EXISTS equipment_status_history",
+        "DROP TABLE IF EXISTS notification_reads",
+        "DROP TABLE IF EXISTS notifications",
         "DROP TABLE IF EXISTS equipment_files",
+        "DROP TABLE IF EXISTS service_contracts",
+        "DROP TABLE IF EXISTS experiment_calibration_flags",
+        "DROP TABLE IF EXISTS calibration_points",
+        "DROP TABLE IF EXISTS calibration_results",
+        "DROP TABLE IF EXISTS calibration_standards",
+        "DROP TABLE IF EXISTS equipment_part_usage",
         "DROP TABLE IF EXISTS equipment_maintenance",
+        "DROP TABLE IF EXISTS equipment_maintenance_plans",
         "DROP TABLE IF EXISTS equipment_parts",
         "DROP TABLE IF EXISTS experiment_equipment",
         "DROP TABLE IF EXISTS experiment_reagents",
         "DROP TABLE IF EXISTS experiment_documents",
+        "DROP TRIGGER IF EXISTS experiment_notebook_fts_insert",
+        "DROP TABLE IF EXISTS experiment_notebook_fts",
+        "DROP TRIGGER IF EXISTS experiment_notebook_links_no_delete",
+        "DROP TRIGGER IF EXISTS experiment_notebook_entries_no_delete",
+        "DROP TABLE IF EXISTS experiment_notebook_links",
+        "DROP TABLE IF EXISTS experiment_notebook_entries",
+        "DROP TABLE IF EXISTS experiment_status_history",
+        "DROP TABLE IF EXISTS experiment_signoffs",
+        "DROP TABLE IF EXISTS experiment_status_transitions",
         "DROP TABLE IF EXISTS experiments",
         "DROP TABLE IF EXISTS rooms",
         "DROP TABLE IF EXISTS equipment",
@@ -865,8 +2232,24 @@ pub async fn reset_database(pool: &SqlitePool) -> Result<()> {
         "DROP TABLE IF EXISTS users",
         "DROP TABLE IF EXISTS reagent_stock_cache",
         "DROP TABLE IF EXISTS reagent_count_cache",
+        "DROP TABLE IF EXISTS goods_receipts",
+        "DROP TABLE IF EXISTS purchase_order_lines",
+        "DROP TABLE IF EXISTS purchase_orders",
+        "DROP TABLE IF EXISTS temperature_excursion_containers",
+        "DROP TABLE IF EXISTS temperature_excursions",
+        "DROP TABLE IF EXISTS temperature_readings",
+        "DROP TABLE IF EXISTS temperature_sensors",
+        "DROP TABLE IF EXISTS waste_entries",
+        "DROP TABLE IF EXISTS waste_containers",
+        "DROP TABLE IF EXISTS waste_manifests",
+        "DROP TABLE IF EXISTS stocktake_adjustments",
+        "DROP TABLE IF EXISTS stocktake_counts",
+        "DROP TABLE IF EXISTS stocktake_sessions",
+        "DROP TABLE IF EXISTS container_movements",
+        "DROP TABLE IF EXISTS scan_code_sequences",
         "DROP TABLE IF EXISTS batch_containers",
         "DROP TABLE IF EXISTS batch_placements",
+        "DROP TABLE IF EXISTS storage_locations",
         "DROP TABLE IF EXISTS storage_positions",
         "DROP TABLE IF EXISTS storage_zones",
         
@@ -896,7 +2279,6 @@ pub async fn column_exists(pool: &SqlitePool, table: &str, column: &str) -> Resu
 }
 
 /// Get table info for debugging
-#[allow(dead_code)]
 pub async fn get_table_columns(pool: &SqlitePool, table: &str) -> Result<Vec<String>> {
     let query = format!("SELECT name FROM pragma_table_info('{}')", table);
     let rows: Vec<(String,)> = sqlx::query_as(&query)
@@ -933,21 +2315,41 @@ pub async fn rebuild_reagent_cache(pool: &SqlitePool) -> Result<u64> {
     Ok(result.rows_affected())
 }
 
-/// Rebuild FTS index (for maintenance after bulk imports)
+/// Rebuild FTS indexes (for maintenance after bulk imports)
 pub async fn rebuild_fts_index(pool: &SqlitePool) -> Result<u64> {
-    info!("Rebuilding FTS index...");
+    info!("Rebuilding FTS indexes...");
+
+    let tables = [
+        ("reagents_fts", "reagents"),
+        ("experiments_fts", "experiments"),
+        ("equipment_fts", "equipment"),
+        ("equipment_parts_fts", "equipment_parts"),
+        ("experiment_notebook_fts", "experiment_notebook_entries"),
+    ];
 
-    // Clear and repopulate
-    let _ = sqlx::query("DELETE FROM reagents_fts").execute(pool).await;
+    let mut total = 0u64;
+    for (fts_table, main_table) in tables {
+        // 'rebuild' re-reads the external content table from scratch
+        sqlx::query(&format!("INSERT INTO {fts}({fts}) VALUES('rebuild')", fts = fts_table)).execute(pool).await?;
+        let _ = sqlx::query(&format!("INSERT INTO {fts}({fts}) VALUES('optimize')", fts = fts_table)).execute(pool).await;
 
-    let result = sqlx::query(r#"
-        INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-        SELECT rowid, name, cas_number, formula FROM reagents
-    "#).execute(pool).await?;
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", main_table)).fetch_one(pool).await?;
+        info!("FTS index {} rebuilt: {} rows", fts_table, rows);
+        total += rows as u64;
+    }
 
-    // Optimize
-    let _ = sqlx::query("INSERT INTO reagents_fts(reagents_fts) VALUES('optimize')").execute(pool).await;
+    Ok(total)
+}
 
-    info!("FTS index rebuilt: {} rows", result.rows_affected());
-    Ok(result.rows_affected())
+/// Чистая in-memory база со всеми миграциями — для тестов, которым нужна схема.
+/// Одно соединение: у каждого соединения `:memory:` своя база.
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory sqlite");
+    run_migrations(&pool).await.expect("migrations");
+    pool
 }
@@ -7,23 +7,26 @@
 //! - Загрузка и хранение файлов (мануалы, изображения)
 //! - FTS5 полнотекстовый поиск
 
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_multipart::Multipart;
 use futures_util::StreamExt;
 use sqlx::SqlitePool;
 use std::sync::Arc;
-use std::io::Write;
 use std::str::FromStr;
 use chrono::Utc;
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::AppState;
+use crate::{AppState, audit, auth};
+use crate::storage::BlobStore;
+use crate::thumbnails::{self, Thumbnail, ThumbnailSize};
+use crate::maintenance_handlers;
+use crate::spare_parts_handlers;
 use crate::models::{
     Equipment, CreateEquipmentRequest, UpdateEquipmentRequest,
     EquipmentPart, CreateEquipmentPartRequest, UpdateEquipmentPartRequest,
     EquipmentMaintenance,
-    CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest,
+    CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest, PartUsageInput,
     EquipmentFile, EquipmentDetailResponse
 };
 use crate::error::{ApiError, ApiResult};
@@ -33,6 +36,7 @@ use crate::query_builders::{
     EquipmentType, MaintenanceType, MaintenanceStatus,
     MaintenanceValidator, generate_unique_filename, validate_file_size, validate_mime_type,
 };
+use crate::query_builders::fts::{FtsHit, FtsQueryBuilder, config::FtsConfig};
 
 // ==================== КОНСТАНТЫ ====================
 
@@ -84,17 +88,6 @@ const ALLOWED_DOC_TYPES: &[&str] = &[
     "text/plain",
 ];
 
-/// Возвращает путь к директории файлов оборудования (кроссплатформенно)
-fn get_equipment_files_dir() -> std::path::PathBuf {
-    std::env::var("EQUIPMENT_FILES_DIR")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| {
-            std::path::PathBuf::from(".")
-                .join("uploads")
-                .join("equipment")
-        })
-}
-
 // ==================== ОСНОВНЫЕ CRUD ОПЕРАЦИИ ====================
 
 /// Получение списка оборудования с пагинацией и фильтрами
@@ -283,6 +276,14 @@ pub async fn update_equipment(
         updates.push("quantity = ?");
         values.push(quantity.to_string());
     }
+    if let Some(date) = update.purchase_date {
+        updates.push("purchase_date = ?");
+        values.push(date.to_string());
+    }
+    if let Some(date) = update.warranty_until {
+        updates.push("warranty_until = ?");
+        values.push(date.to_string());
+    }
 
     if updates.is_empty() {
         return Err(ApiError::bad_request("No fields to update"));
@@ -321,6 +322,17 @@ pub async fn delete_equipment(
 ) -> ApiResult<HttpResponse> {
     let equipment_id = path.into_inner();
 
+    // Результаты калибровки — доказательная база (ON DELETE RESTRICT)
+    let calibrations: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM calibration_results WHERE equipment_id = ?")
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if calibrations > 0 {
+        return Err(ApiError::bad_request(
+            "Equipment has calibration records and cannot be deleted; set its status to 'retired' instead"
+        ));
+    }
+
     // Удаляем связанные данные
     sqlx::query("DELETE FROM equipment_parts WHERE equipment_id = ?")
         .bind(&equipment_id)
@@ -332,7 +344,7 @@ pub async fn delete_equipment(
         .execute(&app_state.db_pool)
         .await?;
 
-    // Удаляем файлы с диска
+    // Удаляем файлы (содержимое — только если на него больше никто не ссылается)
     let files: Vec<EquipmentFile> = sqlx::query_as(
         "SELECT * FROM equipment_files WHERE equipment_id = ?"
     )
@@ -340,22 +352,10 @@ pub async fn delete_equipment(
         .fetch_all(&app_state.db_pool)
         .await?;
 
-    for file in files {
-        let _ = std::fs::remove_file(&file.file_path);
+    for file in &files {
+        delete_equipment_file_record(&app_state.db_pool, &app_state.storage, file).await?;
     }
 
-    sqlx::query("DELETE FROM equipment_files WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
-        .await?;
-
-    // Удаляем из FTS
-    sqlx::query("DELETE FROM equipment_fts WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
-        .await
-        .ok(); // Игнорируем ошибку если FTS таблица не существует
-
     // Удаляем само оборудование
     let result = sqlx::query("DELETE FROM equipment WHERE id = ?")
         .bind(&equipment_id)
@@ -417,9 +417,9 @@ pub async fn add_equipment_part(
     sqlx::query(
         r#"INSERT INTO equipment_parts
            (id, equipment_id, name, part_number, manufacturer, quantity, 
-            min_quantity, status, last_replaced, next_replacement, notes,
+            min_quantity, status, last_replaced, next_replacement, replacement_interval_days, unit_cost, notes,
             created_by, created_at, updated_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
         .bind(&equipment_id)
@@ -431,6 +431,8 @@ pub async fn add_equipment_part(
         .bind(status)
         .bind(&part.last_replaced)
         .bind(&part.next_replacement)
+        .bind(part.replacement_interval_days)
+        .bind(part.unit_cost)
         .bind(&part.notes)
         .bind(&user_id)
         .bind(&now)
@@ -508,6 +510,24 @@ pub async fn update_equipment_part(
         updates.push("status = ?");
         values.push(status.clone());
     }
+    if let Some(ref last_replaced) = update.last_replaced {
+        maintenance_handlers::parse_day(last_replaced)?;
+        updates.push("last_replaced = ?");
+        values.push(last_replaced.clone());
+    }
+    if let Some(ref next_replacement) = update.next_replacement {
+        maintenance_handlers::parse_day(next_replacement)?;
+        updates.push("next_replacement = ?");
+        values.push(next_replacement.clone());
+    }
+    if let Some(interval) = update.replacement_interval_days {
+        updates.push("replacement_interval_days = ?");
+        values.push(interval.to_string());
+    }
+    if let Some(unit_cost) = update.unit_cost {
+        updates.push("unit_cost = ?");
+        values.push(unit_cost.to_string());
+    }
     if let Some(ref notes) = update.notes {
         updates.push("notes = ?");
         values.push(notes.clone());
@@ -620,6 +640,15 @@ pub async fn create_maintenance(
     let now = Utc::now();
     let status = maintenance.status.as_deref().unwrap_or("scheduled");
 
+    // Запчасти списываются только по факту выполненной работы
+    if !maintenance.parts_used.is_empty() && status != "completed" {
+        return Err(ApiError::bad_request(
+            "Parts can only be consumed by completed maintenance; pass them when completing the record"
+        ));
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+
     sqlx::query(
         r#"INSERT INTO equipment_maintenance
            (id, equipment_id, maintenance_type, status, scheduled_date, completed_date,
@@ -641,9 +670,15 @@ pub async fn create_maintenance(
         .bind(&user_id)
         .bind(&now)
         .bind(&now)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
 
+    let used_date = maintenance.completed_date.as_deref().unwrap_or(&maintenance.scheduled_date);
+    record_parts_used(&mut tx, &equipment_id, &id, &maintenance.parts_used, used_date, &user_id).await?;
+
+    maintenance_handlers::refresh_equipment_schedule(&mut tx, &equipment_id).await?;
+    tx.commit().await?;
+
     let created: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ?"
     )
@@ -659,24 +694,21 @@ pub async fn update_maintenance(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
     update: web::Json<UpdateMaintenanceRequest>,
-    _user_id: String,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
     update.validate()?;
     let (equipment_id, maintenance_id) = path.into_inner();
 
     check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
 
-    let existing: Option<EquipmentMaintenance> = sqlx::query_as(
+    let existing: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
     )
         .bind(&maintenance_id)
         .bind(&equipment_id)
         .fetch_optional(&app_state.db_pool)
-        .await?;
-
-    if existing.is_none() {
-        return Err(ApiError::not_found("Maintenance record"));
-    }
+        .await?
+        .ok_or_else(|| ApiError::not_found("Maintenance record"))?;
 
     let mut updates = Vec::new();
     let mut values: Vec<String> = Vec::new();
@@ -726,48 +758,72 @@ pub async fn update_maintenance(
         updates.join(", ")
     );
 
+    let mut tx = app_state.db_pool.begin().await?;
     let mut query = sqlx::query(&sql);
     for value in &values {
         query = query.bind(value);
     }
     query = query.bind(&maintenance_id);
-    query.execute(&app_state.db_pool).await?;
+    query.execute(&mut *tx).await?;
 
     let updated: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ?"
     )
         .bind(&maintenance_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+    // Закрытие через PUT должно вести себя так же, как /complete
+    let was_open = existing.status == "scheduled" || existing.status == "in_progress";
+    match updated.status.as_str() {
+        "completed" if was_open => {
+            let completed_date = updated.completed_date.clone()
+                .unwrap_or_else(|| Utc::now().format("%Y-%m-%d").to_string());
+            maintenance_handlers::on_maintenance_closed(&mut tx, &existing, Some(&completed_date), Some(&user_id)).await?;
+        }
+        "cancelled" if was_open => {
+            maintenance_handlers::on_maintenance_closed(&mut tx, &existing, None, Some(&user_id)).await?;
+        }
+        _ => maintenance_handlers::refresh_equipment_schedule(&mut tx, &equipment_id).await?,
+    }
+    tx.commit().await?;
+
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
 }
 
-/// Завершение обслуживания
+/// Завершение обслуживания: обновляет сроки оборудования и планирует
+/// следующее выполнение, если запись создана по плану
 pub async fn complete_maintenance(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
     body: web::Json<CompleteMaintenanceRequest>,
-    _user_id: String,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
+    body.validate()?;
     let (equipment_id, maintenance_id) = path.into_inner();
 
     check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
 
-    let existing: Option<EquipmentMaintenance> = sqlx::query_as(
+    let existing: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
     )
         .bind(&maintenance_id)
         .bind(&equipment_id)
         .fetch_optional(&app_state.db_pool)
-        .await?;
+        .await?
+        .ok_or_else(|| ApiError::not_found("Maintenance record"))?;
 
-    if existing.is_none() {
-        return Err(ApiError::not_found("Maintenance record"));
+    if existing.status == "completed" || existing.status == "cancelled" {
+        return Err(ApiError::bad_request(&format!(
+            "Maintenance record is already {}", existing.status
+        )));
     }
 
     let completed_date = body.completed_date.clone()
         .unwrap_or_else(|| Utc::now().format("%Y-%m-%d").to_string());
+    maintenance_handlers::parse_day(&completed_date)?;
+
+    let mut tx = app_state.db_pool.begin().await?;
 
     sqlx::query(
         r#"UPDATE equipment_maintenance 
@@ -780,9 +836,13 @@ pub async fn complete_maintenance(
         .bind(&body.notes)
         .bind(Utc::now())
         .bind(&maintenance_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
 
+    record_parts_used(&mut tx, &equipment_id, &maintenance_id, &body.parts_used, &completed_date, &user_id).await?;
+    maintenance_handlers::on_maintenance_closed(&mut tx, &existing, Some(&completed_date), Some(&user_id)).await?;
+    tx.commit().await?;
+
     let updated: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ?"
     )
@@ -793,6 +853,30 @@ pub async fn complete_maintenance(
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
 }
 
+/// Списать запчасти по записи обслуживания; если `parts_replaced` не заполнено
+/// вручную, туда пишется сводка по списанию
+async fn record_parts_used(
+    conn: &mut sqlx::SqliteConnection,
+    equipment_id: &str,
+    maintenance_id: &str,
+    parts: &[PartUsageInput],
+    used_date: &str,
+    user_id: &str,
+) -> ApiResult<()> {
+    if let Some(summary) = spare_parts_handlers::consume_parts(
+        conn, equipment_id, Some(maintenance_id), parts, used_date, user_id,
+    ).await? {
+        sqlx::query(
+            "UPDATE equipment_maintenance SET parts_replaced = COALESCE(parts_replaced, ?) WHERE id = ?"
+        )
+            .bind(summary.chars().take(1000).collect::<String>())
+            .bind(maintenance_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
 /// Удаление записи об обслуживании
 pub async fn delete_maintenance(
     app_state: web::Data<Arc<AppState>>,
@@ -802,18 +886,34 @@ pub async fn delete_maintenance(
 
     check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
 
+    let mut tx = app_state.db_pool.begin().await?;
+
+    // Калибровка, выполненная в рамках обслуживания, держит запись (ON DELETE RESTRICT)
+    let calibrations: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM calibration_results WHERE maintenance_id = ?")
+        .bind(&maintenance_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if calibrations > 0 {
+        return Err(ApiError::bad_request(
+            "Maintenance record has calibration results and cannot be deleted"
+        ));
+    }
+
     let result = sqlx::query(
         "DELETE FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
     )
         .bind(&maintenance_id)
         .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
 
     if result.rows_affected() == 0 {
         return Err(ApiError::not_found("Maintenance record"));
     }
 
+    maintenance_handlers::refresh_equipment_schedule(&mut tx, &equipment_id).await?;
+    tx.commit().await?;
+
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
         (),
         "Maintenance record deleted successfully".to_string(),
@@ -836,11 +936,9 @@ pub async fn get_equipment_files(
     Ok(HttpResponse::Ok().json(ApiResponse::success(files)))
 }
 
-/// Загрузка файла для оборудования с древовидной структурой папок
-/// Структура: 
-///   uploads/equipment/{equipment_name}/images/       - фото оборудования
-///   uploads/equipment/{equipment_name}/manuals/      - мануалы
-///   uploads/equipment/{equipment_name}/parts/{part_name}/images/ - фото запчасти
+/// Загрузка файла оборудования (или его запчасти).
+/// Содержимое сохраняется в хранилище по SHA-256, поэтому расположение не
+/// зависит от названия оборудования, а одинаковые файлы хранятся один раз.
 pub async fn upload_equipment_file(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
@@ -864,6 +962,7 @@ pub async fn upload_equipment_file(
     let mut form_file_type: Option<String> = None;
     let mut form_description: Option<String> = None;
     let mut form_part_id: Option<String> = None;
+    let mut form_contract_id: Option<String> = None;
 
     // Читаем все поля формы
     while let Some(item) = payload.next().await {
@@ -909,9 +1008,10 @@ pub async fn upload_equipment_file(
                 }
                 if let Ok(value) = String::from_utf8(bytes) {
                     let value = value.trim().to_string();
-                    let valid_types = ["manual", "image", "certificate", "specification", "maintenance_log", "other"];
+                    let valid_types = ["manual", "photo", "image", "certificate", "specification", "maintenance_log", "other"];
                     if valid_types.contains(&value.as_str()) {
-                        form_file_type = Some(value);
+                        // 'image' — старое название типа 'photo'
+                        form_file_type = Some(if value == "image" { "photo".to_string() } else { value });
                     }
                 }
             }
@@ -941,6 +1041,19 @@ pub async fn upload_equipment_file(
                     }
                 }
             }
+            "contract_id" => {
+                let mut bytes = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    bytes.extend_from_slice(&chunk);
+                }
+                if let Ok(value) = String::from_utf8(bytes) {
+                    let value = value.trim().to_string();
+                    if !value.is_empty() {
+                        form_contract_id = Some(value);
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -957,60 +1070,47 @@ pub async fn upload_equipment_file(
         }
     });
 
-    // Создаём древовидную структуру папок
-    let sanitized_equip_name = sanitize_folder_name(&equipment.name);
-    let type_folder = get_type_folder(&file_type);
-
-    let file_path = if let Some(ref part_id) = form_part_id {
-        // Получаем имя запчасти
-        let part: EquipmentPart = sqlx::query_as(
-            "SELECT * FROM equipment_parts WHERE id = ? AND equipment_id = ?"
+    if let Some(ref part_id) = form_part_id {
+        let part_exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM equipment_parts WHERE id = ? AND equipment_id = ?)"
         )
             .bind(part_id)
             .bind(&equipment_id)
-            .fetch_optional(&app_state.db_pool)
-            .await?
-            .ok_or_else(|| ApiError::not_found("Part"))?;
-
-        let sanitized_part_name = sanitize_folder_name(&part.name);
-
-        // Структура: equipment/{equip_name}/parts/{part_name}/{type}/
-        let type_dir = get_equipment_files_dir()
-            .join(&sanitized_equip_name)
-            .join("parts")
-            .join(&sanitized_part_name)
-            .join(type_folder);
+            .fetch_one(&app_state.db_pool)
+            .await?;
+        if !part_exists {
+            return Err(ApiError::not_found("Part"));
+        }
+    }
 
-        std::fs::create_dir_all(&type_dir)
-            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
+    if let Some(ref contract_id) = form_contract_id {
+        let contract_exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM service_contracts WHERE id = ? AND equipment_id = ?)"
+        )
+            .bind(contract_id)
+            .bind(&equipment_id)
+            .fetch_one(&app_state.db_pool)
+            .await?;
+        if !contract_exists {
+            return Err(ApiError::not_found("Service contract"));
+        }
+    }
 
-        let unique_filename = generate_unique_filename(&original_filename);
-        type_dir.join(&unique_filename).to_string_lossy().to_string()
+    // Фото: убираем EXIF, считаем размеры и готовим превью (CPU — вне async-потока)
+    let photo = if file_type == "photo" && ALLOWED_IMAGE_TYPES.contains(&content_type.as_str()) {
+        let bytes = file_bytes.clone();
+        Some(web::block(move || thumbnails::process_photo(&bytes)).await
+            .map_err(|e| ApiError::InternalServerError(format!("Image processing failed: {}", e)))??)
     } else {
-        // Структура: equipment/{equip_name}/{type}/
-        let type_dir = get_equipment_files_dir()
-            .join(&sanitized_equip_name)
-            .join(type_folder);
-
-        std::fs::create_dir_all(&type_dir)
-            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
-
-        let unique_filename = generate_unique_filename(&original_filename);
-        type_dir.join(&unique_filename).to_string_lossy().to_string()
+        None
     };
+    let stored_bytes = photo.as_ref().and_then(|p| p.stripped.as_deref()).unwrap_or(&file_bytes);
 
-    // Извлекаем stored_filename из полного пути
-    let stored_filename = std::path::Path::new(&file_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&original_filename)
-        .to_string();
-
-    // Сохраняем файл
-    let mut f = std::fs::File::create(&file_path)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to create file: {}", e)))?;
-    f.write_all(&file_bytes)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to write file: {}", e)))?;
+    let blob = app_state.storage.store(stored_bytes).await?;
+    if blob.deduplicated {
+        log::info!("Upload '{}' for equipment {} deduplicated as {}", original_filename, equipment.id, blob.key);
+    }
+    let stored_filename = generate_unique_filename(&original_filename);
 
     // Сохраняем в БД
     let id = Uuid::new_v4().to_string();
@@ -1018,24 +1118,39 @@ pub async fn upload_equipment_file(
 
     sqlx::query(
         r#"INSERT INTO equipment_files
-           (id, equipment_id, part_id, file_type, original_filename, stored_filename,
-            file_path, file_size, mime_type, description, uploaded_by, created_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+           (id, equipment_id, part_id, contract_id, file_type, original_filename, stored_filename,
+            file_path, file_size, mime_type, description, storage_key, sha256,
+            image_width, image_height, orientation, exif_orientation, uploaded_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
         .bind(&equipment_id)
         .bind(&form_part_id)
+        .bind(&form_contract_id)
         .bind(&file_type)
         .bind(&original_filename)
         .bind(&stored_filename)
-        .bind(&file_path)
-        .bind(file_bytes.len() as i64)
+        .bind(&blob.key)
+        .bind(blob.size)
         .bind(&content_type)
         .bind(&form_description)
+        .bind(&blob.key)
+        .bind(&blob.sha256)
+        .bind(photo.as_ref().map(|p| p.width as i64))
+        .bind(photo.as_ref().map(|p| p.height as i64))
+        .bind(photo.as_ref().map(|p| p.orientation()))
+        .bind(photo.as_ref().map(|p| p.exif_orientation as i64))
         .bind(&user_id)
         .bind(&now)
         .execute(&app_state.db_pool)
         .await?;
+    app_state.storage.confirm(&blob, stored_bytes).await?;
+
+    if let Some(ref photo) = photo {
+        for thumbnail in &photo.thumbnails {
+            save_thumbnail(&app_state.db_pool, &app_state.storage, &id, thumbnail).await?;
+        }
+    }
 
     let created: EquipmentFile = sqlx::query_as(
         "SELECT * FROM equipment_files WHERE id = ?"
@@ -1047,54 +1162,163 @@ pub async fn upload_equipment_file(
     Ok(HttpResponse::Created().json(ApiResponse::success(created)))
 }
 
-/// Очистка имени папки от спецсимволов
-fn sanitize_folder_name(name: &str) -> String {
-    name.chars()
-        .map(|c| {
-            if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
-                c
-            } else {
-                '_'
-            }
-        })
-        .collect::<String>()
-        .trim()
-        .replace(' ', "_")
-        .to_lowercase()
-}
-
-/// Получение имени папки для типа файла
-fn get_type_folder(file_type: &str) -> &'static str {
-    match file_type {
-        "photo" => "images",  // Changed from "image" to match DB constraint
-        "manual" => "manuals",
-        "certificate" => "certificates",
-        "specification" => "specifications",
-        "maintenance_log" => "maintenance_logs",
-        _ => "other"
-    }
+/// Скачивание файла оборудования
+#[derive(Debug, serde::Deserialize)]
+pub struct FileDownloadQuery {
+    /// thumb | small | medium; без параметра — оригинал
+    pub size: Option<ThumbnailSize>,
 }
 
-/// Скачивание файла оборудования
 pub async fn download_equipment_file(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
+    query: web::Query<FileDownloadQuery>,
+    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let (equipment_id, file_id) = path.into_inner();
+    let claims = auth::get_current_user(&http_request)?;
 
-    let file: Option<EquipmentFile> = sqlx::query_as(
+    let file = load_equipment_file(&app_state.db_pool, &equipment_id, &file_id).await?;
+
+    let desc = match query.size {
+        Some(size) => format!("Downloaded {} preview of file '{}' of equipment {}", size.as_str(), file.original_filename, equipment_id),
+        None => format!("Downloaded file '{}' of equipment {}", file.original_filename, equipment_id),
+    };
+    audit::audit(&app_state.db_pool, &claims.sub, "download", "equipment_file", &file_id, &desc, &http_request).await;
+
+    file_response(&app_state.db_pool, &app_state.storage, file, query.size, "private, max-age=3600").await
+}
+
+pub async fn load_equipment_file(pool: &SqlitePool, equipment_id: &str, file_id: &str) -> ApiResult<EquipmentFile> {
+    sqlx::query_as::<_, EquipmentFile>(
         "SELECT * FROM equipment_files WHERE id = ? AND equipment_id = ?"
     )
-        .bind(&file_id)
-        .bind(&equipment_id)
-        .fetch_optional(&app_state.db_pool)
+        .bind(file_id)
+        .bind(equipment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("File"))
+}
+
+/// Прочитать содержимое файла: из хранилища с проверкой хеша, либо с диска
+/// для файлов, загруженных до перехода на хранилище
+async fn read_equipment_file(storage: &BlobStore, file: &EquipmentFile) -> ApiResult<Vec<u8>> {
+    match (&file.storage_key, &file.sha256) {
+        (Some(key), Some(sha256)) => storage.load(key, sha256).await,
+        _ => tokio::fs::read(&file.file_path).await
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {}", e))),
+    }
+}
+
+/// Удалить запись файла вместе с превью и освободить содержимое. Блоб
+/// удаляется, только если на него не ссылаются другие записи (дедупликация).
+async fn delete_equipment_file_record(pool: &SqlitePool, storage: &BlobStore, file: &EquipmentFile) -> ApiResult<()> {
+    let mut tx = pool.begin().await?;
+    let mut keys: Vec<String> = sqlx::query_scalar("SELECT storage_key FROM equipment_file_thumbnails WHERE file_id = ?")
+        .bind(&file.id)
+        .fetch_all(&mut *tx)
         .await?;
 
-    let file = file.ok_or_else(|| ApiError::not_found("File"))?;
+    sqlx::query("DELETE FROM equipment_file_thumbnails WHERE file_id = ?")
+        .bind(&file.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM equipment_files WHERE id = ?")
+        .bind(&file.id)
+        .execute(&mut *tx)
+        .await?;
+
+    match file.storage_key {
+        Some(ref key) => keys.push(key.clone()),
+        None => {
+            let _ = std::fs::remove_file(&file.file_path);
+        }
+    }
+
+    for key in keys {
+        storage.release(&mut tx, &key).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
 
-    // Читаем файл
-    let contents = std::fs::read(&file.file_path)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {}", e)))?;
+#[derive(sqlx::FromRow)]
+struct StoredThumbnail {
+    storage_key: String,
+    sha256: String,
+    mime_type: String,
+}
+
+async fn save_thumbnail(pool: &SqlitePool, storage: &BlobStore, file_id: &str, thumbnail: &Thumbnail) -> ApiResult<()> {
+    let blob = storage.store(&thumbnail.bytes).await?;
+    sqlx::query(
+        r#"INSERT OR REPLACE INTO equipment_file_thumbnails
+           (file_id, size, storage_key, sha256, width, height, mime_type, file_size, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(file_id)
+        .bind(thumbnail.size.as_str())
+        .bind(&blob.key)
+        .bind(&blob.sha256)
+        .bind(thumbnail.width as i64)
+        .bind(thumbnail.height as i64)
+        .bind(thumbnail.mime_type)
+        .bind(blob.size)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    storage.confirm(&blob, &thumbnail.bytes).await?;
+    Ok(())
+}
+
+/// Превью из кэша; для фото, загруженных до появления превью, создаётся при первом запросе
+async fn load_thumbnail(
+    pool: &SqlitePool,
+    storage: &BlobStore,
+    file: &EquipmentFile,
+    size: ThumbnailSize,
+) -> ApiResult<(Vec<u8>, String)> {
+    if !file.mime_type.starts_with("image/") {
+        return Err(ApiError::bad_request("Previews are only available for images"));
+    }
+
+    let cached: Option<StoredThumbnail> = sqlx::query_as(
+        "SELECT storage_key, sha256, mime_type FROM equipment_file_thumbnails WHERE file_id = ? AND size = ?"
+    )
+        .bind(&file.id)
+        .bind(size.as_str())
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some(thumb) = cached {
+        return Ok((storage.load(&thumb.storage_key, &thumb.sha256).await?, thumb.mime_type));
+    }
+
+    let original = read_equipment_file(storage, file).await?;
+    let thumbnail = web::block(move || thumbnails::thumbnail_from_bytes(&original, size)).await
+        .map_err(|e| ApiError::InternalServerError(format!("Image processing failed: {}", e)))??;
+    save_thumbnail(pool, storage, &file.id, &thumbnail).await?;
+    Ok((thumbnail.bytes, thumbnail.mime_type.to_string()))
+}
+
+/// Отдать содержимое файла (или его превью) с нужными заголовками
+pub async fn file_response(
+    pool: &SqlitePool,
+    storage: &BlobStore,
+    file: EquipmentFile,
+    size: Option<ThumbnailSize>,
+    cache_control: &str,
+) -> ApiResult<HttpResponse> {
+    if let Some(size) = size {
+        let (contents, mime_type) = load_thumbnail(pool, storage, &file, size).await?;
+        return Ok(HttpResponse::Ok()
+            .content_type(mime_type)
+            .insert_header(("Content-Disposition", format!("inline; filename=\"{}-{}\"", size.as_str(), file.original_filename)))
+            .insert_header(("Cache-Control", cache_control.to_string()))
+            .body(contents));
+    }
+
+    let contents = read_equipment_file(storage, &file).await?;
 
     // Определяем Content-Disposition: inline для изображений, attachment для остальных
     let disposition = if file.mime_type.starts_with("image/") {
@@ -1106,7 +1330,7 @@ pub async fn download_equipment_file(
     Ok(HttpResponse::Ok()
         .content_type(file.mime_type)
         .insert_header(("Content-Disposition", disposition))
-        .insert_header(("Cache-Control", "public, max-age=3600"))
+        .insert_header(("Cache-Control", cache_control.to_string()))
         .body(contents))
 }
 
@@ -1128,14 +1352,7 @@ pub async fn delete_equipment_file(
 
     let file = file.ok_or_else(|| ApiError::not_found("File"))?;
 
-    // Удаляем файл с диска
-    let _ = std::fs::remove_file(&file.file_path);
-
-    // Удаляем из БД
-    sqlx::query("DELETE FROM equipment_files WHERE id = ?")
-        .bind(&file_id)
-        .execute(&app_state.db_pool)
-        .await?;
+    delete_equipment_file_record(&app_state.db_pool, &app_state.storage, &file).await?;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
         (),
@@ -1145,58 +1362,46 @@ pub async fn delete_equipment_file(
 
 // ==================== ПОИСК ====================
 
-/// Полнотекстовый поиск по оборудованию
+/// Полнотекстовый поиск по оборудованию (bm25 + подсветка, LIKE без FTS)
 pub async fn search_equipment(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<SearchQuery>,
 ) -> ApiResult<HttpResponse> {
+    let hits: Vec<FtsHit<Equipment>> = ranked_search(
+        &app_state.db_pool, &FtsConfig::for_equipment(), &query, "e.name",
+    ).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(hits)))
+}
+
+/// Полнотекстовый поиск по запчастям всего оборудования
+pub async fn search_equipment_parts(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<SearchQuery>,
+) -> ApiResult<HttpResponse> {
+    let hits: Vec<FtsHit<EquipmentPart>> = ranked_search(
+        &app_state.db_pool, &FtsConfig::for_equipment_parts(), &query, "e.name",
+    ).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(hits)))
+}
+
+/// Общий ранжированный поиск по таблице из FtsConfig
+async fn ranked_search<T>(
+    pool: &SqlitePool,
+    config: &FtsConfig,
+    query: &SearchQuery,
+    fallback_order: &str,
+) -> ApiResult<Vec<FtsHit<T>>>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> + Send + Unpin,
+{
     let search_term = query.q.as_deref().unwrap_or("").trim();
 
     if search_term.is_empty() {
         return Err(ApiError::bad_request("Search query cannot be empty"));
     }
 
-    let limit = query.limit.unwrap_or(20).min(100);
-
-    // Проверяем доступность FTS
-    let fts_available: bool = sqlx::query_scalar(
-        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type='table' AND name='equipment_fts'"
-    )
-        .fetch_one(&app_state.db_pool)
-        .await
-        .unwrap_or(false);
-
-    let equipment: Vec<Equipment> = if fts_available {
-        // FTS поиск
-        let escaped_term = search_term.replace("\"", "\"\"");
-        let sql = format!(
-            r#"SELECT e.* FROM equipment e
-               JOIN equipment_fts f ON e.id = f.equipment_id
-               WHERE equipment_fts MATCH '"{}"'
-               ORDER BY rank
-               LIMIT ?"#,
-            escaped_term
-        );
-
-        sqlx::query_as::<_, Equipment>(&sql)
-            .bind(limit)
-            .fetch_all(&app_state.db_pool)
-            .await?
-    } else {
-        // Fallback на LIKE
-        let pattern = format!("%{}%", search_term);
-        sqlx::query_as::<_, Equipment>(
-            "SELECT * FROM equipment WHERE name LIKE ? OR description LIKE ? OR location LIKE ? ORDER BY name LIMIT ?"
-        )
-            .bind(&pattern)
-            .bind(&pattern)
-            .bind(&pattern)
-            .bind(limit)
-            .fetch_all(&app_state.db_pool)
-            .await?
-    };
-
-    Ok(HttpResponse::Ok().json(ApiResponse::success(equipment)))
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    Ok(FtsQueryBuilder::search_ranked(pool, config, search_term, limit, fallback_order).await?)
 }
 
 // ==================== ВСПОМОГАТЕЛЬНЫЕ ФУНКЦИИ ====================
@@ -0,0 +1,348 @@
+// src/equipment_hierarchy.rs
+//! Иерархия оборудования: система → модули → (модули модулей).
+//!
+//! Модуль (насос, автосэмплер, детектор) в каждый момент установлен не более
+//! чем в одну систему. Перемещение закрывает текущую связь и открывает новую
+//! той же датой, так что `equipment_links` хранит полную историю установки.
+//! Состояние ТО и калибровки модулей сворачивается в сводный статус системы.
+//!
+//! Endpoints:
+//!   GET  /api/v1/equipment/{id}/hierarchy                  — дерево и сводный статус
+//!   GET  /api/v1/equipment/{id}/modules/history            — история связей (как системы и как модуля)
+//!   POST /api/v1/equipment/{id}/modules                    — установить/переместить модуль
+//!   POST /api/v1/equipment/{id}/modules/{child_id}/detach  — снять модуль
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    AttachModuleRequest, DetachModuleRequest, EquipmentHierarchyResponse, EquipmentLink,
+    EquipmentStatusRollup, EquipmentTreeNode,
+};
+
+const LINK_SELECT: &str = r#"
+    SELECT l.id, l.parent_id, p.name as parent_name, l.child_id, c.name as child_name,
+           l.slot, l.linked_at, l.unlinked_at, l.linked_by, l.unlinked_by, l.notes
+    FROM equipment_links l
+    JOIN equipment p ON l.parent_id = p.id
+    JOIN equipment c ON l.child_id = c.id
+"#;
+
+/// Насколько статус ограничивает работу системы (0 — не ограничивает)
+fn status_severity(status: &str) -> u8 {
+    match status {
+        "damaged" => 3,
+        "calibration" => 2,
+        "maintenance" => 1,
+        _ => 0,
+    }
+}
+
+/// Свернуть состояние дерева (первый узел — корень) в сводный статус
+pub fn rollup_status(tree: &[EquipmentTreeNode]) -> EquipmentStatusRollup {
+    let root_status = tree.first().map(|n| n.status.clone()).unwrap_or_default();
+    let worst = tree.iter().map(|n| status_severity(&n.status)).max().unwrap_or(0);
+
+    let (status, affected) = if worst == 0 {
+        (root_status, Vec::new())
+    } else {
+        let affected: Vec<&EquipmentTreeNode> = tree.iter()
+            .filter(|n| status_severity(&n.status) == worst)
+            .collect();
+        (affected[0].status.clone(), affected.iter().map(|n| n.id.clone()).collect())
+    };
+
+    EquipmentStatusRollup {
+        status,
+        affected,
+        overdue_maintenance: tree.iter().map(|n| n.overdue_maintenance).sum(),
+        overdue_calibrations: tree.iter().map(|n| n.overdue_calibrations).sum(),
+        failed_calibrations: tree.iter().filter(|n| n.last_calibration_passed == Some(false)).count() as i64,
+        next_maintenance_date: tree.iter().filter_map(|n| n.next_maintenance_date.clone()).min(),
+    }
+}
+
+/// Система и все установленные в неё модули (по текущим связям)
+async fn load_tree(conn: &mut SqliteConnection, equipment_id: &str) -> ApiResult<Vec<EquipmentTreeNode>> {
+    // path защищает от зацикливания, даже если в данные попал цикл
+    let tree: Vec<EquipmentTreeNode> = sqlx::query_as(r#"
+        WITH RECURSIVE tree(id, parent_id, slot, linked_at, depth, path) AS (
+            SELECT ?, NULL, NULL, NULL, 0, '/' || ? || '/'
+            UNION ALL
+            SELECT l.child_id, l.parent_id, l.slot, l.linked_at, t.depth + 1, t.path || l.child_id || '/'
+            FROM equipment_links l
+            JOIN tree t ON l.parent_id = t.id
+            WHERE l.unlinked_at IS NULL AND instr(t.path, '/' || l.child_id || '/') = 0
+        )
+        SELECT e.id, e.name, e.status, e.serial_number, e.model,
+               t.parent_id, t.slot, t.linked_at, t.depth,
+               (SELECT COUNT(*) FROM equipment_maintenance m
+                WHERE m.equipment_id = e.id AND m.status IN ('scheduled', 'in_progress')
+                  AND date(m.scheduled_date) < date('now')) as overdue_maintenance,
+               (SELECT COUNT(*) FROM equipment_maintenance m
+                WHERE m.equipment_id = e.id AND m.status IN ('scheduled', 'in_progress')
+                  AND m.maintenance_type = 'calibration'
+                  AND date(m.scheduled_date) < date('now')) as overdue_calibrations,
+               (SELECT MIN(date(m.scheduled_date)) FROM equipment_maintenance m
+                WHERE m.equipment_id = e.id AND m.status IN ('scheduled', 'in_progress')) as next_maintenance_date,
+               (SELECT r.passed FROM calibration_results r
+                WHERE r.equipment_id = e.id
+                ORDER BY r.performed_at DESC, r.created_at DESC LIMIT 1) as last_calibration_passed
+        FROM tree t
+        JOIN equipment e ON e.id = t.id
+        ORDER BY t.depth, t.path
+    "#)
+        .bind(equipment_id)
+        .bind(equipment_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    if tree.is_empty() {
+        return Err(ApiError::not_found("Equipment"));
+    }
+    Ok(tree)
+}
+
+async fn current_parent_link(conn: &mut SqliteConnection, child_id: &str) -> ApiResult<Option<EquipmentLink>> {
+    Ok(sqlx::query_as(&format!("{} WHERE l.child_id = ? AND l.unlinked_at IS NULL", LINK_SELECT))
+        .bind(child_id)
+        .fetch_optional(&mut *conn)
+        .await?)
+}
+
+async fn get_link(conn: &mut SqliteConnection, link_id: &str) -> ApiResult<EquipmentLink> {
+    sqlx::query_as(&format!("{} WHERE l.id = ?", LINK_SELECT))
+        .bind(link_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Equipment link"))
+}
+
+async fn equipment_status(conn: &mut SqliteConnection, equipment_id: &str, entity: &str) -> ApiResult<String> {
+    sqlx::query_scalar("SELECT status FROM equipment WHERE id = ?")
+        .bind(equipment_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found(entity))
+}
+
+fn resolve_date(value: Option<DateTime<Utc>>) -> ApiResult<DateTime<Utc>> {
+    let now = Utc::now();
+    match value {
+        Some(date) if date > now => Err(ApiError::bad_request("Date cannot be in the future")),
+        Some(date) => Ok(date),
+        None => Ok(now),
+    }
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_equipment_hierarchy(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    let mut conn = app_state.db_pool.acquire().await?;
+
+    let tree = load_tree(&mut conn, &equipment_id).await?;
+    let parent = current_parent_link(&mut conn, &equipment_id).await?;
+    let rollup = rollup_status(&tree);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(EquipmentHierarchyResponse {
+        equipment_id,
+        parent,
+        tree,
+        rollup,
+    })))
+}
+
+/// История связей, где оборудование было системой или модулем
+pub async fn get_module_history(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    let mut conn = app_state.db_pool.acquire().await?;
+    equipment_status(&mut conn, &equipment_id, "Equipment").await?;
+
+    let history: Vec<EquipmentLink> = sqlx::query_as(&format!(
+        "{} WHERE l.parent_id = ? OR l.child_id = ? ORDER BY l.linked_at DESC, l.unlinked_at DESC",
+        LINK_SELECT
+    ))
+        .bind(&equipment_id)
+        .bind(&equipment_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(history)))
+}
+
+/// Установить модуль в систему. Если модуль стоит в другой системе — это перемещение.
+pub async fn attach_module(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AttachModuleRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let parent_id = path.into_inner();
+    let child_id = body.child_id.trim().to_string();
+    let linked_at = resolve_date(body.linked_at)?;
+
+    if parent_id == child_id {
+        return Err(ApiError::bad_request("Equipment cannot be a module of itself"));
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    if equipment_status(&mut tx, &parent_id, "Equipment").await? == "retired" {
+        return Err(ApiError::bad_request("Cannot install modules into retired equipment"));
+    }
+    if equipment_status(&mut tx, &child_id, "Module").await? == "retired" {
+        return Err(ApiError::bad_request("Retired equipment cannot be installed as a module"));
+    }
+
+    // Модуль не может оказаться выше системы в её собственном дереве
+    let creates_cycle: bool = sqlx::query_scalar(r#"
+        WITH RECURSIVE ancestors(id) AS (
+            SELECT ?
+            UNION
+            SELECT l.parent_id FROM equipment_links l
+            JOIN ancestors a ON l.child_id = a.id
+            WHERE l.unlinked_at IS NULL
+        )
+        SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?)
+    "#)
+        .bind(&parent_id)
+        .bind(&child_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if creates_cycle {
+        return Err(ApiError::bad_request("Module already contains this equipment; links cannot form a cycle"));
+    }
+
+    let previous = current_parent_link(&mut tx, &child_id).await?;
+    if let Some(ref link) = previous {
+        if link.parent_id == parent_id {
+            return Err(ApiError::bad_request("Module is already installed in this equipment"));
+        }
+        if linked_at < link.linked_at {
+            return Err(ApiError::bad_request("Move date is earlier than the module's current installation"));
+        }
+        sqlx::query("UPDATE equipment_links SET unlinked_at = ?, unlinked_by = ? WHERE id = ?")
+            .bind(linked_at)
+            .bind(&user_id)
+            .bind(&link.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let link_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO equipment_links (id, parent_id, child_id, slot, linked_at, linked_by, notes)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&link_id)
+        .bind(&parent_id)
+        .bind(&child_id)
+        .bind(body.slot.as_deref().map(str::trim).filter(|s| !s.is_empty()))
+        .bind(linked_at)
+        .bind(&user_id)
+        .bind(&body.notes)
+        .execute(&mut *tx)
+        .await?;
+
+    let link = get_link(&mut tx, &link_id).await?;
+    tx.commit().await?;
+
+    let message = match previous {
+        Some(prev) => format!("Module '{}' moved from '{}' to '{}'", link.child_name, prev.parent_name, link.parent_name),
+        None => format!("Module '{}' installed into '{}'", link.child_name, link.parent_name),
+    };
+    Ok(HttpResponse::Created().json(ApiResponse::success_with_message(link, message)))
+}
+
+pub async fn detach_module(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<DetachModuleRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (parent_id, child_id) = path.into_inner();
+    let unlinked_at = resolve_date(body.unlinked_at)?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    let link = current_parent_link(&mut tx, &child_id).await?
+        .filter(|l| l.parent_id == parent_id)
+        .ok_or_else(|| ApiError::not_found("Installed module"))?;
+    if unlinked_at < link.linked_at {
+        return Err(ApiError::bad_request("Removal date is earlier than the installation date"));
+    }
+
+    sqlx::query(
+        "UPDATE equipment_links SET unlinked_at = ?, unlinked_by = ?, notes = COALESCE(?, notes) WHERE id = ?"
+    )
+        .bind(unlinked_at)
+        .bind(&user_id)
+        .bind(&body.notes)
+        .bind(&link.id)
+        .execute(&mut *tx)
+        .await?;
+
+    let link = get_link(&mut tx, &link.id).await?;
+    tx.commit().await?;
+
+    let message = format!("Module '{}' removed from '{}'", link.child_name, link.parent_name);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(link, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, status: &str, overdue: i64, passed: Option<bool>, next: Option<&str>) -> EquipmentTreeNode {
+        EquipmentTreeNode {
+            id: id.to_string(),
+            name: id.to_string(),
+            status: status.to_string(),
+            serial_number: None,
+            model: None,
+            parent_id: None,
+            slot: None,
+            linked_at: None,
+            depth: 0,
+            overdue_maintenance: overdue,
+            overdue_calibrations: 0,
+            next_maintenance_date: next.map(str::to_string),
+            last_calibration_passed: passed,
+        }
+    }
+
+    #[test]
+    fn test_rollup_takes_worst_module_status() {
+        let tree = vec![
+            node("hplc", "in_use", 0, Some(true), Some("2025-03-01")),
+            node("pump", "maintenance", 1, None, Some("2025-01-10")),
+            node("detector", "calibration", 0, Some(false), None),
+        ];
+        let rollup = rollup_status(&tree);
+        assert_eq!(rollup.status, "calibration");
+        assert_eq!(rollup.affected, vec!["detector".to_string()]);
+        assert_eq!(rollup.overdue_maintenance, 1);
+        assert_eq!(rollup.failed_calibrations, 1);
+        assert_eq!(rollup.next_maintenance_date.as_deref(), Some("2025-01-10"));
+
+        let healthy = rollup_status(&tree[..1]);
+        assert_eq!(healthy.status, "in_use");
+        assert!(healthy.affected.is_empty());
+    }
+}
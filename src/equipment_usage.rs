@@ -0,0 +1,389 @@
+// src/equipment_usage.rs
+//! Журнал использования приборов (check-out / check-in).
+//!
+//! Выдача переводит прибор в 'in_use' и открывает сеанс, возврат закрывает
+//! сеанс с числом прогонов и возвращает 'available'. На приборе может быть
+//! только один открытый сеанс. Планы обслуживания с `interval_runs` считают
+//! прогоны с последнего выполнения; когда лимит набран, открытая запись плана
+//! переносится на сегодня и дальше работает обычный механизм сроков ТО.
+//!
+//! Endpoints:
+//!   GET  /api/v1/equipment/usage/active
+//!   GET  /api/v1/equipment/maintenance/run-based?due_only=
+//!   GET  /api/v1/equipment/{id}/usage?from=&to=&user_id=&limit=
+//!   POST /api/v1/equipment/{id}/checkout
+//!   POST /api/v1/equipment/{id}/checkin
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::SqliteConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::maintenance_handlers::{apply_due_maintenance_statuses, parse_day, refresh_equipment_schedule};
+use crate::models::{
+    CheckInEquipmentRequest, CheckOutEquipmentRequest, EquipmentUsageSession, RunBasedMaintenance,
+    UsageLogQuery,
+};
+
+const DEFAULT_USAGE_LIMIT: i64 = 200;
+
+const SESSION_SELECT: &str = r#"
+    SELECT s.id, s.equipment_id, e.name as equipment_name, s.user_id, u.username,
+           s.experiment_id, x.title as experiment_title, s.purpose, s.started_at, s.ended_at,
+           CASE WHEN s.ended_at IS NULL THEN NULL
+                ELSE CAST(ROUND((julianday(s.ended_at) - julianday(s.started_at)) * 1440) AS INTEGER)
+           END as duration_minutes,
+           s.run_count, s.notes, s.checked_in_by
+    FROM equipment_usage_log s
+    JOIN equipment e ON s.equipment_id = e.id
+    LEFT JOIN users u ON s.user_id = u.id
+    LEFT JOIN experiments x ON s.experiment_id = x.id
+"#;
+
+/// Прогоны считаются с момента закрытия последней выполненной записи плана
+/// (или с создания плана, если выполнений ещё не было)
+const RUN_PLANS_SELECT: &str = r#"
+    SELECT *, runs_since_maintenance >= interval_runs as is_due FROM (
+        SELECT p.id as plan_id, p.name as plan_name, p.maintenance_type, p.equipment_id,
+               e.name as equipment_name, p.interval_runs, p.last_completed_date, p.next_due_date,
+               COALESCE((
+                   SELECT SUM(s.run_count) FROM equipment_usage_log s
+                   WHERE s.equipment_id = p.equipment_id AND s.ended_at IS NOT NULL
+                     AND julianday(s.ended_at) > julianday(COALESCE((
+                         SELECT MAX(m.updated_at) FROM equipment_maintenance m
+                         WHERE m.plan_id = p.id AND m.status = 'completed'
+                     ), p.created_at))
+               ), 0) as runs_since_maintenance
+        FROM equipment_maintenance_plans p
+        JOIN equipment e ON p.equipment_id = e.id
+        WHERE p.is_active = 1 AND p.interval_runs IS NOT NULL
+    )
+"#;
+
+#[derive(Debug, Deserialize)]
+pub struct RunBasedMaintenanceQuery {
+    pub due_only: Option<bool>,
+}
+
+/// Период отчёта [from, to] (включительно); по умолчанию — последние `default_days` дней
+pub fn period_bounds(from: Option<&str>, to: Option<&str>, default_days: i64) -> ApiResult<(NaiveDate, NaiveDate)> {
+    let to = match to {
+        Some(d) => parse_day(d)?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match from {
+        Some(d) => parse_day(d)?,
+        None => to - Duration::days(default_days - 1),
+    };
+    if from > to {
+        return Err(ApiError::bad_request("'from' must not be later than 'to'"));
+    }
+    if (to - from).num_days() > 366 * 5 {
+        return Err(ApiError::bad_request("Period cannot exceed 5 years"));
+    }
+    Ok((from, to))
+}
+
+fn resolve_time(value: Option<DateTime<Utc>>) -> ApiResult<DateTime<Utc>> {
+    let now = Utc::now();
+    match value {
+        // Небольшой допуск на расхождение часов клиента
+        Some(t) if t > now + Duration::minutes(5) => Err(ApiError::bad_request("Time cannot be in the future")),
+        Some(t) => Ok(t),
+        None => Ok(now),
+    }
+}
+
+async fn get_session(conn: &mut SqliteConnection, session_id: &str) -> ApiResult<EquipmentUsageSession> {
+    sqlx::query_as(&format!("{} WHERE s.id = ?", SESSION_SELECT))
+        .bind(session_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Usage session"))
+}
+
+/// Перенести на сегодня открытые записи планов, у которых набран лимит прогонов.
+/// Возвращает сработавшие планы.
+pub async fn flag_run_based_maintenance(conn: &mut SqliteConnection, equipment_id: &str) -> ApiResult<Vec<RunBasedMaintenance>> {
+    let due: Vec<RunBasedMaintenance> = sqlx::query_as(&format!(
+        "{} WHERE equipment_id = ? AND is_due", RUN_PLANS_SELECT
+    ))
+        .bind(equipment_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    if due.is_empty() {
+        return Ok(due);
+    }
+
+    let today = Utc::now().date_naive().format("%Y-%m-%d").to_string();
+    let now = Utc::now();
+    for plan in &due {
+        sqlx::query(
+            r#"UPDATE equipment_maintenance SET scheduled_date = ?, updated_at = ?
+               WHERE plan_id = ? AND status = 'scheduled' AND date(scheduled_date) > date(?)"#
+        )
+            .bind(&today)
+            .bind(now)
+            .bind(&plan.plan_id)
+            .bind(&today)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            "UPDATE equipment_maintenance_plans SET next_due_date = ?, updated_at = ? WHERE id = ? AND (next_due_date IS NULL OR next_due_date > ?)"
+        )
+            .bind(&today)
+            .bind(now)
+            .bind(&plan.plan_id)
+            .bind(&today)
+            .execute(&mut *conn)
+            .await?;
+        info!("Maintenance plan '{}' of equipment {} is due by run count ({}/{})",
+            plan.plan_name, equipment_id, plan.runs_since_maintenance, plan.interval_runs);
+    }
+
+    refresh_equipment_schedule(conn, equipment_id).await?;
+    Ok(due)
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn check_out_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CheckOutEquipmentRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let equipment_id = path.into_inner();
+    let started_at = resolve_time(body.started_at)?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    let status: String = sqlx::query_scalar("SELECT status FROM equipment WHERE id = ?")
+        .bind(&equipment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Equipment"))?;
+
+    let open: Option<String> = sqlx::query_scalar(
+        "SELECT u.username FROM equipment_usage_log s LEFT JOIN users u ON s.user_id = u.id WHERE s.equipment_id = ? AND s.ended_at IS NULL"
+    )
+        .bind(&equipment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|name: Option<String>| name.unwrap_or_default());
+    if let Some(holder) = open {
+        return Err(ApiError::bad_request(&format!("Equipment is already checked out by {}", holder)));
+    }
+    if status != "available" {
+        return Err(ApiError::bad_request(&format!("Equipment is '{}' and cannot be checked out", status)));
+    }
+
+    if let Some(ref experiment_id) = body.experiment_id {
+        let experiment_status: String = sqlx::query_scalar("SELECT status FROM experiments WHERE id = ?")
+            .bind(experiment_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Experiment"))?;
+        if experiment_status == "cancelled" {
+            return Err(ApiError::bad_request("Cannot use equipment for a cancelled experiment"));
+        }
+
+        // Прибор попадает в список оборудования эксперимента (важно для флагов калибровки)
+        sqlx::query(r#"
+            INSERT OR IGNORE INTO experiment_equipment (id, experiment_id, equipment_id, notes, created_by, created_at)
+            VALUES (?, ?, ?, NULL, ?, ?)
+        "#)
+            .bind(Uuid::new_v4().to_string())
+            .bind(experiment_id)
+            .bind(&equipment_id)
+            .bind(&user_id)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let session_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO equipment_usage_log
+           (id, equipment_id, user_id, experiment_id, purpose, started_at, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&session_id)
+        .bind(&equipment_id)
+        .bind(&user_id)
+        .bind(&body.experiment_id)
+        .bind(&body.purpose)
+        .bind(started_at)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE equipment SET status = 'in_use', updated_by = ?, updated_at = ? WHERE id = ?")
+        .bind(&user_id)
+        .bind(Utc::now())
+        .bind(&equipment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let session = get_session(&mut tx, &session_id).await?;
+    tx.commit().await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(session)))
+}
+
+/// Возврат прибора. Чужой сеанс может закрыть только пользователь с правом
+/// редактирования оборудования (`can_close_others`).
+pub async fn check_in_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CheckInEquipmentRequest>,
+    user_id: String,
+    can_close_others: bool,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let equipment_id = path.into_inner();
+    let ended_at = resolve_time(body.ended_at)?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    let session: EquipmentUsageSession = sqlx::query_as(&format!(
+        "{} WHERE s.equipment_id = ? AND s.ended_at IS NULL", SESSION_SELECT
+    ))
+        .bind(&equipment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Equipment is not checked out"))?;
+
+    if session.user_id != user_id && !can_close_others {
+        return Err(ApiError::Forbidden("Equipment is checked out by another user".to_string()));
+    }
+    if ended_at < session.started_at {
+        return Err(ApiError::bad_request("Check-in time is earlier than check-out time"));
+    }
+
+    sqlx::query(
+        "UPDATE equipment_usage_log SET ended_at = ?, run_count = ?, notes = ?, checked_in_by = ? WHERE id = ?"
+    )
+        .bind(ended_at)
+        .bind(body.run_count)
+        .bind(&body.notes)
+        .bind(&user_id)
+        .bind(&session.id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Статус, выставленный вручную за время сеанса (например, 'damaged'), не трогаем
+    sqlx::query("UPDATE equipment SET status = 'available', updated_by = ?, updated_at = ? WHERE id = ? AND status = 'in_use'")
+        .bind(&user_id)
+        .bind(Utc::now())
+        .bind(&equipment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let maintenance_due = flag_run_based_maintenance(&mut tx, &equipment_id).await?;
+    let session = get_session(&mut tx, &session.id).await?;
+    tx.commit().await?;
+
+    if !maintenance_due.is_empty() {
+        apply_due_maintenance_statuses(&app_state.db_pool).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "session": session,
+        "maintenance_due": maintenance_due,
+    }))))
+}
+
+pub async fn get_equipment_usage(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<UsageLogQuery>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)")
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Equipment"));
+    }
+
+    let from = query.from.as_deref().map(parse_day).transpose()?;
+    let to = query.to.as_deref().map(parse_day).transpose()?.map(|d| d + Duration::days(1));
+    let limit = query.limit.unwrap_or(DEFAULT_USAGE_LIMIT).clamp(1, 1000);
+
+    let sessions: Vec<EquipmentUsageSession> = sqlx::query_as(&format!(r#"{}
+        WHERE s.equipment_id = ?
+          AND (? IS NULL OR julianday(COALESCE(s.ended_at, 'now')) >= julianday(?))
+          AND (? IS NULL OR julianday(s.started_at) < julianday(?))
+          AND (? IS NULL OR s.user_id = ?)
+        ORDER BY s.started_at DESC
+        LIMIT ?"#, SESSION_SELECT))
+        .bind(&equipment_id)
+        .bind(from.map(|d| d.to_string()))
+        .bind(from.map(|d| d.to_string()))
+        .bind(to.map(|d| d.to_string()))
+        .bind(to.map(|d| d.to_string()))
+        .bind(&query.user_id)
+        .bind(&query.user_id)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sessions)))
+}
+
+/// Кто сейчас работает на каких приборах
+pub async fn get_active_sessions(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let sessions: Vec<EquipmentUsageSession> = sqlx::query_as(&format!(
+        "{} WHERE s.ended_at IS NULL ORDER BY s.started_at ASC", SESSION_SELECT
+    ))
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sessions)))
+}
+
+pub async fn get_run_based_maintenance(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<RunBasedMaintenanceQuery>,
+) -> ApiResult<HttpResponse> {
+    let condition = if query.due_only.unwrap_or(false) { "WHERE is_due" } else { "" };
+    let plans: Vec<RunBasedMaintenance> = sqlx::query_as(&format!(
+        "{} {} ORDER BY is_due DESC, CAST(runs_since_maintenance AS REAL) / interval_runs DESC",
+        RUN_PLANS_SELECT, condition
+    ))
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(plans)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_bounds_defaults_and_validation() {
+        let (from, to) = period_bounds(Some("2024-03-01"), Some("2024-03-31"), 30).unwrap();
+        assert_eq!((to - from).num_days(), 30);
+
+        let (from, to) = period_bounds(None, Some("2024-03-31"), 7).unwrap();
+        assert_eq!(from, NaiveDate::from_ymd_opt(2024, 3, 25).unwrap());
+        assert_eq!(to, NaiveDate::from_ymd_opt(2024, 3, 31).unwrap());
+
+        assert!(period_bounds(Some("2024-04-01"), Some("2024-03-31"), 30).is_err());
+        assert!(period_bounds(Some("01.04.2024"), None, 30).is_err());
+    }
+}
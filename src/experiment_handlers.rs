@@ -1,14 +1,19 @@
 // src/experiment_handlers.rs
 //! Обработчики для экспериментов (v2.1)
 
+use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
-use actix_files::NamedFile;
+use futures_util::StreamExt;
 use std::sync::Arc;
-use std::path::PathBuf;
 use crate::AppState;
 use crate::models::*;
 use crate::error::{ApiError, ApiResult};
 use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::query_builders::fts::{FtsHit, FtsQueryBuilder, config::FtsConfig};
+use crate::query_builders::{validate_file_size, validate_mime_type};
+use crate::experiment_workflow::{
+    perform_transition, try_auto_transition, ExperimentSignoff, TransitionOutcome, TransitionRequest,
+};
 use chrono::Utc;
 use uuid::Uuid;
 use validator::Validate;
@@ -230,65 +235,20 @@ pub async fn update_experiment(
     // === ЖЕЛЕЗОБЕТОННОЕ АВТО-СПИСАНИЕ (в единой транзакции с обновлением) ===
     let mut tx = app_state.db_pool.begin().await?;
 
-    if status == "completed" && existing.status != "completed" {
-        let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-            SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-            FROM experiment_reagents 
-            WHERE experiment_id = ?
-        "#)
-            .bind(&experiment_id)
-            .fetch_all(&mut *tx)
-            .await?;
-
-        for reagent in reagents {
-            if !reagent.is_consumed {
-                let qty = reagent.planned_quantity.unwrap_or(0.0);
-                if qty > 0.0 {
-                    sqlx::query(r#"
-                        UPDATE batches 
-                        SET quantity = MAX(0, quantity - ?),
-                            reserved_quantity = MAX(0, reserved_quantity - ?)
-                        WHERE id = ?
-                    "#)
-                        .bind(qty)
-                        .bind(qty)
-                        .bind(&reagent.batch_id)
-                        .execute(&mut *tx)
-                        .await?;
-                }
-
-                sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                    .bind(&reagent.id)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-        }
-    } else if status == "cancelled" && existing.status != "cancelled" {
-        let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-            SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-            FROM experiment_reagents 
-            WHERE experiment_id = ?
-        "#)
-            .bind(&experiment_id)
-            .fetch_all(&mut *tx)
-            .await?;
-
-        for reagent in reagents {
-            if !reagent.is_consumed {
-                let qty = reagent.planned_quantity.unwrap_or(0.0);
-                if qty > 0.0 {
-                    sqlx::query(r#"
-                        UPDATE batches 
-                        SET reserved_quantity = MAX(0, reserved_quantity - ?)
-                        WHERE id = ?
-                    "#)
-                        .bind(qty)
-                        .bind(&reagent.batch_id)
-                        .execute(&mut *tx)
-                        .await?;
-                }
-            }
+    let status_changed = status != &existing.status;
+    if status_changed {
+        // Смена статуса через PUT допустима только для переходов без подписи
+        let rule = crate::experiment_workflow::check_transition(&mut tx, &existing, status, &[]).await?;
+        if rule.requires_signoff {
+            return Err(ApiError::bad_request(&format!(
+                "Transition '{}' -> '{}' requires sign-off; use POST /experiments/{}/status",
+                existing.status, status, experiment_id
+            )));
         }
+        settle_reagents_for_status(&mut tx, &experiment_id, status, &[], Some(&user_id)).await?;
+        crate::experiment_workflow::record_transition(
+            &mut tx, &experiment_id, &existing.status, status, Some(&user_id), None, None,
+        ).await?;
     }
 
     sqlx::query(r#"
@@ -337,6 +297,19 @@ pub async fn delete_experiment(
 ) -> ApiResult<HttpResponse> {
     let experiment_id = path.into_inner();
 
+    // Журнал неизменяем — эксперимент с записями можно только отменить
+    let notebook_entries: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM experiment_notebook_entries WHERE experiment_id = ?"
+    )
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if notebook_entries > 0 {
+        return Err(ApiError::bad_request(
+            "Experiment has lab notebook entries and cannot be deleted; cancel it instead"
+        ));
+    }
+
     let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
         SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
         FROM experiment_reagents 
@@ -346,6 +319,13 @@ pub async fn delete_experiment(
         .fetch_all(&app_state.db_pool)
         .await?;
 
+    let document_keys: Vec<String> = sqlx::query_scalar(
+        "SELECT storage_key FROM experiment_documents WHERE experiment_id = ?"
+    )
+        .bind(&experiment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
     let mut tx = app_state.db_pool.begin().await?;
 
     for reagent in &reagents {
@@ -373,6 +353,11 @@ pub async fn delete_experiment(
         return Err(ApiError::not_found("Experiment"));
     }
 
+    // Строки документов удалены каскадом вместе с экспериментом
+    for key in document_keys {
+        app_state.storage.release(&mut tx, &key).await?;
+    }
+
     tx.commit().await?;
 
     info!("User {} deleted experiment: {}", user_id, experiment_id);
@@ -383,48 +368,40 @@ pub async fn delete_experiment(
 
 // ==================== EXPERIMENT STATUS ====================
 
-#[derive(Debug, Deserialize)]
-pub struct UpdateStatusRequest {
-    pub status: String,
-}
-
+/// Смена статуса через машину состояний (см. experiment_workflow)
 pub async fn update_experiment_status(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
-    body: web::Json<UpdateStatusRequest>,
+    body: web::Json<TransitionRequest>,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
-    let experiment_id = path.into_inner();
-    let now = Utc::now();
+    crate::experiment_workflow::transition_experiment(app_state, path, body, user_id).await
+}
 
-    let valid_statuses = ["planned", "in_progress", "completed", "cancelled"];
-    if !valid_statuses.contains(&body.status.as_str()) {
-        return Err(ApiError::bad_request(&format!(
-            "Invalid status. Must be one of: {}", valid_statuses.join(", ")
-        )));
-    }
+// ==================== EXPERIMENT SEARCH ====================
 
-    let result = sqlx::query(
-        "UPDATE experiments SET status = ?, updated_by = ?, updated_at = ? WHERE id = ?"
-    )
-        .bind(&body.status)
-        .bind(&user_id)
-        .bind(&now)
-        .bind(&experiment_id)
-        .execute(&app_state.db_pool)
-        .await?;
+#[derive(Debug, Deserialize)]
+pub struct ExperimentSearchQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
 
-    if result.rows_affected() == 0 {
-        return Err(ApiError::not_found("Experiment"));
+/// Полнотекстовый поиск по title/description/protocol/notes (bm25 + подсветка)
+pub async fn search_experiments(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ExperimentSearchQuery>,
+) -> ApiResult<HttpResponse> {
+    let search_term = query.q.as_deref().unwrap_or("").trim();
+    if search_term.is_empty() {
+        return Err(ApiError::bad_request("Search query cannot be empty"));
     }
 
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await?;
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let hits: Vec<FtsHit<Experiment>> = FtsQueryBuilder::search_ranked(
+        &app_state.db_pool, &FtsConfig::for_experiments(), search_term, limit, "e.experiment_date DESC",
+    ).await?;
 
-    info!("User {} changed experiment {} status to {}", user_id, experiment_id, body.status);
-    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(hits)))
 }
 
 // ==================== EXPERIMENT STATISTICS ====================
@@ -546,14 +523,16 @@ pub async fn add_reagent_to_experiment(
         unit: String,
         quantity: f64,
         reserved_quantity: f64,
+        status: String,
     }
 
     // Check batch exists and has enough quantity
-    let batch: BatchInfo = sqlx::query_as("SELECT reagent_id, unit, quantity, reserved_quantity FROM batches WHERE id = ?")
+    let batch: BatchInfo = sqlx::query_as("SELECT reagent_id, unit, quantity, reserved_quantity, status FROM batches WHERE id = ?")
         .bind(&body.batch_id)
         .fetch_one(&app_state.db_pool)
         .await
         .map_err(|_| ApiError::not_found("Batch"))?;
+    crate::goods_in::ensure_released(&batch.status)?;
 
     let available = batch.quantity - batch.reserved_quantity;
     if body.quantity_used > available {
@@ -650,228 +629,433 @@ pub async fn remove_reagent_from_experiment(
     }))))
 }
 
-// ==================== START/COMPLETE/CANCEL EXPERIMENT ====================
+// ==================== EXPERIMENT EQUIPMENT ====================
 
-/// Запустить эксперимент (planned -> in_progress)
-pub async fn start_experiment(
+/// Оборудование, использованное в эксперименте (нужно для отзыва результатов
+/// при неудачной калибровке)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExperimentEquipmentWithDetails {
+    pub id: String,
+    pub experiment_id: String,
+    pub equipment_id: String,
+    pub notes: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub equipment_name: String,
+    pub equipment_status: String,
+    pub serial_number: Option<String>,
+}
+
+pub async fn get_experiment_equipment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
-    user_id: String,
 ) -> ApiResult<HttpResponse> {
     let experiment_id = path.into_inner();
-    let now = Utc::now();
 
-    let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let _: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
         .fetch_one(&app_state.db_pool)
         .await
         .map_err(|_| ApiError::not_found("Experiment"))?;
 
-    if existing.status != "planned" {
-        return Err(ApiError::bad_request(&format!(
-            "Cannot start experiment with status '{}'. Only 'planned' experiments can be started.",
-            existing.status
-        )));
-    }
-
-    sqlx::query(r#"
-        UPDATE experiments 
-        SET status = 'in_progress', start_date = COALESCE(start_date, ?), updated_by = ?, updated_at = ?
-        WHERE id = ?
+    let equipment: Vec<ExperimentEquipmentWithDetails> = sqlx::query_as(r#"
+        SELECT xe.id, xe.experiment_id, xe.equipment_id, xe.notes, xe.created_at,
+               e.name as equipment_name, e.status as equipment_status, e.serial_number
+        FROM experiment_equipment xe
+        JOIN equipment e ON xe.equipment_id = e.id
+        WHERE xe.experiment_id = ?
+        ORDER BY e.name ASC
     "#)
-        .bind(&now)
-        .bind(&user_id)
-        .bind(&now)
-        .bind(&experiment_id)
-        .execute(&app_state.db_pool)
-        .await?;
-
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_all(&app_state.db_pool)
         .await?;
 
-    info!("User {} started experiment: {}", user_id, experiment_id);
-    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(equipment)))
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddEquipmentToExperimentRequest {
+    #[validate(length(min = 1, message = "Equipment is required"))]
+    pub equipment_id: String,
+    #[validate(length(max = 500, message = "Notes cannot exceed 500 characters"))]
+    pub notes: Option<String>,
+}
 
-/// Завершить эксперимент (in_progress -> completed) и израсходовать реагенты
-pub async fn complete_experiment(
+pub async fn add_equipment_to_experiment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    body: web::Json<AddEquipmentToExperimentRequest>,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
+    body.validate()?;
     let experiment_id = path.into_inner();
-    let now = Utc::now();
 
-    let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
         .fetch_one(&app_state.db_pool)
         .await
         .map_err(|_| ApiError::not_found("Experiment"))?;
 
-    if existing.status != "in_progress" {
-        return Err(ApiError::bad_request(&format!(
-            "Cannot complete experiment with status '{}'. Only 'in_progress' experiments can be completed.",
-            existing.status
-        )));
+    if experiment.status == "cancelled" {
+        return Err(ApiError::bad_request("Cannot add equipment to cancelled experiment"));
     }
 
-    let mut tx = app_state.db_pool.begin().await?;
+    let status: String = sqlx::query_scalar("SELECT status FROM equipment WHERE id = ?")
+        .bind(&body.equipment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Equipment"))?;
 
-    // Явно указываем колонки, чтобы избежать ошибок маппинга
-    let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-        SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-        FROM experiment_reagents 
-        WHERE experiment_id = ?
+    if status == "damaged" || status == "retired" {
+        return Err(ApiError::bad_request(&format!("Equipment is {} and cannot be used", status)));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(r#"
+        INSERT OR IGNORE INTO experiment_equipment (id, experiment_id, equipment_id, notes, created_by, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
     "#)
+        .bind(&id)
         .bind(&experiment_id)
-        .fetch_all(&mut *tx)
+        .bind(&body.equipment_id)
+        .bind(&body.notes)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&app_state.db_pool)
         .await?;
 
-    let mut consumed_count = 0;
-
-    // Проверяем статус is_consumed на стороне Rust (самый надежный способ)
-    for reagent in reagents {
-        if !reagent.is_consumed {
-            let qty = reagent.planned_quantity.unwrap_or(0.0);
-            
-            if qty > 0.0 {
-                // Списываем количество из батча
-                sqlx::query(r#"
-                    UPDATE batches 
-                    SET quantity = MAX(0, quantity - ?),
-                        reserved_quantity = MAX(0, reserved_quantity - ?)
-                    WHERE id = ?
-                "#)
-                    .bind(qty)
-                    .bind(qty)
-                    .bind(&reagent.batch_id)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-
-            // Помечаем как consumed
-            sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                .bind(&reagent.id)
-                .execute(&mut *tx)
-                .await?;
-                
-            consumed_count += 1;
-        }
+    if result.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Equipment is already linked to this experiment"));
     }
 
-    // Обновляем статус эксперимента
-    sqlx::query(r#"
-        UPDATE experiments 
-        SET status = 'completed', end_date = COALESCE(end_date, ?), updated_by = ?, updated_at = ?
-        WHERE id = ?
-    "#)
-        .bind(&now)
-        .bind(&user_id)
-        .bind(&now)
-        .bind(&experiment_id)
-        .execute(&mut *tx)
-        .await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(serde_json::json!({
+        "id": id,
+        "message": "Equipment added to experiment"
+    }))))
+}
 
-    tx.commit().await?;
+pub async fn remove_equipment_from_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (experiment_id, equipment_id) = path.into_inner();
 
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let result = sqlx::query("DELETE FROM experiment_equipment WHERE experiment_id = ? AND equipment_id = ?")
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
+        .bind(&equipment_id)
+        .execute(&app_state.db_pool)
         .await?;
 
-    info!("User {} completed experiment: {} (consumed {} reagents)", 
-          user_id, experiment_id, consumed_count);
-    
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Experiment equipment link"));
+    }
+
     Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-        "experiment": updated,
-        "reagents_consumed": consumed_count
+        "message": "Equipment removed from experiment"
     }))))
 }
 
-/// Отменить эксперимент (planned|in_progress -> cancelled) и вернуть реагенты
-pub async fn cancel_experiment(
-    app_state: web::Data<Arc<AppState>>,
-    path: web::Path<String>,
-    user_id: String,
-) -> ApiResult<HttpResponse> {
-    let experiment_id = path.into_inner();
-    let now = Utc::now();
+// ==================== START/COMPLETE/CANCEL EXPERIMENT ====================
 
-    let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Experiment"))?;
+/// Фактический расход одного реагента при завершении эксперимента
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReagentUsage {
+    pub experiment_reagent_id: String,
+    #[validate(range(min = 0.0, message = "Actual quantity cannot be negative"))]
+    pub actual_quantity: f64,
+    /// Контейнер, из которого брали реагент и куда вернули остаток:
+    /// с него списывается только фактический расход. Без него расход
+    /// снимается с контейнеров батча (сначала вскрытые).
+    pub return_container_id: Option<String>,
+    #[validate(length(max = 500, message = "Notes cannot exceed 500 characters"))]
+    pub notes: Option<String>,
+}
 
-    if !["planned", "in_progress"].contains(&existing.status.as_str()) {
-        return Err(ApiError::bad_request(&format!(
-            "Cannot cancel experiment with status '{}'. Only 'planned' or 'in_progress' experiments can be cancelled.",
-            existing.status
-        )));
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct CompleteExperimentRequest {
+    #[serde(default)]
+    #[validate(nested)]
+    pub reagents: Vec<ReagentUsage>,
+    #[serde(default)]
+    #[validate(length(max = 1000, message = "Reason cannot exceed 1000 characters"))]
+    pub reason: Option<String>,
+}
+
+/// Пустое тело — фактический расход не указан; битый JSON — ошибка, а не «расход по плану»
+fn parse_complete_request(body: &[u8]) -> ApiResult<CompleteExperimentRequest> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(CompleteExperimentRequest::default());
     }
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::bad_request(&format!("Invalid completion request: {}", e)))
+}
 
-    let mut tx = app_state.db_pool.begin().await?;
+/// Побочные эффекты смены статуса для реагентов эксперимента.
+/// completed — списать фактический расход (по умолчанию planned_quantity),
+/// вернуть остаток в батч или контейнер и записать usage_logs;
+/// cancelled — снять резерв.
+/// Возвращает число затронутых строк experiment_reagents.
+pub async fn settle_reagents_for_status(
+    conn: &mut sqlx::SqliteConnection,
+    experiment_id: &str,
+    to_status: &str,
+    usage: &[ReagentUsage],
+    user_id: Option<&str>,
+) -> ApiResult<i32> {
+    if to_status != "completed" && to_status != "cancelled" {
+        return Ok(0);
+    }
 
-    let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-        SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-        FROM experiment_reagents 
-        WHERE experiment_id = ?
+    #[derive(sqlx::FromRow)]
+    struct OpenReagent {
+        id: String,
+        reagent_id: String,
+        batch_id: Option<String>,
+        planned_quantity: f64,
+        unit: String,
+    }
+
+    let reagents: Vec<OpenReagent> = sqlx::query_as(r#"
+        SELECT id, reagent_id, batch_id, planned_quantity, unit
+        FROM experiment_reagents
+        WHERE experiment_id = ? AND is_consumed = 0
     "#)
-        .bind(&experiment_id)
-        .fetch_all(&mut *tx)
+        .bind(experiment_id)
+        .fetch_all(&mut *conn)
         .await?;
 
-    let mut returned_count = 0;
+    if let Some(unknown) = usage.iter().find(|u| !reagents.iter().any(|r| r.id == u.experiment_reagent_id)) {
+        return Err(ApiError::bad_request(&format!(
+            "Experiment reagent '{}' is not pending consumption in this experiment", unknown.experiment_reagent_id
+        )));
+    }
+
+    if to_status == "cancelled" {
+        for reagent in reagents.iter().filter(|r| r.batch_id.is_some()) {
+            sqlx::query(r#"
+                UPDATE batches
+                SET reserved_quantity = MAX(0, reserved_quantity - ?)
+                WHERE id = ?
+            "#)
+                .bind(reagent.planned_quantity)
+                .bind(&reagent.batch_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        return Ok(reagents.len() as i32);
+    }
+
+    let now = Utc::now();
+    let purpose: Option<String> = sqlx::query_scalar("SELECT title FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|title: String| format!("Experiment: {}", title));
 
-    // Возвращаем зарезервированное количество в батчи
-    for reagent in reagents {
-        if !reagent.is_consumed {
-            let qty = reagent.planned_quantity.unwrap_or(0.0);
-            if qty > 0.0 {
-                sqlx::query(r#"
-                    UPDATE batches 
-                    SET reserved_quantity = MAX(0, reserved_quantity - ?)
-                    WHERE id = ?
-                "#)
-                    .bind(qty)
-                    .bind(&reagent.batch_id)
-                    .execute(&mut *tx)
-                    .await?;
+    for reagent in &reagents {
+        let entry = usage.iter().find(|u| u.experiment_reagent_id == reagent.id);
+        let planned = reagent.planned_quantity;
+        let actual = entry.map(|u| u.actual_quantity).unwrap_or(planned);
+
+        sqlx::query("UPDATE experiment_reagents SET is_consumed = 1, actual_quantity = ?, updated_at = ? WHERE id = ?")
+            .bind(actual)
+            .bind(now)
+            .bind(&reagent.id)
+            .execute(&mut *conn)
+            .await?;
+
+        // Без батча нет ни резерва, ни контейнеров, ни usage_logs
+        let Some(batch_id) = reagent.batch_id.as_deref() else { continue };
+
+        if actual > planned {
+            let (quantity, reserved): (f64, f64) = sqlx::query_as(
+                "SELECT quantity, reserved_quantity FROM batches WHERE id = ?"
+            )
+                .bind(batch_id)
+                .fetch_one(&mut *conn)
+                .await?;
+            let available = quantity - (reserved - planned).max(0.0);
+            if actual > available + 0.001 {
+                return Err(ApiError::insufficient_quantity(available, actual));
             }
-            
-            returned_count += 1;
+        }
+
+        // Резерв снимается целиком, из батча уходит только фактический расход
+        sqlx::query(r#"
+            UPDATE batches
+            SET quantity = MAX(0, quantity - ?),
+                reserved_quantity = MAX(0, reserved_quantity - ?)
+            WHERE id = ?
+        "#)
+            .bind(actual)
+            .bind(planned)
+            .bind(batch_id)
+            .execute(&mut *conn)
+            .await?;
+
+        // Контейнеры должны сходиться с batch.quantity: с них уходит тот же фактический расход
+        let container_id = entry.and_then(|u| u.return_container_id.as_deref());
+        draw_from_containers(&mut *conn, batch_id, container_id, actual).await?;
+
+        if actual > 0.0 {
+            sqlx::query(r#"
+                INSERT INTO usage_logs
+                (id, reagent_id, batch_id, user_id, experiment_id, quantity_used, unit, purpose, notes, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#)
+                .bind(Uuid::new_v4().to_string())
+                .bind(&reagent.reagent_id)
+                .bind(batch_id)
+                .bind(user_id)
+                .bind(experiment_id)
+                .bind(actual)
+                .bind(&reagent.unit)
+                .bind(&purpose)
+                .bind(entry.and_then(|u| u.notes.as_deref()))
+                .bind(now)
+                .execute(&mut *conn)
+                .await?;
         }
     }
 
-    // Обновляем статус эксперимента
-    sqlx::query(r#"
-        UPDATE experiments 
-        SET status = 'cancelled', updated_by = ?, updated_at = ?
-        WHERE id = ?
-    "#)
-        .bind(&user_id)
-        .bind(&now)
-        .bind(&experiment_id)
-        .execute(&mut *tx)
-        .await?;
+    Ok(reagents.len() as i32)
+}
 
-    tx.commit().await?;
+/// Списать фактический расход с контейнеров батча.
+/// Если указан контейнер — весь расход снимается с него (остаток уже вернули туда же),
+/// иначе по очереди со вскрытых, затем с целых контейнеров. Батч без контейнеров не трогаем.
+async fn draw_from_containers(
+    conn: &mut sqlx::SqliteConnection,
+    batch_id: &str,
+    container_id: Option<&str>,
+    quantity: f64,
+) -> ApiResult<()> {
+    let containers: Vec<BatchContainer> = match container_id {
+        Some(container_id) => {
+            let container: BatchContainer = sqlx::query_as("SELECT * FROM batch_containers WHERE id = ?")
+                .bind(container_id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or_else(|| ApiError::not_found("Container"))?;
+
+            if container.batch_id != batch_id {
+                return Err(ApiError::bad_request("Return container belongs to a different batch"));
+            }
+            if container.status == "disposed" {
+                return Err(ApiError::bad_request("Cannot return reagent to a disposed container"));
+            }
+            if quantity > container.quantity + 0.001 {
+                return Err(ApiError::bad_request(&format!(
+                    "Container #{} holds only {:.2}, cannot account for {:.2} consumed",
+                    container.sequence_number, container.quantity, quantity
+                )));
+            }
+            vec![container]
+        }
+        None => sqlx::query_as(r#"
+            SELECT * FROM batch_containers
+            WHERE batch_id = ? AND status IN ('full', 'partial')
+            ORDER BY is_opened DESC, sequence_number
+        "#)
+            .bind(batch_id)
+            .fetch_all(&mut *conn)
+            .await?,
+    };
 
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await?;
+    let now = Utc::now();
+    let mut remaining = quantity;
+    for container in containers {
+        if remaining <= 0.001 {
+            break;
+        }
+        let taken = remaining.min(container.quantity);
+        let new_qty = (container.quantity - taken).max(0.0);
+        remaining -= taken;
+
+        sqlx::query("UPDATE batch_containers SET quantity = ?, status = ?, updated_at = ? WHERE id = ?")
+            .bind(new_qty)
+            .bind(crate::container_handlers::compute_container_status(new_qty, container.original_quantity))
+            .bind(now)
+            .bind(&container.id)
+            .execute(&mut *conn)
+            .await?;
+    }
 
-    info!("User {} cancelled experiment: {} (returned {} reagents)", 
-          user_id, experiment_id, returned_count);
-    
-    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-        "experiment": updated,
-        "reagents_returned": returned_count
-    }))))
+    Ok(())
+}
+
+fn signoff_pending_response(signoff: ExperimentSignoff) -> HttpResponse {
+    HttpResponse::Accepted().json(ApiResponse::success_with_message(
+        signoff,
+        "Transition requires supervisor sign-off".to_string(),
+    ))
+}
+
+/// Запустить эксперимент (planned|on_hold -> in_progress)
+pub async fn start_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    match perform_transition(&app_state.db_pool, &experiment_id, "in_progress", &user_id, None, &[]).await? {
+        TransitionOutcome::Applied(updated, _) => {
+            info!("User {} started experiment: {}", user_id, experiment_id);
+            Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+        }
+        TransitionOutcome::PendingSignoff(signoff) => Ok(signoff_pending_response(signoff)),
+    }
+}
+
+
+/// Завершить эксперимент (in_progress -> completed) и израсходовать реагенты.
+/// Тело запроса необязательно: без него списывается planned_quantity.
+pub async fn complete_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Bytes,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let body = parse_complete_request(&body)?;
+    body.validate()?;
+
+    match perform_transition(
+        &app_state.db_pool, &experiment_id, "completed", &user_id, body.reason.as_deref(), &body.reagents,
+    ).await? {
+        TransitionOutcome::Applied(updated, consumed_count) => {
+            info!("User {} completed experiment: {} (consumed {} reagents)",
+                  user_id, experiment_id, consumed_count);
+
+            Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+                "experiment": updated,
+                "reagents_consumed": consumed_count
+            }))))
+        }
+        TransitionOutcome::PendingSignoff(signoff) => Ok(signoff_pending_response(signoff)),
+    }
+}
+
+/// Отменить эксперимент (planned|in_progress|on_hold -> cancelled) и вернуть реагенты
+pub async fn cancel_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    match perform_transition(&app_state.db_pool, &experiment_id, "cancelled", &user_id, None, &[]).await? {
+        TransitionOutcome::Applied(updated, returned_count) => {
+            info!("User {} cancelled experiment: {} (returned {} reagents)",
+                  user_id, experiment_id, returned_count);
+
+            Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+                "experiment": updated,
+                "reagents_returned": returned_count
+            }))))
+        }
+        TransitionOutcome::PendingSignoff(signoff) => Ok(signoff_pending_response(signoff)),
+    }
 }
 
 
@@ -879,7 +1063,7 @@ pub async fn cancel_experiment(
 pub async fn consume_experiment_reagent(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-    _user_id: String,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
     let (experiment_id, reagent_link_id) = path.into_inner();
 
@@ -931,8 +1115,29 @@ pub async fn consume_experiment_reagent(
         .execute(&mut *tx)
         .await?;
 
+    draw_from_containers(&mut tx, &reagent.batch_id, None, qty).await?;
+
     // Помечаем как consumed
-    sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
+    sqlx::query("UPDATE experiment_reagents SET is_consumed = 1, actual_quantity = ?, updated_at = ? WHERE id = ?")
+        .bind(qty)
+        .bind(Utc::now())
+        .bind(&reagent_link_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Фиксируем расход в истории использования
+    sqlx::query(r#"
+        INSERT INTO usage_logs
+        (id, reagent_id, batch_id, user_id, experiment_id, quantity_used, unit, purpose, created_at)
+        SELECT ?, er.reagent_id, er.batch_id, ?, er.experiment_id, ?, er.unit, ?, ?
+        FROM experiment_reagents er
+        WHERE er.id = ?
+    "#)
+        .bind(Uuid::new_v4().to_string())
+        .bind(&user_id)
+        .bind(qty)
+        .bind(format!("Experiment: {}", experiment.title))
+        .bind(Utc::now())
         .bind(&reagent_link_id)
         .execute(&mut *tx)
         .await?;
@@ -951,6 +1156,7 @@ pub async fn consume_experiment_reagent(
 pub struct AutoUpdateResult {
     pub started: i32,
     pub completed: i32,
+    pub skipped: i32,
     pub total_updated: i32,
 }
 
@@ -981,90 +1187,77 @@ pub async fn seconds_until_next_transition(pool: &sqlx::SqlitePool) -> Result<Op
 /// КЛЮЧЕВОЙ ФИX: datetime() нормализует формат дат перед сравнением.
 /// Без этого SQLite сравнивает даты как текст и "2025-01-01T09:00:00Z" > "2025-01-01 12:00:00+00:00"
 /// потому что 'T' (0x54) > ' ' (0x20) в ASCII.
-pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpdateResult, sqlx::Error> {
+/// Переходы идут через машину состояний: отключённые, требующие подписи
+/// или заблокированные guard-ами эксперименты пропускаются (skipped).
+/// Каждый эксперимент — в своей транзакции: сбой одного (например, не хватает
+/// остатка для списания) не откатывает остальные и считается пропуском
+async fn auto_transition_one(pool: &sqlx::SqlitePool, experiment_id: &str, to_status: &str) -> bool {
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let applied = try_auto_transition(&mut tx, experiment_id, to_status).await?;
+        if applied {
+            tx.commit().await?;
+        }
+        Ok::<bool, ApiError>(applied)
+    }.await;
+    result.unwrap_or_else(|e| {
+        log::warn!("Auto-transition of {} to {} failed: {}", experiment_id, to_status, e);
+        false
+    })
+}
+
+pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> ApiResult<AutoUpdateResult> {
     let now = Utc::now();
-    let mut tx = pool.begin().await?;
 
     // 1. planned → in_progress (пришло время start_date)
     // datetime() нормализует оба операнда в "YYYY-MM-DD HH:MM:SS"
-    let started_result = sqlx::query(r#"
-        UPDATE experiments
-        SET status = 'in_progress', updated_at = ?
+    let to_start: Vec<String> = sqlx::query_scalar(r#"
+        SELECT id FROM experiments
         WHERE status = 'planned'
           AND start_date IS NOT NULL
           AND datetime(start_date) <= datetime(?)
     "#)
-        .bind(&now)
-        .bind(&now)
-        .execute(&mut *tx)
+        .bind(now)
+        .fetch_all(pool)
         .await?;
 
-    let started = started_result.rows_affected() as i32;
-
-    // 2. in_progress → completed (пришло время end_date)
+    // 2. in_progress → completed (пришло время end_date), реагенты списываются
     let to_complete: Vec<String> = sqlx::query_scalar(r#"
         SELECT id FROM experiments
         WHERE status = 'in_progress'
           AND end_date IS NOT NULL
           AND datetime(end_date) <= datetime(?)
     "#)
-        .bind(&now)
-        .fetch_all(&mut *tx)
+        .bind(now)
+        .fetch_all(pool)
         .await?;
 
-    let completed = to_complete.len() as i32;
+    let mut started = 0;
+    let mut completed = 0;
+    let mut skipped = 0;
 
-    // 3. Для каждого завершаемого — списываем реагенты
-    for exp_id in &to_complete {
-        let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-            SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-            FROM experiment_reagents
-            WHERE experiment_id = ? AND is_consumed = 0
-        "#)
-            .bind(exp_id)
-            .fetch_all(&mut *tx)
-            .await?;
-
-        for reagent in reagents {
-            let qty = reagent.planned_quantity.unwrap_or(0.0);
-            if qty > 0.0 {
-                sqlx::query(r#"
-                    UPDATE batches
-                    SET quantity = MAX(0, quantity - ?),
-                        reserved_quantity = MAX(0, reserved_quantity - ?)
-                    WHERE id = ?
-                "#)
-                    .bind(qty)
-                    .bind(qty)
-                    .bind(&reagent.batch_id)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-            sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                .bind(&reagent.id)
-                .execute(&mut *tx)
-                .await?;
+    for exp_id in &to_start {
+        if auto_transition_one(pool, exp_id, "in_progress").await {
+            started += 1;
+        } else {
+            skipped += 1;
         }
-
-        sqlx::query(r#"
-            UPDATE experiments
-            SET status = 'completed', updated_at = ?
-            WHERE id = ?
-        "#)
-            .bind(&now)
-            .bind(exp_id)
-            .execute(&mut *tx)
-            .await?;
     }
 
-    tx.commit().await?;
+    for exp_id in &to_complete {
+        if auto_transition_one(pool, exp_id, "completed").await {
+            completed += 1;
+        } else {
+            skipped += 1;
+        }
+    }
 
     let total_updated = started + completed;
     if total_updated > 0 {
         info!("Auto-updated: {} started, {} completed (reagents consumed)", started, completed);
     }
 
-    Ok(AutoUpdateResult { started, completed, total_updated })
+    Ok(AutoUpdateResult { started, completed, skipped, total_updated })
 }
 
 /// HTTP-хендлер (обёртка)
@@ -1158,24 +1351,136 @@ pub async fn get_experiments_calendar(
 }
 
 // ==================== DOCUMENTS ====================
+// Содержимое хранится в BlobStore (по SHA-256), в таблице — только метаданные
+
+/// Максимальный размер документа эксперимента (20 МБ)
+const MAX_DOCUMENT_SIZE: usize = 20 * 1024 * 1024;
+
+const ALLOWED_DOCUMENT_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.ms-excel",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "text/csv",
+    "text/plain",
+    "image/jpeg",
+    "image/png",
+];
+
+async fn get_document_or_404(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    doc_id: &str,
+) -> ApiResult<ExperimentDocument> {
+    sqlx::query_as("SELECT * FROM experiment_documents WHERE id = ? AND experiment_id = ?")
+        .bind(doc_id)
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Document"))
+}
 
-pub async fn get_experiment_documents(
+/// Загрузка документа (multipart: file, description)
+pub async fn upload_experiment_document(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    mut payload: Multipart,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
     let experiment_id = path.into_inner();
+    let exists: Option<String> = sqlx::query_scalar("SELECT id FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found("Experiment"));
+    }
 
-    #[derive(Debug, Serialize, sqlx::FromRow)]
-    struct ExperimentDocument {
-        id: String,
-        experiment_id: String,
-        filename: String,
-        original_name: String,
-        mime_type: String,
-        size: i64,
-        uploaded_by: Option<String>,
-        created_at: chrono::DateTime<Utc>,
+    let mut file: Option<(Vec<u8>, String, String)> = None;
+    let mut description: Option<String> = None;
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| ApiError::bad_request(&format!("Multipart error: {}", e)))?;
+        let content_disposition = field.content_disposition();
+        match content_disposition.get_name().unwrap_or("") {
+            "file" => {
+                let filename = content_disposition
+                    .get_filename()
+                    .ok_or_else(|| ApiError::bad_request("Filename not provided"))?
+                    .to_string();
+                let mime = field.content_type()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                validate_mime_type(&mime, ALLOWED_DOCUMENT_TYPES)?;
+
+                let mut bytes = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    bytes.extend_from_slice(&chunk);
+                    validate_file_size(bytes.len(), MAX_DOCUMENT_SIZE)?;
+                }
+                file = Some((bytes, filename, mime));
+            }
+            "description" => {
+                let mut bytes = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    bytes.extend_from_slice(&chunk);
+                }
+                let value = String::from_utf8(bytes)
+                    .map_err(|_| ApiError::bad_request("Description must be UTF-8 text"))?
+                    .trim()
+                    .to_string();
+                if value.chars().count() > 500 {
+                    return Err(ApiError::bad_request("Description cannot exceed 500 characters"));
+                }
+                if !value.is_empty() {
+                    description = Some(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (bytes, filename, mime) = file.ok_or_else(|| ApiError::bad_request("No file provided"))?;
+    if bytes.is_empty() {
+        return Err(ApiError::bad_request("Document is empty"));
+    }
+    if filename.chars().count() > 255 {
+        return Err(ApiError::bad_request("Filename cannot exceed 255 characters"));
     }
+    let blob = app_state.storage.store(&bytes).await?;
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(r#"
+        INSERT INTO experiment_documents
+        (id, experiment_id, original_filename, mime_type, file_size, storage_key, sha256, description, uploaded_by, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(&id)
+        .bind(&experiment_id)
+        .bind(&filename)
+        .bind(&mime)
+        .bind(blob.size)
+        .bind(&blob.key)
+        .bind(&blob.sha256)
+        .bind(&description)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&app_state.db_pool)
+        .await?;
+    app_state.storage.confirm(&blob, &bytes).await?;
+
+    info!("User {} uploaded document '{}' to experiment {}", user_id, filename, experiment_id);
+    let doc = get_document_or_404(&app_state.db_pool, &experiment_id, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(doc)))
+}
+
+pub async fn get_experiment_documents(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
 
     let docs: Vec<ExperimentDocument> = sqlx::query_as(
         "SELECT * FROM experiment_documents WHERE experiment_id = ? ORDER BY created_at DESC"
@@ -1190,27 +1495,264 @@ pub async fn get_experiment_documents(
 pub async fn download_experiment_document(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-) -> Result<NamedFile, ApiError> {
+) -> ApiResult<HttpResponse> {
     let (experiment_id, doc_id) = path.into_inner();
+    let doc = get_document_or_404(&app_state.db_pool, &experiment_id, &doc_id).await?;
+    let contents = app_state.storage.load(&doc.storage_key, &doc.sha256).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(doc.mime_type.clone())
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", doc.original_filename)))
+        .insert_header(("Cache-Control", "private, max-age=3600"))
+        .body(contents))
+}
 
-    #[derive(sqlx::FromRow)]
-    struct DocInfo {
-        filename: String,
-        #[allow(dead_code)]
-        original_name: String,
+/// Удалить документ; содержимое удаляется, если на него больше нет ссылок
+pub async fn delete_experiment_document(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (experiment_id, doc_id) = path.into_inner();
+    let doc = get_document_or_404(&app_state.db_pool, &experiment_id, &doc_id).await?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+    sqlx::query("DELETE FROM experiment_documents WHERE id = ?")
+        .bind(&doc.id)
+        .execute(&mut *tx)
+        .await?;
+    app_state.storage.release(&mut tx, &doc.storage_key).await?;
+    tx.commit().await?;
+
+    info!("User {} deleted document '{}' from experiment {}", user_id, doc.original_filename, experiment_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message((), "Document deleted".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn container_total(pool: &sqlx::SqlitePool, batch_id: &str) -> f64 {
+        sqlx::query_scalar("SELECT SUM(quantity) FROM batch_containers WHERE batch_id = ?")
+            .bind(batch_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
     }
 
-    let doc: DocInfo = sqlx::query_as(
-        "SELECT filename, original_name FROM experiment_documents WHERE id = ? AND experiment_id = ?"
-    )
-        .bind(&doc_id)
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Document"))?;
+    #[test]
+    fn test_parse_complete_request() {
+        assert!(parse_complete_request(b"").unwrap().reagents.is_empty());
+        assert!(parse_complete_request(b"  \n").unwrap().reagents.is_empty());
+        let body = br#"{"reagents": [{"experiment_reagent_id": "er1", "actual_quantity": 4.5}]}"#;
+        assert_eq!(parse_complete_request(body).unwrap().reagents.len(), 1);
+        // Опечатка в типе не должна превращаться в «расход по плану»
+        assert!(parse_complete_request(br#"{"reagents": [{"experiment_reagent_id": "er1", "actual_quantity": "4.5"}]}"#).is_err());
+        assert!(parse_complete_request(b"{not json").is_err());
+    }
 
-    let file_path = PathBuf::from("./uploads/experiments").join(&doc.filename);
-    
-    NamedFile::open(&file_path)
-        .map_err(|_| ApiError::not_found("Document file"))
+    #[actix_rt::test]
+    async fn test_completion_keeps_containers_in_sync_with_batch() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        for (batch_id, reserved) in [("b1", 20.0), ("b2", 10.0)] {
+            sqlx::query(r#"
+                INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity,
+                                     unit, received_date, created_at, updated_at)
+                VALUES (?, 'r1', ?, 100, 100, ?, 'ml', ?, ?, ?)
+            "#).bind(batch_id).bind(batch_id).bind(reserved).bind(now).bind(now).bind(now)
+                .execute(&pool).await.unwrap();
+            for seq in [1, 2] {
+                sqlx::query(r#"
+                    INSERT INTO batch_containers (id, batch_id, sequence_number, quantity, original_quantity, status, created_at, updated_at)
+                    VALUES (?, ?, ?, 50, 50, 'full', ?, ?)
+                "#).bind(format!("{}-c{}", batch_id, seq)).bind(batch_id).bind(seq).bind(now).bind(now)
+                    .execute(&pool).await.unwrap();
+            }
+        }
+        sqlx::query("INSERT INTO experiments (id, title, experiment_date, status, created_at, updated_at) VALUES ('e1', 'Titration', ?, 'in_progress', ?, ?)")
+            .bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        for (id, batch_id, planned) in [("er1", "b1", 20.0), ("er2", "b2", 10.0)] {
+            sqlx::query(r#"
+                INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+                VALUES (?, 'e1', 'r1', ?, ?, 'ml', ?, ?)
+            "#).bind(id).bind(batch_id).bind(planned).bind(now).bind(now).execute(&pool).await.unwrap();
+        }
+
+        // er1: взяли 20 из b1-c2, 8 вернули туда же; er2: расход по плану без указания контейнера
+        let usage = vec![ReagentUsage {
+            experiment_reagent_id: "er1".to_string(),
+            actual_quantity: 12.0,
+            return_container_id: Some("b1-c2".to_string()),
+            notes: None,
+        }];
+        let mut conn = pool.acquire().await.unwrap();
+        let settled = settle_reagents_for_status(&mut conn, "e1", "completed", &usage, None).await.unwrap();
+        drop(conn);
+        assert_eq!(settled, 2);
+
+        for (batch_id, expected) in [("b1", 88.0), ("b2", 90.0)] {
+            let batch_qty: f64 = sqlx::query_scalar("SELECT quantity FROM batches WHERE id = ?")
+                .bind(batch_id).fetch_one(&pool).await.unwrap();
+            assert!((batch_qty - expected).abs() < 1e-9);
+            assert!((container_total(&pool, batch_id).await - batch_qty).abs() < 1e-9);
+        }
+
+        let c2: f64 = sqlx::query_scalar("SELECT quantity FROM batch_containers WHERE id = 'b1-c2'")
+            .fetch_one(&pool).await.unwrap();
+        assert!((c2 - 38.0).abs() < 1e-9);
+    }
+
+    #[actix_rt::test]
+    async fn test_complete_without_body_settles_planned_quantity() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query(r#"
+            INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+            VALUES ('u1', 'chemist', 'chemist@lab.local', 'x', 'researcher', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity,
+                                 unit, received_date, created_at, updated_at)
+            VALUES ('b1', 'r1', 'B-1', 100, 100, 15, 'ml', ?, ?, ?)
+        "#).bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO experiments (id, title, experiment_date, start_date, status, created_at, updated_at) VALUES ('e1', 'Titration', ?, ?, 'in_progress', ?, ?)")
+            .bind(now).bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+            VALUES ('er1', 'e1', 'r1', 'b1', 15, 'ml', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            storage: Arc::new(crate::storage::BlobStore::new(Arc::new(
+                crate::storage::LocalStorage::new(dir.path().to_str().unwrap()),
+            ))),
+        }));
+
+        // Фронтенд шлёт POST /complete без тела
+        let response = complete_experiment(app_state, web::Path::from("e1".to_string()), web::Bytes::new(), "u1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+
+        let status: String = sqlx::query_scalar("SELECT status FROM experiments WHERE id = 'e1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(status, "completed");
+        let (quantity, reserved): (f64, f64) = sqlx::query_as("SELECT quantity, reserved_quantity FROM batches WHERE id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert!((quantity - 85.0).abs() < 1e-9);
+        assert!(reserved.abs() < 1e-9);
+        let logged: f64 = sqlx::query_scalar("SELECT SUM(quantity_used) FROM usage_logs WHERE experiment_id = 'e1'")
+            .fetch_one(&pool).await.unwrap();
+        assert!((logged - 15.0).abs() < 1e-9);
+    }
+
+    #[actix_rt::test]
+    async fn test_reagent_without_batch_does_not_block_settling() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        for (id, status) in [("e1", "in_progress"), ("e2", "planned")] {
+            sqlx::query("INSERT INTO experiments (id, title, experiment_date, start_date, status, created_at, updated_at) VALUES (?, 'Titration', ?, ?, ?, ?, ?)")
+                .bind(id).bind(now).bind(now).bind(status).bind(now).bind(now).execute(&pool).await.unwrap();
+            sqlx::query(r#"
+                INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+                VALUES (?, ?, 'r1', NULL, 5, 'ml', ?, ?)
+            "#).bind(format!("er-{}", id)).bind(id).bind(now).bind(now).execute(&pool).await.unwrap();
+        }
+
+        let mut tx = pool.begin().await.unwrap();
+        assert_eq!(settle_reagents_for_status(&mut tx, "e1", "completed", &[], None).await.unwrap(), 1);
+        assert_eq!(settle_reagents_for_status(&mut tx, "e2", "cancelled", &[], None).await.unwrap(), 1);
+        tx.commit().await.unwrap();
+
+        let consumed: i64 = sqlx::query_scalar("SELECT is_consumed FROM experiment_reagents WHERE id = 'er-e1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(consumed, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_auto_update_isolates_failing_experiment() {
+        let pool = crate::db::test_pool().await;
+        let past = Utc::now() - chrono::Duration::hours(2);
+
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(past).bind(past).execute(&pool).await.unwrap();
+        for id in ["e1", "e2"] {
+            sqlx::query(r#"
+                INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, created_at, updated_at)
+                VALUES (?, 'Titration', ?, ?, ?, 'in_progress', ?, ?)
+            "#).bind(id).bind(past).bind(past).bind(past).bind(past).bind(past).execute(&pool).await.unwrap();
+            sqlx::query(r#"
+                INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+                VALUES (?, ?, 'r1', NULL, 5, 'ml', ?, ?)
+            "#).bind(format!("er-{}", id)).bind(id).bind(past).bind(past).execute(&pool).await.unwrap();
+        }
+        // Списание для e2 падает на каждом запуске
+        sqlx::query(r#"
+            CREATE TEMP TRIGGER fail_e2 BEFORE UPDATE ON experiment_reagents WHEN OLD.id = 'er-e2'
+            BEGIN SELECT RAISE(ABORT, 'settlement failed'); END
+        "#).execute(&pool).await.unwrap();
+
+        let result = run_auto_update_statuses(&pool).await.unwrap();
+        assert_eq!((result.completed, result.skipped), (1, 1));
+
+        let statuses: Vec<String> = sqlx::query_scalar("SELECT status FROM experiments ORDER BY id")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(statuses, vec!["completed", "in_progress"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_completion_guard_requires_usage_for_every_reagent() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query(r#"
+            INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+            VALUES ('u1', 'chemist', 'chemist@lab.local', 'x', 'researcher', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO experiments (id, title, experiment_date, start_date, status, created_at, updated_at) VALUES ('e1', 'Titration', ?, ?, 'in_progress', ?, ?)")
+            .bind(now).bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        for id in ["er1", "er2"] {
+            sqlx::query(r#"
+                INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+                VALUES (?, 'e1', 'r1', NULL, 5, 'ml', ?, ?)
+            "#).bind(id).bind(now).bind(now).execute(&pool).await.unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            storage: Arc::new(crate::storage::BlobStore::new(Arc::new(
+                crate::storage::LocalStorage::new(dir.path().to_str().unwrap()),
+            ))),
+        }));
+
+        // Расход указан только для одного реагента — второй не учтён
+        let partial = web::Bytes::from_static(br#"{"reagents": [{"experiment_reagent_id": "er1", "actual_quantity": 3}]}"#);
+        let result = complete_experiment(app_state.clone(), web::Path::from("e1".to_string()), partial, "u1".to_string()).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+
+        let full = web::Bytes::from_static(
+            br#"{"reagents": [{"experiment_reagent_id": "er1", "actual_quantity": 3}, {"experiment_reagent_id": "er2", "actual_quantity": 0}]}"#,
+        );
+        let response = complete_experiment(app_state, web::Path::from("e1".to_string()), full, "u1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+    }
 }
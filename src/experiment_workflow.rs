@@ -0,0 +1,792 @@
+// src/experiment_workflow.rs
+//! Декларативная машина состояний для статусов экспериментов.
+//!
+//! Разрешённые переходы хранятся в `experiment_status_transitions` и
+//! настраиваются администратором: guard-условия, обязательная подпись
+//! руководителя (electronic sign-off) и включение/отключение перехода.
+//! Каждый применённый переход пишется в `experiment_status_history`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::auth::{self, AuthService, UserRole, User};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::Experiment;
+use crate::experiment_handlers::{settle_reagents_for_status, ReagentUsage};
+
+pub const EXPERIMENT_STATUSES: [&str; 6] = [
+    "draft", "planned", "in_progress", "completed", "cancelled", "on_hold",
+];
+
+// ==================== GUARDS ====================
+
+/// Условие, которое должно выполняться перед переходом
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionGuard {
+    /// Все реагенты эксперимента израсходованы, возвращены
+    /// или учтены в фактическом расходе самого перехода. Переход без
+    /// фактического расхода списывает всё по плану и проходит
+    ReagentsSettled,
+    /// У эксперимента есть хотя бы один реагент
+    HasReagents,
+    /// Поле `results` заполнено
+    HasResults,
+}
+
+impl TransitionGuard {
+    pub const ALL: [TransitionGuard; 3] = [
+        TransitionGuard::ReagentsSettled,
+        TransitionGuard::HasReagents,
+        TransitionGuard::HasResults,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransitionGuard::ReagentsSettled => "reagents_settled",
+            TransitionGuard::HasReagents => "has_reagents",
+            TransitionGuard::HasResults => "has_results",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.as_str() == s)
+    }
+
+    /// Разбирает список guard-ов из колонки `guards` ("a,b,c")
+    pub fn parse_list(raw: &str) -> Result<Vec<Self>, String> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Self::from_str(s).ok_or_else(|| format!("Unknown transition guard '{}'", s)))
+            .collect()
+    }
+
+    pub fn join_list(guards: &[Self]) -> String {
+        guards.iter().map(|g| g.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Проверяет условие; Err содержит человекочитаемую причину отказа.
+    /// `usage` — фактический расход, переданный вместе с переходом.
+    pub async fn check(
+        &self,
+        conn: &mut SqliteConnection,
+        experiment: &Experiment,
+        usage: &[ReagentUsage],
+    ) -> ApiResult<Result<(), String>> {
+        let outcome = match self {
+            TransitionGuard::ReagentsSettled if usage.is_empty() => Ok(()),
+            TransitionGuard::ReagentsSettled => {
+                let open_ids: Vec<String> = sqlx::query_scalar(
+                    "SELECT id FROM experiment_reagents WHERE experiment_id = ? AND is_consumed = 0"
+                )
+                    .bind(&experiment.id)
+                    .fetch_all(&mut *conn)
+                    .await?;
+                let open = open_ids.iter()
+                    .filter(|id| !usage.iter().any(|u| &u.experiment_reagent_id == *id))
+                    .count();
+                if open > 0 {
+                    Err(format!("{} reagent(s) are neither consumed nor returned", open))
+                } else {
+                    Ok(())
+                }
+            }
+            TransitionGuard::HasReagents => {
+                let total: i64 = sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM experiment_reagents WHERE experiment_id = ?"
+                )
+                    .bind(&experiment.id)
+                    .fetch_one(&mut *conn)
+                    .await?;
+                if total == 0 {
+                    Err("Experiment has no reagents".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+            TransitionGuard::HasResults => {
+                if experiment.results.as_deref().map(str::trim).unwrap_or("").is_empty() {
+                    Err("Experiment results are empty".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        Ok(outcome)
+    }
+}
+
+// ==================== RULES ====================
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TransitionRule {
+    pub from_status: String,
+    pub to_status: String,
+    pub guards: String,
+    pub requires_signoff: bool,
+    pub signoff_role: String,
+    pub is_enabled: bool,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TransitionRule {
+    pub fn guard_list(&self) -> Vec<TransitionGuard> {
+        // Значения проверяются при сохранении, неизвестные просто пропускаем
+        self.guards
+            .split(',')
+            .filter_map(|s| TransitionGuard::from_str(s.trim()))
+            .collect()
+    }
+
+    pub fn can_sign(&self, role: &UserRole) -> bool {
+        match role {
+            UserRole::Admin => true,
+            UserRole::Researcher => self.signoff_role == "researcher",
+            UserRole::Viewer => false,
+        }
+    }
+}
+
+pub async fn load_rule(conn: &mut SqliteConnection, from: &str, to: &str) -> ApiResult<Option<TransitionRule>> {
+    let rule = sqlx::query_as::<_, TransitionRule>(
+        "SELECT * FROM experiment_status_transitions WHERE from_status = ? AND to_status = ?"
+    )
+        .bind(from)
+        .bind(to)
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(rule)
+}
+
+/// Проверяет, что переход разрешён и все guard-условия выполнены.
+/// Не проверяет sign-off — это решает вызывающий код.
+pub async fn check_transition(
+    conn: &mut SqliteConnection,
+    experiment: &Experiment,
+    to_status: &str,
+    usage: &[ReagentUsage],
+) -> ApiResult<TransitionRule> {
+    if !EXPERIMENT_STATUSES.contains(&to_status) {
+        return Err(ApiError::bad_request(&format!(
+            "Invalid status. Must be one of: {}", EXPERIMENT_STATUSES.join(", ")
+        )));
+    }
+
+    let rule = load_rule(&mut *conn, &experiment.status, to_status)
+        .await?
+        .filter(|r| r.is_enabled)
+        .ok_or_else(|| ApiError::bad_request(&format!(
+            "Transition '{}' -> '{}' is not allowed", experiment.status, to_status
+        )))?;
+
+    let mut failures = Vec::new();
+    for guard in rule.guard_list() {
+        if let Err(reason) = guard.check(&mut *conn, experiment, usage).await? {
+            failures.push(format!("{}: {}", guard.as_str(), reason));
+        }
+    }
+    if !failures.is_empty() {
+        return Err(ApiError::bad_request(&format!(
+            "Transition '{}' -> '{}' blocked: {}", experiment.status, to_status, failures.join("; ")
+        )));
+    }
+
+    Ok(rule)
+}
+
+/// Записывает строку в историю переходов
+pub async fn record_transition(
+    conn: &mut SqliteConnection,
+    experiment_id: &str,
+    from_status: &str,
+    to_status: &str,
+    changed_by: Option<&str>,
+    reason: Option<&str>,
+    signoff_id: Option<&str>,
+) -> ApiResult<()> {
+    sqlx::query(r#"
+        INSERT INTO experiment_status_history
+        (id, experiment_id, from_status, to_status, changed_by, reason, signoff_id, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(Uuid::new_v4().to_string())
+        .bind(experiment_id)
+        .bind(from_status)
+        .bind(to_status)
+        .bind(changed_by)
+        .bind(reason)
+        .bind(signoff_id)
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+// ==================== TRANSITION ENGINE ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExperimentSignoff {
+    pub id: String,
+    pub experiment_id: String,
+    pub from_status: String,
+    pub to_status: String,
+    pub status: String,
+    pub reason: Option<String>,
+    #[serde(skip_serializing)]
+    pub payload: Option<String>,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    pub signed_by: Option<String>,
+    pub signed_at: Option<DateTime<Utc>>,
+    pub signature_meaning: Option<String>,
+    pub signature_hash: Option<String>,
+    pub comment: Option<String>,
+}
+
+pub enum TransitionOutcome {
+    /// Переход применён; второе поле — число затронутых строк experiment_reagents
+    Applied(Experiment, i32),
+    /// Переход ждёт подписи руководителя
+    PendingSignoff(ExperimentSignoff),
+}
+
+/// Единая точка смены статуса эксперимента.
+/// Проверяет правило и guard-ы, при необходимости создаёт запрос на подпись,
+/// иначе применяет побочные эффекты (списание/возврат реагентов) и пишет историю.
+/// `usage` — фактический расход реагентов (учитывается только при completed).
+pub async fn perform_transition(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    to_status: &str,
+    user_id: &str,
+    reason: Option<&str>,
+    usage: &[ReagentUsage],
+) -> ApiResult<TransitionOutcome> {
+    let mut tx = pool.begin().await?;
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Experiment"))?;
+
+    let rule = check_transition(&mut tx, &experiment, to_status, usage).await?;
+
+    if rule.requires_signoff {
+        let pending: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM experiment_signoffs WHERE experiment_id = ? AND status = 'pending'"
+        )
+            .bind(experiment_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if pending > 0 {
+            return Err(ApiError::bad_request("Experiment already has a pending sign-off request"));
+        }
+
+        // Фактический расход сохраняется вместе с запросом и применяется после подписи
+        let payload = if usage.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(usage).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        };
+
+        let signoff_id = Uuid::new_v4().to_string();
+        sqlx::query(r#"
+            INSERT INTO experiment_signoffs
+            (id, experiment_id, from_status, to_status, status, reason, payload, requested_by, requested_at)
+            VALUES (?, ?, ?, ?, 'pending', ?, ?, ?, ?)
+        "#)
+            .bind(&signoff_id)
+            .bind(experiment_id)
+            .bind(&experiment.status)
+            .bind(to_status)
+            .bind(reason)
+            .bind(&payload)
+            .bind(user_id)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+
+        let signoff: ExperimentSignoff = sqlx::query_as("SELECT * FROM experiment_signoffs WHERE id = ?")
+            .bind(&signoff_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        info!("User {} requested sign-off for experiment {}: {} -> {}",
+              user_id, experiment_id, experiment.status, to_status);
+        return Ok(TransitionOutcome::PendingSignoff(signoff));
+    }
+
+    let affected = apply_transition(&mut tx, &experiment, to_status, Some(user_id), reason, None, usage).await?;
+    tx.commit().await?;
+
+    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_one(pool)
+        .await?;
+
+    info!("User {} moved experiment {}: {} -> {}", user_id, experiment_id, experiment.status, to_status);
+    Ok(TransitionOutcome::Applied(updated, affected))
+}
+
+/// Применяет уже проверенный переход внутри транзакции
+async fn apply_transition(
+    conn: &mut SqliteConnection,
+    experiment: &Experiment,
+    to_status: &str,
+    user_id: Option<&str>,
+    reason: Option<&str>,
+    signoff_id: Option<&str>,
+    usage: &[ReagentUsage],
+) -> ApiResult<i32> {
+    let now = Utc::now();
+    let affected = settle_reagents_for_status(&mut *conn, &experiment.id, to_status, usage, user_id).await?;
+
+    sqlx::query(r#"
+        UPDATE experiments
+        SET status = ?,
+            start_date = CASE WHEN ? = 'in_progress' THEN COALESCE(start_date, ?) ELSE start_date END,
+            end_date = CASE WHEN ? = 'completed' THEN COALESCE(end_date, ?) ELSE end_date END,
+            updated_by = COALESCE(?, updated_by),
+            updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(to_status)
+        .bind(to_status)
+        .bind(now)
+        .bind(to_status)
+        .bind(now)
+        .bind(user_id)
+        .bind(now)
+        .bind(&experiment.id)
+        .execute(&mut *conn)
+        .await?;
+
+    record_transition(&mut *conn, &experiment.id, &experiment.status, to_status, user_id, reason, signoff_id).await?;
+    Ok(affected)
+}
+
+/// Переход по расписанию (фоновая задача): применяется, только если правило
+/// включено, не требует подписи и guard-ы выполнены. Возвращает true, если применён.
+pub async fn try_auto_transition(
+    conn: &mut SqliteConnection,
+    experiment_id: &str,
+    to_status: &str,
+) -> ApiResult<bool> {
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let rule = match check_transition(&mut *conn, &experiment, to_status, &[]).await {
+        Ok(rule) => rule,
+        Err(ApiError::BadRequest(reason)) => {
+            log::debug!("Auto-transition of {} skipped: {}", experiment_id, reason);
+            return Ok(false);
+        }
+        Err(e) => return Err(e),
+    };
+    if rule.requires_signoff {
+        return Ok(false);
+    }
+
+    apply_transition(&mut *conn, &experiment, to_status, None, Some("Scheduled auto-update"), None, &[]).await?;
+    Ok(true)
+}
+
+/// Хеш электронной подписи: связывает подписанта, смысл подписи и сам переход
+pub fn signature_hash(
+    signoff_id: &str,
+    experiment_id: &str,
+    from_status: &str,
+    to_status: &str,
+    signed_by: &str,
+    signed_at: &DateTime<Utc>,
+    meaning: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [signoff_id, experiment_id, from_status, to_status, signed_by, &signed_at.to_rfc3339(), meaning] {
+        hasher.update(part.as_bytes());
+        hasher.update([0x1f]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+// ==================== HTTP HANDLERS ====================
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct TransitionRequest {
+    pub status: String,
+    #[validate(length(max = 1000, message = "Reason cannot exceed 1000 characters"))]
+    pub reason: Option<String>,
+}
+
+/// POST /experiments/{id}/status — общий переход по машине состояний
+pub async fn transition_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<TransitionRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let experiment_id = path.into_inner();
+
+    match perform_transition(&app_state.db_pool, &experiment_id, &body.status, &user_id, body.reason.as_deref(), &[]).await? {
+        TransitionOutcome::Applied(experiment, _) => {
+            Ok(HttpResponse::Ok().json(ApiResponse::success(experiment)))
+        }
+        TransitionOutcome::PendingSignoff(signoff) => {
+            Ok(HttpResponse::Accepted().json(ApiResponse::success_with_message(
+                signoff,
+                "Transition requires supervisor sign-off".to_string(),
+            )))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StatusHistoryEntry {
+    pub id: String,
+    pub experiment_id: String,
+    pub from_status: String,
+    pub to_status: String,
+    pub changed_by: Option<String>,
+    pub changed_by_username: Option<String>,
+    pub reason: Option<String>,
+    pub signoff_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// GET /experiments/{id}/status-history
+pub async fn get_status_history(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    let history: Vec<StatusHistoryEntry> = sqlx::query_as(r#"
+        SELECT h.id, h.experiment_id, h.from_status, h.to_status, h.changed_by,
+               u.username as changed_by_username, h.reason, h.signoff_id, h.created_at
+        FROM experiment_status_history h
+        LEFT JOIN users u ON h.changed_by = u.id
+        WHERE h.experiment_id = ?
+        ORDER BY h.created_at ASC
+    "#)
+        .bind(&experiment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(history)))
+}
+
+/// GET /experiments/{id}/signoffs
+pub async fn get_signoffs(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    let signoffs: Vec<ExperimentSignoff> = sqlx::query_as(
+        "SELECT * FROM experiment_signoffs WHERE experiment_id = ? ORDER BY requested_at DESC"
+    )
+        .bind(&experiment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(signoffs)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SignoffRequest {
+    /// Повторный ввод пароля — обязательная часть электронной подписи
+    pub password: String,
+    pub approve: bool,
+    #[validate(length(min = 1, max = 255, message = "Signature meaning must be between 1 and 255 characters"))]
+    pub meaning: String,
+    #[validate(length(max = 1000, message = "Comment cannot exceed 1000 characters"))]
+    pub comment: Option<String>,
+}
+
+/// POST /experiments/{id}/signoffs/{signoff_id}/sign
+pub async fn sign_transition(
+    app_state: web::Data<Arc<AppState>>,
+    auth_service: web::Data<Arc<AuthService>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<SignoffRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = auth::get_current_user(&http_request)?;
+    let (experiment_id, signoff_id) = path.into_inner();
+
+    // Подпись = повторная аутентификация подписанта
+    let signer = User::find_by_id(&app_state.db_pool, &claims.sub).await?;
+    let password_ok = auth_service.verify_password(&body.password, &signer.password_hash)
+        .map_err(|_| ApiError::InternalServerError("Password verification failed".to_string()))?;
+    if !password_ok {
+        return Err(ApiError::AuthError("Invalid password for electronic signature".to_string()));
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    let signoff: ExperimentSignoff = sqlx::query_as(
+        "SELECT * FROM experiment_signoffs WHERE id = ? AND experiment_id = ?"
+    )
+        .bind(&signoff_id)
+        .bind(&experiment_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Sign-off request"))?;
+
+    if signoff.status != "pending" {
+        return Err(ApiError::bad_request(&format!("Sign-off request is already '{}'", signoff.status)));
+    }
+    if signoff.requested_by == claims.sub {
+        return Err(ApiError::Forbidden("Requester cannot sign off their own transition".to_string()));
+    }
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    if experiment.status != signoff.from_status {
+        return Err(ApiError::bad_request(&format!(
+            "Experiment status changed to '{}' since the sign-off was requested", experiment.status
+        )));
+    }
+
+    let rule = load_rule(&mut tx, &signoff.from_status, &signoff.to_status)
+        .await?
+        .ok_or_else(|| ApiError::bad_request("Transition is no longer configured"))?;
+    if !rule.can_sign(&claims.role) {
+        return Err(ApiError::Forbidden(format!("Sign-off requires role '{}'", rule.signoff_role)));
+    }
+
+    let now = Utc::now();
+    let hash = signature_hash(
+        &signoff.id, &experiment_id, &signoff.from_status, &signoff.to_status,
+        &claims.sub, &now, &body.meaning,
+    );
+    let decision = if body.approve { "approved" } else { "rejected" };
+
+    sqlx::query(r#"
+        UPDATE experiment_signoffs
+        SET status = ?, signed_by = ?, signed_at = ?, signature_meaning = ?, signature_hash = ?, comment = ?
+        WHERE id = ?
+    "#)
+        .bind(decision)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&body.meaning)
+        .bind(&hash)
+        .bind(&body.comment)
+        .bind(&signoff.id)
+        .execute(&mut *tx)
+        .await?;
+
+    if body.approve {
+        // Guard-ы проверяются повторно: состояние могло измениться с момента запроса
+        let usage: Vec<ReagentUsage> = match signoff.payload.as_deref() {
+            Some(raw) => serde_json::from_str(raw)
+                .map_err(|e| ApiError::internal_error(format!("Corrupted sign-off payload: {}", e)))?,
+            None => Vec::new(),
+        };
+        check_transition(&mut tx, &experiment, &signoff.to_status, &usage).await?;
+        apply_transition(
+            &mut tx, &experiment, &signoff.to_status,
+            Some(&signoff.requested_by), signoff.reason.as_deref(), Some(&signoff.id), &usage,
+        ).await?;
+    }
+
+    tx.commit().await?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "sign", "experiment", &experiment_id,
+        &format!("Sign-off {} for {} -> {} ({})", decision, signoff.from_status, signoff.to_status, body.meaning),
+        &http_request,
+    ).await;
+
+    let updated: ExperimentSignoff = sqlx::query_as("SELECT * FROM experiment_signoffs WHERE id = ?")
+        .bind(&signoff.id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+}
+
+/// POST /experiments/{id}/signoffs/{signoff_id}/withdraw
+/// Отзыв ожидающего запроса на подпись: только автор запроса или администратор.
+pub async fn withdraw_signoff(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let (experiment_id, signoff_id) = path.into_inner();
+
+    let signoff: ExperimentSignoff = sqlx::query_as(
+        "SELECT * FROM experiment_signoffs WHERE id = ? AND experiment_id = ?"
+    )
+        .bind(&signoff_id)
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Sign-off request"))?;
+
+    if signoff.requested_by != claims.sub && claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Only the requester or an admin can withdraw a sign-off request".to_string()));
+    }
+
+    let result = sqlx::query(
+        "UPDATE experiment_signoffs SET status = 'withdrawn' WHERE id = ? AND status = 'pending'"
+    )
+        .bind(&signoff.id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::bad_request(&format!("Sign-off request is already '{}'", signoff.status)));
+    }
+
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "withdraw", "experiment", &experiment_id,
+        &format!("Sign-off withdrawn for {} -> {}", signoff.from_status, signoff.to_status),
+        &http_request,
+    ).await;
+
+    let updated: ExperimentSignoff = sqlx::query_as("SELECT * FROM experiment_signoffs WHERE id = ?")
+        .bind(&signoff.id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+}
+
+/// GET /experiments/workflow/transitions
+pub async fn get_transition_rules(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let rules: Vec<TransitionRule> = sqlx::query_as(
+        "SELECT * FROM experiment_status_transitions ORDER BY from_status, to_status"
+    )
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "transitions": rules,
+        "available_guards": TransitionGuard::ALL,
+        "statuses": EXPERIMENT_STATUSES,
+    }))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpsertTransitionRequest {
+    pub from_status: String,
+    pub to_status: String,
+    pub guards: Option<Vec<String>>,
+    pub requires_signoff: Option<bool>,
+    pub signoff_role: Option<String>,
+    pub is_enabled: Option<bool>,
+}
+
+/// PUT /experiments/workflow/transitions — создать или изменить правило (admin)
+pub async fn upsert_transition_rule(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<UpsertTransitionRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    for status in [&body.from_status, &body.to_status] {
+        if !EXPERIMENT_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Invalid status '{}'", status)));
+        }
+    }
+    if body.from_status == body.to_status {
+        return Err(ApiError::bad_request("from_status and to_status must differ"));
+    }
+    if let Some(ref role) = body.signoff_role {
+        if !["admin", "researcher"].contains(&role.as_str()) {
+            return Err(ApiError::bad_request("signoff_role must be 'admin' or 'researcher'"));
+        }
+    }
+
+    let guards = match body.guards {
+        Some(ref list) => Some(TransitionGuard::join_list(&TransitionGuard::parse_list(&list.join(","))?)),
+        None => None,
+    };
+
+    let mut conn = app_state.db_pool.acquire().await?;
+    let existing = load_rule(&mut conn, &body.from_status, &body.to_status).await?;
+
+    let guards = guards.or_else(|| existing.as_ref().map(|r| r.guards.clone())).unwrap_or_default();
+    let requires_signoff = body.requires_signoff.or(existing.as_ref().map(|r| r.requires_signoff)).unwrap_or(false);
+    let signoff_role = body.signoff_role.clone()
+        .or_else(|| existing.as_ref().map(|r| r.signoff_role.clone()))
+        .unwrap_or_else(|| "admin".to_string());
+    let is_enabled = body.is_enabled.or(existing.as_ref().map(|r| r.is_enabled)).unwrap_or(true);
+
+    sqlx::query(r#"
+        INSERT INTO experiment_status_transitions
+        (from_status, to_status, guards, requires_signoff, signoff_role, is_enabled, updated_by, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(from_status, to_status) DO UPDATE SET
+            guards = excluded.guards,
+            requires_signoff = excluded.requires_signoff,
+            signoff_role = excluded.signoff_role,
+            is_enabled = excluded.is_enabled,
+            updated_by = excluded.updated_by,
+            updated_at = excluded.updated_at
+    "#)
+        .bind(&body.from_status)
+        .bind(&body.to_status)
+        .bind(&guards)
+        .bind(requires_signoff)
+        .bind(&signoff_role)
+        .bind(is_enabled)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+
+    let rule = load_rule(&mut conn, &body.from_status, &body.to_status)
+        .await?
+        .ok_or_else(|| ApiError::internal("Transition rule not saved"))?;
+
+    info!("User {} configured experiment transition {} -> {}", user_id, body.from_status, body.to_status);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(rule)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_list_roundtrip() {
+        let guards = TransitionGuard::parse_list("reagents_settled, has_results").unwrap();
+        assert_eq!(guards, vec![TransitionGuard::ReagentsSettled, TransitionGuard::HasResults]);
+        assert_eq!(TransitionGuard::join_list(&guards), "reagents_settled,has_results");
+        assert!(TransitionGuard::parse_list("").unwrap().is_empty());
+        assert!(TransitionGuard::parse_list("no_such_guard").is_err());
+    }
+
+    #[test]
+    fn test_signature_hash_binds_signer() {
+        let at = Utc::now();
+        let a = signature_hash("s1", "e1", "in_progress", "completed", "u1", &at, "Approved");
+        let b = signature_hash("s1", "e1", "in_progress", "completed", "u2", &at, "Approved");
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+    }
+}
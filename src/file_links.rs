@@ -0,0 +1,291 @@
+// src/file_links.rs
+//! Подписанные ссылки на скачивание файлов оборудования.
+//!
+//! Ссылку выдаёт аутентифицированный endpoint; сама ссылка работает без
+//! токена, но только до `expires` и только с верной HMAC-SHA256 подписью.
+//! Ключ подписи выводится из JWT-секрета конфигурации (`JWT_SECRET`),
+//! поэтому после его смены ранее выданные ссылки перестают работать — это
+//! допустимо для ссылок со сроком жизни в минуты.
+//!
+//! Endpoints:
+//!   POST /api/v1/equipment/{id}/files/{file_id}/link
+//!   GET  /api/v1/public/equipment/{id}/files/{file_id}?link=&expires=&sig=
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::AppState;
+use crate::audit;
+use crate::equipment_handlers::load_equipment_file;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::thumbnails::ThumbnailSize;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const DEFAULT_LINK_TTL_MINUTES: i64 = 15;
+const PUBLIC_FILES_PREFIX: &str = "/api/v1/public/equipment";
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateFileLinkRequest {
+    #[validate(range(min = 1, max = 1440, message = "Link lifetime must be between 1 and 1440 minutes"))]
+    pub expires_in_minutes: Option<i64>,
+    /// Ссылка перестаёт работать после первого скачивания
+    #[serde(default)]
+    pub single_use: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileLinkResponse {
+    pub link_id: String,
+    /// Относительный URL, не требующий авторизации
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+    pub single_use: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignedLinkQuery {
+    pub link: String,
+    pub expires: i64,
+    pub sig: String,
+    /// Превью фото (thumb | small | medium); подпись покрывает файл целиком
+    pub size: Option<ThumbnailSize>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct FileDownloadLink {
+    id: String,
+    single_use: bool,
+    used_at: Option<DateTime<Utc>>,
+    created_by: String,
+}
+
+/// Ключ подписи ссылок: отдельный от ключа JWT, но производный от него
+fn signing_key(secret: &str) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(b"lims-file-links:")
+        .chain_update(secret.as_bytes())
+        .finalize()
+        .to_vec()
+}
+
+fn signed_payload(link_id: &str, equipment_id: &str, file_id: &str, expires: i64) -> String {
+    format!("{}:{}:{}:{}", link_id, equipment_id, file_id, expires)
+}
+
+pub fn sign_link(secret: &str, link_id: &str, equipment_id: &str, file_id: &str, expires: i64) -> String {
+    let mut mac = HmacSha256::new_from_slice(&signing_key(secret))
+        .expect("HMAC accepts keys of any length");
+    mac.update(signed_payload(link_id, equipment_id, file_id, expires).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Проверка подписи за постоянное время (`verify_slice`)
+pub fn verify_link(secret: &str, link_id: &str, equipment_id: &str, file_id: &str, expires: i64, sig: &str) -> bool {
+    let Ok(sig) = hex::decode(sig) else { return false };
+    let mut mac = HmacSha256::new_from_slice(&signing_key(secret))
+        .expect("HMAC accepts keys of any length");
+    mac.update(signed_payload(link_id, equipment_id, file_id, expires).as_bytes());
+    mac.verify_slice(&sig).is_ok()
+}
+
+/// Выдать подписанную ссылку на файл
+pub async fn create_file_link(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<CreateFileLinkRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (equipment_id, file_id) = path.into_inner();
+    load_equipment_file(&app_state.db_pool, &equipment_id, &file_id).await?;
+
+    let link_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let expires_at = now + Duration::minutes(body.expires_in_minutes.unwrap_or(DEFAULT_LINK_TTL_MINUTES));
+    let expires = expires_at.timestamp();
+
+    sqlx::query(
+        r#"INSERT INTO file_download_links
+           (id, equipment_id, file_id, expires_at, single_use, created_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&link_id)
+        .bind(&equipment_id)
+        .bind(&file_id)
+        .bind(expires_at)
+        .bind(body.single_use)
+        .bind(&user_id)
+        .bind(now)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let sig = sign_link(&app_state.config.auth.jwt_secret, &link_id, &equipment_id, &file_id, expires);
+    let url = format!(
+        "{}/{}/files/{}?link={}&expires={}&sig={}",
+        PUBLIC_FILES_PREFIX, equipment_id, file_id, link_id, expires, sig
+    );
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(FileLinkResponse {
+        link_id,
+        url,
+        expires_at,
+        single_use: body.single_use,
+    })))
+}
+
+/// Скачивание по подписанной ссылке (без авторизации)
+pub async fn download_signed_equipment_file(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    query: web::Query<SignedLinkQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, file_id) = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    // Подпись проверяется до обращения к БД, чтобы не раскрывать существование файлов
+    if !verify_link(&app_state.config.auth.jwt_secret, &query.link, &equipment_id, &file_id, query.expires, &query.sig) {
+        return Err(ApiError::Forbidden("Invalid download link".to_string()));
+    }
+    if Utc::now().timestamp() > query.expires {
+        return Err(ApiError::Forbidden("Download link has expired".to_string()));
+    }
+
+    let link: FileDownloadLink = sqlx::query_as(
+        "SELECT id, single_use, used_at, created_by FROM file_download_links WHERE id = ? AND equipment_id = ? AND file_id = ?"
+    )
+        .bind(&query.link)
+        .bind(&equipment_id)
+        .bind(&file_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::Forbidden("Download link has been revoked".to_string()))?;
+
+    if link.single_use {
+        // Атомарно «гасим» ссылку: параллельный запрос получит 0 строк
+        let claimed = sqlx::query(
+            "UPDATE file_download_links SET used_at = ? WHERE id = ? AND used_at IS NULL"
+        )
+            .bind(Utc::now())
+            .bind(&link.id)
+            .execute(pool)
+            .await?;
+        if claimed.rows_affected() == 0 {
+            return Err(ApiError::Forbidden("Download link has already been used".to_string()));
+        }
+    } else if link.used_at.is_none() {
+        sqlx::query("UPDATE file_download_links SET used_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&link.id)
+            .execute(pool)
+            .await?;
+    }
+
+    let served = async {
+        let file = load_equipment_file(pool, &equipment_id, &file_id).await?;
+        let filename = file.original_filename.clone();
+        let response = crate::equipment_handlers::file_response(pool, &app_state.storage, file, query.size, "private, no-store").await?;
+        Ok::<_, ApiError>((filename, response))
+    }.await;
+    let (filename, response) = match served {
+        Ok(served) => served,
+        Err(e) => {
+            // Файл не отдан — ссылка не должна считаться использованной
+            if link.single_use || link.used_at.is_none() {
+                if let Err(reset) = sqlx::query("UPDATE file_download_links SET used_at = NULL WHERE id = ?")
+                    .bind(&link.id)
+                    .execute(pool)
+                    .await
+                {
+                    log::error!("Failed to reset download link {}: {}", link.id, reset);
+                }
+            }
+            return Err(e);
+        }
+    };
+
+    let desc = format!(
+        "Downloaded file '{}' of equipment {} via signed link {}{}",
+        filename, equipment_id, link.id, if link.single_use { " (single-use)" } else { "" }
+    );
+    audit::audit(pool, &link.created_by, "download", "equipment_file", &file_id, &desc, &http_request).await;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_signature_binds_all_fields() {
+        let secret = "test-secret-with-enough-entropy-0123456789";
+        let sig = sign_link(secret, "link", "eq", "file", 1_700_000_000);
+        assert!(verify_link(secret, "link", "eq", "file", 1_700_000_000, &sig));
+        assert!(!verify_link(secret, "link", "eq", "file", 1_700_000_001, &sig));
+        assert!(!verify_link(secret, "link", "eq", "other", 1_700_000_000, &sig));
+        assert!(!verify_link("another-secret", "link", "eq", "file", 1_700_000_000, &sig));
+        assert!(!verify_link(secret, "link", "eq", "file", 1_700_000_000, "not-hex"));
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_download_keeps_single_use_link() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query(r#"
+            INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+            VALUES ('u1', 'engineer', 'engineer@lab.local', 'x', 'researcher', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO equipment (id, name, type_, created_at, updated_at) VALUES ('eq1', 'Centrifuge', 'instrument', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        // Содержимого в хранилище нет — отдача файла завершится ошибкой
+        let sha256 = crate::storage::sha256_hex(b"manual");
+        sqlx::query(r#"
+            INSERT INTO equipment_files (id, equipment_id, file_type, original_filename, stored_filename, file_path,
+                                         file_size, mime_type, storage_key, sha256, created_at)
+            VALUES ('f1', 'eq1', 'manual', 'manual.pdf', 'manual.pdf', '', 6, 'application/pdf', ?, ?, ?)
+        "#).bind(crate::storage::content_key(&sha256)).bind(&sha256).bind(now).execute(&pool).await.unwrap();
+        let expires_at = now + Duration::minutes(5);
+        sqlx::query(r#"
+            INSERT INTO file_download_links (id, equipment_id, file_id, expires_at, single_use, created_by, created_at)
+            VALUES ('l1', 'eq1', 'f1', ?, 1, 'u1', ?)
+        "#).bind(expires_at).bind(now).execute(&pool).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            storage: Arc::new(crate::storage::BlobStore::new(Arc::new(
+                crate::storage::LocalStorage::new(dir.path().to_str().unwrap()),
+            ))),
+        }));
+        let expires = expires_at.timestamp();
+        let query = SignedLinkQuery {
+            link: "l1".to_string(),
+            expires,
+            sig: sign_link(&app_state.config.auth.jwt_secret, "l1", "eq1", "f1", expires),
+            size: None,
+        };
+
+        let result = download_signed_equipment_file(
+            app_state,
+            web::Path::from(("eq1".to_string(), "f1".to_string())),
+            web::Query(query),
+            actix_web::test::TestRequest::default().to_http_request(),
+        ).await;
+        assert!(result.is_err());
+
+        let used_at: Option<DateTime<Utc>> = sqlx::query_scalar("SELECT used_at FROM file_download_links WHERE id = 'l1'")
+            .fetch_one(&pool).await.unwrap();
+        assert!(used_at.is_none());
+    }
+}
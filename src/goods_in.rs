@@ -0,0 +1,1081 @@
+// src/goods_in.rs
+//! Входной контроль (goods-in): приёмка поставки на карантин и решение QA.
+//!
+//! Поставка сопоставляется со строкой заказа поставщику (`purchase_orders` /
+//! `purchase_order_lines`): фиксируется поставщик, накладная, принятое
+//! количество против заказанного (недопоставка/перепоставка), номер и файл
+//! сертификата анализа (CoA). Принятая партия создаётся в статусе `quarantine`
+//! и не участвует в остатках, не расходуется через `use_reagent`,
+//! `dispense_units`, контейнеры и резервы экспериментов.
+//!
+//! Выпустить (`released` → партия `available`) или отклонить (`rejected` →
+//! партия `rejected`) поставку может только пользователь с правом
+//! `release_batch` (по умолчанию администратор). Выпуск требует приложенного
+//! CoA; отклонение требует причины и снимает количество со строки заказа,
+//! чтобы замену можно было принять по тому же заказу.
+//!
+//! Endpoints:
+//!   GET  /api/v1/goods-in/purchase-orders?status=&supplier=
+//!   POST /api/v1/goods-in/purchase-orders
+//!   GET  /api/v1/goods-in/purchase-orders/{id}          — заказ со строками и приёмками
+//!   POST /api/v1/goods-in/purchase-orders/{id}/cancel
+//!   GET  /api/v1/goods-in/receipts?qa_status=&purchase_order_id=
+//!   POST /api/v1/goods-in/receipts                      — принять поставку на карантин
+//!   GET  /api/v1/goods-in/receipts/{id}
+//!   POST /api/v1/goods-in/receipts/{id}/coa             — multipart: file, coa_number
+//!   GET  /api/v1/goods-in/receipts/{id}/coa
+//!   POST /api/v1/goods-in/receipts/{id}/release
+//!   POST /api/v1/goods-in/receipts/{id}/reject
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqliteExecutor, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::auth::get_current_user;
+use crate::error::{validate_unit, ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::notifications::{notify, NewNotification};
+use crate::query_builders::{validate_file_size, validate_mime_type};
+use crate::validator::UnitConverter;
+
+const PURCHASE_ORDER_STATUSES: [&str; 4] = ["open", "partially_received", "received", "cancelled"];
+
+const QA_STATUSES: [&str; 3] = ["pending", "released", "rejected"];
+
+/// Максимальный размер файла CoA (10 МБ)
+const MAX_COA_SIZE: usize = 10 * 1024 * 1024;
+
+const ALLOWED_COA_TYPES: &[&str] = &["application/pdf", "image/jpeg", "image/png"];
+
+const QUANTITY_TOLERANCE: f64 = 0.001;
+
+// ==================== MODELS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PurchaseOrder {
+    pub id: String,
+    pub po_number: String,
+    pub supplier: String,
+    pub status: String,
+    pub order_date: Option<DateTime<Utc>>,
+    pub expected_date: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PurchaseOrderLine {
+    pub id: String,
+    pub purchase_order_id: String,
+    pub reagent_id: String,
+    #[sqlx(default)]
+    pub reagent_name: Option<String>,
+    pub ordered_quantity: f64,
+    pub unit: String,
+    pub received_quantity: f64,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PurchaseOrderLine {
+    pub fn outstanding_quantity(&self) -> f64 {
+        (self.ordered_quantity - self.received_quantity).max(0.0)
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct GoodsReceipt {
+    pub id: String,
+    pub batch_id: String,
+    #[sqlx(default)]
+    pub batch_number: Option<String>,
+    #[sqlx(default)]
+    pub batch_status: Option<String>,
+    #[sqlx(default)]
+    pub reagent_id: Option<String>,
+    #[sqlx(default)]
+    pub reagent_name: Option<String>,
+    pub purchase_order_line_id: Option<String>,
+    #[sqlx(default)]
+    pub purchase_order_id: Option<String>,
+    #[sqlx(default)]
+    pub po_number: Option<String>,
+    pub supplier: String,
+    pub delivery_note: String,
+    pub ordered_quantity: Option<f64>,
+    pub received_quantity: f64,
+    pub unit: String,
+    pub coa_number: Option<String>,
+    pub coa_filename: Option<String>,
+    pub coa_mime_type: Option<String>,
+    pub coa_size: Option<i64>,
+    #[serde(skip_serializing)]
+    pub coa_storage_key: Option<String>,
+    #[serde(skip_serializing)]
+    pub coa_sha256: Option<String>,
+    pub coa_uploaded_by: Option<String>,
+    pub coa_uploaded_at: Option<DateTime<Utc>>,
+    pub inspection_notes: Option<String>,
+    pub qa_status: String,
+    pub qa_decided_by: Option<String>,
+    pub qa_decided_at: Option<DateTime<Utc>>,
+    pub qa_reason: Option<String>,
+    pub received_by: Option<String>,
+    pub received_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Соответствие принятого количества заказанному
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryMatch {
+    Short,
+    Exact,
+    Over,
+    /// Поставка без заказа
+    Unmatched,
+}
+
+/// Приёмка с расхождением против заказа
+#[derive(Debug, Serialize)]
+pub struct GoodsReceiptView {
+    #[serde(flatten)]
+    pub receipt: GoodsReceipt,
+    pub delivery_match: DeliveryMatch,
+    /// Принято минус заказано, в единицах приёмки
+    pub quantity_variance: Option<f64>,
+    pub coa_attached: bool,
+}
+
+// ==================== REQUESTS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct PurchaseOrderQuery {
+    pub status: Option<String>,
+    pub supplier: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct PurchaseOrderLineInput {
+    pub reagent_id: String,
+    #[validate(range(min = 0.001, message = "Ordered quantity must be positive"))]
+    pub ordered_quantity: f64,
+    pub unit: String,
+    #[validate(length(max = 500, message = "Notes max 500 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePurchaseOrderRequest {
+    #[validate(length(min = 1, max = 100, message = "PO number must be 1-100 characters"))]
+    pub po_number: String,
+    #[validate(length(min = 1, max = 255, message = "Supplier must be 1-255 characters"))]
+    pub supplier: String,
+    pub order_date: Option<DateTime<Utc>>,
+    pub expected_date: Option<DateTime<Utc>>,
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+    #[validate(length(min = 1, max = 200, message = "Purchase order must have 1-200 lines"))]
+    #[validate(nested)]
+    pub lines: Vec<PurchaseOrderLineInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReceiptQuery {
+    pub qa_status: Option<String>,
+    pub purchase_order_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ReceiveGoodsRequest {
+    /// Строка заказа; без неё нужно указать reagent_id и supplier
+    pub purchase_order_line_id: Option<String>,
+    pub reagent_id: Option<String>,
+    /// По умолчанию — поставщик из заказа
+    #[validate(length(min = 1, max = 255, message = "Supplier must be 1-255 characters"))]
+    pub supplier: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "Delivery note must be 1-100 characters"))]
+    pub delivery_note: String,
+    /// Заказанное количество для поставки без строки заказа
+    #[validate(range(min = 0.001, message = "Ordered quantity must be positive"))]
+    pub ordered_quantity: Option<f64>,
+    #[validate(range(min = 0.001, message = "Received quantity must be positive"))]
+    pub received_quantity: f64,
+    pub unit: String,
+    #[validate(length(min = 1, max = 100, message = "Batch number must be between 1 and 100 characters"))]
+    pub batch_number: String,
+    #[validate(length(max = 100, message = "Lot number cannot exceed 100 characters"))]
+    pub lot_number: Option<String>,
+    #[validate(length(max = 100, message = "Cat number cannot exceed 100 characters"))]
+    pub cat_number: Option<String>,
+    #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
+    pub manufacturer: Option<String>,
+    #[validate(range(min = 0.001, message = "Pack size must be positive"))]
+    pub pack_size: Option<f64>,
+    pub expiry_date: Option<DateTime<Utc>>,
+    #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
+    pub location: Option<String>,
+    #[validate(length(max = 100, message = "CoA number max 100 characters"))]
+    pub coa_number: Option<String>,
+    #[validate(length(max = 1000, message = "Inspection notes max 1000 characters"))]
+    pub inspection_notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ReleaseReceiptRequest {
+    #[validate(length(max = 1000, message = "Inspection notes max 1000 characters"))]
+    pub inspection_notes: Option<String>,
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RejectReceiptRequest {
+    #[validate(length(min = 1, max = 1000, message = "Reason must be 1-1000 characters"))]
+    pub reason: String,
+    #[validate(length(max = 1000, message = "Inspection notes max 1000 characters"))]
+    pub inspection_notes: Option<String>,
+}
+
+// ==================== HELPERS ====================
+
+/// Партия на карантине или отклонённая QA не может расходоваться
+pub fn ensure_released(status: &str) -> ApiResult<()> {
+    match status {
+        "quarantine" => Err(ApiError::bad_request(
+            "Batch is in QA quarantine and cannot be used until it is released"
+        )),
+        "rejected" => Err(ApiError::bad_request("Batch was rejected by QA and cannot be used")),
+        _ => Ok(()),
+    }
+}
+
+/// Статусы, которые меняются только через решение QA
+pub fn is_qa_hold(status: &str) -> bool {
+    matches!(status, "quarantine" | "rejected")
+}
+
+pub fn delivery_match(ordered: Option<f64>, received: f64) -> DeliveryMatch {
+    match ordered {
+        None => DeliveryMatch::Unmatched,
+        Some(ordered) if received < ordered - QUANTITY_TOLERANCE => DeliveryMatch::Short,
+        Some(ordered) if received > ordered + QUANTITY_TOLERANCE => DeliveryMatch::Over,
+        Some(_) => DeliveryMatch::Exact,
+    }
+}
+
+fn receipt_view(receipt: GoodsReceipt) -> GoodsReceiptView {
+    GoodsReceiptView {
+        delivery_match: delivery_match(receipt.ordered_quantity, receipt.received_quantity),
+        quantity_variance: receipt.ordered_quantity.map(|o| receipt.received_quantity - o),
+        coa_attached: receipt.coa_storage_key.is_some(),
+        receipt,
+    }
+}
+
+/// Пересчёт количества между единицами строки заказа и приёмки
+fn convert_quantity(quantity: f64, from: &str, to: &str) -> ApiResult<f64> {
+    if from == to {
+        return Ok(quantity);
+    }
+    UnitConverter::new().convert(quantity, from, to).map_err(|e| ApiError::bad_request(&format!(
+        "{}; receive in the purchase order unit ({})", e, from
+    )))
+}
+
+const RECEIPT_SELECT: &str = r#"
+    SELECT gr.*, b.batch_number, b.status AS batch_status, b.reagent_id, r.name AS reagent_name,
+           pol.purchase_order_id, po.po_number
+    FROM goods_receipts gr
+    JOIN batches b ON b.id = gr.batch_id
+    LEFT JOIN reagents r ON r.id = b.reagent_id
+    LEFT JOIN purchase_order_lines pol ON pol.id = gr.purchase_order_line_id
+    LEFT JOIN purchase_orders po ON po.id = pol.purchase_order_id
+"#;
+
+async fn get_receipt_or_404(executor: impl SqliteExecutor<'_>, id: &str) -> ApiResult<GoodsReceipt> {
+    sqlx::query_as(&format!("{} WHERE gr.id = ?", RECEIPT_SELECT))
+        .bind(id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Goods receipt"))
+}
+
+async fn get_order_or_404(pool: &SqlitePool, id: &str) -> ApiResult<PurchaseOrder> {
+    sqlx::query_as("SELECT * FROM purchase_orders WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Purchase order"))
+}
+
+async fn get_order_line_or_404(executor: impl SqliteExecutor<'_>, id: &str) -> ApiResult<PurchaseOrderLine> {
+    sqlx::query_as(
+        r#"SELECT pol.*, r.name AS reagent_name
+           FROM purchase_order_lines pol
+           LEFT JOIN reagents r ON r.id = pol.reagent_id
+           WHERE pol.id = ?"#
+    )
+        .bind(id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Purchase order line"))
+}
+
+/// Статус заказа по строкам: всё принято → received, что-то принято → partially_received
+async fn refresh_order_status(conn: &mut SqliteConnection, order_id: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"UPDATE purchase_orders
+           SET status = CASE
+                   WHEN status = 'cancelled' THEN status
+                   WHEN NOT EXISTS (SELECT 1 FROM purchase_order_lines l
+                                    WHERE l.purchase_order_id = ?1 AND l.received_quantity < l.ordered_quantity - ?2)
+                       THEN 'received'
+                   WHEN EXISTS (SELECT 1 FROM purchase_order_lines l
+                                WHERE l.purchase_order_id = ?1 AND l.received_quantity > ?2)
+                       THEN 'partially_received'
+                   ELSE 'open' END,
+               updated_at = ?3
+           WHERE id = ?1"#
+    )
+        .bind(order_id)
+        .bind(QUANTITY_TOLERANCE)
+        .bind(now)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+fn map_unique_violation(e: sqlx::Error, message: &str) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.message().contains("UNIQUE") => ApiError::bad_request(message),
+        other => other.into(),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+// ==================== PURCHASE ORDERS ====================
+
+pub async fn list_purchase_orders(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<PurchaseOrderQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(ref status) = query.status {
+        if !PURCHASE_ORDER_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Unknown status '{}'", status)));
+        }
+    }
+    let orders: Vec<PurchaseOrder> = sqlx::query_as(
+        r#"SELECT * FROM purchase_orders
+           WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR supplier LIKE '%' || ?2 || '%')
+           ORDER BY status IN ('received', 'cancelled'), created_at DESC"#
+    )
+        .bind(&query.status)
+        .bind(&query.supplier)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(orders)))
+}
+
+async fn order_details(pool: &SqlitePool, order: PurchaseOrder) -> ApiResult<serde_json::Value> {
+    let lines: Vec<PurchaseOrderLine> = sqlx::query_as(
+        r#"SELECT pol.*, r.name AS reagent_name
+           FROM purchase_order_lines pol
+           LEFT JOIN reagents r ON r.id = pol.reagent_id
+           WHERE pol.purchase_order_id = ?
+           ORDER BY pol.created_at, pol.id"#
+    )
+        .bind(&order.id)
+        .fetch_all(pool)
+        .await?;
+    let receipts: Vec<GoodsReceipt> = sqlx::query_as(&format!(
+        "{} WHERE pol.purchase_order_id = ? ORDER BY gr.received_at", RECEIPT_SELECT
+    ))
+        .bind(&order.id)
+        .fetch_all(pool)
+        .await?;
+
+    let lines: Vec<serde_json::Value> = lines.into_iter()
+        .map(|line| serde_json::json!({
+            "outstanding_quantity": line.outstanding_quantity(),
+            "line": line,
+        }))
+        .collect();
+    let receipts: Vec<GoodsReceiptView> = receipts.into_iter().map(receipt_view).collect();
+    Ok(serde_json::json!({ "order": order, "lines": lines, "receipts": receipts }))
+}
+
+pub async fn get_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let order = get_order_or_404(pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(order_details(pool, order).await?)))
+}
+
+pub async fn create_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreatePurchaseOrderRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    for line in &body.lines {
+        validate_unit(&line.unit)?;
+        sqlx::query_as::<_, Reagent>("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+            .bind(&line.reagent_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Reagent"))?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let po_number = body.po_number.trim();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"INSERT INTO purchase_orders
+           (id, po_number, supplier, status, order_date, expected_date, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, 'open', ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(po_number)
+        .bind(body.supplier.trim())
+        .bind(body.order_date)
+        .bind(body.expected_date)
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, "A purchase order with this number already exists"))?;
+    for line in &body.lines {
+        sqlx::query(
+            r#"INSERT INTO purchase_order_lines
+               (id, purchase_order_id, reagent_id, ordered_quantity, unit, received_quantity, notes, created_at)
+               VALUES (?, ?, ?, ?, ?, 0, ?, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&id)
+            .bind(&line.reagent_id)
+            .bind(line.ordered_quantity)
+            .bind(&line.unit)
+            .bind(&line.notes)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    crate::audit::audit(pool, &claims.sub, "create", "purchase_order", &id,
+        &format!("Purchase order {} from {} ({} lines)", po_number, body.supplier.trim(), body.lines.len()), &http_request).await;
+
+    let order = get_order_or_404(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(order_details(pool, order).await?)))
+}
+
+pub async fn cancel_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let order = get_order_or_404(pool, &path.into_inner()).await?;
+    if matches!(order.status.as_str(), "received" | "cancelled") {
+        return Err(ApiError::bad_request(&format!("Purchase order is already {}", order.status)));
+    }
+
+    sqlx::query("UPDATE purchase_orders SET status = 'cancelled', updated_at = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(&order.id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &claims.sub, "update", "purchase_order", &order.id,
+        &format!("Purchase order {} cancelled", order.po_number), &http_request).await;
+
+    let order = get_order_or_404(pool, &order.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(order_details(pool, order).await?)))
+}
+
+// ==================== RECEIVING ====================
+
+pub async fn list_receipts(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ReceiptQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(ref qa_status) = query.qa_status {
+        if !QA_STATUSES.contains(&qa_status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Unknown QA status '{}'", qa_status)));
+        }
+    }
+    let receipts: Vec<GoodsReceipt> = sqlx::query_as(&format!(
+        r#"{} WHERE (?1 IS NULL OR gr.qa_status = ?1) AND (?2 IS NULL OR pol.purchase_order_id = ?2)
+           ORDER BY gr.qa_status != 'pending', gr.received_at DESC"#,
+        RECEIPT_SELECT,
+    ))
+        .bind(&query.qa_status)
+        .bind(&query.purchase_order_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    let views: Vec<GoodsReceiptView> = receipts.into_iter().map(receipt_view).collect();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(views)))
+}
+
+pub async fn get_receipt(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let receipt = get_receipt_or_404(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(receipt_view(receipt))))
+}
+
+/// Приёмка поставки: партия создаётся на карантине, строка заказа пополняется
+pub async fn receive_goods(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<ReceiveGoodsRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    validate_unit(&body.unit)?;
+
+    // Сопоставление с заказом: реагент, поставщик и ожидаемое количество берутся из строки
+    let (line, order) = match body.purchase_order_line_id {
+        Some(ref line_id) => {
+            let line = get_order_line_or_404(pool, line_id).await?;
+            let order = get_order_or_404(pool, &line.purchase_order_id).await?;
+            if order.status == "cancelled" {
+                return Err(ApiError::bad_request(&format!("Purchase order {} is cancelled", order.po_number)));
+            }
+            if line.outstanding_quantity() <= QUANTITY_TOLERANCE {
+                return Err(ApiError::bad_request(&format!(
+                    "Purchase order {} line for {} is already fully received",
+                    order.po_number, line.reagent_name.as_deref().unwrap_or(&line.reagent_id)
+                )));
+            }
+            if body.reagent_id.as_deref().is_some_and(|r| r != line.reagent_id) {
+                return Err(ApiError::bad_request("reagent_id does not match the purchase order line"));
+            }
+            if body.ordered_quantity.is_some() {
+                return Err(ApiError::bad_request("ordered_quantity is taken from the purchase order line"));
+            }
+            (Some(line), Some(order))
+        }
+        None => (None, None),
+    };
+
+    let reagent_id = match (&line, &body.reagent_id) {
+        (Some(line), _) => line.reagent_id.clone(),
+        (None, Some(reagent_id)) => reagent_id.clone(),
+        (None, None) => return Err(ApiError::bad_request("Either purchase_order_line_id or reagent_id is required")),
+    };
+    let supplier = match (non_empty(&body.supplier), &order) {
+        (Some(supplier), _) => supplier.to_string(),
+        (None, Some(order)) => order.supplier.clone(),
+        (None, None) => return Err(ApiError::bad_request("Supplier is required for deliveries without a purchase order")),
+    };
+    let (ordered_quantity, line_quantity) = match line {
+        Some(ref line) => (
+            Some(convert_quantity(line.outstanding_quantity(), &line.unit, &body.unit)?),
+            Some(convert_quantity(body.received_quantity, &body.unit, &line.unit)?),
+        ),
+        None => (body.ordered_quantity, None),
+    };
+
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+        .bind(&reagent_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Reagent"))?;
+
+    let now = Utc::now();
+    let batch_id = Uuid::new_v4().to_string();
+    let receipt_id = Uuid::new_v4().to_string();
+    let batch_number = body.batch_number.trim();
+    let delivery_note = body.delivery_note.trim();
+
+    let mut tx = pool.begin().await?;
+
+    // Освобождаем номер, занятый удалённой партией (как при обычном создании)
+    sqlx::query(
+        "UPDATE batches SET batch_number = batch_number || '_deleted_' || id WHERE reagent_id = ? AND batch_number = ? AND deleted_at IS NOT NULL"
+    )
+        .bind(&reagent_id)
+        .bind(batch_number)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"INSERT INTO batches (
+            id, reagent_id, lot_number, batch_number, cat_number,
+            quantity, original_quantity, reserved_quantity, unit, pack_size,
+            expiry_date, supplier, manufacturer, received_date,
+            status, location, notes, created_by, updated_by,
+            created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, 0.0, ?, ?, ?, ?, ?, ?, 'quarantine', ?, ?, ?, ?, ?, ?)"#,
+    )
+        .bind(&batch_id)
+        .bind(&reagent_id)
+        .bind(&body.lot_number)
+        .bind(batch_number)
+        .bind(&body.cat_number)
+        .bind(body.received_quantity)
+        .bind(body.received_quantity)
+        .bind(&body.unit)
+        .bind(body.pack_size)
+        .bind(body.expiry_date)
+        .bind(&supplier)
+        .bind(&body.manufacturer)
+        .bind(now)
+        .bind(&body.location)
+        .bind(format!("Received on delivery note {}", delivery_note))
+        .bind(&claims.sub)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, "A batch with this number already exists for the reagent"))?;
+
+    sqlx::query(
+        r#"INSERT INTO goods_receipts
+           (id, batch_id, purchase_order_line_id, supplier, delivery_note, ordered_quantity, received_quantity, unit,
+            coa_number, inspection_notes, qa_status, received_by, received_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'pending', ?, ?, ?)"#
+    )
+        .bind(&receipt_id)
+        .bind(&batch_id)
+        .bind(&body.purchase_order_line_id)
+        .bind(&supplier)
+        .bind(delivery_note)
+        .bind(ordered_quantity)
+        .bind(body.received_quantity)
+        .bind(&body.unit)
+        .bind(non_empty(&body.coa_number))
+        .bind(non_empty(&body.inspection_notes))
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    if let (Some(line), Some(line_quantity)) = (&line, line_quantity) {
+        sqlx::query("UPDATE purchase_order_lines SET received_quantity = received_quantity + ? WHERE id = ?")
+            .bind(line_quantity)
+            .bind(&line.id)
+            .execute(&mut *tx)
+            .await?;
+        refresh_order_status(&mut tx, &line.purchase_order_id, now).await?;
+    }
+
+    tx.commit().await?;
+
+    let receipt = receipt_view(get_receipt_or_404(pool, &receipt_id).await?);
+    let po_ref = order.as_ref().map(|o| format!(" against PO {}", o.po_number)).unwrap_or_default();
+    crate::audit::audit(pool, &claims.sub, "receive", "goods_receipt", &receipt_id,
+        &format!("Received {} {} of \"{}\" batch {} from {} (delivery note {}){} into quarantine",
+            body.received_quantity, body.unit, reagent.name, batch_number, supplier, delivery_note, po_ref), &http_request).await;
+    info!("📦 Batch {} of \"{}\" received into quarantine{}", batch_number, reagent.name, po_ref);
+
+    let mismatch = match receipt.delivery_match {
+        DeliveryMatch::Short => " (short delivery)",
+        DeliveryMatch::Over => " (over delivery)",
+        _ => "",
+    };
+    // Поставка уже сохранена: сбой уведомления не должен превращаться в ошибку запроса
+    if let Err(e) = notify(pool, &NewNotification {
+        category: "qa_quarantine",
+        severity: if mismatch.is_empty() { "info" } else { "warning" },
+        title: "Delivery awaiting QA release".to_string(),
+        message: format!(
+            "Batch {} of \"{}\" from {} is in quarantine{}",
+            batch_number, reagent.name, supplier, mismatch
+        ),
+        entity_type: Some("goods_receipt"),
+        entity_id: Some(&receipt_id),
+        dedupe_key: format!("goods_receipt:{}:pending", receipt_id),
+    }).await {
+        log::error!("Failed to notify about goods receipt {}: {}", receipt_id, e);
+    }
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(receipt)))
+}
+
+// ==================== CERTIFICATE OF ANALYSIS ====================
+
+async fn read_text_field(field: &mut actix_multipart::Field) -> ApiResult<String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8(bytes)
+        .map(|v| v.trim().to_string())
+        .map_err(|_| ApiError::bad_request("Form field must be UTF-8 text"))
+}
+
+/// Загрузка сертификата анализа (PDF или скан). Повторная загрузка заменяет файл.
+pub async fn upload_coa(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let receipt = get_receipt_or_404(pool, &path.into_inner()).await?;
+    if receipt.qa_status != "pending" {
+        return Err(ApiError::bad_request(&format!("Receipt is already {}", receipt.qa_status)));
+    }
+
+    let mut file: Option<(Vec<u8>, String, String)> = None;
+    let mut coa_number: Option<String> = None;
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| ApiError::bad_request(&format!("Multipart error: {}", e)))?;
+        let content_disposition = field.content_disposition();
+        match content_disposition.get_name().unwrap_or("") {
+            "file" => {
+                let filename = content_disposition
+                    .get_filename()
+                    .ok_or_else(|| ApiError::bad_request("Filename not provided"))?
+                    .to_string();
+                let mime = field.content_type()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                validate_mime_type(&mime, ALLOWED_COA_TYPES)?;
+
+                let mut bytes = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    bytes.extend_from_slice(&chunk);
+                    validate_file_size(bytes.len(), MAX_COA_SIZE)?;
+                }
+                file = Some((bytes, filename, mime));
+            }
+            "coa_number" => {
+                let value = read_text_field(&mut field).await?;
+                if value.chars().count() > 100 {
+                    return Err(ApiError::bad_request("CoA number max 100 characters"));
+                }
+                if !value.is_empty() {
+                    coa_number = Some(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (bytes, filename, mime) = file.ok_or_else(|| ApiError::bad_request("No file provided"))?;
+    if bytes.is_empty() {
+        return Err(ApiError::bad_request("CoA file is empty"));
+    }
+    let blob = app_state.storage.store(&bytes).await?;
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    let previous_key: Option<String> = sqlx::query_scalar("SELECT coa_storage_key FROM goods_receipts WHERE id = ?")
+        .bind(&receipt.id)
+        .fetch_one(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"UPDATE goods_receipts
+           SET coa_number = COALESCE(?, coa_number), coa_filename = ?, coa_mime_type = ?, coa_size = ?,
+               coa_storage_key = ?, coa_sha256 = ?, coa_uploaded_by = ?, coa_uploaded_at = ?, updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(&coa_number)
+        .bind(&filename)
+        .bind(&mime)
+        .bind(blob.size)
+        .bind(&blob.key)
+        .bind(&blob.sha256)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .bind(&receipt.id)
+        .execute(&mut *tx)
+        .await?;
+    // Заменённый сертификат больше никому не нужен, если содержимое не общее
+    if let Some(previous_key) = previous_key.filter(|k| *k != blob.key) {
+        app_state.storage.release(&mut tx, &previous_key).await?;
+    }
+    tx.commit().await?;
+    app_state.storage.confirm(&blob, &bytes).await?;
+
+    crate::audit::audit(pool, &claims.sub, "upload", "goods_receipt", &receipt.id,
+        &format!("CoA '{}' attached to batch {}", filename, receipt.batch_number.as_deref().unwrap_or(&receipt.batch_id)),
+        &http_request).await;
+
+    let receipt = get_receipt_or_404(pool, &receipt.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(receipt_view(receipt))))
+}
+
+pub async fn download_coa(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let receipt = get_receipt_or_404(&app_state.db_pool, &path.into_inner()).await?;
+    let (Some(key), Some(sha256)) = (&receipt.coa_storage_key, &receipt.coa_sha256) else {
+        return Err(ApiError::not_found("Certificate of analysis"));
+    };
+    let contents = app_state.storage.load(key, sha256).await?;
+    let filename = receipt.coa_filename.as_deref().unwrap_or("coa");
+
+    Ok(HttpResponse::Ok()
+        .content_type(receipt.coa_mime_type.clone().unwrap_or_else(|| "application/octet-stream".to_string()))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .insert_header(("Cache-Control", "private, max-age=3600"))
+        .body(contents))
+}
+
+// ==================== QA DECISION ====================
+
+/// Читается внутри транзакции решения; сами UPDATE ещё раз проверяют статусы
+async fn get_pending_receipt(conn: &mut SqliteConnection, id: &str) -> ApiResult<GoodsReceipt> {
+    let receipt = get_receipt_or_404(&mut *conn, id).await?;
+    if receipt.qa_status != "pending" {
+        return Err(ApiError::bad_request(&format!("Receipt is already {}", receipt.qa_status)));
+    }
+    if receipt.batch_status.as_deref() != Some("quarantine") {
+        return Err(ApiError::bad_request("Batch is no longer in quarantine"));
+    }
+    Ok(receipt)
+}
+
+/// Выпуск после проверки CoA и осмотра: партия становится доступной
+pub async fn release_receipt(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<ReleaseReceiptRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let mut tx = pool.begin().await?;
+    let receipt = get_pending_receipt(&mut tx, &path.into_inner()).await?;
+    if receipt.coa_storage_key.is_none() {
+        return Err(ApiError::bad_request("Attach the certificate of analysis before releasing the batch"));
+    }
+
+    let now = Utc::now();
+    let decided = sqlx::query(
+        r#"UPDATE goods_receipts
+           SET qa_status = 'released', qa_decided_by = ?, qa_decided_at = ?, qa_reason = ?,
+               inspection_notes = COALESCE(?, inspection_notes), updated_at = ?
+           WHERE id = ? AND qa_status = 'pending'"#
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(non_empty(&body.notes))
+        .bind(non_empty(&body.inspection_notes))
+        .bind(now)
+        .bind(&receipt.id)
+        .execute(&mut *tx)
+        .await?;
+    if decided.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Receipt has already been decided"));
+    }
+    // Просроченная ещё на карантине партия выпускается сразу как expired
+    let status: Option<String> = sqlx::query_scalar(
+        r#"UPDATE batches
+           SET status = CASE WHEN expiry_date IS NOT NULL AND expiry_date < ?1 THEN 'expired'
+                             WHEN quantity <= 0 THEN 'depleted'
+                             ELSE 'available' END,
+               updated_by = ?2, updated_at = ?1
+           WHERE id = ?3 AND status = 'quarantine'
+           RETURNING status"#
+    )
+        .bind(now)
+        .bind(&claims.sub)
+        .bind(&receipt.batch_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let status = status.ok_or_else(|| ApiError::bad_request("Batch is no longer in quarantine"))?;
+    tx.commit().await?;
+
+    let batch_number = receipt.batch_number.as_deref().unwrap_or(&receipt.batch_id);
+    crate::audit::audit(pool, &claims.sub, "release", "goods_receipt", &receipt.id,
+        &format!("QA released batch {} (status: {})", batch_number, status), &http_request).await;
+    info!("✅ QA released batch {} → {}", batch_number, status);
+
+    let receipt = get_receipt_or_404(pool, &receipt.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        receipt_view(receipt),
+        format!("Batch {} released as {}", batch_number, status),
+    )))
+}
+
+/// Отклонение поставки: партия остаётся заблокированной, строка заказа снова ждёт поставки
+pub async fn reject_receipt(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<RejectReceiptRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiError::bad_request("Reason is required"));
+    }
+
+    let mut tx = pool.begin().await?;
+    let receipt = get_pending_receipt(&mut tx, &path.into_inner()).await?;
+    let line = match receipt.purchase_order_line_id {
+        Some(ref line_id) => Some(get_order_line_or_404(&mut *tx, line_id).await?),
+        None => None,
+    };
+    let line_quantity = match line {
+        Some(ref line) => Some(convert_quantity(receipt.received_quantity, &receipt.unit, &line.unit)?),
+        None => None,
+    };
+
+    let now = Utc::now();
+    let decided = sqlx::query(
+        r#"UPDATE goods_receipts
+           SET qa_status = 'rejected', qa_decided_by = ?, qa_decided_at = ?, qa_reason = ?,
+               inspection_notes = COALESCE(?, inspection_notes), updated_at = ?
+           WHERE id = ? AND qa_status = 'pending'"#
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(reason)
+        .bind(non_empty(&body.inspection_notes))
+        .bind(now)
+        .bind(&receipt.id)
+        .execute(&mut *tx)
+        .await?;
+    if decided.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Receipt has already been decided"));
+    }
+    let blocked = sqlx::query(
+        "UPDATE batches SET status = 'rejected', updated_by = ?, updated_at = ? WHERE id = ? AND status = 'quarantine'"
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&receipt.batch_id)
+        .execute(&mut *tx)
+        .await?;
+    if blocked.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Batch is no longer in quarantine"));
+    }
+    if let (Some(line), Some(line_quantity)) = (&line, line_quantity) {
+        sqlx::query("UPDATE purchase_order_lines SET received_quantity = MAX(received_quantity - ?, 0) WHERE id = ?")
+            .bind(line_quantity)
+            .bind(&line.id)
+            .execute(&mut *tx)
+            .await?;
+        refresh_order_status(&mut tx, &line.purchase_order_id, now).await?;
+    }
+    tx.commit().await?;
+
+    let batch_number = receipt.batch_number.as_deref().unwrap_or(&receipt.batch_id);
+    crate::audit::audit(pool, &claims.sub, "reject", "goods_receipt", &receipt.id,
+        &format!("QA rejected batch {}: {}", batch_number, reason), &http_request).await;
+    info!("⛔ QA rejected batch {}: {}", batch_number, reason);
+
+    let receipt = get_receipt_or_404(pool, &receipt.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        receipt_view(receipt),
+        format!("Batch {} rejected", batch_number),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::HttpMessage;
+
+    #[test]
+    fn test_delivery_match_and_qa_hold() {
+        assert_eq!(delivery_match(None, 5.0), DeliveryMatch::Unmatched);
+        assert_eq!(delivery_match(Some(10.0), 8.0), DeliveryMatch::Short);
+        assert_eq!(delivery_match(Some(10.0), 10.0004), DeliveryMatch::Exact);
+        assert_eq!(delivery_match(Some(10.0), 12.0), DeliveryMatch::Over);
+
+        assert!(ensure_released("quarantine").is_err());
+        assert!(ensure_released("rejected").is_err());
+        assert!(ensure_released("available").is_ok());
+        assert!(is_qa_hold("quarantine") && is_qa_hold("rejected"));
+        assert!(!is_qa_hold("expired"));
+    }
+
+    #[actix_rt::test]
+    async fn test_second_reject_does_not_touch_order_line_again() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        sqlx::query(r#"
+            INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+            VALUES ('qa', 'inspector', 'qa@lab.local', 'x', 'admin', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO purchase_orders (id, po_number, supplier, created_at, updated_at) VALUES ('po1', 'PO-1', 'Acme', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO purchase_order_lines (id, purchase_order_id, reagent_id, ordered_quantity, unit, received_quantity, created_at)
+            VALUES ('pol1', 'po1', 'r1', 10, 'l', 10, ?)
+        "#).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, received_date, status, created_at, updated_at)
+            VALUES ('b1', 'r1', 'B-1', 10, 10, 'l', ?, 'quarantine', ?, ?)
+        "#).bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO goods_receipts (id, batch_id, purchase_order_line_id, supplier, delivery_note, received_quantity, unit, received_at, updated_at)
+            VALUES ('gr1', 'b1', 'pol1', 'Acme', 'DN-1', 10, 'l', ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            storage: Arc::new(crate::storage::BlobStore::new(Arc::new(
+                crate::storage::LocalStorage::new(dir.path().to_str().unwrap()),
+            ))),
+        }));
+        let reject = || {
+            let request = actix_web::test::TestRequest::default().to_http_request();
+            request.extensions_mut().insert(crate::auth::Claims {
+                sub: "qa".to_string(),
+                username: "qa".to_string(),
+                email: "qa@lab.local".to_string(),
+                role: crate::auth::UserRole::Admin,
+                exp: 0,
+                iat: 0,
+            });
+            let body = RejectReceiptRequest { reason: "Broken seal".to_string(), inspection_notes: None };
+            reject_receipt(app_state.clone(), web::Path::from("gr1".to_string()), web::Json(body), request)
+        };
+
+        reject().await.unwrap();
+        assert!(reject().await.is_err());
+
+        let received: f64 = sqlx::query_scalar("SELECT received_quantity FROM purchase_order_lines WHERE id = 'pol1'")
+            .fetch_one(&pool).await.unwrap();
+        assert!(received.abs() < 1e-9);
+    }
+}
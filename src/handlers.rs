@@ -212,6 +212,7 @@ pub async fn use_reagent(
         .await
         .map_err(|_| ApiError::batch_not_found(&batch_id))?;
 
+    crate::goods_in::ensure_released(&batch.status)?;
     if batch.status != "available" {
         return Err(ApiError::BadRequest("Batch is not available for use".to_string()));
     }
@@ -251,34 +252,9 @@ pub async fn use_reagent(
         .execute(&mut *tx)
         .await?;
 
-    // Deduct from specific placement if placement_id is provided
+    // Deduct from the container at the given placement (the placement itself stays)
     if let Some(ref placement_id) = request.placement_id {
-        let placement: Option<(String, f64)> = sqlx::query_as(
-            "SELECT id, quantity FROM batch_placements WHERE id = ? AND batch_id = ?"
-        )
-        .bind(placement_id)
-        .bind(&batch_id)
-        .fetch_optional(&mut *tx)
-        .await?;
-
-        if let Some((_pid, p_qty)) = placement {
-            let new_p_qty = p_qty - request.quantity_used;
-            if new_p_qty <= 0.001 {
-                // Remove empty placement
-                sqlx::query("DELETE FROM batch_placements WHERE id = ?")
-                    .bind(placement_id)
-                    .execute(&mut *tx)
-                    .await?;
-            } else {
-                sqlx::query("UPDATE batch_placements SET quantity = ?, updated_at = ? WHERE id = ?")
-                    .bind(new_p_qty.max(0.0))
-                    .bind(&now)
-                    .bind(placement_id)
-                    .execute(&mut *tx)
-                    .await?;
-            }
-        }
-        // If placement not found, still proceed (backward compat — batch total was already decremented)
+        crate::container_handlers::draw_from_placement(&mut tx, placement_id, &batch_id, request.quantity_used).await?;
     }
 
     tx.commit().await?;
@@ -392,6 +368,10 @@ pub async fn get_dashboard_stats(
         expiring_soon: i64,
         total_equipment: i64,
         equipment_alerts: i64,
+        low_stock_parts: i64,
+        parts_due_replacement: i64,
+        expiring_service_contracts: i64,
+        expiring_warranties: i64,
         active_experiments: i64,
     }
 
@@ -409,8 +389,12 @@ pub async fn get_dashboard_stats(
         .await?;
 
     let expiring_soon: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM batches WHERE expiry_date IS NOT NULL AND expiry_date <= datetime('now', '+30 days') AND status = 'available' AND deleted_at IS NULL AND reagent_id NOT IN (SELECT id FROM reagents WHERE deleted_at IS NOT NULL)"
+        "SELECT COUNT(*) FROM batches WHERE ((expiry_date IS NOT NULL AND expiry_date <= datetime('now', '+30 days')) \
+         OR EXISTS (SELECT 1 FROM batch_containers bc WHERE bc.batch_id = batches.id AND bc.status IN ('full', 'partial') \
+                    AND bc.quantity > 0 AND bc.open_expiry_date <= ?)) \
+         AND status = 'available' AND deleted_at IS NULL AND reagent_id NOT IN (SELECT id FROM reagents WHERE deleted_at IS NOT NULL)"
     )
+        .bind(Utc::now() + chrono::Duration::days(30))
         .fetch_one(&app_state.db_pool)
         .await?;
 
@@ -430,6 +414,30 @@ pub async fn get_dashboard_stats(
         .await
         .unwrap_or((0,));
 
+    // Spare parts at or below their minimum stock
+    let low_stock_parts: (i64,) = sqlx::query_as(&format!(
+        "SELECT COUNT(*) FROM equipment_parts p JOIN equipment e ON p.equipment_id = e.id WHERE e.status != 'retired' AND {}",
+        crate::spare_parts_handlers::LOW_STOCK_CONDITION
+    ))
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap_or((0,));
+
+    // Spare parts whose scheduled replacement date has passed
+    let parts_due_replacement: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM equipment_parts p JOIN equipment e ON p.equipment_id = e.id WHERE e.status != 'retired' AND p.next_replacement IS NOT NULL AND date(p.next_replacement) <= date('now') AND p.status NOT IN ('replaced', 'missing')"
+    )
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap_or((0,));
+
+    // Warranties and service contracts inside their alert window (incl. recently expired)
+    let coverage = crate::service_contracts::load_expiring_coverage(&app_state.db_pool, None)
+        .await
+        .unwrap_or_default();
+    let expiring_service_contracts = coverage.iter().filter(|c| c.kind == "contract").count() as i64;
+    let expiring_warranties = coverage.iter().filter(|c| c.kind == "warranty").count() as i64;
+
     // Active experiments: in_progress + planned
     let active_experiments: (i64,) = sqlx::query_as(
         "SELECT COUNT(*) FROM experiments WHERE status IN ('in_progress', 'planned')"
@@ -445,6 +453,10 @@ pub async fn get_dashboard_stats(
         expiring_soon: expiring_soon.0,
         total_equipment: total_equipment.0,
         equipment_alerts: equipment_alerts.0,
+        low_stock_parts: low_stock_parts.0,
+        parts_due_replacement: parts_due_replacement.0,
+        expiring_service_contracts,
+        expiring_warranties,
         active_experiments: active_experiments.0,
     };
 
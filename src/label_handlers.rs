@@ -0,0 +1,241 @@
+// src/label_handlers.rs
+//! Печать этикеток GHS для контейнеров и партий.
+//!
+//! Endpoints:
+//!   GET  /api/v1/labels/layouts
+//!   POST /api/v1/labels                       { container_ids: [...], format, layout, ... }
+//!   GET  /api/v1/containers/{id}/label?format=pdf|zpl&layout=&symbology=&copies=&start_at=&dpi=
+//!   GET  /api/v1/batches/{id}/labels          — все контейнеры партии (после split)
+
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::AppState;
+use crate::barcodes::Symbology;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::labels::{self, LabelData, LabelFormat, LABEL_LAYOUTS};
+
+/// Максимум этикеток в одном запросе
+const MAX_LABELS: usize = 500;
+
+const LABEL_SELECT: &str = r#"
+    SELECT bc.id AS container_id, bc.short_code AS container_code, bc.sequence_number,
+           bc.quantity AS container_quantity,
+           (SELECT COUNT(*) FROM batch_containers x WHERE x.batch_id = b.id AND x.status != 'disposed') AS container_count,
+           b.short_code AS batch_code, b.batch_number, b.lot_number, b.expiry_date, b.quantity, b.unit,
+           r.name AS reagent_name, r.cas_number, r.formula, r.storage_conditions, r.hazard_pictograms, r.signal_word
+    FROM batches b
+    JOIN reagents r ON r.id = b.reagent_id
+"#;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct LabelRow {
+    container_id: Option<String>,
+    container_code: Option<String>,
+    sequence_number: Option<i64>,
+    container_quantity: Option<f64>,
+    container_count: i64,
+    batch_code: Option<String>,
+    batch_number: String,
+    lot_number: Option<String>,
+    expiry_date: Option<String>,
+    quantity: f64,
+    unit: String,
+    reagent_name: String,
+    cas_number: Option<String>,
+    formula: Option<String>,
+    storage_conditions: Option<String>,
+    hazard_pictograms: Option<String>,
+    signal_word: Option<String>,
+}
+
+fn format_quantity(value: f64, unit: &str) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    format!("{} {}", rounded, unit)
+}
+
+impl From<LabelRow> for LabelData {
+    fn from(row: LabelRow) -> Self {
+        let pictograms = labels::parse_pictograms(row.hazard_pictograms.as_deref());
+        let signal_word = labels::effective_signal_word(row.signal_word.as_deref(), &pictograms);
+        let expiry_date = row.expiry_date.as_deref()
+            .and_then(|d| d.get(..10))
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+        let (quantity, container, code) = match row.container_id {
+            Some(id) => (
+                row.container_quantity.map(|q| format_quantity(q, &row.unit)),
+                row.sequence_number.map(|n| format!("{}/{}", n, row.container_count)),
+                row.container_code.unwrap_or(id),
+            ),
+            None => (Some(format_quantity(row.quantity, &row.unit)), None, row.batch_code.unwrap_or_default()),
+        };
+        LabelData {
+            reagent_name: row.reagent_name,
+            cas_number: row.cas_number,
+            formula: row.formula,
+            batch_number: row.batch_number,
+            lot_number: row.lot_number,
+            expiry_date,
+            quantity,
+            container,
+            storage_conditions: row.storage_conditions,
+            pictograms,
+            signal_word,
+            code,
+        }
+    }
+}
+
+// ==================== OPTIONS ====================
+
+#[derive(Debug, Deserialize, Default)]
+pub struct LabelOptions {
+    #[serde(default)]
+    pub format: LabelFormat,
+    /// По умолчанию: a4_l7163 для PDF, roll_100x50 для ZPL
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub symbology: Symbology,
+    /// Копий каждой этикетки (1-10)
+    pub copies: Option<u32>,
+    /// Сколько этикеток на первом листе уже использовано (только PDF)
+    pub start_at: Option<usize>,
+    /// Разрешение термопринтера: 203 или 300 dpi (только ZPL)
+    pub dpi: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintLabelsRequest {
+    pub container_ids: Vec<String>,
+    #[serde(flatten)]
+    pub options: LabelOptions,
+}
+
+fn render(mut items: Vec<LabelData>, options: &LabelOptions, filename: &str) -> ApiResult<HttpResponse> {
+    let copies = options.copies.unwrap_or(1);
+    if !(1..=10).contains(&copies) {
+        return Err(ApiError::bad_request("copies must be between 1 and 10"));
+    }
+    let default_layout = match options.format {
+        LabelFormat::Pdf => "a4_l7163",
+        LabelFormat::Zpl => "roll_100x50",
+    };
+    let layout = labels::find_layout(options.layout.as_deref().unwrap_or(default_layout))?;
+
+    match options.format {
+        LabelFormat::Pdf => {
+            if copies > 1 {
+                items = items.into_iter()
+                    .flat_map(|label| std::iter::repeat_n(label, copies as usize))
+                    .collect();
+            }
+            if items.len() > MAX_LABELS {
+                return Err(ApiError::bad_request(&format!("Too many labels (max {})", MAX_LABELS)));
+            }
+            let pdf = labels::render_pdf(&items, layout, options.symbology, options.start_at.unwrap_or(0))?;
+            Ok(HttpResponse::Ok()
+                .content_type("application/pdf")
+                .insert_header(("Content-Disposition", format!("inline; filename=\"{}.pdf\"", filename)))
+                .body(pdf))
+        }
+        LabelFormat::Zpl => {
+            let dpi = options.dpi.unwrap_or(203);
+            if dpi != 203 && dpi != 300 {
+                return Err(ApiError::bad_request("dpi must be 203 or 300"));
+            }
+            let zpl = labels::render_zpl(&items, layout, options.symbology, dpi, copies)?;
+            Ok(HttpResponse::Ok()
+                .content_type("application/zpl; charset=utf-8")
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.zpl\"", filename)))
+                .body(zpl))
+        }
+    }
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_label_layouts() -> ApiResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ApiResponse::success(LABEL_LAYOUTS)))
+}
+
+pub async fn get_container_label(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<LabelOptions>,
+) -> ApiResult<HttpResponse> {
+    let container_id = path.into_inner();
+    let sql = format!("{} JOIN batch_containers bc ON bc.batch_id = b.id WHERE bc.id = ? AND b.deleted_at IS NULL", LABEL_SELECT);
+    let row: LabelRow = sqlx::query_as(&sql)
+        .bind(&container_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Container"))?;
+    let label = LabelData::from(row);
+    let filename = format!("label-{}", label.code);
+    render(vec![label], &query, &filename)
+}
+
+/// Этикетки всех (не утилизированных) контейнеров партии;
+/// если партия не разделена на контейнеры — одна этикетка партии
+pub async fn get_batch_labels(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<LabelOptions>,
+) -> ApiResult<HttpResponse> {
+    let batch_id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let sql = format!(
+        "{} JOIN batch_containers bc ON bc.batch_id = b.id
+         WHERE b.id = ? AND b.deleted_at IS NULL AND bc.status != 'disposed' ORDER BY bc.sequence_number",
+        LABEL_SELECT,
+    );
+    let mut rows: Vec<LabelRow> = sqlx::query_as(&sql).bind(&batch_id).fetch_all(pool).await?;
+    if rows.is_empty() {
+        // Колонки контейнера остаются NULL
+        let sql = format!("{} LEFT JOIN batch_containers bc ON 0 WHERE b.id = ? AND b.deleted_at IS NULL", LABEL_SELECT);
+        rows = sqlx::query_as(&sql).bind(&batch_id).fetch_all(pool).await?;
+        if rows.is_empty() {
+            return Err(ApiError::not_found("Batch"));
+        }
+    }
+    let items: Vec<LabelData> = rows.into_iter().map(LabelData::from).collect();
+    let filename = format!("labels-{}", items[0].batch_number.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_"));
+    render(items, &query, &filename)
+}
+
+pub async fn print_labels(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<PrintLabelsRequest>,
+) -> ApiResult<HttpResponse> {
+    let request = body.into_inner();
+    if request.container_ids.is_empty() {
+        return Err(ApiError::bad_request("container_ids is required"));
+    }
+    if request.container_ids.len() > MAX_LABELS {
+        return Err(ApiError::bad_request(&format!("Too many containers (max {})", MAX_LABELS)));
+    }
+
+    let placeholders = vec!["?"; request.container_ids.len()].join(",");
+    let sql = format!(
+        "{} JOIN batch_containers bc ON bc.batch_id = b.id WHERE bc.id IN ({}) AND b.deleted_at IS NULL",
+        LABEL_SELECT, placeholders,
+    );
+    let mut query = sqlx::query_as::<_, LabelRow>(&sql);
+    for id in &request.container_ids {
+        query = query.bind(id);
+    }
+    let rows = query.fetch_all(&app_state.db_pool).await?;
+
+    // Порядок печати — как в запросе
+    let mut items = Vec::with_capacity(request.container_ids.len());
+    for id in &request.container_ids {
+        let row = rows.iter().find(|r| r.container_id.as_deref() == Some(id.as_str()))
+            .ok_or_else(|| ApiError::not_found(&format!("Container {}", id)))?;
+        items.push(LabelData::from(row.clone()));
+    }
+    render(items, &request.options, "labels")
+}
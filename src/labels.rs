@@ -0,0 +1,558 @@
+// src/labels.rs
+//! Этикетки GHS на стороне сервера: PDF (листы A4/Letter и рулонные этикетки)
+//! и ZPL для термопринтеров Zebra.
+//!
+//! Этикетка раскладывается один раз в набор элементов (текст, штрихкод, пиктограммы)
+//! в миллиметрах, затем выводится в нужный формат:
+//! - PDF: SVG страницы → svg2pdf; текст переводится в кривые шрифтами системы,
+//!   поэтому кириллица печатается без встроенных шрифтов принтера;
+//! - ZPL: текст и штрихкоды — командами принтера (`^A0`, `^BC`, `^BQ`),
+//!   пиктограммы — растром `^GFA`.
+//!
+//! Пиктограммы — те же SVG, что и во фронтенде (`lims-frontend/public/assets/ghs`).
+
+use base64::Engine;
+use chrono::NaiveDate;
+use pdf_writer::{Content, Name, Pdf, Rect, Ref};
+use resvg::tiny_skia;
+use resvg::usvg::{self, fontdb, PostProcessingSteps, TreeParsing, TreePostProc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::barcodes::{self, Symbology};
+use crate::error::{ApiError, ApiResult};
+
+pub const GHS_PICTOGRAMS: [(&str, &str); 9] = [
+    ("GHS01", include_str!("../lims-frontend/public/assets/ghs/GHS01.svg")),
+    ("GHS02", include_str!("../lims-frontend/public/assets/ghs/GHS02.svg")),
+    ("GHS03", include_str!("../lims-frontend/public/assets/ghs/GHS03.svg")),
+    ("GHS04", include_str!("../lims-frontend/public/assets/ghs/GHS04.svg")),
+    ("GHS05", include_str!("../lims-frontend/public/assets/ghs/GHS05.svg")),
+    ("GHS06", include_str!("../lims-frontend/public/assets/ghs/GHS06.svg")),
+    ("GHS07", include_str!("../lims-frontend/public/assets/ghs/GHS07.svg")),
+    ("GHS08", include_str!("../lims-frontend/public/assets/ghs/GHS08.svg")),
+    ("GHS09", include_str!("../lims-frontend/public/assets/ghs/GHS09.svg")),
+];
+
+const MM_TO_PT: f32 = 72.0 / 25.4;
+const FONT_FAMILY: &str = "DejaVu Sans, Liberation Sans, Arial, Helvetica, sans-serif";
+
+lazy_static::lazy_static! {
+    /// Системные шрифты для перевода текста этикеток в кривые
+    static ref LABEL_FONTS: fontdb::Database = {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        db
+    };
+}
+
+// ==================== DATA ====================
+
+/// Всё, что печатается на одной этикетке
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelData {
+    pub reagent_name: String,
+    pub cas_number: Option<String>,
+    pub formula: Option<String>,
+    pub batch_number: String,
+    pub lot_number: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    /// "500 ml"
+    pub quantity: Option<String>,
+    /// "2/4" — номер контейнера в партии
+    pub container: Option<String>,
+    pub storage_conditions: Option<String>,
+    pub pictograms: Vec<&'static str>,
+    pub signal_word: Option<String>,
+    /// Короткий код для штрихкода (C000123 / B000045)
+    pub code: String,
+}
+
+/// "GHS02, ghs7;GHS02" → ["GHS02", "GHS07"]; неизвестные коды отбрасываются
+pub fn parse_pictograms(value: Option<&str>) -> Vec<&'static str> {
+    let mut codes: Vec<&'static str> = value.unwrap_or_default()
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(|raw| {
+            let num = raw.trim().to_ascii_uppercase();
+            let num: u32 = num.strip_prefix("GHS")?.parse().ok()?;
+            GHS_PICTOGRAMS.get((num as usize).checked_sub(1)?).map(|(code, _)| *code)
+        })
+        .collect();
+    codes.sort_unstable();
+    codes.dedup();
+    codes
+}
+
+/// Сигнальное слово реагента; если не задано — только однозначные случаи:
+/// GHS06 (острая токсичность 1-3) — всегда Danger, только GHS07/GHS09 — Warning.
+pub fn effective_signal_word(stored: Option<&str>, pictograms: &[&str]) -> Option<String> {
+    if let Some(word) = stored {
+        return Some(word.to_string());
+    }
+    if pictograms.contains(&"GHS06") {
+        Some("Danger".to_string())
+    } else if pictograms.contains(&"GHS07") && pictograms.iter().all(|p| *p == "GHS07" || *p == "GHS09") {
+        Some("Warning".to_string())
+    } else {
+        None
+    }
+}
+
+// ==================== LAYOUTS ====================
+
+/// Формат листа или рулона; размеры в мм
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LabelLayout {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub label_width: f32,
+    pub label_height: f32,
+    pub page_width: f32,
+    pub page_height: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub margin_left: f32,
+    pub margin_top: f32,
+    pub gap_x: f32,
+    pub gap_y: f32,
+}
+
+impl LabelLayout {
+    /// Рулонная этикетка: одна этикетка на страницу, подходит для ZPL
+    pub fn is_roll(&self) -> bool {
+        self.columns == 1 && self.rows == 1
+    }
+
+    pub fn per_page(&self) -> usize {
+        self.columns * self.rows
+    }
+}
+
+const fn roll(name: &'static str, description: &'static str, w: f32, h: f32) -> LabelLayout {
+    LabelLayout {
+        name, description, label_width: w, label_height: h, page_width: w, page_height: h,
+        columns: 1, rows: 1, margin_left: 0.0, margin_top: 0.0, gap_x: 0.0, gap_y: 0.0,
+    }
+}
+
+pub const LABEL_LAYOUTS: &[LabelLayout] = &[
+    LabelLayout {
+        name: "a4_l7163", description: "A4, 14 labels 99.1×38.1 mm (Avery L7163)",
+        label_width: 99.1, label_height: 38.1, page_width: 210.0, page_height: 297.0,
+        columns: 2, rows: 7, margin_left: 4.65, margin_top: 15.15, gap_x: 2.5, gap_y: 0.0,
+    },
+    LabelLayout {
+        name: "a4_l7165", description: "A4, 8 labels 99.1×67.7 mm (Avery L7165)",
+        label_width: 99.1, label_height: 67.7, page_width: 210.0, page_height: 297.0,
+        columns: 2, rows: 4, margin_left: 4.65, margin_top: 13.1, gap_x: 2.5, gap_y: 0.0,
+    },
+    LabelLayout {
+        name: "letter_5163", description: "US Letter, 10 labels 4×2 in (Avery 5163)",
+        label_width: 101.6, label_height: 50.8, page_width: 215.9, page_height: 279.4,
+        columns: 2, rows: 5, margin_left: 3.97, margin_top: 12.7, gap_x: 4.76, gap_y: 0.0,
+    },
+    roll("roll_100x50", "Roll label 100×50 mm", 100.0, 50.0),
+    roll("roll_76x51", "Roll label 3×2 in", 76.2, 50.8),
+    roll("roll_57x32", "Roll label 57×32 mm", 57.0, 32.0),
+];
+
+pub fn find_layout(name: &str) -> ApiResult<&'static LabelLayout> {
+    LABEL_LAYOUTS.iter()
+        .find(|l| l.name == name)
+        .ok_or_else(|| ApiError::bad_request(&format!("Unknown label layout: {}", name)))
+}
+
+// ==================== COMPOSITION ====================
+
+/// Элемент этикетки; координаты в мм от левого верхнего угла
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    /// `y` — базовая линия, `size` — кегль в мм
+    Text { x: f32, y: f32, size: f32, bold: bool, text: String },
+    Barcode { x: f32, y: f32, width: f32, height: f32 },
+    Pictogram { x: f32, y: f32, size: f32, code: &'static str },
+}
+
+/// Средняя ширина символа в долях кегля (Helvetica/DejaVu)
+fn char_width(bold: bool) -> f32 {
+    if bold { 0.62 } else { 0.56 }
+}
+
+/// Обрезает строку с многоточием, чтобы она уместилась в `width`
+fn fit_text(text: &str, width: f32, size: f32, bold: bool) -> String {
+    let max_chars = (width / (size * char_width(bold))).floor().max(1.0) as usize;
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// Раскладка этикетки размером `w`×`h` мм
+pub fn compose(label: &LabelData, w: f32, h: f32, symbology: Symbology) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let pad = (h * 0.05).clamp(1.0, 2.5);
+
+    // Пиктограммы — сетка справа, по две в столбце
+    let n = label.pictograms.len();
+    let (pict_rows, pict_cols) = match n {
+        0 => (0, 0),
+        1 => (1, 1),
+        _ => (2, n.div_ceil(2)),
+    };
+    let pict = if n > 0 {
+        ((h - 2.0 * pad) / pict_rows as f32).min(w * 0.42 / pict_cols as f32)
+    } else {
+        0.0
+    };
+    let pict_width = pict * pict_cols as f32;
+    for (i, code) in label.pictograms.iter().enumerate() {
+        elements.push(Element::Pictogram {
+            x: w - pad - pict_width + (i / pict_rows) as f32 * pict,
+            y: pad + (i % pict_rows) as f32 * pict,
+            size: pict,
+            code,
+        });
+    }
+
+    let text_x = pad;
+    let text_w = w - 2.0 * pad - if n > 0 { pict_width + pad } else { 0.0 };
+
+    // Штрихкод — полоса внизу текстовой колонки
+    let code_size = (h * 0.06).clamp(1.5, 2.6);
+    let band = match symbology {
+        Symbology::Code128 => {
+            let band = (h * 0.26).clamp(6.0, 13.0);
+            elements.push(Element::Barcode { x: text_x, y: h - pad - band, width: text_w, height: band - code_size * 1.3 });
+            elements.push(Element::Text { x: text_x, y: h - pad, size: code_size, bold: false, text: label.code.clone() });
+            band
+        }
+        Symbology::Qr => {
+            let side = (h * 0.4).min(text_w * 0.45);
+            elements.push(Element::Barcode { x: text_x, y: h - pad - side, width: side, height: side });
+            elements.push(Element::Text { x: text_x + side + 1.0, y: h - pad - 0.5, size: code_size, bold: false, text: label.code.clone() });
+            side
+        }
+    };
+
+    // Строки текста: (текст, жирный, приоритет — при нехватке места убираются строки с большим)
+    let join = |parts: Vec<Option<String>>| {
+        let parts: Vec<String> = parts.into_iter().flatten().filter(|s| !s.trim().is_empty()).collect();
+        (!parts.is_empty()).then(|| parts.join("  "))
+    };
+    let candidates = [
+        (join(vec![label.cas_number.as_ref().map(|c| format!("CAS {}", c)), label.formula.clone()]), false, 3),
+        (join(vec![label.lot_number.as_ref().map(|l| format!("Lot {}", l)), Some(format!("Batch {}", label.batch_number))]), false, 1),
+        (join(vec![
+            Some(format!("Exp {}", label.expiry_date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "—".to_string()))),
+            label.quantity.clone(),
+            label.container.as_ref().map(|c| format!("#{}", c)),
+        ]), false, 1),
+        (label.signal_word.as_ref().map(|s| s.to_uppercase()), true, 0),
+        (label.storage_conditions.clone(), false, 4),
+    ];
+    let mut lines: Vec<(String, bool, u8)> = candidates.into_iter()
+        .filter_map(|(text, bold, prio)| text.map(|t| (t, bold, prio)))
+        .collect();
+
+    let top = pad;
+    let avail = h - pad - band - 0.8 - top;
+    const TITLE_FACTOR: f32 = 1.4;
+    const LEADING: f32 = 1.2;
+    let body = loop {
+        let body = (avail / ((lines.len() as f32 + TITLE_FACTOR) * LEADING)).min(3.0);
+        if body >= 1.4 || lines.is_empty() {
+            break body.max(1.0);
+        }
+        // Не помещается — убираем наименее важную строку
+        let worst = lines.iter().enumerate().max_by_key(|(_, l)| l.2).map(|(i, _)| i).unwrap();
+        lines.remove(worst);
+    };
+
+    let name_width_size = text_w / (label.reagent_name.chars().count().max(1) as f32 * char_width(true));
+    let title = (body * TITLE_FACTOR).min(name_width_size).max(body);
+    let mut y = top + title * 0.9;
+    elements.push(Element::Text { x: text_x, y, size: title, bold: true, text: fit_text(&label.reagent_name, text_w, title, true) });
+    y += title * 0.3;
+    for (text, bold, _) in lines {
+        y += body * LEADING;
+        elements.push(Element::Text { x: text_x, y, size: body, bold, text: fit_text(&text, text_w, body, bold) });
+    }
+    elements
+}
+
+// ==================== PDF ====================
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn pictogram_svg(code: &str) -> &'static str {
+    GHS_PICTOGRAMS.iter().find(|(c, _)| *c == code).map(|(_, svg)| *svg).unwrap_or_default()
+}
+
+/// SVG-фрагмент одной этикетки со смещением (`ox`, `oy`)
+fn label_svg(out: &mut String, elements: &[Element], code: &str, symbology: Symbology, ox: f32, oy: f32) -> ApiResult<()> {
+    out.push_str(&format!(r#"<g transform="translate({:.3} {:.3})">"#, ox, oy));
+    for element in elements {
+        match element {
+            Element::Text { x, y, size, bold, text } => out.push_str(&format!(
+                r#"<text x="{:.3}" y="{:.3}" font-size="{:.3}" font-family="{}" font-weight="{}">{}</text>"#,
+                x, y, size, FONT_FAMILY, if *bold { "bold" } else { "normal" }, xml_escape(text),
+            )),
+            Element::Barcode { x, y, width, height } => {
+                let matrix = barcodes::barcode_matrix(code, symbology)?;
+                let (mw, mh) = matrix.module_size();
+                // Code128 растягивается по высоте полосы, QR — квадрат
+                let module = width / mw as f32;
+                let q = matrix.quiet_zone as f32 * module;
+                let (qy, row_scale) = match symbology {
+                    Symbology::Code128 => (0.0, height / matrix.row_height as f32),
+                    Symbology::Qr => (q, (height / mh as f32).min(module)),
+                };
+                out.push_str(r##"<g fill="#000000">"##);
+                for (ry, rx, len) in matrix.dark_runs() {
+                    out.push_str(&format!(
+                        r#"<rect x="{:.3}" y="{:.3}" width="{:.3}" height="{:.3}"/>"#,
+                        x + q + rx as f32 * module,
+                        y + qy + ry as f32 * matrix.row_height as f32 * row_scale,
+                        len as f32 * module,
+                        matrix.row_height as f32 * row_scale,
+                    ));
+                }
+                out.push_str("</g>");
+            }
+            Element::Pictogram { x, y, size, code } => {
+                let data = base64::engine::general_purpose::STANDARD.encode(pictogram_svg(code));
+                out.push_str(&format!(
+                    r#"<image x="{:.3}" y="{:.3}" width="{:.3}" height="{:.3}" href="data:image/svg+xml;base64,{}"/>"#,
+                    x, y, size, size, data,
+                ));
+            }
+        }
+    }
+    out.push_str("</g>");
+    Ok(())
+}
+
+/// PDF с этикетками; `start_at` — сколько этикеток на первом листе уже использовано
+pub fn render_pdf(labels: &[LabelData], layout: &LabelLayout, symbology: Symbology, start_at: usize) -> ApiResult<Vec<u8>> {
+    if LABEL_FONTS.is_empty() {
+        return Err(ApiError::internal_error("No system fonts available for label rendering"));
+    }
+    let per_page = layout.per_page();
+    let start_at = start_at % per_page;
+    let page_count = (start_at + labels.len()).div_ceil(per_page).max(1);
+
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let mut next = Ref::new(3);
+    let alloc = |next: &mut Ref| {
+        let id = *next;
+        *next = Ref::new(id.get() + 1);
+        id
+    };
+
+    let (pw, ph) = (layout.page_width, layout.page_height);
+    let mut page_ids = Vec::with_capacity(page_count);
+    for page in 0..page_count {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{pw}" height="{ph}" viewBox="0 0 {pw} {ph}">"#,
+            pw = pw, ph = ph,
+        );
+        for slot in 0..per_page {
+            let index = page * per_page + slot;
+            let Some(label) = index.checked_sub(start_at).and_then(|i| labels.get(i)) else { continue };
+            let (col, row) = (slot % layout.columns, slot / layout.columns);
+            let ox = layout.margin_left + col as f32 * (layout.label_width + layout.gap_x);
+            let oy = layout.margin_top + row as f32 * (layout.label_height + layout.gap_y);
+            let elements = compose(label, layout.label_width, layout.label_height, symbology);
+            label_svg(&mut svg, &elements, &label.code, symbology, ox, oy)?;
+        }
+        svg.push_str("</svg>");
+
+        let mut tree = usvg::Tree::from_str(&svg, &usvg::Options::default())
+            .map_err(|e| ApiError::internal_error(format!("Label SVG error: {}", e)))?;
+        tree.postprocess(PostProcessingSteps::default(), &LABEL_FONTS);
+
+        let page_id = alloc(&mut next);
+        let content_id = alloc(&mut next);
+        let xobject_id = alloc(&mut next);
+        next = svg2pdf::convert_tree_into(&tree, svg2pdf::Options::default(), &mut pdf, xobject_id);
+
+        let (w_pt, h_pt) = (pw * MM_TO_PT, ph * MM_TO_PT);
+        {
+            let mut page_obj = pdf.page(page_id);
+            page_obj.media_box(Rect::new(0.0, 0.0, w_pt, h_pt));
+            page_obj.parent(page_tree_id);
+            page_obj.contents(content_id);
+            page_obj.resources().x_objects().pair(Name(b"Labels"), xobject_id);
+        }
+
+        let mut content = Content::new();
+        content.transform([w_pt, 0.0, 0.0, h_pt, 0.0, 0.0]).x_object(Name(b"Labels"));
+        pdf.stream(content_id, &content.finish());
+        page_ids.push(page_id);
+    }
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+    Ok(pdf.finish())
+}
+
+// ==================== ZPL ====================
+
+/// Пиктограмма → `^GFA` (1 бит на точку, красная рамка печатается чёрным)
+fn pictogram_gfa(code: &str, size_dots: u32) -> ApiResult<String> {
+    let mut tree = usvg::Tree::from_str(pictogram_svg(code), &usvg::Options::default())
+        .map_err(|e| ApiError::internal_error(format!("Pictogram SVG error: {}", e)))?;
+    tree.postprocess(PostProcessingSteps::default(), &fontdb::Database::new());
+    let mut pixmap = tiny_skia::Pixmap::new(size_dots, size_dots)
+        .ok_or_else(|| ApiError::internal_error("Invalid pictogram size"))?;
+    let scale = size_dots as f32 / tree.size.width().max(tree.size.height());
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let bytes_per_row = size_dots.div_ceil(8) as usize;
+    let mut hex = String::with_capacity(bytes_per_row * size_dots as usize * 2);
+    for row in pixmap.pixels().chunks(size_dots as usize) {
+        let mut bits = vec![0u8; bytes_per_row];
+        for (x, px) in row.iter().enumerate() {
+            let c = px.demultiply();
+            let luma = 0.299 * c.red() as f32 + 0.587 * c.green() as f32 + 0.114 * c.blue() as f32;
+            if c.alpha() > 127 && luma < 160.0 {
+                bits[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        for b in bits {
+            hex.push_str(&format!("{:02X}", b));
+        }
+    }
+    let total = bytes_per_row * size_dots as usize;
+    Ok(format!("^GFA,{},{},{},{}", total, total, bytes_per_row, hex))
+}
+
+/// `^FH` + `^FD`: служебные символы ZPL экранируются как `_XX`
+fn zpl_field(text: &str) -> String {
+    let escaped = text.replace('_', "_5F").replace('^', "_5E").replace('~', "_7E");
+    format!("^FH^FD{}^FS", escaped)
+}
+
+/// ZPL: по одному `^XA…^XZ` на этикетку; `dpi` — 203 или 300
+pub fn render_zpl(labels: &[LabelData], layout: &LabelLayout, symbology: Symbology, dpi: u32, copies: u32) -> ApiResult<String> {
+    if !layout.is_roll() {
+        return Err(ApiError::bad_request("ZPL requires a roll layout (roll_*)"));
+    }
+    let dots = dpi as f32 / 25.4;
+    let d = |mm: f32| (mm * dots).round().max(0.0) as u32;
+    let mut pictograms: HashMap<(&str, u32), String> = HashMap::new();
+
+    let mut out = String::new();
+    for label in labels {
+        out.push_str(&format!("^XA^CI28^PW{}^LL{}^LH0,0\n", d(layout.label_width), d(layout.label_height)));
+        for element in compose(label, layout.label_width, layout.label_height, symbology) {
+            match element {
+                Element::Text { x, y, size, bold, text } => {
+                    // ^A0 позиционируется по верхнему краю; жирный — чуть шире
+                    let h = d(size).max(10);
+                    let w = if bold { h * 11 / 10 } else { h };
+                    out.push_str(&format!("^FO{},{}^A0N,{},{}{}\n", d(x), d(y - size * 0.8), h, w, zpl_field(&text)));
+                }
+                Element::Barcode { x, y, width, height } => {
+                    let matrix = barcodes::barcode_matrix(&label.code, symbology)?;
+                    let module = (d(width) / matrix.module_size().0 as u32).clamp(1, 10);
+                    let quiet = matrix.quiet_zone as u32 * module;
+                    match symbology {
+                        Symbology::Code128 => out.push_str(&format!(
+                            "^FO{},{}^BY{}^BCN,{},N,N,N{}\n",
+                            d(x) + quiet, d(y), module, d(height), zpl_field(&label.code),
+                        )),
+                        Symbology::Qr => out.push_str(&format!(
+                            "^FO{},{}^BQN,2,{}^FDQA,{}^FS\n",
+                            d(x) + quiet, d(y) + quiet, module, label.code,
+                        )),
+                    }
+                }
+                Element::Pictogram { x, y, size, code } => {
+                    let size_dots = d(size);
+                    let gfa = match pictograms.entry((code, size_dots)) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(pictogram_gfa(code, size_dots)?),
+                    };
+                    out.push_str(&format!("^FO{},{}{}^FS\n", d(x), d(y), gfa));
+                }
+            }
+        }
+        out.push_str(&format!("^PQ{}\n^XZ\n", copies.max(1)));
+    }
+    Ok(out)
+}
+
+// ==================== REQUEST OPTIONS ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    #[default]
+    Pdf,
+    Zpl,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> LabelData {
+        LabelData {
+            reagent_name: "Acetone, HPLC grade".to_string(),
+            cas_number: Some("67-64-1".to_string()),
+            formula: Some("C3H6O".to_string()),
+            batch_number: "B-1".to_string(),
+            lot_number: Some("MKCD1234".to_string()),
+            expiry_date: NaiveDate::from_ymd_opt(2027, 3, 31),
+            quantity: Some("500 ml".to_string()),
+            container: Some("1/2".to_string()),
+            storage_conditions: Some("Flammables cabinet".to_string()),
+            pictograms: parse_pictograms(Some("GHS07, ghs2 ,GHS02,GHS99")),
+            signal_word: Some("Danger".to_string()),
+            code: "C000001".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_compose_fits_label() {
+        let label = sample();
+        assert_eq!(label.pictograms, vec!["GHS02", "GHS07"]);
+        assert_eq!(effective_signal_word(None, &["GHS07", "GHS09"]).as_deref(), Some("Warning"));
+        assert_eq!(effective_signal_word(None, &["GHS02", "GHS07"]), None);
+
+        for layout in LABEL_LAYOUTS {
+            for symbology in [Symbology::Code128, Symbology::Qr] {
+                let elements = compose(&label, layout.label_width, layout.label_height, symbology);
+                assert!(elements.iter().any(|e| matches!(e, Element::Text { text, .. } if text == "DANGER")));
+                for e in &elements {
+                    let (x, y, right, bottom) = match e {
+                        Element::Text { x, y, size, text, bold } => (*x, y - size, x + text.chars().count() as f32 * size * char_width(*bold), *y),
+                        Element::Barcode { x, y, width, height } => (*x, *y, x + width, y + height),
+                        Element::Pictogram { x, y, size, .. } => (*x, *y, x + size, y + size),
+                    };
+                    assert!(x >= 0.0 && y >= 0.0, "{} {:?}", layout.name, e);
+                    assert!(right <= layout.label_width + 0.01 && bottom <= layout.label_height + 0.01, "{} {:?}", layout.name, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_zpl() {
+        let layout = find_layout("roll_100x50").unwrap();
+        let zpl = render_zpl(&[sample()], layout, Symbology::Code128, 203, 2).unwrap();
+        assert!(zpl.starts_with("^XA^CI28^PW799^LL400"));
+        assert!(zpl.contains("^BCN,") && zpl.contains("^FH^FDC000001^FS"));
+        assert_eq!(zpl.matches("^GFA,").count(), 2);
+        assert!(zpl.trim_end().ends_with("^PQ2\n^XZ"));
+        assert!(render_zpl(&[sample()], find_layout("a4_l7163").unwrap(), Symbology::Qr, 203, 1).is_err());
+    }
+}
@@ -32,11 +32,15 @@ mod db;
 mod error;
 mod handlers;
 mod experiment_handlers;
+mod experiment_workflow;
+mod notebook_handlers;
 mod report_handlers;
 mod models;
 mod monitoring;
 mod jwt_rotation;
 mod storage_handlers;
+mod storage_locations;
+mod search_handlers;
 pub mod validator;
 mod placement_handlers;
 mod container_handlers;
@@ -46,6 +50,24 @@ mod reagent_handlers;
 pub mod room_handlers;
 mod batch_handlers;
 mod equipment_handlers;
+mod equipment_hierarchy;
+mod equipment_usage;
+mod maintenance_handlers;
+mod service_contracts;
+mod barcodes;
+mod scan_handlers;
+mod labels;
+mod label_handlers;
+mod stocktake;
+mod waste;
+mod sensors;
+mod goods_in;
+mod notifications;
+mod calibration_handlers;
+mod spare_parts_handlers;
+mod file_links;
+mod storage;
+mod thumbnails;
 mod import_export;
 mod pagination;
 mod routes;
@@ -60,6 +82,7 @@ use experiment_handlers::{run_auto_update_statuses, seconds_until_next_transitio
 pub struct AppState {
     pub db_pool: SqlitePool,
     pub config: Config,
+    pub storage: Arc<storage::BlobStore>,
 }
 
 // ==================== ADMIN PROTECTED ====================
@@ -94,9 +117,12 @@ async fn main() -> anyhow::Result<()> {
     let auth_service = Arc::new(AuthService::new(&config.auth.jwt_secret));
     create_default_admin_if_needed(&pool, &auth_service).await?;
 
+    let storage = Arc::new(storage::from_config(&config.storage)?);
+
     let app_state = Arc::new(AppState {
         db_pool: pool.clone(),
         config: config.clone(),
+        storage,
     });
 
     // Background tasks
@@ -109,6 +135,8 @@ async fn main() -> anyhow::Result<()> {
         use tokio::time::{sleep, Duration};
         const MAX_IDLE_SECS: u64 = 300;
         const MIN_PAUSE_SECS: u64 = 2;
+        // Overdue experiments blocked by workflow guards or pending sign-off
+        const BLOCKED_RECHECK_SECS: u64 = 60;
 
         sleep(Duration::from_secs(5)).await;
         log::info!("Experiment auto-update task started (event-driven, idle check: {}s)", MAX_IDLE_SECS);
@@ -119,11 +147,12 @@ async fn main() -> anyhow::Result<()> {
                     match run_auto_update_statuses(&experiment_pool).await {
                         Ok(r) if r.total_updated > 0 => {
                             log::info!("BG auto-update: {} started, {} completed", r.started, r.completed);
+                            MIN_PAUSE_SECS
                         }
-                        Err(e) => log::error!("BG auto-update error: {}", e),
-                        _ => {}
+                        Ok(r) if r.skipped > 0 => BLOCKED_RECHECK_SECS,
+                        Err(e) => { log::error!("BG auto-update error: {}", e); MIN_PAUSE_SECS }
+                        _ => MIN_PAUSE_SECS,
                     }
-                    MIN_PAUSE_SECS
                 }
                 Ok(Some(secs)) => {
                     let wait = (secs as u64).min(MAX_IDLE_SECS) + 1;
@@ -176,10 +205,12 @@ async fn main() -> anyhow::Result<()> {
                     .route("/register", web::post().to(register))
             )
 
-            // Public file access
+            // Public file access: only via signed, expiring links;
+            // sensor ingestion: authenticated by per-sensor API key
             .service(
                 web::scope("/api/v1/public")
-                    .route("/equipment/{id}/files/{file_id}", web::get().to(equipment_handlers::download_equipment_file))
+                    .route("/equipment/{id}/files/{file_id}", web::get().to(file_links::download_signed_equipment_file))
+                    .route("/sensors/readings", web::post().to(sensors::ingest_readings))
             )
 
             // All protected API routes
@@ -0,0 +1,520 @@
+// src/maintenance_handlers.rs
+//! Планово-предупредительное обслуживание оборудования.
+//!
+//! План — повторяющаяся задача (калибровка раз в 365 дней, чистка раз в 30).
+//! У каждого активного плана ровно одна открытая запись в `equipment_maintenance`;
+//! при её закрытии создаётся следующая. `equipment.last_maintenance` и
+//! `equipment.next_maintenance` пересчитываются из записей обслуживания.
+//!
+//! Endpoints:
+//!   GET    /api/v1/equipment/maintenance/upcoming?days=&limit=   — предстоящее и просроченное
+//!   POST   /api/v1/equipment/maintenance/apply-due               — перевести оборудование в статус ТО
+//!   GET    /api/v1/equipment/{id}/maintenance-plans
+//!   POST   /api/v1/equipment/{id}/maintenance-plans
+//!   PUT    /api/v1/equipment/{id}/maintenance-plans/{plan_id}
+//!   DELETE /api/v1/equipment/{id}/maintenance-plans/{plan_id}
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, NaiveDate, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    CreateMaintenancePlanRequest, EquipmentMaintenance, MaintenancePlan,
+    UpcomingMaintenance, UpcomingMaintenanceQuery, UpdateMaintenancePlanRequest,
+};
+use crate::query_builders::{MaintenanceType, MaintenanceValidator};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// ==================== HELPERS ====================
+
+/// Дата из 'YYYY-MM-DD' или 'YYYY-MM-DDTHH:MM:SS...'
+pub fn parse_day(value: &str) -> ApiResult<NaiveDate> {
+    value.get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, DATE_FORMAT).ok())
+        .ok_or_else(|| ApiError::bad_request(&format!("Invalid date format: {}", value)))
+}
+
+fn add_days(day: NaiveDate, days: i64) -> String {
+    (day + Duration::days(days)).format(DATE_FORMAT).to_string()
+}
+
+async fn get_plan_or_404(conn: &mut SqliteConnection, equipment_id: &str, plan_id: &str) -> ApiResult<MaintenancePlan> {
+    sqlx::query_as::<_, MaintenancePlan>(
+        "SELECT * FROM equipment_maintenance_plans WHERE id = ? AND equipment_id = ?"
+    )
+        .bind(plan_id)
+        .bind(equipment_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Maintenance plan"))
+}
+
+/// Создать запланированную запись по плану и запомнить срок в плане
+async fn schedule_occurrence(
+    conn: &mut SqliteConnection,
+    plan: &MaintenancePlan,
+    due_date: &str,
+    user_id: Option<&str>,
+) -> ApiResult<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"INSERT INTO equipment_maintenance
+           (id, equipment_id, maintenance_type, status, scheduled_date, description,
+            plan_id, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, 'scheduled', ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&plan.equipment_id)
+        .bind(&plan.maintenance_type)
+        .bind(due_date)
+        .bind(&plan.name)
+        .bind(&plan.id)
+        .bind(user_id)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE equipment_maintenance_plans SET next_due_date = ?, updated_at = ? WHERE id = ?")
+        .bind(due_date)
+        .bind(now)
+        .bind(&plan.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(id)
+}
+
+/// Пересчитать `last_maintenance`/`next_maintenance` оборудования.
+/// Без открытых записей следующий срок — последнее ТО + `maintenance_interval_days`.
+pub async fn refresh_equipment_schedule(conn: &mut SqliteConnection, equipment_id: &str) -> ApiResult<()> {
+    sqlx::query(r#"
+        UPDATE equipment SET
+            last_maintenance = COALESCE((
+                SELECT MAX(date(completed_date)) FROM equipment_maintenance
+                WHERE equipment_id = equipment.id AND status = 'completed' AND completed_date IS NOT NULL
+            ), last_maintenance),
+            next_maintenance = COALESCE((
+                SELECT MIN(date(scheduled_date)) FROM equipment_maintenance
+                WHERE equipment_id = equipment.id AND status IN ('scheduled', 'in_progress')
+            ), CASE
+                WHEN maintenance_interval_days > 0 AND (
+                    SELECT MAX(date(completed_date)) FROM equipment_maintenance
+                    WHERE equipment_id = equipment.id AND status = 'completed' AND completed_date IS NOT NULL
+                ) IS NOT NULL
+                THEN date((
+                    SELECT MAX(date(completed_date)) FROM equipment_maintenance
+                    WHERE equipment_id = equipment.id AND status = 'completed' AND completed_date IS NOT NULL
+                ), '+' || maintenance_interval_days || ' days')
+                ELSE NULL
+            END),
+            updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(Utc::now())
+        .bind(equipment_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Вызывается после перевода записи в 'completed' или 'cancelled':
+/// возвращает статус оборудования, планирует следующее выполнение по плану
+/// и пересчитывает сроки оборудования.
+pub async fn on_maintenance_closed(
+    conn: &mut SqliteConnection,
+    record: &EquipmentMaintenance,
+    completed_date: Option<&str>,
+    user_id: Option<&str>,
+) -> ApiResult<()> {
+    restore_equipment_status(conn, &record.equipment_id).await?;
+
+    if let Some(ref plan_id) = record.plan_id {
+        if let Some(plan) = sqlx::query_as::<_, MaintenancePlan>(
+            "SELECT * FROM equipment_maintenance_plans WHERE id = ?"
+        )
+            .bind(plan_id)
+            .fetch_optional(&mut *conn)
+            .await?
+        {
+            if let Some(done) = completed_date {
+                sqlx::query("UPDATE equipment_maintenance_plans SET last_completed_date = ?, updated_at = ? WHERE id = ?")
+                    .bind(parse_day(done)?.format(DATE_FORMAT).to_string())
+                    .bind(Utc::now())
+                    .bind(&plan.id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+
+            let open: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM equipment_maintenance WHERE plan_id = ? AND status IN ('scheduled', 'in_progress')"
+            )
+                .bind(&plan.id)
+                .fetch_one(&mut *conn)
+                .await?;
+
+            if plan.is_active && open == 0 {
+                // Отмена не сдвигает график: считаем от исходного срока
+                let base = parse_day(completed_date.unwrap_or(&record.scheduled_date))?;
+                schedule_occurrence(conn, &plan, &add_days(base, plan.interval_days), user_id).await?;
+            }
+        }
+    }
+
+    refresh_equipment_schedule(conn, &record.equipment_id).await
+}
+
+/// Вернуть оборудованию статус, который был до автоматического перевода в ТО,
+/// если открытых записей, державших этот статус, больше нет
+async fn restore_equipment_status(conn: &mut SqliteConnection, equipment_id: &str) -> ApiResult<()> {
+    let still_held: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM equipment_maintenance
+           WHERE equipment_id = ? AND status IN ('scheduled', 'in_progress')
+             AND previous_equipment_status IS NOT NULL"#
+    )
+        .bind(equipment_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    if still_held > 0 {
+        return Ok(());
+    }
+
+    let previous: Option<String> = sqlx::query_scalar(
+        r#"SELECT previous_equipment_status FROM equipment_maintenance
+           WHERE equipment_id = ? AND previous_equipment_status IS NOT NULL
+           ORDER BY updated_at DESC LIMIT 1"#
+    )
+        .bind(equipment_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+
+    if let Some(previous) = previous {
+        sqlx::query(
+            "UPDATE equipment SET status = ?, updated_at = ? WHERE id = ? AND status IN ('maintenance', 'calibration')"
+        )
+            .bind(&previous)
+            .bind(Utc::now())
+            .bind(equipment_id)
+            .execute(&mut *conn)
+            .await?;
+
+        // Отметка отработала — чтобы ручной перевод в ТО позже не откатывался
+        sqlx::query(
+            r#"UPDATE equipment_maintenance SET previous_equipment_status = NULL
+               WHERE equipment_id = ? AND status NOT IN ('scheduled', 'in_progress')"#
+        )
+            .bind(equipment_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Перевести доступное оборудование в 'maintenance'/'calibration', когда наступил
+/// срок плановой записи с `auto_status`. Возвращает число переведённых единиц.
+pub async fn apply_due_maintenance_statuses(pool: &SqlitePool) -> ApiResult<u64> {
+    let due: Vec<(String, String, String)> = sqlx::query_as(r#"
+        SELECT m.id, m.equipment_id,
+               CASE WHEN p.maintenance_type = 'calibration' THEN 'calibration' ELSE 'maintenance' END
+        FROM equipment_maintenance m
+        JOIN equipment_maintenance_plans p ON m.plan_id = p.id
+        JOIN equipment e ON m.equipment_id = e.id
+        WHERE m.status IN ('scheduled', 'in_progress')
+          AND m.previous_equipment_status IS NULL
+          AND date(m.scheduled_date) <= date('now')
+          AND p.auto_status = 1 AND p.is_active = 1
+          AND e.status = 'available'
+        ORDER BY m.scheduled_date
+    "#)
+        .fetch_all(pool)
+        .await?;
+
+    let mut updated = 0u64;
+    for (maintenance_id, equipment_id, target_status) in due {
+        let mut tx = pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE equipment SET status = ?, updated_at = ? WHERE id = ? AND status = 'available'"
+        )
+            .bind(&target_status)
+            .bind(Utc::now())
+            .bind(&equipment_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Оборудование уже переведено другой записью этого же дня
+        if result.rows_affected() == 0 {
+            continue;
+        }
+
+        sqlx::query("UPDATE equipment_maintenance SET previous_equipment_status = 'available', updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&maintenance_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("Equipment {} moved to '{}' (maintenance {} due)", equipment_id, target_status, maintenance_id);
+        updated += 1;
+    }
+
+    Ok(updated)
+}
+
+// ==================== PLANS ====================
+
+pub async fn get_maintenance_plans(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+
+    let plans: Vec<MaintenancePlan> = sqlx::query_as(
+        "SELECT * FROM equipment_maintenance_plans WHERE equipment_id = ? ORDER BY is_active DESC, next_due_date ASC"
+    )
+        .bind(&equipment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(plans)))
+}
+
+pub async fn create_maintenance_plan(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CreateMaintenancePlanRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let equipment_id = path.into_inner();
+    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+
+    if MaintenanceType::from_str(&body.maintenance_type).is_err() {
+        return Err(ApiError::bad_request(&format!("Invalid maintenance type: {}", body.maintenance_type)));
+    }
+
+    let first_due = match body.first_due_date {
+        Some(ref date) => {
+            MaintenanceValidator::validate_date_format(date).map_err(|e| ApiError::bad_request(&e))?;
+            parse_day(date)?.format(DATE_FORMAT).to_string()
+        }
+        None => add_days(Utc::now().date_naive(), body.interval_days),
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut tx = app_state.db_pool.begin().await?;
+
+    sqlx::query(
+        r#"INSERT INTO equipment_maintenance_plans
+           (id, equipment_id, name, maintenance_type, interval_days, interval_runs, auto_status, is_active,
+            description, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, 1, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&equipment_id)
+        .bind(&body.name)
+        .bind(&body.maintenance_type)
+        .bind(body.interval_days)
+        .bind(body.interval_runs)
+        .bind(body.auto_status.unwrap_or(true))
+        .bind(&body.description)
+        .bind(&user_id)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    let plan = get_plan_or_404(&mut tx, &equipment_id, &id).await?;
+    schedule_occurrence(&mut tx, &plan, &first_due, Some(&user_id)).await?;
+    refresh_equipment_schedule(&mut tx, &equipment_id).await?;
+
+    let plan = get_plan_or_404(&mut tx, &equipment_id, &id).await?;
+    tx.commit().await?;
+
+    info!("Maintenance plan '{}' ({} every {} days) created for equipment {}",
+        plan.name, plan.maintenance_type, plan.interval_days, equipment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(plan)))
+}
+
+pub async fn update_maintenance_plan(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<UpdateMaintenancePlanRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (equipment_id, plan_id) = path.into_inner();
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let existing = get_plan_or_404(&mut tx, &equipment_id, &plan_id).await?;
+
+    sqlx::query(
+        r#"UPDATE equipment_maintenance_plans SET
+               name = COALESCE(?, name),
+               interval_days = COALESCE(?, interval_days),
+               interval_runs = COALESCE(?, interval_runs),
+               auto_status = COALESCE(?, auto_status),
+               is_active = COALESCE(?, is_active),
+               description = COALESCE(?, description),
+               updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(&body.name)
+        .bind(body.interval_days)
+        .bind(body.interval_runs)
+        .bind(body.auto_status)
+        .bind(body.is_active)
+        .bind(&body.description)
+        .bind(Utc::now())
+        .bind(&plan_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let plan = get_plan_or_404(&mut tx, &equipment_id, &plan_id).await?;
+
+    if existing.is_active && !plan.is_active {
+        // Приостановка: открытые записи плана отменяются
+        cancel_open_occurrences(&mut tx, &plan.id).await?;
+        sqlx::query("UPDATE equipment_maintenance_plans SET next_due_date = NULL WHERE id = ?")
+            .bind(&plan.id)
+            .execute(&mut *tx)
+            .await?;
+    } else if !existing.is_active && plan.is_active {
+        let base = plan.last_completed_date.as_deref()
+            .map(parse_day)
+            .transpose()?
+            .unwrap_or_else(|| Utc::now().date_naive());
+        let due = add_days(base, plan.interval_days).max(Utc::now().date_naive().format(DATE_FORMAT).to_string());
+        schedule_occurrence(&mut tx, &plan, &due, Some(&user_id)).await?;
+    }
+
+    restore_equipment_status(&mut tx, &equipment_id).await?;
+    refresh_equipment_schedule(&mut tx, &equipment_id).await?;
+    let plan = get_plan_or_404(&mut tx, &equipment_id, &plan_id).await?;
+    tx.commit().await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(plan)))
+}
+
+/// Удаление плана: открытые записи отменяются, история выполнения остаётся
+pub async fn delete_maintenance_plan(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, plan_id) = path.into_inner();
+
+    let mut tx = app_state.db_pool.begin().await?;
+    get_plan_or_404(&mut tx, &equipment_id, &plan_id).await?;
+
+    cancel_open_occurrences(&mut tx, &plan_id).await?;
+    sqlx::query("DELETE FROM equipment_maintenance_plans WHERE id = ?")
+        .bind(&plan_id)
+        .execute(&mut *tx)
+        .await?;
+
+    restore_equipment_status(&mut tx, &equipment_id).await?;
+    refresh_equipment_schedule(&mut tx, &equipment_id).await?;
+    tx.commit().await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Maintenance plan deleted successfully".to_string(),
+    )))
+}
+
+async fn cancel_open_occurrences(conn: &mut SqliteConnection, plan_id: &str) -> ApiResult<()> {
+    sqlx::query(
+        r#"UPDATE equipment_maintenance SET status = 'cancelled', updated_at = ?
+           WHERE plan_id = ? AND status IN ('scheduled', 'in_progress')"#
+    )
+        .bind(Utc::now())
+        .bind(plan_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+// ==================== UPCOMING / OVERDUE ====================
+
+/// Открытые записи обслуживания со сроком в ближайшие `days` дней, включая просроченные
+pub async fn get_upcoming_maintenance(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<UpcomingMaintenanceQuery>,
+) -> ApiResult<HttpResponse> {
+    let days = query.days.unwrap_or(30).clamp(0, 365);
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+
+    let items: Vec<UpcomingMaintenance> = sqlx::query_as(r#"
+        SELECT m.id, m.equipment_id, e.name as equipment_name, e.status as equipment_status,
+               e.location as equipment_location, m.maintenance_type, m.status, m.scheduled_date,
+               m.plan_id, p.name as plan_name,
+               CAST(julianday(date(m.scheduled_date)) - julianday(date('now')) AS INTEGER) as days_until_due,
+               date(m.scheduled_date) < date('now') as is_overdue
+        FROM equipment_maintenance m
+        JOIN equipment e ON m.equipment_id = e.id
+        LEFT JOIN equipment_maintenance_plans p ON m.plan_id = p.id
+        WHERE m.status IN ('scheduled', 'in_progress')
+          AND date(m.scheduled_date) <= date('now', '+' || ? || ' days')
+          AND e.status != 'retired'
+        ORDER BY date(m.scheduled_date) ASC, e.name ASC
+        LIMIT ?
+    "#)
+        .bind(days)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    let overdue = items.iter().filter(|i| i.is_overdue).count();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "days": days,
+        "overdue_count": overdue,
+        "upcoming_count": items.len() - overdue,
+        "items": items,
+    }))))
+}
+
+pub async fn apply_due_statuses(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let updated = apply_due_maintenance_statuses(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({ "updated": updated }))))
+}
+
+async fn check_equipment_exists(pool: &SqlitePool, equipment_id: &str) -> ApiResult<()> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)")
+        .bind(equipment_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Equipment"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_day_accepts_date_and_timestamp() {
+        let day = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        assert_eq!(parse_day("2024-02-28").unwrap(), day);
+        assert_eq!(parse_day("2024-02-28T10:15:00Z").unwrap(), day);
+        assert!(parse_day("28.02.2024").is_err());
+        assert_eq!(add_days(day, 2), "2024-03-01");
+    }
+}
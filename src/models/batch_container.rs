@@ -22,6 +22,12 @@ pub struct BatchContainer {
     #[serde(rename = "container_status")]
     pub status: String,             // full | partial | empty | disposed
     pub notes: Option<String>,
+    #[sqlx(default)]
+    pub short_code: Option<String>, // C000123 — для этикетки и сканера
+    #[sqlx(default)]
+    pub open_expiry_date: Option<DateTime<Utc>>, // opened_at + срок после вскрытия реагента
+    #[sqlx(default)]
+    pub retest_date: Option<DateTime<Utc>>,      // opened_at (или последняя перепроверка) + интервал
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -40,6 +46,15 @@ pub struct ContainerWithLocation {
     pub opened_by: Option<String>,
     pub container_status: String,
     pub container_notes: Option<String>,
+    #[sqlx(default)]
+    pub short_code: Option<String>,
+    #[sqlx(default)]
+    pub open_expiry_date: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub retest_date: Option<DateTime<Utc>>,
+    /// Раньшая из дат: срок партии и срок после вскрытия
+    #[sqlx(default)]
+    pub effective_expiry_date: Option<DateTime<Utc>>,
     pub container_created_at: DateTime<Utc>,
     pub container_updated_at: DateTime<Utc>,
     // Placement + Location (NULL if not placed)
@@ -47,6 +62,8 @@ pub struct ContainerWithLocation {
     pub position_id: Option<String>,
     pub position_name: Option<String>,
     pub position_label: Option<String>,
+    #[sqlx(default)]
+    pub cell: Option<String>,
     pub zone_id: Option<String>,
     pub zone_name: Option<String>,
     pub zone_type: Option<String>,
@@ -104,6 +121,9 @@ pub struct SplitBatchRequest {
 pub struct PlaceContainerRequest {
     pub position_id: String,
 
+    /// Grid cell ("B7") for grid positions; omitted — first free cell
+    pub cell: Option<String>,
+
     #[validate(length(max = 500, message = "Notes cannot exceed 500 characters"))]
     pub notes: Option<String>,
 }
@@ -111,6 +131,49 @@ pub struct PlaceContainerRequest {
 #[derive(Debug, Deserialize)]
 pub struct MoveContainerRequest {
     pub new_position_id: String,
+    /// Grid cell in the new position; omitted — first free cell
+    pub cell: Option<String>,
+}
+
+/// Результат перепроверки (retest) вскрытого контейнера
+#[derive(Debug, Deserialize, Validate)]
+pub struct RetestContainerRequest {
+    /// true — годен, дата перепроверки сдвигается на интервал; false — контейнер просрочен
+    pub passed: bool,
+
+    #[validate(length(max = 500, message = "Notes cannot exceed 500 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpiringContainersQuery {
+    pub days: Option<i64>,
+}
+
+/// Контейнер, срок которого (партии, после вскрытия или перепроверки) скоро наступит
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct ExpiringContainer {
+    pub id: String,
+    pub short_code: Option<String>,
+    pub sequence_number: i64,
+    pub batch_id: String,
+    pub batch_number: String,
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub container_status: String,
+    pub is_opened: bool,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub batch_expiry_date: Option<DateTime<Utc>>,
+    pub open_expiry_date: Option<DateTime<Utc>>,
+    pub effective_expiry_date: Option<DateTime<Utc>>,
+    pub retest_date: Option<DateTime<Utc>>,
+    /// batch_expiry | opened_shelf_life | retest
+    #[sqlx(default)]
+    pub reason: String,
+    #[sqlx(default)]
+    pub days_left: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -124,3 +187,45 @@ pub struct UseFromContainerRequest {
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
 }
+
+// ==================== MOVEMENTS (append-only) ====================
+
+/// One row of `container_movements` with resolved location names.
+/// action: place | move | unplace | dispose
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct ContainerMovement {
+    pub id: String,
+    pub container_id: String,
+    pub batch_id: String,
+    pub sequence_number: Option<i64>,
+    pub batch_number: Option<String>,
+    pub reagent_name: Option<String>,
+    pub action: String,
+    pub from_position_id: Option<String>,
+    pub from_position_name: Option<String>,
+    pub from_room_id: Option<String>,
+    pub from_room_name: Option<String>,
+    pub to_position_id: Option<String>,
+    pub to_position_name: Option<String>,
+    pub to_room_id: Option<String>,
+    pub to_room_name: Option<String>,
+    #[sqlx(default)]
+    pub from_cell: Option<String>,
+    #[sqlx(default)]
+    pub to_cell: Option<String>,
+    pub moved_by: Option<String>,
+    pub moved_by_username: Option<String>,
+    pub moved_at: DateTime<Utc>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MovementTimelineQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomInventoryQuery {
+    /// 'YYYY-MM-DD' (end of that day) or RFC 3339 timestamp; omitted — current inventory
+    pub as_of: Option<String>,
+}
@@ -16,6 +16,8 @@ pub struct BatchPlacement {
     pub placed_by: Option<String>,
     pub placed_at: DateTime<Utc>,
     pub notes: Option<String>,
+    #[sqlx(default)]
+    pub cell: Option<String>,   // grid cell "A1" for grid positions
 }
 
 /// Backward-compat alias: PlacementWithRoom
@@ -75,6 +77,8 @@ pub struct PositionInventoryItem {
     // Position
     pub position_id: String,
     pub position_name: String,
+    #[sqlx(default)]
+    pub cell: Option<String>,
     // Batch
     pub batch_id: String,
     pub batch_number: String,
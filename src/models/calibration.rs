@@ -0,0 +1,172 @@
+// src/models/calibration.rs
+//! Калибровка оборудования (ISO/IEC 17025): эталоны, точки, неопределённость, допуск.
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+// ==================== REFERENCE STANDARDS (ЭТАЛОНЫ) ====================
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct CalibrationStandard {
+    pub id: String,
+    pub name: String,
+    pub serial_number: Option<String>,
+    pub certificate_number: String,
+    pub certificate_issuer: Option<String>,
+    /// Цепочка прослеживаемости (например, "NIST SRM 2034 → ...")
+    pub traceability: Option<String>,
+    pub valid_until: Option<String>,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateCalibrationStandardRequest {
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
+    pub name: String,
+    #[validate(length(max = 100, message = "Serial number cannot exceed 100 characters"))]
+    pub serial_number: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "Certificate number is required"))]
+    pub certificate_number: String,
+    #[validate(length(max = 255, message = "Certificate issuer cannot exceed 255 characters"))]
+    pub certificate_issuer: Option<String>,
+    #[validate(length(max = 1000, message = "Traceability cannot exceed 1000 characters"))]
+    pub traceability: Option<String>,
+    pub valid_until: Option<String>,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateCalibrationStandardRequest {
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
+    pub name: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "Certificate number is required"))]
+    pub certificate_number: Option<String>,
+    #[validate(length(max = 255, message = "Certificate issuer cannot exceed 255 characters"))]
+    pub certificate_issuer: Option<String>,
+    #[validate(length(max = 1000, message = "Traceability cannot exceed 1000 characters"))]
+    pub traceability: Option<String>,
+    pub valid_until: Option<String>,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+// ==================== RESULTS (РЕЗУЛЬТАТЫ) ====================
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct CalibrationResult {
+    pub id: String,
+    pub maintenance_id: String,
+    pub equipment_id: String,
+    pub standard_id: String,
+    pub performed_at: String,
+    pub performed_by: Option<String>,
+    pub passed: bool,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct CalibrationPoint {
+    pub id: i64,
+    pub result_id: String,
+    pub nominal_value: f64,
+    pub measured_value: f64,
+    pub unit: Option<String>,
+    /// Расширенная неопределённость U (k=2), в единицах измерения
+    pub uncertainty: f64,
+    /// Допустимое отклонение ±, в единицах измерения
+    pub tolerance: f64,
+    pub deviation: f64,
+    pub passed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct CalibrationPointInput {
+    pub nominal_value: f64,
+    pub measured_value: f64,
+    #[validate(length(max = 20, message = "Unit cannot exceed 20 characters"))]
+    pub unit: Option<String>,
+    #[validate(range(min = 0.0, message = "Uncertainty cannot be negative"))]
+    pub uncertainty: f64,
+    #[validate(range(min = 0.0, message = "Tolerance cannot be negative"))]
+    pub tolerance: f64,
+}
+
+impl CalibrationPointInput {
+    pub fn deviation(&self) -> f64 {
+        self.measured_value - self.nominal_value
+    }
+
+    /// Решающее правило с защитной полосой (ISO 14253-1): соответствие
+    /// подтверждается, только если |отклонение| + U не выходит за допуск
+    pub fn passes(&self) -> bool {
+        self.deviation().abs() + self.uncertainty <= self.tolerance
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RecordCalibrationRequest {
+    #[validate(length(min = 1, message = "Reference standard is required"))]
+    pub standard_id: String,
+    /// Дата калибровки (по умолчанию — сегодня)
+    pub performed_at: Option<String>,
+    #[validate(length(max = 255, message = "Performed by cannot exceed 255 characters"))]
+    pub performed_by: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "Between 1 and 100 calibration points required"))]
+    #[validate(nested)]
+    pub points: Vec<CalibrationPointInput>,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CalibrationFlag {
+    pub id: String,
+    pub experiment_id: String,
+    pub experiment_title: String,
+    pub experiment_status: String,
+    pub equipment_id: String,
+    pub calibration_result_id: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<String>,
+    pub resolution: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResolveCalibrationFlagRequest {
+    #[validate(length(min = 1, max = 1000, message = "Resolution must be between 1 and 1000 characters"))]
+    pub resolution: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalibrationResultDetail {
+    #[serde(flatten)]
+    pub result: CalibrationResult,
+    pub standard: Option<CalibrationStandard>,
+    pub points: Vec<CalibrationPoint>,
+    pub flagged_experiments: Vec<CalibrationFlag>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(nominal: f64, measured: f64, uncertainty: f64, tolerance: f64) -> CalibrationPointInput {
+        CalibrationPointInput { nominal_value: nominal, measured_value: measured, unit: None, uncertainty, tolerance }
+    }
+
+    #[test]
+    fn test_guard_banded_decision_rule() {
+        assert!(point(100.0, 100.02, 0.01, 0.05).passes());
+        // Отклонение в допуске, но с учётом неопределённости — нет
+        assert!(!point(100.0, 100.045, 0.01, 0.05).passes());
+        assert!(!point(10.0, 9.9, 0.0, 0.05).passes());
+    }
+}
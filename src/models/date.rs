@@ -0,0 +1,91 @@
+// src/models/date.rs
+//! Календарная дата без времени (гарантия, договоры, закупка).
+//!
+//! В JSON и в БД хранится как 'YYYY-MM-DD'. Некорректные даты отклоняются
+//! при десериализации запроса, поэтому в TEXT-колонки больше не попадает
+//! произвольный текст вроде "до конца года".
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{Sqlite, SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Type};
+use std::fmt;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IsoDate(pub NaiveDate);
+
+impl IsoDate {
+    /// Строгий разбор 'YYYY-MM-DD'
+    pub fn parse(value: &str) -> Result<Self, String> {
+        NaiveDate::parse_from_str(value.trim(), DATE_FORMAT)
+            .map(IsoDate)
+            .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", value))
+    }
+}
+
+impl fmt::Display for IsoDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format(DATE_FORMAT))
+    }
+}
+
+impl Serialize for IsoDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IsoDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        IsoDate::parse(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Type<Sqlite> for IsoDate {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for IsoDate {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        <String as Encode<'q, Sqlite>>::encode(self.to_string(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for IsoDate {
+    /// Значения, записанные как метка времени, читаются по первым 10 символам
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let text = <&str as Decode<'r, Sqlite>>::decode(value)?;
+        Ok(IsoDate::parse(text.get(..10).unwrap_or(text))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_date_parse_and_serde() {
+        let date = IsoDate::parse("2025-02-28").unwrap();
+        assert_eq!(date.to_string(), "2025-02-28");
+        assert!(IsoDate::parse("2025-02-30").is_err());
+        assert!(IsoDate::parse("28.02.2025").is_err());
+        assert!(IsoDate::parse("until end of year").is_err());
+
+        let json = serde_json::to_string(&date).unwrap();
+        assert_eq!(json, "\"2025-02-28\"");
+        let back: IsoDate = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, date);
+        assert!(serde_json::from_str::<IsoDate>("\"2025-13-01\"").is_err());
+    }
+}
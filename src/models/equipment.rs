@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 use chrono::{DateTime, Utc};
 
+use super::IsoDate;
+
 // ==================== EQUIPMENT (ОБОРУДОВАНИЕ) ====================
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -21,8 +23,8 @@ pub struct Equipment {
     pub serial_number: Option<String>,
     pub manufacturer: Option<String>,
     pub model: Option<String>,
-    pub purchase_date: Option<String>,
-    pub warranty_until: Option<String>,
+    pub purchase_date: Option<IsoDate>,
+    pub warranty_until: Option<IsoDate>,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -60,8 +62,8 @@ pub struct CreateEquipmentRequest {
     #[validate(length(max = 255, message = "Model cannot exceed 255 characters"))]
     pub model: Option<String>,
 
-    pub purchase_date: Option<String>,
-    pub warranty_until: Option<String>,
+    pub purchase_date: Option<IsoDate>,
+    pub warranty_until: Option<IsoDate>,
 }
 
 /// Расширенный запрос на создание (с большим списком допустимых типов)
@@ -95,8 +97,8 @@ pub struct CreateEquipmentRequestExtended {
     #[validate(length(max = 255, message = "Model cannot exceed 255 characters"))]
     pub model: Option<String>,
 
-    pub purchase_date: Option<String>,
-    pub warranty_until: Option<String>,
+    pub purchase_date: Option<IsoDate>,
+    pub warranty_until: Option<IsoDate>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -126,8 +128,8 @@ pub struct UpdateEquipmentRequest {
     #[validate(length(max = 255, message = "Model cannot exceed 255 characters"))]
     pub model: Option<String>,
 
-    pub purchase_date: Option<String>,
-    pub warranty_until: Option<String>,
+    pub purchase_date: Option<IsoDate>,
+    pub warranty_until: Option<IsoDate>,
 }
 
 pub type UpdateEquipmentRequestExtended = UpdateEquipmentRequest;
@@ -146,6 +148,12 @@ pub struct EquipmentPart {
     pub status: String,
     pub last_replaced: Option<String>,
     pub next_replacement: Option<String>,
+    /// Интервал замены в днях: next_replacement сдвигается при каждой замене
+    #[sqlx(default)]
+    pub replacement_interval_days: Option<i32>,
+    /// Цена за единицу — для отчёта о стоимости владения
+    #[sqlx(default)]
+    pub unit_cost: Option<f64>,
     pub notes: Option<String>,
     pub created_by: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -163,7 +171,9 @@ pub struct CreateEquipmentPartRequest {
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
     pub manufacturer: Option<String>,
 
+    #[validate(range(min = 0, message = "Quantity cannot be negative"))]
     pub quantity: Option<i32>,
+    #[validate(range(min = 0, message = "Minimum quantity cannot be negative"))]
     pub min_quantity: Option<i32>,
 
     #[validate(length(max = 50, message = "Status cannot exceed 50 characters"))]
@@ -172,6 +182,12 @@ pub struct CreateEquipmentPartRequest {
     pub last_replaced: Option<String>,
     pub next_replacement: Option<String>,
 
+    #[validate(range(min = 1, max = 3650, message = "Replacement interval must be between 1 and 3650 days"))]
+    pub replacement_interval_days: Option<i32>,
+
+    #[validate(range(min = 0.0, message = "Unit cost cannot be negative"))]
+    pub unit_cost: Option<f64>,
+
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
 }
@@ -187,7 +203,9 @@ pub struct UpdateEquipmentPartRequest {
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
     pub manufacturer: Option<String>,
 
+    #[validate(range(min = 0, message = "Quantity cannot be negative"))]
     pub quantity: Option<i32>,
+    #[validate(range(min = 0, message = "Minimum quantity cannot be negative"))]
     pub min_quantity: Option<i32>,
 
     #[validate(length(max = 50, message = "Status cannot exceed 50 characters"))]
@@ -196,10 +214,85 @@ pub struct UpdateEquipmentPartRequest {
     pub last_replaced: Option<String>,
     pub next_replacement: Option<String>,
 
+    #[validate(range(min = 1, max = 3650, message = "Replacement interval must be between 1 and 3650 days"))]
+    pub replacement_interval_days: Option<i32>,
+
+    #[validate(range(min = 0.0, message = "Unit cost cannot be negative"))]
+    pub unit_cost: Option<f64>,
+
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
 }
 
+/// Расход запчасти при обслуживании
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct PartUsageInput {
+    #[validate(length(min = 1, message = "Part is required"))]
+    pub part_id: String,
+    #[validate(range(min = 1, max = 10000, message = "Quantity must be between 1 and 10000"))]
+    pub quantity: i32,
+}
+
+/// Запись журнала расхода запчастей
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EquipmentPartUsage {
+    pub id: String,
+    pub part_id: String,
+    pub part_name: String,
+    pub part_number: Option<String>,
+    pub equipment_id: String,
+    pub maintenance_id: Option<String>,
+    pub maintenance_type: Option<String>,
+    pub quantity: i32,
+    pub quantity_after: i32,
+    pub used_date: String,
+    pub used_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PartUsageQuery {
+    pub part_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Запчасть с остатком на уровне или ниже минимального
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LowStockPart {
+    pub id: String,
+    pub name: String,
+    pub part_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub quantity: i32,
+    pub min_quantity: i32,
+    /// Сколько нужно докупить до минимального остатка
+    pub shortfall: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PartReplacementQuery {
+    pub days: Option<i32>,
+}
+
+/// Запчасть, которую пора (или скоро пора) заменить
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PartReplacementDue {
+    pub id: String,
+    pub name: String,
+    pub part_number: Option<String>,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub status: String,
+    pub quantity: i32,
+    pub last_replaced: Option<String>,
+    pub next_replacement: String,
+    /// Отрицательное значение — замена просрочена
+    pub days_until_due: i64,
+    pub is_overdue: bool,
+}
+
 // ==================== MAINTENANCE (ОБСЛУЖИВАНИЕ) ====================
 
 /// Запись об обслуживании оборудования
@@ -216,6 +309,9 @@ pub struct EquipmentMaintenance {
     pub cost: Option<f64>,
     pub parts_replaced: Option<String>,
     pub notes: Option<String>,
+    /// План, по которому создана запись (None — разовое обслуживание)
+    #[sqlx(default)]
+    pub plan_id: Option<String>,
     pub created_by: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -267,6 +363,11 @@ pub struct CreateMaintenanceRequest {
     #[validate(length(max = 1000, message = "Parts replaced cannot exceed 1000 characters"))]
     pub parts_replaced: Option<String>,
 
+    /// Израсходованные запчасти (только для записей со статусом completed)
+    #[validate(nested)]
+    #[serde(default)]
+    pub parts_used: Vec<PartUsageInput>,
+
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
 }
@@ -293,11 +394,15 @@ pub struct UpdateMaintenanceRequest {
     pub notes: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CompleteMaintenanceRequest {
     pub completed_date: Option<String>,
     pub performed_by: Option<String>,
     pub notes: Option<String>,
+    /// Израсходованные запчасти: списываются со склада оборудования
+    #[validate(nested)]
+    #[serde(default)]
+    pub parts_used: Vec<PartUsageInput>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -306,6 +411,255 @@ pub struct UpcomingMaintenanceQuery {
     pub limit: Option<i32>,
 }
 
+/// Предстоящее или просроченное обслуживание
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UpcomingMaintenance {
+    pub id: String,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub equipment_status: String,
+    pub equipment_location: Option<String>,
+    pub maintenance_type: String,
+    pub status: String,
+    pub scheduled_date: String,
+    pub plan_id: Option<String>,
+    pub plan_name: Option<String>,
+    /// Отрицательное значение — просрочено на столько дней
+    pub days_until_due: i64,
+    pub is_overdue: bool,
+}
+
+// ==================== MAINTENANCE PLANS (ПЛАНЫ ТО) ====================
+
+/// Повторяющаяся задача обслуживания (калибровка раз в год, чистка раз в месяц...)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct MaintenancePlan {
+    pub id: String,
+    pub equipment_id: String,
+    pub name: String,
+    pub maintenance_type: String,
+    pub interval_days: i64,
+    /// Интервал по числу прогонов: срок наступает раньше, если прогонов набралось больше
+    #[sqlx(default)]
+    pub interval_runs: Option<i64>,
+    /// Переводить оборудование в 'maintenance'/'calibration' в день срока
+    pub auto_status: bool,
+    pub is_active: bool,
+    pub description: Option<String>,
+    pub last_completed_date: Option<String>,
+    pub next_due_date: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateMaintenancePlanRequest {
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
+    pub name: String,
+
+    #[validate(length(min = 1, max = 50, message = "Maintenance type is required"))]
+    pub maintenance_type: String,
+
+    #[validate(range(min = 1, max = 3650, message = "Interval must be between 1 and 3650 days"))]
+    pub interval_days: i64,
+
+    #[validate(range(min = 1, max = 1000000, message = "Run interval must be between 1 and 1000000 runs"))]
+    pub interval_runs: Option<i64>,
+
+    /// Дата первого выполнения (по умолчанию — сегодня + интервал)
+    pub first_due_date: Option<String>,
+
+    pub auto_status: Option<bool>,
+
+    #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateMaintenancePlanRequest {
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
+    pub name: Option<String>,
+
+    #[validate(range(min = 1, max = 3650, message = "Interval must be between 1 and 3650 days"))]
+    pub interval_days: Option<i64>,
+
+    #[validate(range(min = 1, max = 1000000, message = "Run interval must be between 1 and 1000000 runs"))]
+    pub interval_runs: Option<i64>,
+
+    pub auto_status: Option<bool>,
+    pub is_active: Option<bool>,
+
+    #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
+    pub description: Option<String>,
+}
+
+// ==================== USAGE LOG (ЖУРНАЛ ИСПОЛЬЗОВАНИЯ) ====================
+
+/// Сеанс работы на приборе: от выдачи (check-out) до возврата (check-in)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct EquipmentUsageSession {
+    pub id: String,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub user_id: String,
+    pub username: Option<String>,
+    pub experiment_id: Option<String>,
+    pub experiment_title: Option<String>,
+    pub purpose: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// None — прибор ещё не возвращён
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_minutes: Option<i64>,
+    pub run_count: i64,
+    pub notes: Option<String>,
+    pub checked_in_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CheckOutEquipmentRequest {
+    pub experiment_id: Option<String>,
+
+    #[validate(length(max = 500, message = "Purpose cannot exceed 500 characters"))]
+    pub purpose: Option<String>,
+
+    /// Начало работы; по умолчанию — сейчас
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CheckInEquipmentRequest {
+    /// Число прогонов (инжекций, циклов) за сеанс
+    #[validate(range(min = 0, max = 100000, message = "Run count must be between 0 and 100000"))]
+    pub run_count: i64,
+
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+
+    /// Конец работы; по умолчанию — сейчас
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageLogQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub user_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// План обслуживания с интервалом по прогонам и счётчиком с последнего выполнения
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RunBasedMaintenance {
+    pub plan_id: String,
+    pub plan_name: String,
+    pub maintenance_type: String,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub interval_runs: i64,
+    pub runs_since_maintenance: i64,
+    pub last_completed_date: Option<String>,
+    pub next_due_date: Option<String>,
+    pub is_due: bool,
+}
+
+// ==================== SERVICE CONTRACTS (СЕРВИСНЫЕ ДОГОВОРЫ) ====================
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ServiceContract {
+    pub id: String,
+    pub equipment_id: String,
+    pub vendor: String,
+    pub contract_number: String,
+    /// Что покрывает договор (выезды, запчасти, калибровка...)
+    pub coverage: Option<String>,
+    pub start_date: IsoDate,
+    pub end_date: IsoDate,
+    pub cost: Option<f64>,
+    pub contact: Option<String>,
+    /// За сколько дней до окончания предупреждать
+    pub alert_days: i64,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateServiceContractRequest {
+    #[validate(length(min = 1, max = 255, message = "Vendor must be between 1 and 255 characters"))]
+    pub vendor: String,
+
+    #[validate(length(min = 1, max = 100, message = "Contract number must be between 1 and 100 characters"))]
+    pub contract_number: String,
+
+    #[validate(length(max = 1000, message = "Coverage cannot exceed 1000 characters"))]
+    pub coverage: Option<String>,
+
+    pub start_date: IsoDate,
+    pub end_date: IsoDate,
+
+    #[validate(range(min = 0.0, message = "Cost cannot be negative"))]
+    pub cost: Option<f64>,
+
+    #[validate(length(max = 255, message = "Contact cannot exceed 255 characters"))]
+    pub contact: Option<String>,
+
+    #[validate(range(min = 0, max = 365, message = "Alert days must be between 0 and 365"))]
+    pub alert_days: Option<i64>,
+
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateServiceContractRequest {
+    #[validate(length(min = 1, max = 255, message = "Vendor must be between 1 and 255 characters"))]
+    pub vendor: Option<String>,
+
+    #[validate(length(min = 1, max = 100, message = "Contract number must be between 1 and 100 characters"))]
+    pub contract_number: Option<String>,
+
+    #[validate(length(max = 1000, message = "Coverage cannot exceed 1000 characters"))]
+    pub coverage: Option<String>,
+
+    pub start_date: Option<IsoDate>,
+    pub end_date: Option<IsoDate>,
+
+    #[validate(range(min = 0.0, message = "Cost cannot be negative"))]
+    pub cost: Option<f64>,
+
+    #[validate(length(max = 255, message = "Contact cannot exceed 255 characters"))]
+    pub contact: Option<String>,
+
+    #[validate(range(min = 0, max = 365, message = "Alert days must be between 0 and 365"))]
+    pub alert_days: Option<i64>,
+
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpiringCoverageQuery {
+    /// Горизонт в днях; по умолчанию — собственный `alert_days` договора (для гарантии — 30)
+    pub days: Option<i64>,
+}
+
+/// Истекающая гарантия или сервисный договор
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExpiringCoverage {
+    /// 'warranty' | 'contract'
+    pub kind: String,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub contract_id: Option<String>,
+    pub vendor: Option<String>,
+    pub contract_number: Option<String>,
+    pub end_date: IsoDate,
+    /// Отрицательное значение — уже истёк
+    pub days_left: i64,
+}
+
 // ==================== FILES (ФАЙЛЫ) ====================
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -313,6 +667,9 @@ pub struct EquipmentFile {
     pub id: String,
     pub equipment_id: String,
     pub part_id: Option<String>,
+    /// Документ сервисного договора
+    #[sqlx(default)]
+    pub contract_id: Option<String>,
     pub file_type: String,
     pub original_filename: String,
     pub stored_filename: String,
@@ -320,6 +677,20 @@ pub struct EquipmentFile {
     pub file_size: i64,
     pub mime_type: String,
     pub description: Option<String>,
+    /// Ключ в хранилище (контентный); None — файл загружен до перехода на хранилище
+    #[sqlx(default)]
+    pub storage_key: Option<String>,
+    #[sqlx(default)]
+    pub sha256: Option<String>,
+    /// Для фото: размеры с учётом поворота и ориентация (landscape/portrait/square)
+    #[sqlx(default)]
+    pub image_width: Option<i64>,
+    #[sqlx(default)]
+    pub image_height: Option<i64>,
+    #[sqlx(default)]
+    pub orientation: Option<String>,
+    #[sqlx(default)]
+    pub exif_orientation: Option<i64>,
     pub uploaded_by: Option<String>,
     pub created_at: DateTime<Utc>,
 }
@@ -333,6 +704,92 @@ pub struct UploadFileRequest {
     pub description: Option<String>,
 }
 
+// ==================== HIERARCHY (СИСТЕМЫ И МОДУЛИ) ====================
+
+/// Связь «система → модуль». Текущая связь — `unlinked_at IS NULL`,
+/// закрытые связи остаются как история перемещений модуля.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct EquipmentLink {
+    pub id: String,
+    pub parent_id: String,
+    pub parent_name: String,
+    pub child_id: String,
+    pub child_name: String,
+    /// Позиция в стеке (например, "pump", "detector")
+    pub slot: Option<String>,
+    pub linked_at: DateTime<Utc>,
+    pub unlinked_at: Option<DateTime<Utc>>,
+    pub linked_by: Option<String>,
+    pub unlinked_by: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AttachModuleRequest {
+    #[validate(length(min = 1, message = "Module id is required"))]
+    pub child_id: String,
+
+    #[validate(length(max = 100, message = "Slot cannot exceed 100 characters"))]
+    pub slot: Option<String>,
+
+    /// Дата установки; по умолчанию — сейчас. Если модуль стоит в другой
+    /// системе, та связь закрывается этой же датой (перемещение).
+    pub linked_at: Option<DateTime<Utc>>,
+
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DetachModuleRequest {
+    pub unlinked_at: Option<DateTime<Utc>>,
+
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+/// Узел дерева системы с собственным состоянием ТО и калибровки
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct EquipmentTreeNode {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    pub serial_number: Option<String>,
+    pub model: Option<String>,
+    pub parent_id: Option<String>,
+    pub slot: Option<String>,
+    pub linked_at: Option<DateTime<Utc>>,
+    pub depth: i64,
+    pub overdue_maintenance: i64,
+    pub overdue_calibrations: i64,
+    pub next_maintenance_date: Option<String>,
+    /// Результат последней калибровки; None — калибровок не было
+    pub last_calibration_passed: Option<bool>,
+}
+
+/// Сводное состояние системы с учётом всех модулей
+#[derive(Debug, Serialize)]
+pub struct EquipmentStatusRollup {
+    /// Наихудший статус по системе и модулям
+    pub status: String,
+    /// Модули (или сама система), определившие сводный статус
+    pub affected: Vec<String>,
+    pub overdue_maintenance: i64,
+    pub overdue_calibrations: i64,
+    pub failed_calibrations: i64,
+    pub next_maintenance_date: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EquipmentHierarchyResponse {
+    pub equipment_id: String,
+    /// Текущая система, в которую установлено оборудование
+    pub parent: Option<EquipmentLink>,
+    /// Система и все модули (вглубь), первым идёт корень
+    pub tree: Vec<EquipmentTreeNode>,
+    pub rollup: EquipmentStatusRollup,
+}
+
 // ==================== DETAIL RESPONSE ====================
 
 /// Детальный ответ с оборудованием и связанными данными
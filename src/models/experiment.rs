@@ -125,13 +125,16 @@ impl Experiment {
 pub struct ExperimentDocument {
     pub id: String,
     pub experiment_id: String,
-    pub filename: String,
     pub original_filename: String,
-    pub file_path: String,
-    pub file_size: i64,
     pub mime_type: String,
-    pub uploaded_by: String,
-    pub uploaded_at: DateTime<Utc>,
+    pub file_size: i64,
+    /// Ключ в BlobStore; наружу не отдаётся
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    pub sha256: String,
+    pub description: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -357,6 +360,7 @@ mod tests {
             instructor: Some("Dr. Smith".to_string()),
             student_group: Some("Group 101".to_string()),
             location: Some("Lab 101".to_string()),
+            room_id: None,
             protocol: None,
             start_date: Some(Utc::now()),
             end_date: Some(Utc::now() + chrono::Duration::hours(2)),
@@ -376,6 +380,7 @@ mod tests {
             instructor: None,
             student_group: None,
             location: None,
+            room_id: None,
             protocol: None,
             start_date: Some(Utc::now()),
             end_date: None, // Missing!
@@ -8,18 +8,26 @@ pub mod experiment;
 pub mod reagent;
 pub mod room;
 pub mod storage_zone;
+pub mod storage_location;
 pub mod user;
 pub mod batch_container;
+pub mod calibration;
+pub mod date;
+pub mod notification;
 
 // 2. Ре-экспортируем содержимое
 pub use batch::*;
 pub use batch_container::*;
 pub use batch_placement::*;
+pub use calibration::*;
+pub use date::*;
 pub use equipment::*;
 pub use experiment::*;
+pub use notification::*;
 pub use reagent::*;
 pub use room::*;
 pub use storage_zone::*;
+pub use storage_location::*;
 pub use user::*;
 
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,26 @@
+// src/models/notification.rs
+//! Системные уведомления (истекающие гарантии, договоры и т.п.).
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Notification {
+    pub id: String,
+    pub category: String,
+    /// info | warning | critical
+    pub severity: String,
+    pub title: String,
+    pub message: String,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Прочитано текущим пользователем
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    pub unread_only: Option<bool>,
+    pub category: Option<String>,
+    pub limit: Option<i64>,
+}
@@ -18,6 +18,12 @@ pub struct Reagent {
     pub storage_conditions: Option<String>,
     pub appearance: Option<String>,
     pub hazard_pictograms: Option<String>,
+    #[sqlx(default)]
+    pub signal_word: Option<String>,     // GHS: Danger | Warning
+    #[sqlx(default)]
+    pub shelf_life_after_opening_days: Option<i64>, // Срок годности после вскрытия контейнера
+    #[sqlx(default)]
+    pub retest_interval_days: Option<i64>,          // Интервал перепроверки вскрытого контейнера
     pub status: String,
     // Cached aggregation fields (обновляются триггерами при изменении batches)
     pub total_quantity: f64,
@@ -64,6 +70,14 @@ pub struct CreateReagentRequest {
 
     #[validate(length(max = 100, message = "Hazard pictograms cannot exceed 100 characters"))]
     pub hazard_pictograms: Option<String>,
+
+    pub signal_word: Option<String>,
+
+    #[validate(range(min = 1, max = 3650, message = "Shelf life after opening must be 1-3650 days"))]
+    pub shelf_life_after_opening_days: Option<i64>,
+
+    #[validate(range(min = 1, max = 3650, message = "Retest interval must be 1-3650 days"))]
+    pub retest_interval_days: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -98,6 +112,17 @@ pub struct UpdateReagentRequest {
     #[validate(length(max = 100, message = "Hazard pictograms cannot exceed 100 characters"))]
     pub hazard_pictograms: Option<String>,
 
+    /// "" — очистить
+    pub signal_word: Option<String>,
+
+    /// 0 — очистить
+    #[validate(range(min = 0, max = 3650, message = "Shelf life after opening must be 0-3650 days"))]
+    pub shelf_life_after_opening_days: Option<i64>,
+
+    /// 0 — очистить
+    #[validate(range(min = 0, max = 3650, message = "Retest interval must be 0-3650 days"))]
+    pub retest_interval_days: Option<i64>,
+
     pub status: Option<String>,
 }
 
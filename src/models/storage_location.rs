@@ -0,0 +1,86 @@
+// src/models/storage_location.rs
+//! Дерево мест хранения произвольной глубины:
+//! building → floor → room → freezer → rack → box, cabinet → shelf → tray и т.д.
+//!
+//! Список смежности (parent_id) + материализованный путь (`/root/…/id/`).
+//! Комнаты, зоны и позиции — тоже узлы дерева с тем же id (linked_entity),
+//! контейнеры по-прежнему размещаются только в позициях.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+/// Допустимые типы узлов (типы зон хранения входят в список)
+pub const LOCATION_TYPES: &[&str] = &[
+    "site", "building", "floor", "room", "cabinet", "refrigerator", "freezer", "fume_hood",
+    "safety_cabinet", "desiccator", "shelf", "drawer", "rack", "tray", "box", "position", "other",
+];
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct StorageLocation {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub location_type: String,
+    pub name: String,
+    /// room | zone | position — узел совпадает с записью этой таблицы; NULL — чисто структурный узел
+    pub linked_entity: Option<String>,
+    pub path: String,
+    pub depth: i32,
+    pub sort_order: i32,
+    pub description: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Узел с количеством контейнеров: в самой позиции и во всём поддереве
+#[derive(Debug, Serialize, Clone)]
+pub struct StorageLocationNode {
+    #[serde(flatten)]
+    pub location: StorageLocation,
+    pub items_count: i64,
+    pub total_items: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LocationChildrenQuery {
+    /// Не задан — корневые узлы
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateStorageLocationRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be 1-100 characters"))]
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub location_type: String,
+    #[validate(length(max = 500, message = "Description max 500 characters"))]
+    pub description: Option<String>,
+    pub sort_order: Option<i32>,
+    /// Создать позицию хранения, в которую можно размещать контейнеры
+    pub placeable: Option<bool>,
+    #[validate(length(max = 20, message = "Label max 20 characters"))]
+    pub position_label: Option<String>,
+    #[validate(range(min = 1, message = "Capacity must be positive"))]
+    pub max_capacity: Option<i32>,
+    #[validate(range(min = 1, max = 26, message = "Grid rows must be 1-26"))]
+    pub grid_rows: Option<i32>,
+    #[validate(range(min = 1, max = 99, message = "Grid columns must be 1-99"))]
+    pub grid_columns: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateStorageLocationRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be 1-100 characters"))]
+    pub name: Option<String>,
+    pub location_type: Option<String>,
+    #[validate(length(max = 500, message = "Description max 500 characters"))]
+    pub description: Option<String>,
+    pub sort_order: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveStorageLocationRequest {
+    /// Не задан — узел становится корневым
+    pub new_parent_id: Option<String>,
+}
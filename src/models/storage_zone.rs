@@ -155,8 +155,14 @@ pub struct StoragePosition {
     pub zone_id: String,
     pub name: String,
     pub position_label: Option<String>,  // "A1", "Shelf 3", etc.
-    pub max_capacity: Option<i32>,       // Максимум единиц хранения
-    pub current_count: i32,              // Кэшированное количество предметов
+    pub max_capacity: Option<i32>,       // Максимум единиц хранения (для сетки = rows × columns)
+    pub current_count: i32,              // Кэшированное количество контейнеров
+    #[sqlx(default)]
+    pub grid_rows: Option<i32>,          // Ряды A..Z (коробка, штатив, ячейки морозильника)
+    #[sqlx(default)]
+    pub grid_columns: Option<i32>,       // Столбцы 1..99
+    #[sqlx(default)]
+    pub short_code: Option<String>,      // P000007 — этикетка на полке/коробке
     pub sort_order: i32,
     pub description: Option<String>,
     pub status: String,
@@ -205,7 +211,12 @@ pub struct CreateStoragePositionRequest {
     pub zone_id: String,
     #[validate(length(max = 20, message = "Label max 20 characters"))]
     pub position_label: Option<String>,
+    #[validate(range(min = 1, message = "Capacity must be positive"))]
     pub max_capacity: Option<i32>,
+    #[validate(range(min = 1, max = 26, message = "Grid rows must be 1-26"))]
+    pub grid_rows: Option<i32>,
+    #[validate(range(min = 1, max = 99, message = "Grid columns must be 1-99"))]
+    pub grid_columns: Option<i32>,
     pub sort_order: Option<i32>,
     #[validate(length(max = 500, message = "Description max 500 characters"))]
     pub description: Option<String>,
@@ -217,48 +228,62 @@ pub struct UpdateStoragePositionRequest {
     pub name: Option<String>,
     #[validate(length(max = 20, message = "Label max 20 characters"))]
     pub position_label: Option<String>,
+    #[validate(range(min = 1, message = "Capacity must be positive"))]
     pub max_capacity: Option<i32>,
+    #[validate(range(min = 1, max = 26, message = "Grid rows must be 1-26"))]
+    pub grid_rows: Option<i32>,
+    #[validate(range(min = 1, max = 99, message = "Grid columns must be 1-99"))]
+    pub grid_columns: Option<i32>,
     pub sort_order: Option<i32>,
     #[validate(length(max = 500, message = "Description max 500 characters"))]
     pub description: Option<String>,
     pub status: Option<String>,
 }
 
-// === Расширенные DTO для иерархического отображения ===
+// === Сетка ячеек (коробки, штативы) ===
 
-/// Зона хранения с вложенными позициями
+/// Ячейка сетки позиции; поля контейнера пустые, если ячейка свободна
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct StorageZoneWithPositions {
-    #[serde(flatten)]
-    pub zone: StorageZone,
-    pub positions: Vec<StoragePosition>,
-    pub items_count: i64,  // Суммарное кол-во предметов в зоне
+pub struct GridCell {
+    pub cell: String,       // "A1"
+    pub row: i32,
+    pub column: i32,
+    pub container_id: Option<String>,
+    pub sequence_number: Option<i64>,
+    pub container_status: Option<String>,
+    pub batch_id: Option<String>,
+    pub batch_number: Option<String>,
+    pub reagent_name: Option<String>,
 }
 
-/// Комната с вложенными зонами хранения
+/// Карта занятости коробки: cells[row][column]
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RoomWithStorage {
-    pub id: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub capacity: Option<i32>,
-    pub color: Option<String>,
-    pub status: String,
-    pub zones: Vec<StorageZoneWithPositions>,
-    pub total_items: i64,
+pub struct PositionGrid {
+    pub position_id: String,
+    pub position_name: String,
+    pub rows: i32,
+    pub columns: i32,
+    pub capacity: i64,
+    pub occupied: i64,
+    pub free: i64,
+    pub cells: Vec<Vec<GridCell>>,
 }
 
+// === Путь к месту хранения ===
+
 /// Полный путь к месту хранения (для отображения)
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StorageLocationPath {
-    pub room_id: String,
-    pub room_name: String,
+    pub room_id: Option<String>,
+    pub room_name: Option<String>,
     pub zone_id: Option<String>,
     pub zone_name: Option<String>,
     pub zone_type: Option<String>,
     pub position_id: Option<String>,
     pub position_name: Option<String>,
     pub position_label: Option<String>,
-    /// Форматированный путь: "Lab 104 → Cabinet A → Shelf 3"
+    /// Форматированный путь любой глубины: "Building 2 → Lab 104 → Freezer 1 → Rack C → Box 7"
     pub full_path: String,
+    /// Узлы дерева от корня до запрошенного
+    pub nodes: Vec<super::StorageLocation>,
 }
@@ -188,6 +188,10 @@ where
 pub async fn start_maintenance_tasks(pool: SqlitePool) {
     let pool_clone1 = pool.clone();
     let pool_clone2 = pool.clone();
+    let pool_clone3 = pool.clone();
+    let pool_clone4 = pool.clone();
+    let pool_clone5 = pool.clone();
+    let pool_clone6 = pool.clone();
     
     tokio::spawn(async move {
         cleanup_old_audit_logs(pool_clone1).await;
@@ -196,6 +200,75 @@ pub async fn start_maintenance_tasks(pool: SqlitePool) {
     tokio::spawn(async move {
         update_batch_statuses(pool_clone2).await;
     });
+
+    tokio::spawn(async move {
+        update_equipment_maintenance_statuses(pool_clone3).await;
+    });
+
+    tokio::spawn(async move {
+        update_container_expiry(pool_clone4).await;
+    });
+
+    tokio::spawn(async move {
+        notify_waste_accumulation(pool_clone5).await;
+    });
+
+    tokio::spawn(async move {
+        prune_temperature_readings(pool_clone6).await;
+    });
+}
+
+async fn update_equipment_maintenance_statuses(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(3600)); // Раз в час
+
+    loop {
+        interval.tick().await;
+        match crate::maintenance_handlers::apply_due_maintenance_statuses(&pool).await {
+            Ok(count) if count > 0 => log::info!("Moved {} equipment items to maintenance/calibration", count),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to apply due maintenance statuses: {}", e),
+        }
+        match crate::spare_parts_handlers::apply_part_replacement_statuses(&pool).await {
+            Ok(count) if count > 0 => log::warn!("{} spare parts are due for replacement", count),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to apply part replacement statuses: {}", e),
+        }
+        match crate::service_contracts::generate_expiry_notifications(&pool).await {
+            Ok(count) if count > 0 => log::warn!("{} warranty/service contract expiry notifications created", count),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to generate expiry notifications: {}", e),
+        }
+    }
+}
+
+/// Контейнеры: срок партии или срок после вскрытия (что раньше)
+async fn update_container_expiry(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(3600)); // Раз в час
+
+    loop {
+        interval.tick().await;
+        match crate::container_handlers::apply_container_expiry(&pool).await {
+            Ok((containers, batches)) if containers + batches > 0 => log::info!(
+                "Marked {} containers and {} batches as expired by container shelf life", containers, batches
+            ),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to apply container expiry: {}", e),
+        }
+    }
+}
+
+/// Опасные отходы: приближение к предельному сроку накопления
+async fn notify_waste_accumulation(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(3600)); // Раз в час
+
+    loop {
+        interval.tick().await;
+        match crate::waste::generate_accumulation_notifications(&pool).await {
+            Ok(count) if count > 0 => log::warn!("{} hazardous waste accumulation notifications created", count),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to generate waste accumulation notifications: {}", e),
+        }
+    }
 }
 
 async fn cleanup_old_audit_logs(pool: SqlitePool) {
@@ -238,6 +311,20 @@ async fn cleanup_old_audit_logs(pool: SqlitePool) {
     }
 }
 
+/// Прореженные показания температуры старше срока хранения
+async fn prune_temperature_readings(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(24 * 3600)); // Раз в день
+
+    loop {
+        interval.tick().await;
+        match crate::sensors::prune_readings(&pool).await {
+            Ok(count) if count > 0 => log::info!("Pruned {} temperature reading buckets", count),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to prune temperature readings: {}", e),
+        }
+    }
+}
+
 async fn update_batch_statuses(pool: SqlitePool) {
     let mut interval = interval(Duration::from_secs(3600)); // Раз в час
 
@@ -0,0 +1,529 @@
+// src/notebook_handlers.rs
+//! Электронный лабораторный журнал (ELN) эксперимента.
+//!
+//! Записи только добавляются: UPDATE/DELETE запрещены триггерами в БД.
+//! Исправление — это новая запись с `amends_entry_id` и обязательной причиной.
+//!
+//! Endpoints:
+//!   GET    /api/v1/experiments/{id}/notebook                     — записи (хронологически)
+//!   POST   /api/v1/experiments/{id}/notebook                     — новая запись
+//!   POST   /api/v1/experiments/{id}/notebook/{entry_id}/amend    — исправление записи
+//!   GET    /api/v1/experiments/{id}/notebook/export              — журнал в Markdown
+//!   GET    /api/v1/experiments/notebook/search?q=                — полнотекстовый поиск
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::Experiment;
+use crate::query_builders::fts::{highlight_snippet, snippet_expr, FtsQueryBuilder};
+
+const NOTEBOOK_FTS_TABLE: &str = "experiment_notebook_fts";
+
+// ==================== MODELS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NotebookEntry {
+    pub id: String,
+    pub experiment_id: String,
+    pub entry_number: i64,
+    pub title: Option<String>,
+    pub content: String,
+    pub author_id: String,
+    pub author_username: Option<String>,
+    pub amends_entry_id: Option<String>,
+    pub amendment_reason: Option<String>,
+    pub superseded_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NotebookLink {
+    #[serde(skip_deserializing)]
+    pub entry_id: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    #[serde(skip_deserializing)]
+    pub entity_label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotebookEntryWithLinks {
+    #[serde(flatten)]
+    pub entry: NotebookEntry,
+    pub links: Vec<NotebookLink>,
+}
+
+const ENTRY_SELECT: &str = r#"
+    SELECT e.id, e.experiment_id, e.entry_number, e.title, e.content, e.author_id,
+           u.username as author_username, e.amends_entry_id, e.amendment_reason,
+           (SELECT a.id FROM experiment_notebook_entries a WHERE a.amends_entry_id = e.id) as superseded_by,
+           e.created_at
+    FROM experiment_notebook_entries e
+    LEFT JOIN users u ON e.author_id = u.id
+"#;
+
+// ==================== REQUESTS ====================
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateNotebookEntryRequest {
+    #[validate(length(max = 255, message = "Title cannot exceed 255 characters"))]
+    pub title: Option<String>,
+    /// Markdown
+    #[validate(length(min = 1, max = 100000, message = "Content must be between 1 and 100000 characters"))]
+    pub content: String,
+    #[serde(default)]
+    pub links: Vec<NotebookLink>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AmendNotebookEntryRequest {
+    #[validate(length(max = 255, message = "Title cannot exceed 255 characters"))]
+    pub title: Option<String>,
+    #[validate(length(min = 1, max = 100000, message = "Content must be between 1 and 100000 characters"))]
+    pub content: String,
+    #[validate(length(min = 1, max = 1000, message = "Amendment reason must be between 1 and 1000 characters"))]
+    pub reason: String,
+    /// None — перенести ссылки исправляемой записи
+    pub links: Option<Vec<NotebookLink>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotebookListQuery {
+    /// Показывать исправленные (заменённые) версии записей
+    pub include_superseded: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotebookSearchQuery {
+    pub q: String,
+    pub experiment_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+// ==================== HELPERS ====================
+
+async fn get_experiment_or_404(pool: &sqlx::SqlitePool, experiment_id: &str) -> ApiResult<Experiment> {
+    sqlx::query_as::<_, Experiment>("SELECT * FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Experiment"))
+}
+
+/// Проверяет, что связанные партии/оборудование существуют
+async fn validate_links(pool: &sqlx::SqlitePool, links: &[NotebookLink]) -> ApiResult<()> {
+    for link in links {
+        let sql = match link.entity_type.as_str() {
+            "batch" => "SELECT COUNT(*) FROM batches WHERE id = ? AND deleted_at IS NULL",
+            "equipment" => "SELECT COUNT(*) FROM equipment WHERE id = ?",
+            other => {
+                return Err(ApiError::bad_request(&format!(
+                    "Invalid link type '{}'. Must be 'batch' or 'equipment'", other
+                )))
+            }
+        };
+        let count: i64 = sqlx::query_scalar(sql).bind(&link.entity_id).fetch_one(pool).await?;
+        if count == 0 {
+            return Err(ApiError::not_found(&format!("Linked {} '{}'", link.entity_type, link.entity_id)));
+        }
+    }
+    Ok(())
+}
+
+async fn insert_entry(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    title: Option<&str>,
+    content: &str,
+    links: &[NotebookLink],
+    author_id: &str,
+    amends: Option<(&str, &str)>,
+) -> ApiResult<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    // Номер считается в том же INSERT: отдельный SELECT MAX+1 гонялся бы
+    // с параллельной записью и упирался в UNIQUE(experiment_id, entry_number)
+    sqlx::query(r#"
+        INSERT INTO experiment_notebook_entries
+        (id, experiment_id, entry_number, title, content, author_id, amends_entry_id, amendment_reason, created_at)
+        SELECT ?1, ?2, COALESCE(MAX(entry_number), 0) + 1, ?3, ?4, ?5, ?6, ?7, ?8
+        FROM experiment_notebook_entries WHERE experiment_id = ?2
+    "#)
+        .bind(&id)
+        .bind(experiment_id)
+        .bind(title)
+        .bind(content)
+        .bind(author_id)
+        .bind(amends.map(|(entry_id, _)| entry_id))
+        .bind(amends.map(|(_, reason)| reason))
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(amendment_conflict)?;
+
+    for link in links {
+        sqlx::query(
+            "INSERT OR IGNORE INTO experiment_notebook_links (entry_id, entity_type, entity_id, created_at) VALUES (?, ?, ?, ?)"
+        )
+            .bind(&id)
+            .bind(&link.entity_type)
+            .bind(&link.entity_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Параллельное исправление той же записи упирается в уникальный индекс по amends_entry_id;
+/// прочие нарушения уникальности к исправлениям отношения не имеют
+fn amendment_conflict(err: sqlx::Error) -> ApiError {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() && db.message().contains("amends_entry_id") => {
+            ApiError::bad_request("Entry has already been amended; amend the latest version")
+        }
+        _ => err.into(),
+    }
+}
+
+async fn load_links(pool: &sqlx::SqlitePool, experiment_id: &str) -> ApiResult<Vec<NotebookLink>> {
+    let links = sqlx::query_as::<_, NotebookLink>(r#"
+        SELECT l.entry_id, l.entity_type, l.entity_id,
+               CASE l.entity_type
+                   WHEN 'batch' THEN (SELECT r.name || ' / ' || b.batch_number FROM batches b
+                                      JOIN reagents r ON b.reagent_id = r.id WHERE b.id = l.entity_id)
+                   WHEN 'equipment' THEN (SELECT eq.name FROM equipment eq WHERE eq.id = l.entity_id)
+               END as entity_label
+        FROM experiment_notebook_links l
+        JOIN experiment_notebook_entries e ON l.entry_id = e.id
+        WHERE e.experiment_id = ?
+    "#)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(links)
+}
+
+async fn load_entries(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    include_superseded: bool,
+) -> ApiResult<Vec<NotebookEntryWithLinks>> {
+    let sql = format!("{} WHERE e.experiment_id = ? ORDER BY e.created_at ASC, e.entry_number ASC", ENTRY_SELECT);
+    let entries: Vec<NotebookEntry> = sqlx::query_as(&sql)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let links = load_links(pool, experiment_id).await?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|e| include_superseded || e.superseded_by.is_none())
+        .map(|entry| {
+            let entry_links = links.iter().filter(|l| l.entry_id == entry.id).cloned().collect();
+            NotebookEntryWithLinks { entry, links: entry_links }
+        })
+        .collect())
+}
+
+async fn get_entry_or_404(pool: &sqlx::SqlitePool, experiment_id: &str, entry_id: &str) -> ApiResult<NotebookEntry> {
+    let sql = format!("{} WHERE e.id = ? AND e.experiment_id = ?", ENTRY_SELECT);
+    sqlx::query_as::<_, NotebookEntry>(&sql)
+        .bind(entry_id)
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Notebook entry"))
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_notebook(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<NotebookListQuery>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    get_experiment_or_404(&app_state.db_pool, &experiment_id).await?;
+
+    let entries = load_entries(&app_state.db_pool, &experiment_id, query.include_superseded.unwrap_or(false)).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entries)))
+}
+
+pub async fn create_notebook_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CreateNotebookEntryRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let experiment_id = path.into_inner();
+    get_experiment_or_404(&app_state.db_pool, &experiment_id).await?;
+    validate_links(&app_state.db_pool, &body.links).await?;
+
+    let id = insert_entry(
+        &app_state.db_pool, &experiment_id, body.title.as_deref(), &body.content, &body.links, &user_id, None,
+    ).await?;
+
+    let entry = get_entry_or_404(&app_state.db_pool, &experiment_id, &id).await?;
+    info!("User {} added notebook entry #{} to experiment {}", user_id, entry.entry_number, experiment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(entry)))
+}
+
+pub async fn amend_notebook_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<AmendNotebookEntryRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (experiment_id, entry_id) = path.into_inner();
+
+    let original = get_entry_or_404(&app_state.db_pool, &experiment_id, &entry_id).await?;
+    if let Some(ref newer) = original.superseded_by {
+        return Err(ApiError::bad_request(&format!(
+            "Entry #{} has already been amended by entry '{}'; amend the latest version", original.entry_number, newer
+        )));
+    }
+
+    let links = match body.links {
+        Some(ref links) => {
+            validate_links(&app_state.db_pool, links).await?;
+            links.clone()
+        }
+        None => load_links(&app_state.db_pool, &experiment_id)
+            .await?
+            .into_iter()
+            .filter(|l| l.entry_id == original.id)
+            .collect(),
+    };
+
+    let title = body.title.as_deref().or(original.title.as_deref());
+    let id = insert_entry(
+        &app_state.db_pool, &experiment_id, title, &body.content, &links, &user_id,
+        Some((&original.id, &body.reason)),
+    ).await?;
+
+    let entry = get_entry_or_404(&app_state.db_pool, &experiment_id, &id).await?;
+    info!("User {} amended notebook entry #{} of experiment {}", user_id, original.entry_number, experiment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(entry)))
+}
+
+/// Хронологический журнал эксперимента в Markdown, включая историю исправлений
+pub async fn export_notebook(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let experiment = get_experiment_or_404(&app_state.db_pool, &experiment_id).await?;
+    let entries = load_entries(&app_state.db_pool, &experiment_id, true).await?;
+
+    let markdown = render_notebook_markdown(&experiment, &entries);
+    let filename = format!("notebook_{}_{}.md", experiment_id, Utc::now().format("%Y%m%d"));
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/markdown; charset=utf-8"))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(markdown))
+}
+
+pub fn render_notebook_markdown(experiment: &Experiment, entries: &[NotebookEntryWithLinks]) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("# Lab notebook: {}\n\n", experiment.title));
+    out.push_str(&format!("- Experiment ID: `{}`\n", experiment.id));
+    out.push_str(&format!("- Status: {}\n", experiment.status));
+    out.push_str(&format!("- Start: {}\n", experiment.start_date.format("%Y-%m-%d %H:%M UTC")));
+    if let Some(end) = experiment.end_date {
+        out.push_str(&format!("- End: {}\n", end.format("%Y-%m-%d %H:%M UTC")));
+    }
+    out.push_str(&format!("- Exported: {}\n", Utc::now().format("%Y-%m-%d %H:%M UTC")));
+
+    for item in entries {
+        let e = &item.entry;
+        out.push_str("\n---\n\n");
+        out.push_str(&format!("## Entry #{}", e.entry_number));
+        if let Some(ref title) = e.title {
+            out.push_str(&format!(" — {}", title));
+        }
+        out.push('\n');
+        out.push_str(&format!(
+            "\n*{} by {}*\n",
+            e.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            e.author_username.as_deref().unwrap_or(&e.author_id)
+        ));
+        if let Some(ref amended) = e.amends_entry_id {
+            let number = entries.iter().find(|x| &x.entry.id == amended).map(|x| x.entry.entry_number);
+            out.push_str(&format!(
+                "\n> Amends entry #{}. Reason: {}\n",
+                number.map(|n| n.to_string()).unwrap_or_else(|| amended.clone()),
+                e.amendment_reason.as_deref().unwrap_or("")
+            ));
+        }
+        if e.superseded_by.is_some() {
+            out.push_str("\n> Superseded by a later amendment.\n");
+        }
+        out.push('\n');
+        out.push_str(e.content.trim_end());
+        out.push('\n');
+        if !item.links.is_empty() {
+            out.push_str("\nUsed:\n");
+            for link in &item.links {
+                out.push_str(&format!(
+                    "- {} {}\n",
+                    link.entity_type,
+                    link.entity_label.as_deref().unwrap_or(&link.entity_id)
+                ));
+            }
+        }
+    }
+
+    out
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NotebookSearchHit {
+    pub entry_id: String,
+    pub experiment_id: String,
+    pub experiment_title: String,
+    pub entry_number: i64,
+    pub title: Option<String>,
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+    pub rank: f64,
+}
+
+pub async fn search_notebook(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<NotebookSearchQuery>,
+) -> ApiResult<HttpResponse> {
+    let fts_query = FtsQueryBuilder::build_fts_query(&query.q);
+    if fts_query.is_empty() {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(Vec::<NotebookSearchHit>::new())));
+    }
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let sql = format!(r#"
+        SELECT e.id as entry_id, e.experiment_id, x.title as experiment_title, e.entry_number, e.title,
+               {snippet} as snippet,
+               e.created_at, bm25({fts}) as rank
+        FROM {fts}
+        JOIN experiment_notebook_entries e ON e.rowid = {fts}.rowid
+        JOIN experiments x ON e.experiment_id = x.id
+        WHERE {fts} MATCH ? AND (? IS NULL OR e.experiment_id = ?)
+        ORDER BY rank
+        LIMIT ?
+    "#, fts = NOTEBOOK_FTS_TABLE, snippet = snippet_expr(NOTEBOOK_FTS_TABLE, 1, 16));
+
+    let mut hits: Vec<NotebookSearchHit> = sqlx::query_as(&sql)
+        .bind(&fts_query)
+        .bind(&query.experiment_id)
+        .bind(&query.experiment_id)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    for hit in &mut hits {
+        hit.snippet = highlight_snippet(&hit.snippet);
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(hits)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, number: i64, content: &str, amends: Option<&str>, superseded_by: Option<&str>) -> NotebookEntryWithLinks {
+        NotebookEntryWithLinks {
+            entry: NotebookEntry {
+                id: id.to_string(),
+                experiment_id: "exp-1".to_string(),
+                entry_number: number,
+                title: None,
+                content: content.to_string(),
+                author_id: "user-1".to_string(),
+                author_username: Some("alice".to_string()),
+                amends_entry_id: amends.map(String::from),
+                amendment_reason: amends.map(|_| "typo in mass".to_string()),
+                superseded_by: superseded_by.map(String::from),
+                created_at: Utc::now(),
+            },
+            links: vec![],
+        }
+    }
+
+    #[test]
+    fn test_markdown_export_keeps_amendment_trail() {
+        let now = Utc::now();
+        let experiment = Experiment {
+            id: "exp-1".to_string(),
+            title: "Titration".to_string(),
+            description: None,
+            experiment_date: now,
+            experiment_type: None,
+            instructor: None,
+            student_group: None,
+            location: None,
+            room_id: None,
+            status: "in_progress".to_string(),
+            protocol: None,
+            start_date: now,
+            end_date: None,
+            results: None,
+            notes: None,
+            created_by: "user-1".to_string(),
+            updated_by: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let entries = vec![
+            entry("e1", 1, "Weighed 1.0 g", None, Some("e2")),
+            entry("e2", 2, "Weighed 1.5 g", Some("e1"), None),
+        ];
+
+        let md = render_notebook_markdown(&experiment, &entries);
+        assert!(md.starts_with("# Lab notebook: Titration"));
+        assert!(md.contains("Weighed 1.0 g"));
+        assert!(md.contains("Superseded by a later amendment."));
+        assert!(md.contains("Amends entry #1. Reason: typo in mass"));
+        assert!(md.find("## Entry #1").unwrap() < md.find("## Entry #2").unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_entry_numbers_and_amendment_conflict() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query(r#"
+            INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+            VALUES ('u1', 'chemist', 'chemist@lab.local', 'x', 'researcher', datetime('now'), datetime('now'))
+        "#).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO experiments (id, title, experiment_date, status, created_at, updated_at) VALUES ('e1', 'Titration', datetime('now'), 'planned', datetime('now'), datetime('now'))")
+            .execute(&pool).await.unwrap();
+
+        let first = insert_entry(&pool, "e1", None, "pH 7.1", &[], "u1", None).await.unwrap();
+        insert_entry(&pool, "e1", None, "pH 7.3", &[], "u1", None).await.unwrap();
+        insert_entry(&pool, "e1", None, "pH 7.0", &[], "u1", Some((&first, "typo"))).await.unwrap();
+
+        // Повторное исправление той же записи — именно конфликт исправления
+        let again = insert_entry(&pool, "e1", None, "pH 7.2", &[], "u1", Some((&first, "typo"))).await;
+        match again {
+            Err(ApiError::BadRequest(message)) => assert!(message.contains("already been amended")),
+            other => panic!("expected amendment conflict, got {:?}", other.map(|_| ())),
+        }
+
+        let numbers: Vec<i64> = sqlx::query_scalar("SELECT entry_number FROM experiment_notebook_entries ORDER BY entry_number")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+}
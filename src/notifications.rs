@@ -0,0 +1,138 @@
+// src/notifications.rs
+//! Системные уведомления.
+//!
+//! Уведомления создаются фоновыми задачами и видны всем пользователям;
+//! прочтение отмечается для каждого пользователя отдельно. `dedupe_key`
+//! делает генерацию идемпотентной: повторный запуск ничего не дублирует.
+//!
+//! Endpoints:
+//!   GET  /api/v1/notifications?unread_only=&category=&limit=
+//!   POST /api/v1/notifications/{id}/read
+//!   POST /api/v1/notifications/read-all
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{Notification, NotificationQuery};
+
+const DEFAULT_LIMIT: i64 = 50;
+
+pub struct NewNotification<'a> {
+    pub category: &'a str,
+    pub severity: &'a str,
+    pub title: String,
+    pub message: String,
+    pub entity_type: Option<&'a str>,
+    pub entity_id: Option<&'a str>,
+    pub dedupe_key: String,
+}
+
+/// Создать уведомление, если уведомления с таким `dedupe_key` ещё нет.
+/// Возвращает true, если уведомление создано.
+pub async fn notify(pool: &SqlitePool, notification: &NewNotification<'_>) -> ApiResult<bool> {
+    let result = sqlx::query(
+        r#"INSERT OR IGNORE INTO notifications
+           (id, category, severity, title, message, entity_type, entity_id, dedupe_key, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(Uuid::new_v4().to_string())
+        .bind(notification.category)
+        .bind(notification.severity)
+        .bind(&notification.title)
+        .bind(&notification.message)
+        .bind(notification.entity_type)
+        .bind(notification.entity_id)
+        .bind(&notification.dedupe_key)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_notifications(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<NotificationQuery>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, 500);
+    let unread_only = query.unread_only.unwrap_or(false);
+
+    let items: Vec<Notification> = sqlx::query_as(r#"
+        SELECT n.id, n.category, n.severity, n.title, n.message, n.entity_type, n.entity_id,
+               n.created_at, r.read_at
+        FROM notifications n
+        LEFT JOIN notification_reads r ON r.notification_id = n.id AND r.user_id = ?
+        WHERE (? = 0 OR r.read_at IS NULL)
+          AND (? IS NULL OR n.category = ?)
+        ORDER BY n.created_at DESC
+        LIMIT ?
+    "#)
+        .bind(&user_id)
+        .bind(unread_only)
+        .bind(&query.category)
+        .bind(&query.category)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    let unread_count: i64 = sqlx::query_scalar(r#"
+        SELECT COUNT(*) FROM notifications n
+        WHERE NOT EXISTS (
+            SELECT 1 FROM notification_reads r WHERE r.notification_id = n.id AND r.user_id = ?
+        )
+    "#)
+        .bind(&user_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "unread_count": unread_count,
+        "items": items,
+    }))))
+}
+
+pub async fn mark_notification_read(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let notification_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM notifications WHERE id = ?)")
+        .bind(&notification_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Notification"));
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO notification_reads (notification_id, user_id, read_at) VALUES (?, ?, ?)")
+        .bind(&notification_id)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message((), "Notification marked as read".to_string())))
+}
+
+pub async fn mark_all_notifications_read(
+    app_state: web::Data<Arc<AppState>>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let result = sqlx::query(r#"
+        INSERT OR IGNORE INTO notification_reads (notification_id, user_id, read_at)
+        SELECT id, ?, ? FROM notifications
+    "#)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({ "marked": result.rows_affected() }))))
+}
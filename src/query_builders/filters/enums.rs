@@ -95,6 +95,8 @@ define_status_enum! {
         Expired => "expired",
         Reserved => "reserved",
         Depleted => "depleted",
+        Quarantine => "quarantine",
+        Rejected => "rejected",
     }
 }
 
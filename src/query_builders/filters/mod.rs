@@ -84,6 +84,16 @@ impl FieldWhitelist {
             "reagent_name", "expiration_status",
         ])
     }
+
+    /// Колонки отчёта о стоимости владения оборудованием
+    pub fn for_equipment_costs() -> Self {
+        Self::new("equipment_costs", &[
+            "equipment_id", "equipment_name", "type_", "status", "location",
+            "manufacturer", "model", "serial_number", "purchase_date", "age_years",
+            "period", "maintenance_cost", "repair_cost", "maintenance_events",
+            "parts_used", "parts_cost", "downtime_days", "total_cost",
+        ])
+    }
 }
 
 // ==================== FILTER TYPES ====================
@@ -416,7 +426,34 @@ impl ReportConfig {
         ]
     }
 
+    pub fn default_equipment_cost_columns() -> Vec<ReportColumn> {
+        vec![
+            ReportColumn::new("equipment_name", "Equipment"),
+            ReportColumn::new("serial_number", "Serial #"),
+            ReportColumn::new("period", "Period"),
+            ReportColumn::new("purchase_date", "Purchase Date"),
+            ReportColumn::new("age_years", "Age (years)"),
+            ReportColumn::new("maintenance_cost", "Maintenance Cost"),
+            ReportColumn::new("repair_cost", "Repair Cost"),
+            ReportColumn::new("parts_used", "Parts Used"),
+            ReportColumn::new("parts_cost", "Parts Cost"),
+            ReportColumn::new("downtime_days", "Downtime (days)"),
+            ReportColumn::new("total_cost", "Total Cost"),
+        ]
+    }
+
     pub fn all_batches() -> Self { Self::new("all_batches") }
+
+    pub fn equipment_cost_of_ownership() -> Self {
+        Self {
+            preset: "equipment_cost_of_ownership".to_string(),
+            name: "Equipment Cost of Ownership".to_string(),
+            description: Some("Maintenance, repair and parts costs with downtime per instrument".to_string()),
+            columns: Self::default_equipment_cost_columns(),
+            sort_by: Some("total_cost".to_string()),
+            ..Default::default()
+        }
+    }
     
     pub fn low_stock(threshold: f64) -> Self {
         let mut config = Self::new("low_stock");
@@ -57,7 +57,7 @@ impl FtsConfig {
             fts_table: "equipment_parts_fts",
             main_table: "equipment_parts",
             id_field: "id",
-            search_fields: vec!["name", "part_number", "description", "notes"],
+            search_fields: vec!["name", "part_number", "manufacturer", "notes"],
         }
     }
 
@@ -4,7 +4,72 @@
 
 pub mod config;
 
-use sqlx::SqlitePool;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, Row, SqlitePool};
+
+use config::FtsConfig;
+
+/// Части SQL для ранжированного поиска с подсветкой
+#[derive(Debug, Clone)]
+pub struct RankedSearch {
+    /// JOIN с FTS-таблицей (пусто для LIKE fallback)
+    pub join: String,
+    /// Условие для WHERE (пусто, если поисковая строка пустая)
+    pub condition: String,
+    /// Выражение для ранга, меньше — релевантнее (колонка `fts_rank`)
+    pub rank_expr: String,
+    /// Выражение для фрагмента с подсветкой (колонка `fts_snippet`)
+    pub snippet_expr: String,
+    pub params: Vec<String>,
+}
+
+/// Границы совпадения в сыром фрагменте: управляющие символы вместо разметки,
+/// чтобы `<mark>` появлялся только после экранирования пользовательского текста
+const SNIPPET_MATCH_START: char = '\u{2}';
+const SNIPPET_MATCH_END: char = '\u{3}';
+
+/// SQL-выражение `snippet()` с границами совпадения для `highlight_snippet`
+pub fn snippet_expr(fts_table: &str, column: i32, tokens: i32) -> String {
+    format!("snippet({}, {}, char(2), char(3), '…', {})", fts_table, column, tokens)
+}
+
+/// Экранирует HTML во фрагменте и размечает совпадения тегами `<mark>`
+pub fn highlight_snippet(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len() + 16);
+    for c in raw.chars() {
+        match c {
+            SNIPPET_MATCH_START => out.push_str("<mark>"),
+            SNIPPET_MATCH_END => out.push_str("</mark>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Результат поиска: строка основной таблицы + ранг и фрагмент
+#[derive(Debug, Serialize)]
+pub struct FtsHit<T> {
+    #[serde(flatten)]
+    pub item: T,
+    pub rank: f64,
+    pub snippet: Option<String>,
+}
+
+impl<'r, T: FromRow<'r, SqliteRow>> FromRow<'r, SqliteRow> for FtsHit<T> {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            item: T::from_row(row)?,
+            rank: row.try_get("fts_rank")?,
+            snippet: row.try_get::<Option<String>, _>("fts_snippet")?.map(|s| highlight_snippet(&s)),
+        })
+    }
+}
 
 pub struct FtsQueryBuilder;
 
@@ -85,6 +150,79 @@ impl FtsQueryBuilder {
         }
     }
 
+    /// Ранжированный поиск: bm25 + snippet при наличии FTS, иначе LIKE без ранга.
+    /// Выражения ранга/фрагмента валидны только вместе с `join`.
+    pub fn build_ranked_search(
+        search: &str,
+        use_fts: bool,
+        config: &FtsConfig,
+        table_alias: &str,
+    ) -> RankedSearch {
+        if !use_fts {
+            let (condition, params) = Self::build_search_condition(
+                search, false, config.fts_table, &config.search_fields, table_alias,
+            );
+            return RankedSearch {
+                join: String::new(),
+                condition,
+                rank_expr: "0.0".to_string(),
+                snippet_expr: "NULL".to_string(),
+                params,
+            };
+        }
+
+        let fts_query = Self::build_fts_query(search.trim());
+        if fts_query.is_empty() {
+            return RankedSearch {
+                join: String::new(),
+                condition: String::new(),
+                rank_expr: "0.0".to_string(),
+                snippet_expr: "NULL".to_string(),
+                params: Vec::new(),
+            };
+        }
+
+        let fts = config.fts_table;
+        RankedSearch {
+            join: format!("JOIN {fts} ON {fts}.rowid = {alias}.rowid", fts = fts, alias = table_alias),
+            condition: format!("{fts} MATCH ?", fts = fts),
+            rank_expr: format!("bm25({})", fts),
+            snippet_expr: snippet_expr(fts, -1, 12),
+            params: vec![fts_query],
+        }
+    }
+
+    /// Выполнить ранжированный поиск по `config.main_table` (алиас `e`).
+    /// Без FTS-таблицы результаты сортируются по `fallback_order`.
+    pub async fn search_ranked<T>(
+        pool: &SqlitePool,
+        config: &FtsConfig,
+        search: &str,
+        limit: i64,
+        fallback_order: &str,
+    ) -> Result<Vec<FtsHit<T>>, sqlx::Error>
+    where
+        T: for<'r> FromRow<'r, SqliteRow> + Send + Unpin,
+    {
+        let use_fts = Self::check_fts_table_available(pool, config.fts_table).await;
+        let ranked = Self::build_ranked_search(search, use_fts, config, "e");
+        if ranked.condition.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let order = if use_fts { "fts_rank" } else { fallback_order };
+        let sql = format!(
+            "SELECT e.*, {} as fts_rank, {} as fts_snippet FROM {} e {} WHERE {} ORDER BY {} LIMIT ?",
+            ranked.rank_expr, ranked.snippet_expr, config.main_table, ranked.join, ranked.condition, order
+        );
+
+        let mut query = sqlx::query_as::<_, FtsHit<T>>(&sql);
+        for param in &ranked.params {
+            query = query.bind(param);
+        }
+        query.bind(limit).fetch_all(pool).await
+    }
+
     /// Специализированный построитель для реагентов (поиск по реагентам + партиям)
     pub fn build_reagent_search_condition(
         search: &str,
@@ -135,4 +273,46 @@ impl FtsQueryBuilder {
 /// Глобальная функция-хелпер, если она используется в других местах напрямую
 pub fn escape_fts_query(query: &str) -> String {
     FtsQueryBuilder::build_fts_query(query)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranked_search_uses_fts_join() {
+        let config = FtsConfig::for_equipment();
+        let ranked = FtsQueryBuilder::build_ranked_search("centrifuge", true, &config, "e");
+        assert_eq!(ranked.join, "JOIN equipment_fts ON equipment_fts.rowid = e.rowid");
+        assert_eq!(ranked.condition, "equipment_fts MATCH ?");
+        assert_eq!(ranked.rank_expr, "bm25(equipment_fts)");
+        assert_eq!(ranked.params.len(), 1);
+    }
+
+    #[test]
+    fn test_ranked_search_like_fallback() {
+        let config = FtsConfig::for_equipment_parts();
+        let ranked = FtsQueryBuilder::build_ranked_search("rotor", false, &config, "e");
+        assert!(ranked.join.is_empty());
+        assert_eq!(ranked.snippet_expr, "NULL");
+        assert_eq!(ranked.params.len(), config.search_fields.len());
+        assert!(ranked.condition.contains("e.part_number LIKE ?"));
+    }
+
+    #[actix_rt::test]
+    async fn test_snippet_escapes_indexed_text() {
+        let pool = crate::db::test_pool().await;
+        sqlx::query("CREATE VIRTUAL TABLE notes_fts USING fts5(body)").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO notes_fts (body) VALUES ('<img src=x onerror=alert(1)> buffer & \"salt\"')")
+            .execute(&pool).await.unwrap();
+
+        let raw: String = sqlx::query_scalar(&format!(
+            "SELECT {} FROM notes_fts WHERE notes_fts MATCH 'buffer'", snippet_expr("notes_fts", 0, 16)
+        ))
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(
+            highlight_snippet(&raw),
+            "&lt;img src=x onerror=alert(1)&gt; <mark>buffer</mark> &amp; &quot;salt&quot;"
+        );
+    }
+}
@@ -93,6 +93,8 @@ pub struct ReagentListItem {
     pub storage_conditions: Option<String>,
     pub appearance: Option<String>,
     pub hazard_pictograms: Option<String>,
+    #[sqlx(default)]
+    pub signal_word: Option<String>,
     pub status: String,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
@@ -118,6 +120,9 @@ pub struct ReagentDetailResponse {
     pub storage_conditions: Option<String>,
     pub appearance: Option<String>,
     pub hazard_pictograms: Option<String>,
+    pub signal_word: Option<String>,
+    pub shelf_life_after_opening_days: Option<i64>,
+    pub retest_interval_days: Option<i64>,
     pub status: String,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
@@ -179,7 +184,8 @@ pub async fn get_reagents(
     let mut builder = CtePaginationBuilder::new("reagents")
         .select("id, name, formula, cas_number, manufacturer, molecular_weight, \
                  physical_state, description, storage_conditions, appearance, \
-                 hazard_pictograms, status, created_by, updated_by, created_at, \
+                 hazard_pictograms, signal_word, shelf_life_after_opening_days, retest_interval_days, \
+                 status, created_by, updated_by, created_at, \
                  updated_at, total_quantity, batches_count, primary_unit")
         .sort(sort_by, sort_order)
         .limit(per_page);
@@ -330,7 +336,7 @@ pub async fn search_reagents(
         sqlx::query_as::<_, ReagentListItem>(
             r#"SELECT id, name, formula, cas_number, manufacturer, molecular_weight,
                       physical_state, description, storage_conditions, appearance,
-                      hazard_pictograms, status, created_by, updated_by, created_at,
+                      hazard_pictograms, signal_word, status, created_by, updated_by, created_at,
                       updated_at, total_quantity, batches_count, primary_unit
                FROM reagents
                WHERE rowid IN (SELECT rowid FROM reagents_fts WHERE reagents_fts MATCH ?)
@@ -347,7 +353,7 @@ pub async fn search_reagents(
         sqlx::query_as::<_, ReagentListItem>(
             r#"SELECT id, name, formula, cas_number, manufacturer, molecular_weight,
                       physical_state, description, storage_conditions, appearance,
-                      hazard_pictograms, status, created_by, updated_by, created_at,
+                      hazard_pictograms, signal_word, status, created_by, updated_by, created_at,
                       updated_at, total_quantity, batches_count, primary_unit
                FROM reagents
                WHERE (name LIKE ? OR cas_number LIKE ? OR formula LIKE ?)
@@ -419,6 +425,9 @@ pub async fn get_reagent_by_id(
         storage_conditions: reagent.storage_conditions,
         appearance: reagent.appearance,
         hazard_pictograms: reagent.hazard_pictograms,
+        signal_word: reagent.signal_word,
+        shelf_life_after_opening_days: reagent.shelf_life_after_opening_days,
+        retest_interval_days: reagent.retest_interval_days,
         status: reagent.status,
         created_by: reagent.created_by,
         updated_by: reagent.updated_by,
@@ -441,6 +450,16 @@ pub async fn get_reagent_by_id(
 
 // ==================== CREATE ====================
 
+/// Сигнальное слово GHS: "danger" → "Danger"; пустая строка — не задано
+fn normalize_signal_word(word: &str) -> ApiResult<Option<String>> {
+    match word.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        "danger" => Ok(Some("Danger".to_string())),
+        "warning" => Ok(Some("Warning".to_string())),
+        _ => Err(ApiError::bad_request("Signal word must be 'Danger' or 'Warning'")),
+    }
+}
+
 pub async fn create_reagent(
     app_state: web::Data<Arc<AppState>>,
     body: web::Json<CreateReagentRequest>,
@@ -454,6 +473,11 @@ pub async fn create_reagent(
         }
     }
 
+    let signal_word = match body.signal_word.as_deref() {
+        Some(word) => normalize_signal_word(word)?,
+        None => None,
+    };
+
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
 
@@ -478,9 +502,9 @@ pub async fn create_reagent(
         INSERT INTO reagents (
             id, name, formula, cas_number, manufacturer, molecular_weight,
             physical_state, description, storage_conditions, appearance,
-            hazard_pictograms, status, total_quantity, batches_count,
-            created_by, created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'active', 0, 0, ?, ?, ?)
+            hazard_pictograms, signal_word, shelf_life_after_opening_days, retest_interval_days,
+            status, total_quantity, batches_count, created_by, created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'active', 0, 0, ?, ?, ?)
     "#)
         .bind(&id)
         .bind(&body.name)
@@ -493,6 +517,9 @@ pub async fn create_reagent(
         .bind(&body.storage_conditions)
         .bind(&body.appearance)
         .bind(&body.hazard_pictograms)
+        .bind(&signal_word)
+        .bind(body.shelf_life_after_opening_days)
+        .bind(body.retest_interval_days)
         .bind(&user_id)
         .bind(&now)
         .bind(&now)
@@ -563,6 +590,21 @@ pub async fn update_reagent(
     upd!(hazard_pictograms, "hazard_pictograms");
     upd!(status, "status");
 
+    if let Some(ref word) = body.signal_word {
+        sets.push("signal_word = ?");
+        vals.push(normalize_signal_word(word)?);
+    }
+
+    if let Some(days) = body.shelf_life_after_opening_days {
+        sets.push("shelf_life_after_opening_days = ?");
+        vals.push((days > 0).then(|| days.to_string()));
+    }
+
+    if let Some(days) = body.retest_interval_days {
+        sets.push("retest_interval_days = ?");
+        vals.push((days > 0).then(|| days.to_string()));
+    }
+
     if let Some(mw) = body.molecular_weight {
         sets.push("molecular_weight = ?");
         vals.push(Some(mw.to_string()));
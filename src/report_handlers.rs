@@ -4,10 +4,12 @@
 use actix_web::{web, HttpResponse, HttpRequest};
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
 
 use crate::AppState;
-use crate::error::ApiResult;
+use crate::equipment_usage::period_bounds;
+use crate::error::{ApiError, ApiResult};
 use crate::handlers::ApiResponse;
 use crate::query_builders::{
     FieldWhitelist, ReportConfig, ReportFilter, ReportColumn,
@@ -571,6 +573,441 @@ pub async fn export_report(
         .body(csv_content))
 }
 
+// ==================== EQUIPMENT UTILIZATION ====================
+
+#[derive(Debug, Deserialize)]
+pub struct UtilizationQuery {
+    /// instrument | week | user
+    pub group_by: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub equipment_id: Option<String>,
+    pub user_id: Option<String>,
+    /// json (по умолчанию) | csv
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UtilizationRow {
+    /// id прибора, id пользователя или дата понедельника недели
+    pub key: String,
+    pub label: String,
+    pub sessions: i64,
+    pub runs: i64,
+    pub hours: f64,
+    /// Доля времени периода (для недели — времени недели), когда прибор был занят;
+    /// для группировки по пользователю не считается
+    pub utilization_percent: Option<f64>,
+    pub distinct_count: i64,
+}
+
+/// Сеансы, обрезанные по границам периода. Параметры: from, to (exclusive), from, to, equipment_id x2, user_id x2
+const UTILIZATION_SESSIONS: &str = r#"
+    WITH sessions AS (
+        SELECT s.equipment_id, e.name as equipment_name, s.user_id, COALESCE(u.username, s.user_id) as username,
+               s.run_count,
+               MAX(julianday(s.started_at), julianday(?)) as start_jd,
+               MIN(julianday(COALESCE(s.ended_at, 'now')), julianday(?)) as end_jd,
+               date(s.started_at, 'weekday 0', '-6 days') as week_start
+        FROM equipment_usage_log s
+        JOIN equipment e ON s.equipment_id = e.id
+        LEFT JOIN users u ON s.user_id = u.id
+        WHERE julianday(COALESCE(s.ended_at, 'now')) > julianday(?)
+          AND julianday(s.started_at) < julianday(?)
+          AND (? IS NULL OR s.equipment_id = ?)
+          AND (? IS NULL OR s.user_id = ?)
+    )
+"#;
+
+fn utilization_group_sql(group_by: &str) -> Option<&'static str> {
+    match group_by {
+        "instrument" => Some(r#"
+            SELECT equipment_id as key, equipment_name as label, COUNT(*) as sessions,
+                   COALESCE(SUM(run_count), 0) as runs,
+                   ROUND(SUM(MAX(end_jd - start_jd, 0)) * 24, 2) as hours,
+                   ROUND(SUM(MAX(end_jd - start_jd, 0)) * 100.0 / ?, 1) as utilization_percent,
+                   COUNT(DISTINCT user_id) as distinct_count
+            FROM sessions GROUP BY equipment_id, equipment_name ORDER BY hours DESC"#),
+        // Загрузка недели — среднее по приборам, работавшим в эту неделю
+        "week" => Some(r#"
+            SELECT week_start as key, 'Week of ' || week_start as label, COUNT(*) as sessions,
+                   COALESCE(SUM(run_count), 0) as runs,
+                   ROUND(SUM(MAX(end_jd - start_jd, 0)) * 24, 2) as hours,
+                   ROUND(SUM(MAX(end_jd - start_jd, 0)) * 100.0 / (7 * COUNT(DISTINCT equipment_id)), 1) as utilization_percent,
+                   COUNT(DISTINCT equipment_id) as distinct_count
+            FROM sessions GROUP BY week_start ORDER BY week_start"#),
+        "user" => Some(r#"
+            SELECT user_id as key, username as label, COUNT(*) as sessions,
+                   COALESCE(SUM(run_count), 0) as runs,
+                   ROUND(SUM(MAX(end_jd - start_jd, 0)) * 24, 2) as hours,
+                   NULL as utilization_percent,
+                   COUNT(DISTINCT equipment_id) as distinct_count
+            FROM sessions GROUP BY user_id, username ORDER BY hours DESC"#),
+        _ => None,
+    }
+}
+
+/// Отчёт о загрузке приборов по прибору, неделе или пользователю
+pub async fn get_equipment_utilization(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<UtilizationQuery>,
+) -> ApiResult<HttpResponse> {
+    let group_by = query.group_by.as_deref().unwrap_or("instrument");
+    let group_sql = utilization_group_sql(group_by)
+        .ok_or_else(|| ApiError::bad_request("group_by must be one of: instrument, week, user"))?;
+
+    let (from, to) = period_bounds(query.from.as_deref(), query.to.as_deref(), 30)?;
+    let from_str = from.to_string();
+    let to_exclusive = (to + chrono::Duration::days(1)).to_string();
+    let period_days = (to - from).num_days() + 1;
+
+    let sql = format!("{}{}", UTILIZATION_SESSIONS, group_sql);
+    let mut data_query = sqlx::query_as::<_, UtilizationRow>(&sql)
+        .bind(&from_str)
+        .bind(&to_exclusive)
+        .bind(&from_str)
+        .bind(&to_exclusive)
+        .bind(&query.equipment_id)
+        .bind(&query.equipment_id)
+        .bind(&query.user_id)
+        .bind(&query.user_id);
+    if group_by == "instrument" {
+        data_query = data_query.bind(period_days as f64);
+    }
+    let rows: Vec<UtilizationRow> = data_query.fetch_all(&app_state.db_pool).await?;
+
+    if query.format.as_deref() == Some("csv") {
+        let distinct_header = if group_by == "instrument" { "Users" } else { "Instruments" };
+        let mut csv_content = String::new();
+        csv_content.push('\u{FEFF}');
+        csv_content.push_str(&format!("Key,Name,Sessions,Runs,Hours,Utilization %,{}\n", distinct_header));
+        for row in &rows {
+            csv_content.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                escape_csv_field(&row.key),
+                escape_csv_field(&row.label),
+                row.sessions,
+                row.runs,
+                row.hours,
+                row.utilization_percent.map(|p| p.to_string()).unwrap_or_default(),
+                row.distinct_count,
+            ));
+        }
+        let filename = format!("equipment_utilization_{}_{}_{}.csv", group_by, from, to);
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+            .body(csv_content));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "group_by": group_by,
+        "from": from,
+        "to": to,
+        "period_days": period_days,
+        "total_sessions": rows.iter().map(|r| r.sessions).sum::<i64>(),
+        "total_runs": rows.iter().map(|r| r.runs).sum::<i64>(),
+        "total_hours": (rows.iter().map(|r| r.hours).sum::<f64>() * 100.0).round() / 100.0,
+        "rows": rows,
+    }))))
+}
+
+// ==================== EQUIPMENT COST OF OWNERSHIP ====================
+
+#[derive(Debug, Deserialize)]
+pub struct CostOfOwnershipRequest {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// none (весь период одной строкой) | month | quarter | year
+    pub period: Option<String>,
+    pub filters: Option<Vec<ReportFilterRequest>>,
+    pub sort_by: Option<String>,
+    pub sort_order: Option<String>,
+    /// json (по умолчанию) | csv | xlsx
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EquipmentCostRow {
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub type_: String,
+    pub status: String,
+    pub location: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial_number: Option<String>,
+    pub purchase_date: Option<String>,
+    /// Возраст на конец периода
+    pub age_years: Option<f64>,
+    /// Ключ периода: '2025-03', '2025-Q1', '2025' или 'from..to'
+    pub period: String,
+    /// Завершённое обслуживание, кроме ремонтов
+    pub maintenance_cost: f64,
+    pub repair_cost: f64,
+    pub maintenance_events: i64,
+    pub parts_used: i64,
+    /// Расход запчастей по их unit_cost
+    pub parts_cost: f64,
+    /// Время в статусах maintenance/damaged
+    pub downtime_days: f64,
+    pub total_cost: f64,
+}
+
+/// Параметры: period, to x2, from, to, from, to, to, from, to, from.
+/// Числовые колонки приведены через CAST, чтобы фильтры со строковыми
+/// параметрами сравнивались как числа.
+const COST_OF_OWNERSHIP_QUERY: &str = r#"
+    WITH status_spans AS (
+        SELECT equipment_id, to_status,
+               julianday(changed_at) as start_jd,
+               julianday(COALESCE(
+                   LEAD(changed_at) OVER (PARTITION BY equipment_id ORDER BY julianday(changed_at), rowid),
+                   'now'
+               )) as end_jd
+        FROM equipment_status_history
+    ),
+    cost_data AS (
+        SELECT e.id as equipment_id, e.name as equipment_name, e.type_, e.status, e.location,
+               e.manufacturer, e.model, e.serial_number, e.purchase_date,
+               ? as period,
+               CASE WHEN e.purchase_date IS NULL OR e.purchase_date > ? THEN NULL
+                    ELSE CAST(ROUND((julianday(?) - julianday(e.purchase_date)) / 365.25, 1) AS REAL)
+               END as age_years,
+               CAST(COALESCE(m.maintenance_cost, 0) AS REAL) as maintenance_cost,
+               CAST(COALESCE(m.repair_cost, 0) AS REAL) as repair_cost,
+               CAST(COALESCE(m.events, 0) AS INTEGER) as maintenance_events,
+               CAST(COALESCE(p.parts_used, 0) AS INTEGER) as parts_used,
+               CAST(COALESCE(p.parts_cost, 0) AS REAL) as parts_cost,
+               CAST(ROUND(COALESCE(d.downtime_days, 0), 2) AS REAL) as downtime_days,
+               CAST(ROUND(COALESCE(m.maintenance_cost, 0) + COALESCE(m.repair_cost, 0) + COALESCE(p.parts_cost, 0), 2) AS REAL) as total_cost
+        FROM equipment e
+        LEFT JOIN (
+            SELECT equipment_id,
+                   SUM(CASE WHEN maintenance_type != 'repair' THEN COALESCE(cost, 0) ELSE 0 END) as maintenance_cost,
+                   SUM(CASE WHEN maintenance_type = 'repair' THEN COALESCE(cost, 0) ELSE 0 END) as repair_cost,
+                   COUNT(*) as events
+            FROM equipment_maintenance
+            WHERE status = 'completed'
+              AND date(COALESCE(completed_date, scheduled_date)) >= ?
+              AND date(COALESCE(completed_date, scheduled_date)) <= ?
+            GROUP BY equipment_id
+        ) m ON m.equipment_id = e.id
+        LEFT JOIN (
+            SELECT u.equipment_id, SUM(u.quantity) as parts_used,
+                   SUM(u.quantity * COALESCE(pt.unit_cost, 0)) as parts_cost
+            FROM equipment_part_usage u
+            JOIN equipment_parts pt ON u.part_id = pt.id
+            WHERE date(u.used_date) >= ? AND date(u.used_date) <= ?
+            GROUP BY u.equipment_id
+        ) p ON p.equipment_id = e.id
+        LEFT JOIN (
+            SELECT equipment_id,
+                   SUM(MAX(MIN(end_jd, julianday(date(?, '+1 day'))) - MAX(start_jd, julianday(?)), 0)) as downtime_days
+            FROM status_spans
+            WHERE to_status IN ('maintenance', 'damaged')
+              AND start_jd < julianday(date(?, '+1 day')) AND end_jd > julianday(?)
+            GROUP BY equipment_id
+        ) d ON d.equipment_id = e.id
+    )
+    SELECT * FROM cost_data
+"#;
+
+/// Периоды отчёта: (ключ, первый день, последний день), обрезанные по [from, to]
+pub fn cost_periods(from: NaiveDate, to: NaiveDate, period: &str) -> Option<Vec<(String, NaiveDate, NaiveDate)>> {
+    let months_per_bucket = match period {
+        "none" => return Some(vec![(format!("{}..{}", from, to), from, to)]),
+        "month" => 1,
+        "quarter" => 3,
+        "year" => 12,
+        _ => return None,
+    };
+
+    let mut buckets = Vec::new();
+    let first_month = (from.month0() / months_per_bucket) * months_per_bucket;
+    let mut start = NaiveDate::from_ymd_opt(from.year(), first_month + 1, 1)?;
+    while start <= to {
+        let next = start.checked_add_months(Months::new(months_per_bucket))?;
+        let key = match period {
+            "month" => start.format("%Y-%m").to_string(),
+            "quarter" => format!("{}-Q{}", start.year(), start.month0() / 3 + 1),
+            _ => start.year().to_string(),
+        };
+        buckets.push((key, start.max(from), next.pred_opt()?.min(to)));
+        start = next;
+    }
+    Some(buckets)
+}
+
+fn cost_column_value(row: &EquipmentCostRow, field: &str) -> ReportCellValue {
+    use ReportCellValue::{Number, Text};
+    match field {
+        "equipment_id" => Text(row.equipment_id.clone()),
+        "equipment_name" => Text(row.equipment_name.clone()),
+        "type_" => Text(row.type_.clone()),
+        "status" => Text(row.status.clone()),
+        "location" => Text(row.location.clone().unwrap_or_default()),
+        "manufacturer" => Text(row.manufacturer.clone().unwrap_or_default()),
+        "model" => Text(row.model.clone().unwrap_or_default()),
+        "serial_number" => Text(row.serial_number.clone().unwrap_or_default()),
+        "purchase_date" => Text(row.purchase_date.clone().unwrap_or_default()),
+        "age_years" => row.age_years.map(Number).unwrap_or(Text(String::new())),
+        "period" => Text(row.period.clone()),
+        "maintenance_cost" => Number(row.maintenance_cost),
+        "repair_cost" => Number(row.repair_cost),
+        "maintenance_events" => Number(row.maintenance_events as f64),
+        "parts_used" => Number(row.parts_used as f64),
+        "parts_cost" => Number(row.parts_cost),
+        "downtime_days" => Number(row.downtime_days),
+        "total_cost" => Number(row.total_cost),
+        _ => Text(String::new()),
+    }
+}
+
+enum ReportCellValue {
+    Text(String),
+    Number(f64),
+}
+
+fn cost_report_csv(columns: &[ReportColumn], rows: &[EquipmentCostRow]) -> String {
+    let mut csv_content = String::new();
+    csv_content.push('\u{FEFF}');
+    let header: Vec<String> = columns.iter().map(|c| escape_csv_field(&c.label)).collect();
+    csv_content.push_str(&header.join(","));
+    csv_content.push('\n');
+    for row in rows {
+        let cells: Vec<String> = columns.iter()
+            .map(|c| match cost_column_value(row, &c.field) {
+                ReportCellValue::Text(text) => escape_csv_field(&text),
+                ReportCellValue::Number(n) => n.to_string(),
+            })
+            .collect();
+        csv_content.push_str(&cells.join(","));
+        csv_content.push('\n');
+    }
+    csv_content
+}
+
+fn cost_report_xlsx(columns: &[ReportColumn], rows: &[EquipmentCostRow]) -> ApiResult<Vec<u8>> {
+    let xlsx_error = |e: XlsxError| ApiError::InternalServerError(format!("Failed to build XLSX: {}", e));
+
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name("Cost of ownership").map_err(xlsx_error)?;
+    let header_format = Format::new().set_bold();
+
+    for (col, column) in columns.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, &column.label, &header_format).map_err(xlsx_error)?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, column) in columns.iter().enumerate() {
+            match cost_column_value(row, &column.field) {
+                ReportCellValue::Text(text) => worksheet.write_string(r, col as u16, &text),
+                ReportCellValue::Number(n) => worksheet.write_number(r, col as u16, n),
+            }.map_err(xlsx_error)?;
+        }
+    }
+    worksheet.autofit();
+
+    workbook.save_to_buffer().map_err(xlsx_error)
+}
+
+/// Стоимость владения оборудованием: обслуживание, ремонты, запчасти и простой
+/// по приборам и периодам. При разбивке по периодам строки без затрат и простоя опускаются.
+pub async fn get_equipment_cost_of_ownership(
+    app_state: web::Data<Arc<AppState>>,
+    request: web::Json<CostOfOwnershipRequest>,
+) -> ApiResult<HttpResponse> {
+    let period = request.period.as_deref().unwrap_or("none");
+    let (from, to) = period_bounds(request.from.as_deref(), request.to.as_deref(), 365)?;
+    let periods = cost_periods(from, to, period)
+        .ok_or_else(|| ApiError::bad_request("period must be one of: none, month, quarter, year"))?;
+
+    let mut config = ReportConfig::equipment_cost_of_ownership();
+    if let Some(ref filters) = request.filters {
+        config.filters.extend(filters.iter().filter_map(|f| f.to_report_filter()));
+    }
+    let whitelist = FieldWhitelist::for_equipment_costs();
+    let (where_clause, params) = build_filter_sql(&config, &whitelist);
+
+    let sort_field = request.sort_by.as_deref()
+        .filter(|f| whitelist.is_allowed(f))
+        .or(config.sort_by.as_deref())
+        .unwrap_or("total_cost");
+    let sort_order = match request.sort_order.as_deref() {
+        Some(order) if order.eq_ignore_ascii_case("asc") => "ASC",
+        _ => "DESC",
+    };
+    let activity_condition = if period == "none" { "" } else { " AND (total_cost > 0 OR downtime_days > 0)" };
+    let sql = format!(
+        "{} WHERE {}{} ORDER BY {} {}, equipment_name ASC",
+        COST_OF_OWNERSHIP_QUERY, where_clause, activity_condition, sort_field, sort_order
+    );
+
+    let mut rows: Vec<EquipmentCostRow> = Vec::new();
+    for (key, start, end) in &periods {
+        let start = start.to_string();
+        let end = end.to_string();
+        let mut data_query = sqlx::query_as::<_, EquipmentCostRow>(&sql)
+            .bind(key)
+            .bind(&end)
+            .bind(&end)
+            .bind(&start)
+            .bind(&end)
+            .bind(&start)
+            .bind(&end)
+            .bind(&end)
+            .bind(&start)
+            .bind(&end)
+            .bind(&start);
+        for p in &params {
+            data_query = data_query.bind(p);
+        }
+        rows.extend(data_query.fetch_all(&app_state.db_pool).await?);
+    }
+
+    let filename = format!("equipment_cost_of_ownership_{}_{}_{}", period, from, to);
+    match request.format.as_deref() {
+        Some("csv") => Ok(HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.csv\"", filename)))
+            .body(cost_report_csv(&config.columns, &rows))),
+        Some("xlsx") => {
+            let bytes = cost_report_xlsx(&config.columns, &rows)?;
+            Ok(HttpResponse::Ok()
+                .insert_header(("Content-Type", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"))
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.xlsx\"", filename)))
+                .body(bytes))
+        }
+        None | Some("json") => {
+            let round = |v: f64| (v * 100.0).round() / 100.0;
+            Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+                "metadata": ReportMetadata {
+                    name: config.name.clone(),
+                    description: config.description.clone(),
+                    preset: config.preset.clone(),
+                    total_items: rows.len() as i64,
+                    generated_at: Utc::now(),
+                    columns: config.columns.clone(),
+                },
+                "from": from,
+                "to": to,
+                "period": period,
+                "totals": {
+                    "maintenance_cost": round(rows.iter().map(|r| r.maintenance_cost).sum()),
+                    "repair_cost": round(rows.iter().map(|r| r.repair_cost).sum()),
+                    "parts_cost": round(rows.iter().map(|r| r.parts_cost).sum()),
+                    "downtime_days": round(rows.iter().map(|r| r.downtime_days).sum()),
+                    "total_cost": round(rows.iter().map(|r| r.total_cost).sum()),
+                },
+                "data": rows,
+            }))))
+        }
+        Some(_) => Err(ApiError::bad_request("format must be one of: json, csv, xlsx")),
+    }
+}
+
 // ==================== TESTS ====================
 
 #[cfg(test)]
@@ -625,6 +1062,31 @@ mod tests {
         assert_eq!(f.operator, ComparisonOperator::Gt);
     }
 
+    #[test]
+    fn test_cost_periods_are_clipped_to_range() {
+        let from = NaiveDate::from_ymd_opt(2025, 2, 15).unwrap();
+        let to = NaiveDate::from_ymd_opt(2025, 7, 10).unwrap();
+
+        let quarters = cost_periods(from, to, "quarter").unwrap();
+        let keys: Vec<&str> = quarters.iter().map(|(k, _, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["2025-Q1", "2025-Q2", "2025-Q3"]);
+        assert_eq!(quarters[0].1, from);
+        assert_eq!(quarters[0].2, NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+        assert_eq!(quarters[2].2, to);
+
+        assert_eq!(cost_periods(from, to, "month").unwrap().len(), 6);
+        assert_eq!(cost_periods(from, to, "none").unwrap()[0].0, "2025-02-15..2025-07-10");
+        assert!(cost_periods(from, to, "week; DROP TABLE equipment").is_none());
+    }
+
+    #[test]
+    fn test_utilization_group_by_whitelist() {
+        assert!(utilization_group_sql("instrument").is_some());
+        assert!(utilization_group_sql("week").is_some());
+        assert!(utilization_group_sql("user").is_some());
+        assert!(utilization_group_sql("equipment_id; DROP TABLE users").is_none());
+    }
+
     #[test]
     fn test_invalid_operator_returns_none() {
         let req = ReportFilterRequest {
@@ -1,7 +1,7 @@
 // src/routes/batches.rs
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::{AppState, auth, auth_handlers, audit, batch_handlers, import_export, filter_handlers, container_handlers, placement_handlers};
+use crate::{AppState, auth, auth_handlers, audit, batch_handlers, import_export, filter_handlers, container_handlers, placement_handlers, scan_handlers, label_handlers};
 use crate::audit::ChangeSet;
 use crate::error::ApiResult;
 
@@ -155,5 +155,7 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{batch_id}/containers", web::post().to(create_container_protected))
             .route("/{batch_id}/containers/split", web::post().to(split_batch_protected))
             .route("/{batch_id}/placements", web::get().to(placement_handlers::get_batch_placements))
+            .route("/{batch_id}/barcode", web::get().to(scan_handlers::get_batch_barcode))
+            .route("/{batch_id}/labels", web::get().to(label_handlers::get_batch_labels))
     );
 }
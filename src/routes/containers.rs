@@ -1,7 +1,7 @@
 // src/routes/containers.rs
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::{AppState, auth_handlers, container_handlers};
+use crate::{AppState, auth_handlers, container_handlers, scan_handlers, label_handlers, waste};
 use crate::error::ApiResult;
 
 // ==================== PROTECTED WRAPPERS ====================
@@ -72,6 +72,26 @@ async fn move_containers_bulk_protected(
     container_handlers::move_containers_bulk(app_state, request, http_request).await
 }
 
+async fn retest_container_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<crate::models::batch_container::RetestContainerRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    container_handlers::retest_container(app_state, path, request, http_request).await
+}
+
+async fn dispose_to_waste_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<waste::DisposeToWasteRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    waste::dispose_to_waste(app_state, path, request, http_request).await
+}
+
 // ==================== ROUTES ====================
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -80,11 +100,17 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             // Bulk operations (static routes FIRST)
             .route("/place-bulk", web::post().to(place_containers_bulk_protected))
             .route("/move-bulk", web::post().to(move_containers_bulk_protected))
+            .route("/expiring", web::get().to(container_handlers::get_expiring_containers))
             // Per-container operations (dynamic routes after)
             .route("/{container_id}/place", web::post().to(place_container_protected))
             .route("/{container_id}/move", web::put().to(move_container_protected))
             .route("/{container_id}/unplace", web::delete().to(unplace_container_protected))
             .route("/{container_id}/use", web::post().to(use_from_container_protected))
+            .route("/{container_id}/retest", web::post().to(retest_container_protected))
+            .route("/{container_id}/waste", web::post().to(dispose_to_waste_protected))
+            .route("/{container_id}/movements", web::get().to(container_handlers::get_container_movements))
+            .route("/{container_id}/barcode", web::get().to(scan_handlers::get_container_barcode))
+            .route("/{container_id}/label", web::get().to(label_handlers::get_container_label))
             .route("/{container_id}", web::delete().to(dispose_container_protected))
     );
 }
@@ -2,8 +2,8 @@
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_multipart::Multipart;
 use std::sync::Arc;
-use crate::{AppState, auth, auth_handlers, audit, equipment_handlers, import_export};
-use crate::models::{CreateEquipmentRequest, UpdateEquipmentRequest, CreateEquipmentPartRequest, UpdateEquipmentPartRequest, CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest};
+use crate::{AppState, auth, auth_handlers, audit, calibration_handlers, equipment_handlers, equipment_hierarchy, equipment_usage, file_links, import_export, maintenance_handlers, service_contracts, spare_parts_handlers};
+use crate::models::{CreateEquipmentRequest, UpdateEquipmentRequest, CreateEquipmentPartRequest, UpdateEquipmentPartRequest, CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest, CreateMaintenancePlanRequest, UpdateMaintenancePlanRequest, CreateCalibrationStandardRequest, UpdateCalibrationStandardRequest, RecordCalibrationRequest, ResolveCalibrationFlagRequest, AttachModuleRequest, DetachModuleRequest, CheckOutEquipmentRequest, CheckInEquipmentRequest, CreateServiceContractRequest, UpdateServiceContractRequest};
 use crate::audit::ChangeSet;
 use crate::error::ApiResult;
 
@@ -48,9 +48,9 @@ async fn update_equipment_protected(
 
     if let Ok(old) = sqlx::query_as::<_, (
         String, i64, String, Option<String>, Option<String>,
-        Option<String>, Option<String>, Option<String>
+        Option<String>, Option<String>, Option<String>, Option<String>
     )>(
-        "SELECT name, quantity, status, location, serial_number, manufacturer, model, description FROM equipment WHERE id = ?"
+        "SELECT name, quantity, status, location, serial_number, manufacturer, model, description, warranty_until FROM equipment WHERE id = ?"
     ).bind(&equipment_id).fetch_one(&app_state.db_pool).await {
         equip_name = old.0.clone();
         if let Some(ref new_val) = update_data.name { cs.add("name", &old.0, new_val); }
@@ -61,6 +61,7 @@ async fn update_equipment_protected(
         if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.5, &Some(new_val.clone())); }
         if let Some(ref new_val) = update_data.model { cs.add_opt("model", &old.6, &Some(new_val.clone())); }
         if let Some(ref new_val) = update_data.description { cs.add_opt("description", &old.7, &Some(new_val.clone())); }
+        if let Some(new_val) = update_data.warranty_until { cs.add_opt("warranty_until", &old.8, &Some(new_val.to_string())); }
     }
 
     let desc = if cs.has_changes() {
@@ -134,12 +135,163 @@ async fn delete_maintenance_protected(app_state: web::Data<Arc<AppState>>, path:
     equipment_handlers::delete_maintenance(app_state, path).await
 }
 
+// Maintenance plans
+async fn create_maintenance_plan_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<CreateMaintenancePlanRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let equipment_id = path.into_inner();
+    let desc = format!("Created maintenance plan '{}' ({} every {} days)", body.name, body.maintenance_type, body.interval_days);
+    let response = maintenance_handlers::create_maintenance_plan(app_state.clone(), web::Path::from(equipment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "maintenance_plan", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn update_maintenance_plan_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, body: web::Json<UpdateMaintenancePlanRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let (equipment_id, plan_id) = path.into_inner();
+    let response = maintenance_handlers::update_maintenance_plan(app_state.clone(), web::Path::from((equipment_id.clone(), plan_id.clone())), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "maintenance_plan", &plan_id, &format!("Updated maintenance plan of equipment {}", equipment_id), &http_request).await;
+    Ok(response)
+}
+async fn delete_maintenance_plan_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
+    let (equipment_id, plan_id) = path.into_inner();
+    let response = maintenance_handlers::delete_maintenance_plan(app_state.clone(), web::Path::from((equipment_id.clone(), plan_id.clone()))).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "delete", "maintenance_plan", &plan_id, &format!("Deleted maintenance plan of equipment {}", equipment_id), &http_request).await;
+    Ok(response)
+}
+async fn apply_due_statuses_protected(app_state: web::Data<Arc<AppState>>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    maintenance_handlers::apply_due_statuses(app_state).await
+}
+
+// Calibration
+async fn create_calibration_standard_protected(app_state: web::Data<Arc<AppState>>, body: web::Json<CreateCalibrationStandardRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let desc = format!("Created calibration standard '{}' (certificate {})", body.name, body.certificate_number);
+    let response = calibration_handlers::create_calibration_standard(app_state.clone(), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "calibration_standard", "", &desc, &http_request).await;
+    Ok(response)
+}
+async fn update_calibration_standard_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<UpdateCalibrationStandardRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let standard_id = path.into_inner();
+    let response = calibration_handlers::update_calibration_standard(app_state.clone(), web::Path::from(standard_id.clone()), body).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "calibration_standard", &standard_id, "Updated calibration standard", &http_request).await;
+    Ok(response)
+}
+async fn record_calibration_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, body: web::Json<RecordCalibrationRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let (equipment_id, maintenance_id) = path.into_inner();
+    let desc = format!("Recorded calibration result ({} points) for maintenance {}", body.points.len(), maintenance_id);
+    let response = calibration_handlers::record_calibration(app_state.clone(), web::Path::from((equipment_id.clone(), maintenance_id)), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "calibration_result", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn resolve_calibration_flag_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<ResolveCalibrationFlagRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let flag_id = path.into_inner();
+    let response = calibration_handlers::resolve_calibration_flag(app_state.clone(), web::Path::from(flag_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "calibration_flag", &flag_id, "Resolved calibration flag", &http_request).await;
+    Ok(response)
+}
+
+// Hierarchy (modules)
+async fn attach_module_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<AttachModuleRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let equipment_id = path.into_inner();
+    let desc = format!("Installed module {} into equipment {}{}", body.child_id, equipment_id, body.slot.as_ref().map(|s| format!(" (slot '{}')", s)).unwrap_or_default());
+    let response = equipment_hierarchy::attach_module(app_state.clone(), web::Path::from(equipment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "equipment_link", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn detach_module_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, body: web::Json<DetachModuleRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let (equipment_id, child_id) = path.into_inner();
+    let desc = format!("Removed module {} from equipment {}", child_id, equipment_id);
+    let response = equipment_hierarchy::detach_module(app_state.clone(), web::Path::from((equipment_id.clone(), child_id)), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "delete", "equipment_link", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+// Usage log
+async fn check_out_equipment_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<CheckOutEquipmentRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::View, &app_state.db_pool).await?;
+    let equipment_id = path.into_inner();
+    let desc = match body.experiment_id {
+        Some(ref experiment_id) => format!("Checked out equipment {} for experiment {}", equipment_id, experiment_id),
+        None => format!("Checked out equipment {}", equipment_id),
+    };
+    let response = equipment_usage::check_out_equipment(app_state.clone(), web::Path::from(equipment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "equipment_usage", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn check_in_equipment_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<CheckInEquipmentRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::View, &app_state.db_pool).await?;
+    let can_close_others = auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await.is_ok();
+    let equipment_id = path.into_inner();
+    let desc = format!("Checked in equipment {} ({} runs)", equipment_id, body.run_count);
+    let response = equipment_usage::check_in_equipment(app_state.clone(), web::Path::from(equipment_id.clone()), body, claims.sub.clone(), can_close_others).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "equipment_usage", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+// Service contracts
+async fn create_service_contract_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, body: web::Json<CreateServiceContractRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let equipment_id = path.into_inner();
+    let desc = format!("Added service contract {} ({}) to equipment {}, valid until {}", body.contract_number, body.vendor, equipment_id, body.end_date);
+    let response = service_contracts::create_service_contract(app_state.clone(), web::Path::from(equipment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "service_contract", &equipment_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn update_service_contract_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, body: web::Json<UpdateServiceContractRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
+    let (equipment_id, contract_id) = path.into_inner();
+    let desc = format!("Updated service contract {} of equipment {}", contract_id, equipment_id);
+    let response = service_contracts::update_service_contract(app_state.clone(), web::Path::from((equipment_id, contract_id.clone())), body).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "service_contract", &contract_id, &desc, &http_request).await;
+    Ok(response)
+}
+async fn delete_service_contract_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
+    let (equipment_id, contract_id) = path.into_inner();
+    let desc = format!("Deleted service contract {} of equipment {}", contract_id, equipment_id);
+    let response = service_contracts::delete_service_contract(app_state.clone(), web::Path::from((equipment_id, contract_id.clone()))).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "delete", "service_contract", &contract_id, &desc, &http_request).await;
+    Ok(response)
+}
+
 // Files
 async fn upload_equipment_file_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, payload: Multipart, http_request: HttpRequest) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
     auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     equipment_handlers::upload_equipment_file(app_state, path, payload, claims.sub).await
 }
+async fn create_file_link_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, body: web::Json<file_links::CreateFileLinkRequest>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::View, &app_state.db_pool).await?;
+    let (equipment_id, file_id) = path.into_inner();
+    let desc = format!(
+        "Issued {}download link for file {} of equipment {}",
+        if body.single_use { "single-use " } else { "" }, file_id, equipment_id
+    );
+    let response = file_links::create_file_link(app_state.clone(), web::Path::from((equipment_id, file_id.clone())), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "file_link", &file_id, &desc, &http_request).await;
+    Ok(response)
+}
 async fn delete_equipment_file_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<(String, String)>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
     auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
     equipment_handlers::delete_equipment_file(app_state, path).await
@@ -153,6 +305,19 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("", web::post().to(create_equipment_protected))
             .route("", web::get().to(equipment_handlers::get_equipment))
             .route("/search", web::get().to(equipment_handlers::search_equipment))
+            .route("/parts/search", web::get().to(equipment_handlers::search_equipment_parts))
+            .route("/parts/low-stock", web::get().to(spare_parts_handlers::get_low_stock_parts))
+            .route("/parts/replacement-due", web::get().to(spare_parts_handlers::get_parts_replacement_due))
+            .route("/maintenance/upcoming", web::get().to(maintenance_handlers::get_upcoming_maintenance))
+            .route("/maintenance/apply-due", web::post().to(apply_due_statuses_protected))
+            .route("/maintenance/run-based", web::get().to(equipment_usage::get_run_based_maintenance))
+            .route("/usage/active", web::get().to(equipment_usage::get_active_sessions))
+            .route("/coverage/expiring", web::get().to(service_contracts::get_expiring_coverage))
+            .route("/calibration-standards", web::get().to(calibration_handlers::get_calibration_standards))
+            .route("/calibration-standards", web::post().to(create_calibration_standard_protected))
+            .route("/calibration-standards/{id}", web::put().to(update_calibration_standard_protected))
+            .route("/calibration-flags", web::get().to(calibration_handlers::get_calibration_flags))
+            .route("/calibration-flags/{flag_id}/resolve", web::post().to(resolve_calibration_flag_protected))
             .route("/export", web::get().to(import_export::export_equipment))
             .route("/import", web::post().to(import_export::import_equipment))
             .route("/import/json", web::post().to(import_export::import_equipment_json))
@@ -162,6 +327,7 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{id}", web::delete().to(delete_equipment_protected))
             .route("/{id}/parts", web::get().to(equipment_handlers::get_equipment_parts))
             .route("/{id}/parts", web::post().to(add_equipment_part_protected))
+            .route("/{id}/parts/usage", web::get().to(spare_parts_handlers::get_part_usage_history))
             .route("/{id}/parts/{part_id}", web::put().to(update_equipment_part_protected))
             .route("/{id}/parts/{part_id}", web::delete().to(delete_equipment_part_protected))
             .route("/{id}/parts/{part_id}/files", web::get().to(equipment_handlers::get_part_files))
@@ -169,10 +335,29 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{id}/maintenance", web::post().to(create_maintenance_protected))
             .route("/{id}/maintenance/{maintenance_id}", web::put().to(update_maintenance_protected))
             .route("/{id}/maintenance/{maintenance_id}/complete", web::post().to(complete_maintenance_protected))
+            .route("/{id}/maintenance/{maintenance_id}/calibration", web::post().to(record_calibration_protected))
+            .route("/{id}/calibrations", web::get().to(calibration_handlers::get_equipment_calibrations))
             .route("/{id}/maintenance/{maintenance_id}", web::delete().to(delete_maintenance_protected))
+            .route("/{id}/maintenance-plans", web::get().to(maintenance_handlers::get_maintenance_plans))
+            .route("/{id}/maintenance-plans", web::post().to(create_maintenance_plan_protected))
+            .route("/{id}/maintenance-plans/{plan_id}", web::put().to(update_maintenance_plan_protected))
+            .route("/{id}/maintenance-plans/{plan_id}", web::delete().to(delete_maintenance_plan_protected))
+            .route("/{id}/usage", web::get().to(equipment_usage::get_equipment_usage))
+            .route("/{id}/checkout", web::post().to(check_out_equipment_protected))
+            .route("/{id}/checkin", web::post().to(check_in_equipment_protected))
+            .route("/{id}/hierarchy", web::get().to(equipment_hierarchy::get_equipment_hierarchy))
+            .route("/{id}/modules", web::post().to(attach_module_protected))
+            .route("/{id}/modules/history", web::get().to(equipment_hierarchy::get_module_history))
+            .route("/{id}/modules/{child_id}/detach", web::post().to(detach_module_protected))
+            .route("/{id}/contracts", web::get().to(service_contracts::get_service_contracts))
+            .route("/{id}/contracts", web::post().to(create_service_contract_protected))
+            .route("/{id}/contracts/{contract_id}", web::put().to(update_service_contract_protected))
+            .route("/{id}/contracts/{contract_id}", web::delete().to(delete_service_contract_protected))
+            .route("/{id}/contracts/{contract_id}/files", web::get().to(service_contracts::get_contract_files))
             .route("/{id}/files", web::get().to(equipment_handlers::get_equipment_files))
             .route("/{id}/files", web::post().to(upload_equipment_file_protected))
             .route("/{id}/files/{file_id}", web::get().to(equipment_handlers::download_equipment_file))
+            .route("/{id}/files/{file_id}/link", web::post().to(create_file_link_protected))
             .route("/{id}/files/{file_id}", web::delete().to(delete_equipment_file_protected))
     );
 }
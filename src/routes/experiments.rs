@@ -1,7 +1,8 @@
 // src/routes/experiments.rs
+use actix_multipart::Multipart;
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::{AppState, auth, auth_handlers, audit, experiment_handlers, filter_handlers};
+use crate::{AppState, auth, auth_handlers, audit, calibration_handlers, experiment_handlers, experiment_workflow, filter_handlers, notebook_handlers};
 use crate::audit::ChangeSet;
 use crate::error::ApiResult;
 
@@ -102,6 +103,34 @@ async fn remove_experiment_reagent_protected(
     experiment_handlers::remove_reagent_from_experiment(app_state, path, claims.sub).await
 }
 
+async fn add_experiment_equipment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<experiment_handlers::AddEquipmentToExperimentRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let desc = format!("Linked equipment {} to experiment", body.equipment_id);
+    let response = experiment_handlers::add_equipment_to_experiment(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "experiment", &experiment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn remove_experiment_equipment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let (experiment_id, equipment_id) = path.into_inner();
+    let response = experiment_handlers::remove_equipment_from_experiment(app_state.clone(), web::Path::from((experiment_id.clone(), equipment_id.clone()))).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "experiment", &experiment_id, &format!("Unlinked equipment {} from experiment", equipment_id), &http_request).await;
+    Ok(response)
+}
+
 async fn start_experiment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
@@ -115,11 +144,12 @@ async fn start_experiment_protected(
 async fn complete_experiment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    body: web::Bytes,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
-    experiment_handlers::complete_experiment(app_state, path, claims.sub).await
+    experiment_handlers::complete_experiment(app_state, path, body, claims.sub).await
 }
 
 async fn cancel_experiment_protected(
@@ -142,6 +172,121 @@ async fn consume_experiment_reagent_protected(
     experiment_handlers::consume_experiment_reagent(app_state, path, claims.sub).await
 }
 
+async fn transition_experiment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<experiment_workflow::TransitionRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let desc = format!("Experiment {} status transition to '{}'", experiment_id, body.status);
+
+    let response = experiment_handlers::update_experiment_status(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "status_change", "experiment", &experiment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn upsert_transition_rule_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<experiment_workflow::UpsertTransitionRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    if claims.role != crate::auth::UserRole::Admin {
+        return Err(crate::error::ApiError::Forbidden("Admin access required".to_string()));
+    }
+    let desc = format!("Configured experiment transition {} -> {}", body.from_status, body.to_status);
+
+    let response = experiment_workflow::upsert_transition_rule(app_state.clone(), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "edit", "experiment_workflow", "", &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn sign_transition_protected(
+    app_state: web::Data<Arc<AppState>>,
+    auth_service: web::Data<Arc<crate::auth::AuthService>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<experiment_workflow::SignoffRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    experiment_workflow::sign_transition(app_state, auth_service, path, body, http_request).await
+}
+
+async fn withdraw_signoff_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    experiment_workflow::withdraw_signoff(app_state, path, http_request).await
+}
+
+async fn create_notebook_entry_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<notebook_handlers::CreateNotebookEntryRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let desc = format!("Added notebook entry to experiment {}", experiment_id);
+
+    let response = notebook_handlers::create_notebook_entry(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "create", "notebook_entry", &experiment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn amend_notebook_entry_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<notebook_handlers::AmendNotebookEntryRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let (experiment_id, entry_id) = path.into_inner();
+    let desc = format!("Amended notebook entry {} of experiment {}: {}", entry_id, experiment_id, body.reason);
+
+    let response = notebook_handlers::amend_notebook_entry(app_state.clone(), web::Path::from((experiment_id, entry_id.clone())), body, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "amend", "notebook_entry", &entry_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn upload_experiment_document_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    payload: Multipart,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let desc = format!("Uploaded document to experiment {}", experiment_id);
+
+    let response = experiment_handlers::upload_experiment_document(app_state.clone(), web::Path::from(experiment_id.clone()), payload, claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "upload", "experiment_document", &experiment_id, &desc, &http_request).await;
+    Ok(response)
+}
+
+async fn delete_experiment_document_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
+    let claims = auth::get_current_user(&http_request)?;
+    let (experiment_id, doc_id) = path.into_inner();
+    let desc = format!("Deleted document {} from experiment {}", doc_id, experiment_id);
+
+    let response = experiment_handlers::delete_experiment_document(app_state.clone(), web::Path::from((experiment_id, doc_id.clone())), claims.sub.clone()).await?;
+    audit::audit(&app_state.db_pool, &claims.sub, "delete", "experiment_document", &doc_id, &desc, &http_request).await;
+    Ok(response)
+}
+
 // ==================== ROUTES ====================
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -150,18 +295,39 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("", web::post().to(create_experiment_protected))
             .route("", web::get().to(experiment_handlers::get_all_experiments))
             .route("/stats", web::get().to(experiment_handlers::get_experiment_stats))
+            .route("/search", web::get().to(experiment_handlers::search_experiments))
             .route("/filter", web::post().to(filter_handlers::get_experiments_filtered))
             .route("/auto-update-statuses", web::post().to(experiment_handlers::auto_update_experiment_statuses))
             .route("/diagnose-dates", web::get().to(experiment_handlers::diagnose_experiment_dates))
+            .route("/workflow/transitions", web::get().to(experiment_workflow::get_transition_rules))
+            .route("/workflow/transitions", web::put().to(upsert_transition_rule_protected))
+            .route("/notebook/search", web::get().to(notebook_handlers::search_notebook))
             .route("/{id}", web::get().to(experiment_handlers::get_experiment))
             .route("/{id}", web::put().to(update_experiment_protected))
             .route("/{id}", web::delete().to(delete_experiment_protected))
             .route("/{id}/start", web::post().to(start_experiment_protected))
             .route("/{id}/complete", web::post().to(complete_experiment_protected))
             .route("/{id}/cancel", web::post().to(cancel_experiment_protected))
+            .route("/{id}/status", web::post().to(transition_experiment_protected))
+            .route("/{id}/status-history", web::get().to(experiment_workflow::get_status_history))
+            .route("/{id}/signoffs", web::get().to(experiment_workflow::get_signoffs))
+            .route("/{id}/signoffs/{signoff_id}/sign", web::post().to(sign_transition_protected))
+            .route("/{id}/signoffs/{signoff_id}/withdraw", web::post().to(withdraw_signoff_protected))
+            .route("/{id}/notebook", web::get().to(notebook_handlers::get_notebook))
+            .route("/{id}/notebook", web::post().to(create_notebook_entry_protected))
+            .route("/{id}/notebook/export", web::get().to(notebook_handlers::export_notebook))
+            .route("/{id}/notebook/{entry_id}/amend", web::post().to(amend_notebook_entry_protected))
             .route("/{id}/reagents", web::get().to(experiment_handlers::get_experiment_reagents))
             .route("/{id}/reagents", web::post().to(add_experiment_reagent_protected))
             .route("/{id}/reagents/{reagent_id}", web::delete().to(remove_experiment_reagent_protected))
             .route("/{id}/reagents/{reagent_id}/consume", web::post().to(consume_experiment_reagent_protected))
+            .route("/{id}/equipment", web::get().to(experiment_handlers::get_experiment_equipment))
+            .route("/{id}/equipment", web::post().to(add_experiment_equipment_protected))
+            .route("/{id}/equipment/{equipment_id}", web::delete().to(remove_experiment_equipment_protected))
+            .route("/{id}/calibration-flags", web::get().to(calibration_handlers::get_experiment_calibration_flags))
+            .route("/{id}/documents", web::get().to(experiment_handlers::get_experiment_documents))
+            .route("/{id}/documents", web::post().to(upload_experiment_document_protected))
+            .route("/{id}/documents/{doc_id}", web::get().to(experiment_handlers::download_experiment_document))
+            .route("/{id}/documents/{doc_id}", web::delete().to(delete_experiment_document_protected))
     );
 }
@@ -0,0 +1,83 @@
+// src/routes/goods_in.rs
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use crate::{AppState, auth_handlers, goods_in};
+use crate::error::ApiResult;
+
+// ==================== PROTECTED WRAPPERS ====================
+// Заказы и приёмку ведут те, кто создаёт партии; решение QA — только право release_batch
+
+async fn create_purchase_order_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<goods_in::CreatePurchaseOrderRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Create, &app_state.db_pool).await?;
+    goods_in::create_purchase_order(app_state, body, http_request).await
+}
+
+async fn cancel_purchase_order_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    goods_in::cancel_purchase_order(app_state, path, http_request).await
+}
+
+async fn receive_goods_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<goods_in::ReceiveGoodsRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Create, &app_state.db_pool).await?;
+    goods_in::receive_goods(app_state, body, http_request).await
+}
+
+async fn upload_coa_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    payload: Multipart,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    goods_in::upload_coa(app_state, path, payload, http_request).await
+}
+
+async fn release_receipt_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<goods_in::ReleaseReceiptRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Release, &app_state.db_pool).await?;
+    goods_in::release_receipt(app_state, path, body, http_request).await
+}
+
+async fn reject_receipt_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<goods_in::RejectReceiptRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Release, &app_state.db_pool).await?;
+    goods_in::reject_receipt(app_state, path, body, http_request).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/goods-in")
+            .route("/purchase-orders", web::get().to(goods_in::list_purchase_orders))
+            .route("/purchase-orders", web::post().to(create_purchase_order_protected))
+            .route("/purchase-orders/{id}", web::get().to(goods_in::get_purchase_order))
+            .route("/purchase-orders/{id}/cancel", web::post().to(cancel_purchase_order_protected))
+            .route("/receipts", web::get().to(goods_in::list_receipts))
+            .route("/receipts", web::post().to(receive_goods_protected))
+            .route("/receipts/{id}", web::get().to(goods_in::get_receipt))
+            .route("/receipts/{id}/coa", web::get().to(goods_in::download_coa))
+            .route("/receipts/{id}/coa", web::post().to(upload_coa_protected))
+            .route("/receipts/{id}/release", web::post().to(release_receipt_protected))
+            .route("/receipts/{id}/reject", web::post().to(reject_receipt_protected))
+    );
+}
@@ -0,0 +1,11 @@
+// src/routes/labels.rs
+use actix_web::web;
+use crate::label_handlers;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/labels")
+            .route("", web::post().to(label_handlers::print_labels))
+            .route("/layouts", web::get().to(label_handlers::get_label_layouts))
+    );
+}
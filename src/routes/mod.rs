@@ -12,6 +12,14 @@ pub mod storage;
 pub mod reports;
 pub mod auth_routes;
 pub mod dashboard;
+pub mod search;
+pub mod scan;
+pub mod labels;
+pub mod stocktake;
+pub mod waste;
+pub mod sensors;
+pub mod goods_in;
+pub mod notifications;
 
 use actix_web::web;
 use actix_web_httpauth::middleware::HttpAuthentication;
@@ -36,6 +44,14 @@ pub fn configure_api(cfg: &mut web::ServiceConfig) {
             .configure(storage::configure)
             .configure(experiments::configure)
             .configure(reports::configure)
+            .configure(search::configure)
+            .configure(scan::configure)
+            .configure(labels::configure)
+            .configure(stocktake::configure)
+            .configure(waste::configure)
+            .configure(sensors::configure)
+            .configure(goods_in::configure)
+            .configure(notifications::configure)
             // Unit conversion
             .service(
                 web::scope("/units")
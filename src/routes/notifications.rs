@@ -0,0 +1,32 @@
+// src/routes/notifications.rs
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use crate::{AppState, auth_handlers, notifications};
+use crate::models::NotificationQuery;
+use crate::error::ApiResult;
+
+// ==================== PROTECTED WRAPPERS ====================
+
+async fn get_notifications_protected(app_state: web::Data<Arc<AppState>>, query: web::Query<NotificationQuery>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    notifications::get_notifications(app_state, query, claims.sub).await
+}
+async fn mark_notification_read_protected(app_state: web::Data<Arc<AppState>>, path: web::Path<String>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    notifications::mark_notification_read(app_state, path, claims.sub).await
+}
+async fn mark_all_notifications_read_protected(app_state: web::Data<Arc<AppState>>, http_request: HttpRequest) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    notifications::mark_all_notifications_read(app_state, claims.sub).await
+}
+
+// ==================== ROUTES ====================
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/notifications")
+            .route("", web::get().to(get_notifications_protected))
+            .route("/read-all", web::post().to(mark_all_notifications_read_protected))
+            .route("/{id}/read", web::post().to(mark_notification_read_protected))
+    );
+}
@@ -9,5 +9,7 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/fields", web::get().to(report_handlers::get_report_fields))
             .route("/generate", web::post().to(report_handlers::generate_report))
             .route("/export", web::post().to(report_handlers::export_report))
+            .route("/equipment-utilization", web::get().to(report_handlers::get_equipment_utilization))
+            .route("/equipment-cost", web::post().to(report_handlers::get_equipment_cost_of_ownership))
     );
 }
@@ -0,0 +1,7 @@
+// src/routes/scan.rs
+use actix_web::web;
+use crate::scan_handlers;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/scan/{code}", web::get().to(scan_handlers::scan_code));
+}
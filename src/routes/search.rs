@@ -0,0 +1,7 @@
+// src/routes/search.rs
+use actix_web::web;
+use crate::search_handlers;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/search", web::get().to(search_handlers::global_search));
+}
@@ -0,0 +1,34 @@
+// src/routes/sensors.rs
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use crate::{AppState, auth_handlers, sensors};
+use crate::error::ApiResult;
+
+// ==================== PROTECTED WRAPPERS ====================
+// Управление датчиками — только admin (проверяется в обработчиках)
+
+async fn acknowledge_excursion_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<sensors::AcknowledgeExcursionRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    sensors::acknowledge_excursion(app_state, path, body, http_request).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/sensors")
+            // Static routes FIRST
+            .route("/excursions", web::get().to(sensors::list_excursions))
+            .route("/excursions/{id}", web::get().to(sensors::get_excursion))
+            .route("/excursions/{id}/acknowledge", web::post().to(acknowledge_excursion_protected))
+            .route("", web::get().to(sensors::list_sensors))
+            .route("", web::post().to(sensors::create_sensor))
+            .route("/{id}", web::get().to(sensors::get_sensor))
+            .route("/{id}", web::put().to(sensors::update_sensor))
+            .route("/{id}/rotate-key", web::post().to(sensors::rotate_sensor_key))
+            .route("/{id}/readings", web::get().to(sensors::get_sensor_readings))
+    );
+}
@@ -0,0 +1,81 @@
+// src/routes/stocktake.rs
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use crate::{AppState, auth_handlers, stocktake};
+use crate::error::ApiResult;
+
+// ==================== PROTECTED WRAPPERS ====================
+// Пересчёт меняет только данные сессии, но открывать и вести её могут те, кто правит партии
+
+async fn create_stocktake_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<stocktake::CreateStocktakeRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::create_stocktake(app_state, body, http_request).await
+}
+
+async fn record_count_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<stocktake::RecordCountRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::record_count(app_state, path, body, http_request).await
+}
+
+async fn delete_count_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::delete_count(app_state, path).await
+}
+
+async fn submit_stocktake_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::submit_stocktake(app_state, path, http_request).await
+}
+
+async fn reopen_stocktake_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::reopen_stocktake(app_state, path, http_request).await
+}
+
+async fn cancel_stocktake_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    stocktake::cancel_stocktake(app_state, path, http_request).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/stocktakes")
+            .route("", web::get().to(stocktake::list_stocktakes))
+            .route("", web::post().to(create_stocktake_protected))
+            .route("/{id}", web::get().to(stocktake::get_stocktake))
+            .route("/{id}/counts", web::get().to(stocktake::get_counts))
+            .route("/{id}/counts", web::post().to(record_count_protected))
+            .route("/{id}/counts/{count_id}", web::delete().to(delete_count_protected))
+            .route("/{id}/variances", web::get().to(stocktake::get_variances))
+            .route("/{id}/submit", web::post().to(submit_stocktake_protected))
+            .route("/{id}/reopen", web::post().to(reopen_stocktake_protected))
+            .route("/{id}/approve", web::post().to(stocktake::approve_stocktake))
+            .route("/{id}/cancel", web::post().to(cancel_stocktake_protected))
+            .route("/{id}/adjustments", web::get().to(stocktake::get_adjustments))
+    );
+}
@@ -1,7 +1,7 @@
 // src/routes/storage.rs
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::{AppState, auth, storage_handlers};
+use crate::{AppState, auth, container_handlers, scan_handlers, storage_handlers, storage_locations};
 use crate::error::ApiResult;
 
 // ==================== PROTECTED WRAPPERS ====================
@@ -30,6 +30,22 @@ async fn delete_storage_position_protected(http_request: HttpRequest, app_state:
     let _claims = auth::get_current_user(&http_request)?;
     storage_handlers::delete_storage_position(app_state, path).await
 }
+async fn create_storage_location_protected(http_request: HttpRequest, app_state: web::Data<Arc<AppState>>, data: web::Json<crate::models::CreateStorageLocationRequest>) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    storage_locations::create_storage_location(app_state, data, claims.sub).await
+}
+async fn update_storage_location_protected(http_request: HttpRequest, app_state: web::Data<Arc<AppState>>, path: web::Path<String>, data: web::Json<crate::models::UpdateStorageLocationRequest>) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    storage_locations::update_storage_location(app_state, path, data, claims.sub).await
+}
+async fn delete_storage_location_protected(http_request: HttpRequest, app_state: web::Data<Arc<AppState>>, path: web::Path<String>) -> ApiResult<HttpResponse> {
+    let _claims = auth::get_current_user(&http_request)?;
+    storage_locations::delete_storage_location(app_state, path).await
+}
+async fn move_storage_location_protected(http_request: HttpRequest, app_state: web::Data<Arc<AppState>>, path: web::Path<String>, data: web::Json<crate::models::MoveStorageLocationRequest>) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    storage_locations::move_storage_location(app_state, path, data, claims.sub).await
+}
 
 // ==================== ROUTES ====================
 
@@ -48,6 +64,18 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/positions/{id}", web::put().to(update_storage_position_protected))
             .route("/positions/{id}", web::delete().to(delete_storage_position_protected))
             .route("/positions/{id}/items", web::get().to(storage_handlers::get_position_items))
+            .route("/positions/{id}/movements", web::get().to(container_handlers::get_position_movements))
+            .route("/positions/{id}/grid", web::get().to(storage_handlers::get_position_grid))
+            .route("/positions/{id}/barcode", web::get().to(scan_handlers::get_position_barcode))
+            .route("/locations", web::get().to(storage_locations::get_location_children))
+            .route("/locations", web::post().to(create_storage_location_protected))
+            .route("/locations/{id}", web::get().to(storage_locations::get_storage_location))
+            .route("/locations/{id}", web::put().to(update_storage_location_protected))
+            .route("/locations/{id}", web::delete().to(delete_storage_location_protected))
+            .route("/locations/{id}/move", web::post().to(move_storage_location_protected))
+            .route("/locations/{id}/tree", web::get().to(storage_locations::get_location_tree))
+            .route("/locations/{id}/inventory", web::get().to(storage_locations::get_location_inventory))
+            .route("/locations/{id}/path", web::get().to(storage_locations::get_storage_location_path))
             .route("/hierarchy", web::get().to(storage_handlers::get_storage_hierarchy))
             .route("/location-path/{id}", web::get().to(storage_handlers::get_location_path))
             .route("/search", web::get().to(storage_handlers::search_storage_locations))
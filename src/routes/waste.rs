@@ -0,0 +1,59 @@
+// src/routes/waste.rs
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use crate::{AppState, auth_handlers, waste};
+use crate::error::ApiResult;
+
+// ==================== PROTECTED WRAPPERS ====================
+
+async fn create_waste_container_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<waste::CreateWasteContainerRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    waste::create_waste_container(app_state, body, http_request).await
+}
+
+async fn seal_waste_container_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    waste::seal_waste_container(app_state, path, http_request).await
+}
+
+async fn create_manifest_protected(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<waste::CreateManifestRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    waste::create_manifest(app_state, body, http_request).await
+}
+
+async fn ship_manifest_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
+    waste::ship_manifest(app_state, path, http_request).await
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/waste")
+            .route("/categories", web::get().to(waste::get_waste_categories))
+            .route("/report", web::get().to(waste::get_waste_report))
+            .route("/containers", web::get().to(waste::list_waste_containers))
+            .route("/containers", web::post().to(create_waste_container_protected))
+            .route("/containers/{id}", web::get().to(waste::get_waste_container))
+            .route("/containers/{id}/seal", web::post().to(seal_waste_container_protected))
+            .route("/manifests", web::get().to(waste::list_manifests))
+            .route("/manifests", web::post().to(create_manifest_protected))
+            .route("/manifests/{id}", web::get().to(waste::get_manifest))
+            .route("/manifests/{id}/ship", web::post().to(ship_manifest_protected))
+    );
+}
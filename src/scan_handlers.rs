@@ -0,0 +1,304 @@
+// src/scan_handlers.rs
+//! Сканирование этикеток и печать штрихкодов.
+//!
+//! `GET /scan/{code}` принимает короткий код (C…/B…/P…), UUID записи
+//! (старые этикетки) или GS1-штрихкод производителя. Для GS1 возвращаются
+//! разобранные поля и партии, совпавшие по лоту и каталожному номеру/GTIN.
+//!
+//! Endpoints:
+//!   GET /api/v1/scan/{code}
+//!   GET /api/v1/containers/{id}/barcode?symbology=code128|qr&format=svg|png&scale=
+//!   GET /api/v1/batches/{id}/barcode
+//!   GET /api/v1/storage/positions/{id}/barcode
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::barcodes::{self, BarcodeFormat, BarcodeQuery, Gs1Data, ScanEntity};
+use crate::container_handlers::CONTAINER_WITH_LOCATION_SELECT;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::storage_locations::load_location_path;
+
+const SCANNED_BATCH_SELECT: &str = r#"
+    SELECT b.id, b.short_code, b.reagent_id, r.name AS reagent_name, b.batch_number, b.lot_number,
+           b.cat_number, b.quantity, b.unit, b.expiry_date, b.status, b.supplier, b.manufacturer
+    FROM batches b
+    JOIN reagents r ON r.id = b.reagent_id
+"#;
+
+/// Максимум партий-кандидатов для GS1
+const GS1_MATCH_LIMIT: i64 = 50;
+
+// ==================== RESPONSE STRUCTURES ====================
+
+#[derive(Debug, Serialize)]
+pub struct ScanResult {
+    pub code: String,
+    /// container | batch | position | gs1
+    pub entity_type: &'static str,
+    pub entity_id: Option<String>,
+    pub details: ScanDetails,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ScanDetails {
+    Container(Box<ScannedContainer>),
+    Batch(Box<ScannedBatchDetails>),
+    Position(Box<ScannedPosition>),
+    Gs1(Box<Gs1Lookup>),
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ScannedBatch {
+    pub id: String,
+    pub short_code: Option<String>,
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub batch_number: String,
+    pub lot_number: Option<String>,
+    pub cat_number: Option<String>,
+    pub quantity: f64,
+    pub unit: String,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub status: String,
+    pub supplier: Option<String>,
+    pub manufacturer: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScannedContainer {
+    #[serde(flatten)]
+    pub container: ContainerWithLocation,
+    pub location_path: String,
+    pub batch: ScannedBatch,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScannedBatchDetails {
+    #[serde(flatten)]
+    pub batch: ScannedBatch,
+    pub containers: Vec<ContainerWithLocation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScannedPosition {
+    #[serde(flatten)]
+    pub position: StoragePosition,
+    pub path: StorageLocationPath,
+    pub containers: Vec<ContainerWithLocation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Gs1Lookup {
+    pub gs1: Gs1Data,
+    /// Партии, совпавшие по лоту и/или каталожному номеру; лучшие совпадения первыми
+    pub matches: Vec<Gs1BatchMatch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Gs1BatchMatch {
+    #[serde(flatten)]
+    pub batch: ScannedBatch,
+    /// lot_number | catalog_number | gtin
+    pub matched_on: Vec<&'static str>,
+}
+
+// ==================== HELPERS ====================
+
+async fn fetch_scanned_batch(pool: &SqlitePool, batch_id: &str) -> ApiResult<Option<ScannedBatch>> {
+    let sql = format!("{} WHERE b.id = ? AND b.deleted_at IS NULL", SCANNED_BATCH_SELECT);
+    Ok(sqlx::query_as(&sql).bind(batch_id).fetch_optional(pool).await?)
+}
+
+async fn fetch_containers(pool: &SqlitePool, condition: &str, value: &str) -> ApiResult<Vec<ContainerWithLocation>> {
+    let sql = format!(
+        "{} WHERE {} = ? AND bc.status != 'disposed' ORDER BY bc.sequence_number",
+        CONTAINER_WITH_LOCATION_SELECT, condition
+    );
+    Ok(sqlx::query_as(&sql).bind(value).fetch_all(pool).await?)
+}
+
+/// Объект по `short_code` или `id`; None — не найден
+async fn resolve_entity(pool: &SqlitePool, entity: ScanEntity, column: &str, value: &str) -> ApiResult<Option<(String, ScanDetails)>> {
+    match entity {
+        ScanEntity::Container => {
+            let sql = format!("{} WHERE bc.{} = ?", CONTAINER_WITH_LOCATION_SELECT, column);
+            let container: Option<ContainerWithLocation> = sqlx::query_as(&sql).bind(value).fetch_optional(pool).await?;
+            let Some(container) = container else { return Ok(None) };
+            let Some(batch) = fetch_scanned_batch(pool, &container.batch_id).await? else { return Ok(None) };
+            let id = container.id.clone();
+            let location_path = container.location_path();
+            Ok(Some((id, ScanDetails::Container(Box::new(ScannedContainer { container, location_path, batch })))))
+        }
+        ScanEntity::Batch => {
+            let sql = format!("{} WHERE b.{} = ? AND b.deleted_at IS NULL", SCANNED_BATCH_SELECT, column);
+            let batch: Option<ScannedBatch> = sqlx::query_as(&sql).bind(value).fetch_optional(pool).await?;
+            let Some(batch) = batch else { return Ok(None) };
+            let containers = fetch_containers(pool, "bc.batch_id", &batch.id).await?;
+            Ok(Some((batch.id.clone(), ScanDetails::Batch(Box::new(ScannedBatchDetails { batch, containers })))))
+        }
+        ScanEntity::Position => {
+            let sql = format!("SELECT * FROM storage_positions WHERE {} = ?", column);
+            let position: Option<StoragePosition> = sqlx::query_as(&sql).bind(value).fetch_optional(pool).await?;
+            let Some(position) = position else { return Ok(None) };
+            let path = load_location_path(pool, &position.id).await?;
+            let containers = fetch_containers(pool, "bp.position_id", &position.id).await?;
+            Ok(Some((position.id.clone(), ScanDetails::Position(Box::new(ScannedPosition { position, path, containers })))))
+        }
+    }
+}
+
+/// Партии по данным GS1: лот и каталожный номер (AI 240) или GTIN, записанный в cat_number
+async fn match_gs1_batches(pool: &SqlitePool, gs1: &Gs1Data) -> ApiResult<Vec<Gs1BatchMatch>> {
+    // GTIN-14 с ведущим нулём часто хранят как GTIN-13
+    let gtin_short = gs1.gtin.as_deref().and_then(|g| g.strip_prefix('0'));
+    if gs1.lot_number.is_none() && gs1.catalog_number.is_none() && gs1.gtin.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let sql = format!(
+        r#"{} WHERE b.deleted_at IS NULL
+             AND (b.lot_number = ?1 COLLATE NOCASE
+                  OR b.cat_number COLLATE NOCASE IN (?2, ?3, ?4))
+           ORDER BY b.expiry_date IS NULL, b.expiry_date
+           LIMIT ?5"#,
+        SCANNED_BATCH_SELECT
+    );
+    let batches: Vec<ScannedBatch> = sqlx::query_as(&sql)
+        .bind(&gs1.lot_number)
+        .bind(&gs1.catalog_number)
+        .bind(&gs1.gtin)
+        .bind(gtin_short)
+        .bind(GS1_MATCH_LIMIT)
+        .fetch_all(pool)
+        .await?;
+
+    let same = |a: Option<&str>, b: Option<&str>| matches!((a, b), (Some(a), Some(b)) if a.eq_ignore_ascii_case(b));
+    let mut matches: Vec<Gs1BatchMatch> = batches.into_iter()
+        .map(|batch| {
+            let cat = batch.cat_number.as_deref();
+            let mut matched_on = Vec::new();
+            if same(batch.lot_number.as_deref(), gs1.lot_number.as_deref()) {
+                matched_on.push("lot_number");
+            }
+            if same(cat, gs1.catalog_number.as_deref()) {
+                matched_on.push("catalog_number");
+            }
+            if same(cat, gs1.gtin.as_deref()) || same(cat, gtin_short) {
+                matched_on.push("gtin");
+            }
+            Gs1BatchMatch { batch, matched_on }
+        })
+        .collect();
+    // Стабильная сортировка сохраняет порядок по сроку годности внутри группы
+    matches.sort_by_key(|m| std::cmp::Reverse(m.matched_on.len()));
+    Ok(matches)
+}
+
+async fn barcode_response(pool: &SqlitePool, entity: ScanEntity, id: &str, query: &BarcodeQuery) -> ApiResult<HttpResponse> {
+    let sql = match entity {
+        ScanEntity::Container => "SELECT short_code FROM batch_containers WHERE id = ?",
+        ScanEntity::Batch => "SELECT short_code FROM batches WHERE id = ? AND deleted_at IS NULL",
+        ScanEntity::Position => "SELECT short_code FROM storage_positions WHERE id = ?",
+    };
+    let code: Option<Option<String>> = sqlx::query_scalar(sql).bind(id).fetch_optional(pool).await?;
+    let code = match code {
+        Some(Some(code)) => code,
+        Some(None) => return Err(ApiError::internal_error("Short code has not been assigned")),
+        None => return Err(match entity {
+            ScanEntity::Container => ApiError::not_found("Container"),
+            ScanEntity::Batch => ApiError::not_found("Batch"),
+            ScanEntity::Position => ApiError::not_found("Storage position"),
+        }),
+    };
+
+    let (bytes, mime) = barcodes::render_barcode(&code, query)?;
+    let ext = match query.format.unwrap_or_default() {
+        BarcodeFormat::Svg => "svg",
+        BarcodeFormat::Png => "png",
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(mime)
+        .insert_header(("Content-Disposition", format!("inline; filename=\"{}.{}\"", code, ext)))
+        .insert_header(("Cache-Control", "private, max-age=86400"))
+        .body(bytes))
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn scan_code(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let raw = path.into_inner();
+    let code = raw.trim();
+    if code.is_empty() {
+        return Err(ApiError::bad_request("Code is required"));
+    }
+    let pool = &app_state.db_pool;
+
+    let mut found = None;
+    if let Some((entity, short_code)) = barcodes::parse_short_code(code) {
+        found = resolve_entity(pool, entity, "short_code", &short_code).await?.map(|r| (entity, r));
+    } else if Uuid::parse_str(code).is_ok() {
+        for entity in [ScanEntity::Container, ScanEntity::Batch, ScanEntity::Position] {
+            if let Some(r) = resolve_entity(pool, entity, "id", code).await? {
+                found = Some((entity, r));
+                break;
+            }
+        }
+    }
+    if let Some((entity, (entity_id, details))) = found {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(ScanResult {
+            code: code.to_string(),
+            entity_type: entity.as_str(),
+            entity_id: Some(entity_id),
+            details,
+        })));
+    }
+
+    if barcodes::looks_like_gs1(code) {
+        let gs1 = barcodes::parse_gs1(code).map_err(|e| ApiError::bad_request(&e))?;
+        let matches = match_gs1_batches(pool, &gs1).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(ScanResult {
+            code: code.to_string(),
+            entity_type: "gs1",
+            entity_id: None,
+            details: ScanDetails::Gs1(Box::new(Gs1Lookup { gs1, matches })),
+        })));
+    }
+
+    Err(ApiError::not_found("Scanned code"))
+}
+
+pub async fn get_container_barcode(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<BarcodeQuery>,
+) -> ApiResult<HttpResponse> {
+    barcode_response(&app_state.db_pool, ScanEntity::Container, &path.into_inner(), &query).await
+}
+
+pub async fn get_batch_barcode(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<BarcodeQuery>,
+) -> ApiResult<HttpResponse> {
+    barcode_response(&app_state.db_pool, ScanEntity::Batch, &path.into_inner(), &query).await
+}
+
+pub async fn get_position_barcode(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<BarcodeQuery>,
+) -> ApiResult<HttpResponse> {
+    barcode_response(&app_state.db_pool, ScanEntity::Position, &path.into_inner(), &query).await
+}
@@ -0,0 +1,291 @@
+// src/search_handlers.rs
+//! Глобальный поиск: один запрос `/api/v1/search?q=` по всем модулям.
+//!
+//! Каждый тип ищется параллельно со своим лимитом; всё, что не уложилось
+//! в общий бюджет времени, возвращается как `timed_out` без результатов.
+//! Типы, на просмотр которых у пользователя нет прав, не запрашиваются.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::auth::get_current_user;
+use crate::auth_handlers::{has_view_permission, Permission};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::query_builders::fts::{highlight_snippet, FtsQueryBuilder, config::FtsConfig};
+use crate::query_builders::utils::escape_like_value;
+use crate::storage_handlers::find_storage_locations;
+
+/// Общий бюджет времени на все источники
+const SEARCH_TIME_BUDGET: Duration = Duration::from_millis(1500);
+const DEFAULT_LIMIT_PER_TYPE: i64 = 5;
+const MAX_LIMIT_PER_TYPE: i64 = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchType {
+    Reagents,
+    Batches,
+    Equipment,
+    EquipmentParts,
+    Experiments,
+    StorageLocations,
+}
+
+impl SearchType {
+    pub const ALL: [SearchType; 6] = [
+        SearchType::Reagents,
+        SearchType::Batches,
+        SearchType::Equipment,
+        SearchType::EquipmentParts,
+        SearchType::Experiments,
+        SearchType::StorageLocations,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchType::Reagents => "reagents",
+            SearchType::Batches => "batches",
+            SearchType::Equipment => "equipment",
+            SearchType::EquipmentParts => "equipment_parts",
+            SearchType::Experiments => "experiments",
+            SearchType::StorageLocations => "storage_locations",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|t| t.as_str() == s)
+    }
+
+    /// Право на просмотр модуля, к которому относится тип
+    fn view_permission(&self) -> Permission {
+        match self {
+            SearchType::Reagents => Permission::ViewReagent,
+            SearchType::Batches => Permission::ViewBatch,
+            SearchType::Equipment | SearchType::EquipmentParts => Permission::ViewEquipment,
+            SearchType::Experiments => Permission::ViewExperiment,
+            SearchType::StorageLocations => Permission::ViewRoom,
+        }
+    }
+
+    /// Разобрать `types=reagents,batches`; пусто — все типы
+    pub fn parse_list(list: Option<&str>) -> ApiResult<Vec<SearchType>> {
+        let list = match list.map(str::trim) {
+            Some(l) if !l.is_empty() => l,
+            _ => return Ok(Self::ALL.to_vec()),
+        };
+        let mut types = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let t = Self::from_str(name).ok_or_else(|| {
+                ApiError::bad_request(&format!("Unknown search type '{}'", name))
+            })?;
+            if !types.contains(&t) {
+                types.push(t);
+            }
+        }
+        Ok(types)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GlobalSearchQuery {
+    pub q: Option<String>,
+    /// Лимит результатов на каждый тип
+    pub limit: Option<i64>,
+    /// Список типов через запятую
+    pub types: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct GlobalSearchHit {
+    pub id: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub snippet: Option<String>,
+    /// Меньше — релевантнее (bm25), сравнимо только внутри группы
+    pub rank: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchGroup {
+    pub entity_type: &'static str,
+    pub results: Vec<GlobalSearchHit>,
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GlobalSearchResponse {
+    pub query: String,
+    pub groups: Vec<SearchGroup>,
+    /// Хотя бы один источник не уложился в бюджет
+    pub partial: bool,
+    pub took_ms: u128,
+}
+
+/// GET /api/v1/search?q=&limit=&types=
+pub async fn global_search(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<GlobalSearchQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let search = query.q.as_deref().unwrap_or("").trim().to_string();
+    if search.is_empty() {
+        return Err(ApiError::bad_request("Search query 'q' is required"));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT_PER_TYPE).clamp(1, MAX_LIMIT_PER_TYPE);
+    let pool = &app_state.db_pool;
+
+    let mut types = Vec::new();
+    for t in SearchType::parse_list(query.types.as_deref())? {
+        if has_view_permission(&claims, t.view_permission(), pool).await {
+            types.push(t);
+        }
+    }
+
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::from_std(started + SEARCH_TIME_BUDGET);
+
+    let outcomes = join_all(types.iter().map(|&t| {
+        let search = search.as_str();
+        async move { (t, tokio::time::timeout_at(deadline, search_type(pool, t, search, limit)).await) }
+    })).await;
+
+    let mut groups = Vec::with_capacity(outcomes.len());
+    for (t, outcome) in outcomes {
+        let group = match outcome {
+            Ok(results) => SearchGroup { entity_type: t.as_str(), results: results?, timed_out: false },
+            Err(_) => {
+                log::warn!("Global search: '{}' exceeded time budget for query '{}'", t.as_str(), search);
+                SearchGroup { entity_type: t.as_str(), results: Vec::new(), timed_out: true }
+            }
+        };
+        groups.push(group);
+    }
+
+    let partial = groups.iter().any(|g| g.timed_out);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(GlobalSearchResponse {
+        query: search,
+        groups,
+        partial,
+        took_ms: started.elapsed().as_millis(),
+    })))
+}
+
+async fn search_type(pool: &SqlitePool, t: SearchType, search: &str, limit: i64) -> ApiResult<Vec<GlobalSearchHit>> {
+    match t {
+        SearchType::Reagents => ranked_source(
+            pool, &FtsConfig::for_reagents(), search, limit,
+            "e.name as title, COALESCE(e.cas_number, e.formula) as subtitle",
+            "", "e.deleted_at IS NULL", "e.name",
+        ).await,
+        SearchType::Equipment => ranked_source(
+            pool, &FtsConfig::for_equipment(), search, limit,
+            "e.name as title, COALESCE(e.model, e.serial_number, e.location) as subtitle",
+            "", "1 = 1", "e.name",
+        ).await,
+        SearchType::EquipmentParts => ranked_source(
+            pool, &FtsConfig::for_equipment_parts(), search, limit,
+            "e.name as title, eq.name as subtitle",
+            "JOIN equipment eq ON eq.id = e.equipment_id", "1 = 1", "e.name",
+        ).await,
+        SearchType::Experiments => ranked_source(
+            pool, &FtsConfig::for_experiments(), search, limit,
+            "e.title as title, e.status as subtitle",
+            "", "1 = 1", "e.experiment_date DESC",
+        ).await,
+        SearchType::Batches => search_batches(pool, search, limit).await,
+        SearchType::StorageLocations => {
+            let locations = find_storage_locations(pool, search, limit).await?;
+            Ok(locations.into_iter().map(|loc| GlobalSearchHit {
+                id: loc["position_id"].as_str().unwrap_or_default().to_string(),
+                title: loc["full_path"].as_str().unwrap_or_default().to_string(),
+                subtitle: loc["zone_type"].as_str().map(String::from),
+                snippet: None,
+                rank: 0.0,
+            }).collect())
+        }
+    }
+}
+
+/// Поиск по FTS-индексу таблицы (алиас `e`), LIKE если индекса нет
+#[allow(clippy::too_many_arguments)]
+async fn ranked_source(
+    pool: &SqlitePool,
+    config: &FtsConfig,
+    search: &str,
+    limit: i64,
+    columns: &str,
+    extra_join: &str,
+    filter: &str,
+    fallback_order: &str,
+) -> ApiResult<Vec<GlobalSearchHit>> {
+    let use_fts = FtsQueryBuilder::check_fts_table_available(pool, config.fts_table).await;
+    let ranked = FtsQueryBuilder::build_ranked_search(search, use_fts, config, "e");
+    if ranked.condition.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let order = if use_fts { "rank" } else { fallback_order };
+    let sql = format!(
+        "SELECT e.id, {}, {} as snippet, {} as rank FROM {} e {} {} WHERE {} AND {} ORDER BY {} LIMIT ?",
+        columns, ranked.snippet_expr, ranked.rank_expr, config.main_table,
+        ranked.join, extra_join, filter, ranked.condition, order
+    );
+
+    let mut q = sqlx::query_as::<_, GlobalSearchHit>(&sql);
+    for param in &ranked.params {
+        q = q.bind(param);
+    }
+    let mut hits = q.bind(limit).fetch_all(pool).await?;
+    for hit in &mut hits {
+        hit.snippet = hit.snippet.as_deref().map(highlight_snippet);
+    }
+    Ok(hits)
+}
+
+/// Номера партий/лотов/каталожные номера — точное совпадение выше подстроки
+async fn search_batches(pool: &SqlitePool, search: &str, limit: i64) -> ApiResult<Vec<GlobalSearchHit>> {
+    let pattern = format!("%{}%", escape_like_value(search));
+    let hits = sqlx::query_as::<_, GlobalSearchHit>(r#"
+        SELECT b.id, r.name || ' / ' || b.batch_number as title,
+               COALESCE(b.lot_number, b.cat_number, b.supplier) as subtitle,
+               NULL as snippet,
+               CASE WHEN b.batch_number = ? COLLATE NOCASE OR b.lot_number = ? COLLATE NOCASE
+                         OR b.cat_number = ? COLLATE NOCASE
+                    THEN 0.0 ELSE 1.0 END as rank
+        FROM batches b
+        JOIN reagents r ON b.reagent_id = r.id
+        WHERE b.deleted_at IS NULL
+          AND (b.batch_number LIKE ? ESCAPE '\' OR b.lot_number LIKE ? ESCAPE '\'
+               OR b.cat_number LIKE ? ESCAPE '\')
+        ORDER BY rank, b.received_date DESC
+        LIMIT ?
+    "#)
+        .bind(search).bind(search).bind(search)
+        .bind(&pattern).bind(&pattern).bind(&pattern)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_search_types() {
+        assert_eq!(SearchType::parse_list(None).unwrap().len(), SearchType::ALL.len());
+        assert_eq!(
+            SearchType::parse_list(Some("batches, reagents,batches")).unwrap(),
+            vec![SearchType::Batches, SearchType::Reagents]
+        );
+        assert!(SearchType::parse_list(Some("widgets")).is_err());
+    }
+}
@@ -0,0 +1,915 @@
+// src/sensors.rs
+//! Мониторинг температуры зон хранения.
+//!
+//! Логгеры холодильников и морозильников отправляют показания в публичный
+//! endpoint, аутентифицируясь собственным ключом API (`Authorization: Bearer lims_sk_…`
+//! или `X-Sensor-Key`). Ключ выдаётся один раз при создании/ротации, в БД хранится
+//! только SHA-256.
+//!
+//! Показания не хранятся поштучно: каждое добавляется в корзины `temperature_readings`
+//! двух разрешений — 5 минут (хранятся 30 дней) и 1 час (2 года). График берёт самое
+//! подробное разрешение, которое ещё хранится для запрошенного интервала.
+//!
+//! Выход за `temperature_min`/`temperature_max` зоны открывает инцидент
+//! (`temperature_excursions`) со списком контейнеров, размещённых в зоне
+//! (`batch_placements`), и уведомлением; возврат в диапазон закрывает его.
+//! Показания старше последнего принятого (догрузка из памяти логгера) попадают
+//! в историю, но инциденты не меняют.
+//!
+//! Endpoints:
+//!   POST /api/v1/public/sensors/readings          — приём показаний (ключ датчика)
+//!   GET  /api/v1/sensors?zone_id=
+//!   POST /api/v1/sensors                          — admin, ответ содержит api_key
+//!   GET  /api/v1/sensors/{id}
+//!   PUT  /api/v1/sensors/{id}                     — admin
+//!   POST /api/v1/sensors/{id}/rotate-key          — admin
+//!   GET  /api/v1/sensors/{id}/readings?from=&to=&resolution=
+//!   GET  /api/v1/sensors/excursions?status=&zone_id=&sensor_id=
+//!   GET  /api/v1/sensors/excursions/{id}
+//!   POST /api/v1/sensors/excursions/{id}/acknowledge
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::{info, warn};
+
+use crate::AppState;
+use crate::auth::{get_current_user, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::notifications::{notify, NewNotification};
+
+const API_KEY_PREFIX: &str = "lims_sk_";
+const API_KEY_LENGTH: usize = 40;
+
+/// (разрешение в секундах, срок хранения в днях) — от подробного к грубому
+pub const READING_RESOLUTIONS: [(i64, i64); 2] = [(300, 30), (3600, 730)];
+
+const MAX_CHART_POINTS: i64 = 2000;
+const DEFAULT_CHART_HOURS: i64 = 24;
+
+/// Допуск на расхождение часов логгера
+const FUTURE_TOLERANCE_MINUTES: i64 = 5;
+
+// ==================== MODELS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct TemperatureSensor {
+    pub id: String,
+    pub name: String,
+    pub zone_id: String,
+    #[sqlx(default)]
+    pub zone_name: Option<String>,
+    #[sqlx(default)]
+    pub temperature_min: Option<f64>,
+    #[sqlx(default)]
+    pub temperature_max: Option<f64>,
+    pub description: Option<String>,
+    /// Начало ключа — чтобы узнать, какой ключ настроен в логгере
+    pub api_key_prefix: String,
+    pub is_active: bool,
+    pub last_reading_at: Option<DateTime<Utc>>,
+    pub last_temperature: Option<f64>,
+    #[sqlx(default)]
+    pub active_excursion_id: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SensorWithKey {
+    pub sensor: TemperatureSensor,
+    /// Показывается только один раз
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct TemperatureExcursion {
+    pub id: String,
+    pub sensor_id: String,
+    #[sqlx(default)]
+    pub sensor_name: Option<String>,
+    pub zone_id: String,
+    #[sqlx(default)]
+    pub zone_name: Option<String>,
+    /// high | low
+    pub kind: String,
+    /// active | resolved
+    pub status: String,
+    pub limit_min: Option<f64>,
+    pub limit_max: Option<f64>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub peak_temperature: f64,
+    pub last_temperature: f64,
+    pub reading_count: i64,
+    pub affected_container_count: i64,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExcursionContainer {
+    pub container_id: String,
+    pub short_code: Option<String>,
+    pub sequence_number: Option<i64>,
+    pub batch_id: String,
+    pub batch_number: Option<String>,
+    pub reagent_name: Option<String>,
+    pub position_id: Option<String>,
+    pub position_name: Option<String>,
+    pub cell: Option<String>,
+    pub quantity: f64,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReadingPoint {
+    pub bucket_start: DateTime<Utc>,
+    pub min_value: f64,
+    pub max_value: f64,
+    pub avg_value: f64,
+    pub sample_count: i64,
+}
+
+// ==================== REQUESTS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct SensorListQuery {
+    pub zone_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateSensorRequest {
+    #[validate(length(min = 1, max = 200, message = "Name must be 1-200 characters"))]
+    pub name: String,
+    pub zone_id: String,
+    #[validate(length(max = 500, message = "Description max 500 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateSensorRequest {
+    #[validate(length(min = 1, max = 200, message = "Name must be 1-200 characters"))]
+    pub name: Option<String>,
+    pub zone_id: Option<String>,
+    #[validate(length(max = 500, message = "Description max 500 characters"))]
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct SensorReading {
+    /// Не задано — время приёма
+    pub recorded_at: Option<DateTime<Utc>>,
+    #[validate(range(min = -273.15, max = 1000.0, message = "Temperature out of physical range"))]
+    pub temperature: f64,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct IngestReadingsRequest {
+    #[validate(length(min = 1, max = 1000, message = "Send 1-1000 readings per request"))]
+    #[validate(nested)]
+    pub readings: Vec<SensorReading>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadingsQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Секунды: 300 или 3600; по умолчанию — подбирается
+    pub resolution: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExcursionQuery {
+    pub status: Option<String>,
+    pub zone_id: Option<String>,
+    pub sensor_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcknowledgeExcursionRequest {
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+// ==================== PURE LOGIC ====================
+
+/// Начало корзины, в которую попадает момент времени
+pub fn bucket_start(at: DateTime<Utc>, resolution: i64) -> DateTime<Utc> {
+    let ts = at.timestamp();
+    Utc.timestamp_opt(ts - ts.rem_euclid(resolution), 0).single().unwrap_or(at)
+}
+
+/// Самое подробное разрешение, которое хранится для `from` и даёт не больше MAX_CHART_POINTS точек
+pub fn chart_resolution(from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    let span = (to - from).num_seconds().max(0);
+    READING_RESOLUTIONS.iter()
+        .find(|(resolution, retention_days)| {
+            from >= now - Duration::days(*retention_days) && span / resolution <= MAX_CHART_POINTS
+        })
+        .or(READING_RESOLUTIONS.last())
+        .map(|(resolution, _)| *resolution)
+        .unwrap_or(3600)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcursionStep {
+    /// В диапазоне, инцидента нет
+    Normal,
+    Open(&'static str),
+    Continue,
+    /// Из high в low (или наоборот): закрыть текущий и открыть новый
+    Switch(&'static str),
+    Close,
+}
+
+/// Переход состояния инцидента по очередному показанию
+pub fn excursion_step(temperature: f64, min: Option<f64>, max: Option<f64>, active_kind: Option<&str>) -> ExcursionStep {
+    let out_of_range = if max.is_some_and(|m| temperature > m) {
+        Some("high")
+    } else if min.is_some_and(|m| temperature < m) {
+        Some("low")
+    } else {
+        None
+    };
+    match (active_kind, out_of_range) {
+        (None, None) => ExcursionStep::Normal,
+        (None, Some(kind)) => ExcursionStep::Open(kind),
+        (Some(active), Some(kind)) if active == kind => ExcursionStep::Continue,
+        (Some(_), Some(kind)) => ExcursionStep::Switch(kind),
+        (Some(_), None) => ExcursionStep::Close,
+    }
+}
+
+// ==================== HELPERS ====================
+
+fn generate_api_key() -> String {
+    let secret: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(API_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{}{}", API_KEY_PREFIX, secret)
+}
+
+fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn key_prefix(key: &str) -> String {
+    key.chars().take(API_KEY_PREFIX.len() + 4).collect()
+}
+
+fn require_admin(http_request: &HttpRequest) -> ApiResult<String> {
+    let claims = get_current_user(http_request)?;
+    if claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Only administrators can manage temperature sensors".to_string()));
+    }
+    Ok(claims.sub)
+}
+
+const SENSOR_SELECT: &str = r#"
+    SELECT ts.*, sz.name AS zone_name, sz.temperature_min, sz.temperature_max,
+           (SELECT te.id FROM temperature_excursions te
+            WHERE te.sensor_id = ts.id AND te.status = 'active' LIMIT 1) AS active_excursion_id
+    FROM temperature_sensors ts
+    JOIN storage_zones sz ON sz.id = ts.zone_id
+"#;
+
+const EXCURSION_SELECT: &str = r#"
+    SELECT te.*, ts.name AS sensor_name, sz.name AS zone_name
+    FROM temperature_excursions te
+    LEFT JOIN temperature_sensors ts ON ts.id = te.sensor_id
+    LEFT JOIN storage_zones sz ON sz.id = te.zone_id
+"#;
+
+async fn get_sensor_or_404(pool: &SqlitePool, sensor_id: &str) -> ApiResult<TemperatureSensor> {
+    sqlx::query_as(&format!("{} WHERE ts.id = ?", SENSOR_SELECT))
+        .bind(sensor_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Temperature sensor"))
+}
+
+async fn get_excursion_or_404(pool: &SqlitePool, excursion_id: &str) -> ApiResult<TemperatureExcursion> {
+    sqlx::query_as(&format!("{} WHERE te.id = ?", EXCURSION_SELECT))
+        .bind(excursion_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Temperature excursion"))
+}
+
+async fn ensure_zone_exists(pool: &SqlitePool, zone_id: &str) -> ApiResult<()> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM storage_zones WHERE id = ?)")
+        .bind(zone_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Storage zone"));
+    }
+    Ok(())
+}
+
+/// Датчик по ключу из `Authorization: Bearer` или `X-Sensor-Key`
+async fn authenticate_sensor(pool: &SqlitePool, http_request: &HttpRequest) -> ApiResult<TemperatureSensor> {
+    let headers = http_request.headers();
+    let key = headers.get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("X-Sensor-Key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+        .filter(|k| k.starts_with(API_KEY_PREFIX))
+        .ok_or_else(|| ApiError::Unauthorized("Sensor API key required".to_string()))?;
+
+    let sensor: TemperatureSensor = sqlx::query_as(&format!("{} WHERE ts.api_key_hash = ?", SENSOR_SELECT))
+        .bind(hash_api_key(key))
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Invalid sensor API key".to_string()))?;
+    if !sensor.is_active {
+        return Err(ApiError::Forbidden("Sensor is deactivated".to_string()));
+    }
+    Ok(sensor)
+}
+
+async fn store_reading(conn: &mut SqliteConnection, sensor_id: &str, at: DateTime<Utc>, temperature: f64) -> ApiResult<()> {
+    for (resolution, _) in READING_RESOLUTIONS {
+        sqlx::query(
+            r#"INSERT INTO temperature_readings
+               (sensor_id, resolution, bucket_start, min_value, max_value, sum_value, sample_count)
+               VALUES (?1, ?2, ?3, ?4, ?4, ?4, 1)
+               ON CONFLICT(sensor_id, resolution, bucket_start) DO UPDATE SET
+                   min_value = MIN(min_value, excluded.min_value),
+                   max_value = MAX(max_value, excluded.max_value),
+                   sum_value = sum_value + excluded.sum_value,
+                   sample_count = sample_count + 1"#
+        )
+            .bind(sensor_id)
+            .bind(resolution)
+            .bind(bucket_start(at, resolution))
+            .bind(temperature)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Открыть инцидент и зафиксировать контейнеры, размещённые в зоне
+async fn open_excursion(
+    conn: &mut SqliteConnection,
+    sensor: &TemperatureSensor,
+    kind: &str,
+    at: DateTime<Utc>,
+    temperature: f64,
+) -> ApiResult<TemperatureExcursion> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO temperature_excursions
+           (id, sensor_id, zone_id, kind, status, limit_min, limit_max, started_at,
+            peak_temperature, last_temperature, reading_count, created_at, updated_at)
+           VALUES (?, ?, ?, ?, 'active', ?, ?, ?, ?, ?, 1, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&sensor.id)
+        .bind(&sensor.zone_id)
+        .bind(kind)
+        .bind(sensor.temperature_min)
+        .bind(sensor.temperature_max)
+        .bind(at)
+        .bind(temperature)
+        .bind(temperature)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+
+    let affected = sqlx::query(
+        r#"INSERT INTO temperature_excursion_containers (excursion_id, container_id, batch_id, position_id, cell, quantity)
+           SELECT ?, bc.id, bc.batch_id, bp.position_id, bp.cell, bc.quantity
+           FROM batch_placements bp
+           JOIN batch_containers bc ON bc.id = bp.container_id
+           JOIN storage_positions sp ON sp.id = bp.position_id
+           WHERE sp.zone_id = ? AND bc.status NOT IN ('empty', 'disposed')"#
+    )
+        .bind(&id)
+        .bind(&sensor.zone_id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    sqlx::query("UPDATE temperature_excursions SET affected_container_count = ? WHERE id = ?")
+        .bind(affected as i64)
+        .bind(&id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(sqlx::query_as(&format!("{} WHERE te.id = ?", EXCURSION_SELECT))
+        .bind(&id)
+        .fetch_one(&mut *conn)
+        .await?)
+}
+
+async fn continue_excursion(conn: &mut SqliteConnection, excursion: &TemperatureExcursion, temperature: f64) -> ApiResult<()> {
+    let peak = if excursion.kind == "high" {
+        excursion.peak_temperature.max(temperature)
+    } else {
+        excursion.peak_temperature.min(temperature)
+    };
+    sqlx::query(
+        r#"UPDATE temperature_excursions
+           SET peak_temperature = ?, last_temperature = ?, reading_count = reading_count + 1, updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(peak)
+        .bind(temperature)
+        .bind(Utc::now())
+        .bind(&excursion.id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn close_excursion(conn: &mut SqliteConnection, excursion_id: &str, at: DateTime<Utc>) -> ApiResult<TemperatureExcursion> {
+    sqlx::query("UPDATE temperature_excursions SET status = 'resolved', ended_at = ?, updated_at = ? WHERE id = ?")
+        .bind(at)
+        .bind(Utc::now())
+        .bind(excursion_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(sqlx::query_as(&format!("{} WHERE te.id = ?", EXCURSION_SELECT))
+        .bind(excursion_id)
+        .fetch_one(&mut *conn)
+        .await?)
+}
+
+fn format_limits(excursion: &TemperatureExcursion) -> String {
+    match (excursion.limit_min, excursion.limit_max) {
+        (Some(min), Some(max)) => format!("{}…{} °C", min, max),
+        (Some(min), None) => format!("≥ {} °C", min),
+        (None, Some(max)) => format!("≤ {} °C", max),
+        (None, None) => "no limits".to_string(),
+    }
+}
+
+async fn notify_excursion(pool: &SqlitePool, excursion: &TemperatureExcursion) -> ApiResult<()> {
+    let zone = excursion.zone_name.as_deref().unwrap_or("storage zone");
+    let (stage, severity, title, message) = if excursion.status == "active" {
+        ("open", "critical", format!("Temperature excursion in {}", zone), format!(
+            "{} reads {:.1} °C ({} excursion, allowed {}); {} containers affected",
+            excursion.sensor_name.as_deref().unwrap_or("Sensor"), excursion.last_temperature,
+            excursion.kind, format_limits(excursion), excursion.affected_container_count,
+        ))
+    } else {
+        ("resolved", "info", format!("Temperature back in range in {}", zone), format!(
+            "{} excursion since {} ended; peak {:.1} °C over {} readings",
+            excursion.kind, excursion.started_at.format("%Y-%m-%d %H:%M"),
+            excursion.peak_temperature, excursion.reading_count,
+        ))
+    };
+    notify(pool, &NewNotification {
+        category: "temperature_excursion",
+        severity,
+        title,
+        message,
+        entity_type: Some("temperature_excursion"),
+        entity_id: Some(&excursion.id),
+        dedupe_key: format!("temperature_excursion:{}:{}", excursion.id, stage),
+    }).await?;
+    Ok(())
+}
+
+/// Удаляет корзины старше срока хранения своего разрешения
+pub async fn prune_readings(pool: &SqlitePool) -> ApiResult<u64> {
+    let now = Utc::now();
+    let mut deleted = 0;
+    for (resolution, retention_days) in READING_RESOLUTIONS {
+        deleted += sqlx::query("DELETE FROM temperature_readings WHERE resolution = ? AND bucket_start < ?")
+            .bind(resolution)
+            .bind(bucket_start(now - Duration::days(retention_days), resolution))
+            .execute(pool)
+            .await?
+            .rows_affected();
+    }
+    Ok(deleted)
+}
+
+// ==================== INGESTION ====================
+
+/// POST /api/v1/public/sensors/readings
+pub async fn ingest_readings(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<IngestReadingsRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let sensor = authenticate_sensor(pool, &http_request).await?;
+    body.validate()?;
+
+    let now = Utc::now();
+    let mut readings: Vec<(DateTime<Utc>, f64)> = body.readings.iter()
+        .map(|r| (r.recorded_at.unwrap_or(now), r.temperature))
+        .collect();
+    if readings.iter().any(|(at, _)| *at > now + Duration::minutes(FUTURE_TOLERANCE_MINUTES)) {
+        return Err(ApiError::bad_request("Reading timestamp is in the future"));
+    }
+    readings.sort_by_key(|(at, _)| *at);
+
+    let mut tx = pool.begin().await?;
+    let mut active: Option<TemperatureExcursion> = sqlx::query_as(&format!(
+        "{} WHERE te.sensor_id = ? AND te.status = 'active' ORDER BY te.started_at DESC LIMIT 1", EXCURSION_SELECT
+    ))
+        .bind(&sensor.id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let mut changed: Vec<TemperatureExcursion> = Vec::new();
+    let mut last = sensor.last_reading_at.map(|at| (at, sensor.last_temperature.unwrap_or_default()));
+    for &(at, temperature) in &readings {
+        store_reading(&mut tx, &sensor.id, at, temperature).await?;
+        // Догрузка старых показаний не меняет инциденты
+        if last.is_some_and(|(last_at, _)| at <= last_at) {
+            continue;
+        }
+        last = Some((at, temperature));
+
+        let step = excursion_step(temperature, sensor.temperature_min, sensor.temperature_max,
+            active.as_ref().map(|e| e.kind.as_str()));
+        match step {
+            ExcursionStep::Normal => {}
+            ExcursionStep::Continue => {
+                if let Some(ref mut excursion) = active {
+                    continue_excursion(&mut tx, excursion, temperature).await?;
+                    excursion.peak_temperature = if excursion.kind == "high" {
+                        excursion.peak_temperature.max(temperature)
+                    } else {
+                        excursion.peak_temperature.min(temperature)
+                    };
+                }
+            }
+            ExcursionStep::Close | ExcursionStep::Switch(_) | ExcursionStep::Open(_) => {
+                if let Some(excursion) = active.take() {
+                    changed.push(close_excursion(&mut tx, &excursion.id, at).await?);
+                }
+                if let ExcursionStep::Open(kind) | ExcursionStep::Switch(kind) = step {
+                    let excursion = open_excursion(&mut tx, &sensor, kind, at, temperature).await?;
+                    changed.push(excursion.clone());
+                    active = Some(excursion);
+                }
+            }
+        }
+    }
+
+    if let Some((at, temperature)) = last {
+        sqlx::query("UPDATE temperature_sensors SET last_reading_at = ?, last_temperature = ? WHERE id = ?")
+            .bind(at)
+            .bind(temperature)
+            .bind(&sensor.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    let opened = changed.iter().filter(|e| e.status == "active").count();
+    let resolved = changed.len() - opened;
+    for excursion in &changed {
+        if excursion.status == "active" {
+            warn!("🌡️ Temperature excursion ({}) at sensor '{}': {:.1} °C, {} containers affected",
+                excursion.kind, sensor.name, excursion.last_temperature, excursion.affected_container_count);
+        }
+        if let Err(e) = notify_excursion(pool, excursion).await {
+            log::error!("Failed to notify about temperature excursion {}: {}", excursion.id, e);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "sensor_id": sensor.id,
+        "accepted": readings.len(),
+        "excursions_opened": opened,
+        "excursions_resolved": resolved,
+        "active_excursion_id": active.map(|e| e.id),
+    }))))
+}
+
+// ==================== SENSORS ====================
+
+pub async fn list_sensors(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<SensorListQuery>,
+) -> ApiResult<HttpResponse> {
+    let sensors: Vec<TemperatureSensor> = sqlx::query_as(&format!(
+        "{} WHERE (?1 IS NULL OR ts.zone_id = ?1) ORDER BY sz.name, ts.name", SENSOR_SELECT
+    ))
+        .bind(&query.zone_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sensors)))
+}
+
+pub async fn get_sensor(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let sensor = get_sensor_or_404(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sensor)))
+}
+
+pub async fn create_sensor(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateSensorRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = require_admin(&http_request)?;
+    body.validate()?;
+    let pool = &app_state.db_pool;
+    ensure_zone_exists(pool, &body.zone_id).await?;
+
+    let id = Uuid::new_v4().to_string();
+    let api_key = generate_api_key();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO temperature_sensors
+           (id, name, zone_id, description, api_key_prefix, api_key_hash, is_active, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, 1, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(body.name.trim())
+        .bind(&body.zone_id)
+        .bind(&body.description)
+        .bind(key_prefix(&api_key))
+        .bind(hash_api_key(&api_key))
+        .bind(&user_id)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &user_id, "create", "temperature_sensor", &id,
+        &format!("Temperature sensor '{}' registered", body.name.trim()), &http_request).await;
+
+    let sensor = get_sensor_or_404(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(SensorWithKey { sensor, api_key })))
+}
+
+pub async fn update_sensor(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<UpdateSensorRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = require_admin(&http_request)?;
+    body.validate()?;
+    let pool = &app_state.db_pool;
+    let sensor = get_sensor_or_404(pool, &path.into_inner()).await?;
+    if let Some(ref zone_id) = body.zone_id {
+        ensure_zone_exists(pool, zone_id).await?;
+    }
+
+    sqlx::query(
+        r#"UPDATE temperature_sensors
+           SET name = COALESCE(?, name), zone_id = COALESCE(?, zone_id),
+               description = COALESCE(?, description), is_active = COALESCE(?, is_active), updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(body.name.as_deref().map(str::trim))
+        .bind(&body.zone_id)
+        .bind(&body.description)
+        .bind(body.is_active)
+        .bind(Utc::now())
+        .bind(&sensor.id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &user_id, "update", "temperature_sensor", &sensor.id,
+        &format!("Temperature sensor '{}' updated", sensor.name), &http_request).await;
+
+    let sensor = get_sensor_or_404(pool, &sensor.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sensor)))
+}
+
+/// Новый ключ; старый перестаёт работать сразу
+pub async fn rotate_sensor_key(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = require_admin(&http_request)?;
+    let pool = &app_state.db_pool;
+    let sensor = get_sensor_or_404(pool, &path.into_inner()).await?;
+
+    let api_key = generate_api_key();
+    sqlx::query("UPDATE temperature_sensors SET api_key_prefix = ?, api_key_hash = ?, updated_at = ? WHERE id = ?")
+        .bind(key_prefix(&api_key))
+        .bind(hash_api_key(&api_key))
+        .bind(Utc::now())
+        .bind(&sensor.id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &user_id, "update", "temperature_sensor", &sensor.id,
+        &format!("API key of temperature sensor '{}' rotated", sensor.name), &http_request).await;
+    info!("🔑 API key of temperature sensor '{}' rotated", sensor.name);
+
+    let sensor = get_sensor_or_404(pool, &sensor.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(SensorWithKey { sensor, api_key })))
+}
+
+/// История для графика: корзины min/avg/max, границы зоны и инциденты в интервале
+pub async fn get_sensor_readings(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ReadingsQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let sensor = get_sensor_or_404(pool, &path.into_inner()).await?;
+
+    let now = Utc::now();
+    let to = query.to.unwrap_or(now);
+    let from = query.from.unwrap_or(to - Duration::hours(DEFAULT_CHART_HOURS));
+    if from >= to {
+        return Err(ApiError::bad_request("'from' must be earlier than 'to'"));
+    }
+    let resolution = match query.resolution {
+        Some(r) if READING_RESOLUTIONS.iter().any(|(res, _)| *res == r) => {
+            if (to - from).num_seconds() / r > MAX_CHART_POINTS {
+                return Err(ApiError::bad_request("Too many points for this resolution; narrow the interval"));
+            }
+            r
+        }
+        Some(_) => return Err(ApiError::bad_request("resolution must be 300 or 3600")),
+        None => chart_resolution(from, to, now),
+    };
+
+    let points: Vec<ReadingPoint> = sqlx::query_as(
+        r#"SELECT bucket_start, min_value, max_value, sum_value / sample_count AS avg_value, sample_count
+           FROM temperature_readings
+           WHERE sensor_id = ? AND resolution = ? AND bucket_start >= ? AND bucket_start <= ?
+           ORDER BY bucket_start"#
+    )
+        .bind(&sensor.id)
+        .bind(resolution)
+        .bind(bucket_start(from, resolution))
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+
+    let excursions: Vec<TemperatureExcursion> = sqlx::query_as(&format!(
+        "{} WHERE te.sensor_id = ? AND te.started_at <= ? AND (te.ended_at IS NULL OR te.ended_at >= ?) ORDER BY te.started_at",
+        EXCURSION_SELECT,
+    ))
+        .bind(&sensor.id)
+        .bind(to)
+        .bind(from)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "sensor_id": sensor.id,
+        "zone_id": sensor.zone_id,
+        "temperature_min": sensor.temperature_min,
+        "temperature_max": sensor.temperature_max,
+        "from": from,
+        "to": to,
+        "resolution": resolution,
+        "points": points,
+        "excursions": excursions,
+    }))))
+}
+
+// ==================== EXCURSIONS ====================
+
+pub async fn list_excursions(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ExcursionQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(ref status) = query.status {
+        if !matches!(status.as_str(), "active" | "resolved") {
+            return Err(ApiError::bad_request("status must be 'active' or 'resolved'"));
+        }
+    }
+    let excursions: Vec<TemperatureExcursion> = sqlx::query_as(&format!(
+        r#"{} WHERE (?1 IS NULL OR te.status = ?1) AND (?2 IS NULL OR te.zone_id = ?2) AND (?3 IS NULL OR te.sensor_id = ?3)
+           ORDER BY te.status = 'resolved', te.started_at DESC
+           LIMIT 500"#,
+        EXCURSION_SELECT,
+    ))
+        .bind(&query.status)
+        .bind(&query.zone_id)
+        .bind(&query.sensor_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(excursions)))
+}
+
+pub async fn get_excursion(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let excursion = get_excursion_or_404(pool, &path.into_inner()).await?;
+    let containers: Vec<ExcursionContainer> = sqlx::query_as(
+        r#"SELECT tc.container_id, bc.short_code, bc.sequence_number, tc.batch_id, b.batch_number,
+                  r.name AS reagent_name, tc.position_id, sp.name AS position_name, tc.cell, tc.quantity, b.unit
+           FROM temperature_excursion_containers tc
+           LEFT JOIN batch_containers bc ON bc.id = tc.container_id
+           LEFT JOIN batches b ON b.id = tc.batch_id
+           LEFT JOIN reagents r ON r.id = b.reagent_id
+           LEFT JOIN storage_positions sp ON sp.id = tc.position_id
+           WHERE tc.excursion_id = ?
+           ORDER BY sp.name, tc.cell, r.name, bc.sequence_number"#
+    )
+        .bind(&excursion.id)
+        .fetch_all(pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "excursion": excursion,
+        "containers": containers,
+    }))))
+}
+
+pub async fn acknowledge_excursion(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AcknowledgeExcursionRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let excursion = get_excursion_or_404(pool, &path.into_inner()).await?;
+    if excursion.acknowledged_at.is_some() {
+        return Err(ApiError::bad_request("Excursion is already acknowledged"));
+    }
+
+    let now = Utc::now();
+    sqlx::query(
+        "UPDATE temperature_excursions SET acknowledged_by = ?, acknowledged_at = ?, notes = ?, updated_at = ? WHERE id = ?"
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&body.notes)
+        .bind(now)
+        .bind(&excursion.id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &claims.sub, "update", "temperature_excursion", &excursion.id,
+        &format!("Temperature excursion in '{}' acknowledged", excursion.zone_name.as_deref().unwrap_or(&excursion.zone_id)),
+        &http_request).await;
+
+    let excursion = get_excursion_or_404(pool, &excursion.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(excursion)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Симулированный логгер холодильника (2…8 °C): дверь открыта, затем разморозка
+    #[test]
+    fn test_simulated_fridge_excursions() {
+        let trace = [4.0, 5.1, 8.4, 9.2, 7.9, 4.5, 1.5, 2.5, 9.0, 0.5];
+        let mut active: Option<&str> = None;
+        let mut steps = Vec::new();
+        for t in trace {
+            let step = excursion_step(t, Some(2.0), Some(8.0), active);
+            active = match step {
+                ExcursionStep::Open(kind) | ExcursionStep::Switch(kind) => Some(kind),
+                ExcursionStep::Close => None,
+                ExcursionStep::Normal | ExcursionStep::Continue => active,
+            };
+            steps.push(step);
+        }
+        use ExcursionStep::*;
+        assert_eq!(steps, vec![
+            Normal, Normal, Open("high"), Continue, Close, Normal, Open("low"), Close, Open("high"), Switch("low"),
+        ]);
+        // Без верхней границы нагрев не считается отклонением
+        assert_eq!(excursion_step(30.0, Some(15.0), None, None), Normal);
+    }
+
+    #[test]
+    fn test_buckets_and_chart_resolution() {
+        let at = DateTime::parse_from_rfc3339("2025-03-10T08:47:31Z").unwrap().with_timezone(&Utc);
+        assert_eq!(bucket_start(at, 300).to_rfc3339(), "2025-03-10T08:45:00+00:00");
+        assert_eq!(bucket_start(at, 3600).to_rfc3339(), "2025-03-10T08:00:00+00:00");
+
+        let now = at;
+        assert_eq!(chart_resolution(now - Duration::hours(24), now, now), 300);
+        // 5-минутные корзины хранятся 30 дней
+        assert_eq!(chart_resolution(now - Duration::days(45), now - Duration::days(44), now), 3600);
+        // Слишком много точек для 5 минут
+        assert_eq!(chart_resolution(now - Duration::days(20), now, now), 3600);
+    }
+}
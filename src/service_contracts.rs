@@ -0,0 +1,335 @@
+// src/service_contracts.rs
+//! Гарантия и сервисные договоры оборудования.
+//!
+//! Документы договора — обычные файлы оборудования с `contract_id`
+//! (загрузка через `POST /equipment/{id}/files` с полем `contract_id`).
+//! За `alert_days` дней до окончания договора (для гарантии — за
+//! `WARRANTY_ALERT_DAYS`) создаётся уведомление и растёт счётчик на дашборде.
+//! Договор, за которым сразу следует продление, не считается истекающим.
+//!
+//! Endpoints:
+//!   GET    /api/v1/equipment/coverage/expiring?days=
+//!   GET    /api/v1/equipment/{id}/contracts
+//!   POST   /api/v1/equipment/{id}/contracts
+//!   PUT    /api/v1/equipment/{id}/contracts/{contract_id}
+//!   DELETE /api/v1/equipment/{id}/contracts/{contract_id}
+//!   GET    /api/v1/equipment/{id}/contracts/{contract_id}/files
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    CreateServiceContractRequest, EquipmentFile, ExpiringCoverage, ExpiringCoverageQuery,
+    ServiceContract, UpdateServiceContractRequest,
+};
+use crate::notifications::{notify, NewNotification};
+
+pub const WARRANTY_ALERT_DAYS: i64 = 30;
+/// Сколько дней после окончания покрытие ещё показывается как «истёкшее»
+const EXPIRED_LOOKBACK_DAYS: i64 = 30;
+
+/// Параметры: горизонт (NULL — alert_days записи), нижняя граница days_left
+const EXPIRING_COVERAGE_QUERY: &str = r#"
+    SELECT * FROM (
+        SELECT 'contract' as kind, c.equipment_id, e.name as equipment_name, c.id as contract_id,
+               c.vendor, c.contract_number, c.end_date,
+               CAST(julianday(c.end_date) - julianday(date('now')) AS INTEGER) as days_left,
+               c.alert_days
+        FROM service_contracts c
+        JOIN equipment e ON c.equipment_id = e.id
+        WHERE e.status != 'retired'
+          AND NOT EXISTS (
+              SELECT 1 FROM service_contracts n
+              WHERE n.equipment_id = c.equipment_id AND n.id != c.id
+                AND n.end_date > c.end_date AND n.start_date <= date(c.end_date, '+1 day')
+          )
+        UNION ALL
+        SELECT 'warranty', e.id, e.name, NULL, e.manufacturer, NULL, e.warranty_until,
+               CAST(julianday(e.warranty_until) - julianday(date('now')) AS INTEGER),
+               ?
+        FROM equipment e
+        WHERE e.warranty_until IS NOT NULL AND e.status != 'retired'
+    )
+    WHERE days_left <= COALESCE(?, alert_days) AND days_left >= ?
+    ORDER BY days_left ASC, equipment_name ASC
+"#;
+
+pub async fn load_expiring_coverage(pool: &SqlitePool, days: Option<i64>) -> ApiResult<Vec<ExpiringCoverage>> {
+    Ok(sqlx::query_as(EXPIRING_COVERAGE_QUERY)
+        .bind(WARRANTY_ALERT_DAYS)
+        .bind(days)
+        .bind(-EXPIRED_LOOKBACK_DAYS)
+        .fetch_all(pool)
+        .await?)
+}
+
+fn coverage_label(item: &ExpiringCoverage) -> String {
+    match item.kind.as_str() {
+        "contract" => format!(
+            "Service contract {} ({})",
+            item.contract_number.as_deref().unwrap_or(""),
+            item.vendor.as_deref().unwrap_or("unknown vendor")
+        ),
+        _ => "Warranty".to_string(),
+    }
+}
+
+/// Стадия уведомления и её важность
+fn expiry_stage(days_left: i64) -> (&'static str, &'static str) {
+    if days_left < 0 { ("expired", "critical") } else { ("upcoming", "warning") }
+}
+
+/// Уведомления об истекающих и истёкших гарантиях/договорах.
+/// Ключ включает дату окончания, поэтому продление заново «взводит» уведомление.
+pub async fn generate_expiry_notifications(pool: &SqlitePool) -> ApiResult<u64> {
+    let mut created = 0u64;
+    for item in load_expiring_coverage(pool, None).await? {
+        let (stage, severity) = expiry_stage(item.days_left);
+        let expired = stage == "expired";
+        let subject = coverage_label(&item);
+        let message = if expired {
+            format!("{} for '{}' expired on {}", subject, item.equipment_name, item.end_date)
+        } else {
+            format!("{} for '{}' expires on {} (in {} days)", subject, item.equipment_name, item.end_date, item.days_left)
+        };
+        let (entity_type, entity_id) = match item.contract_id {
+            Some(ref id) => ("service_contract", id.as_str()),
+            None => ("equipment", item.equipment_id.as_str()),
+        };
+
+        let inserted = notify(pool, &NewNotification {
+            category: if item.kind == "contract" { "contract_expiry" } else { "warranty_expiry" },
+            severity,
+            title: format!("{} {}", subject, if expired { "expired" } else { "expiring" }),
+            message,
+            entity_type: Some(entity_type),
+            entity_id: Some(entity_id),
+            dedupe_key: format!("{}:{}:{}:{}", item.kind, entity_id, item.end_date, stage),
+        }).await?;
+        if inserted {
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+async fn check_equipment_exists(pool: &SqlitePool, equipment_id: &str) -> ApiResult<()> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)")
+        .bind(equipment_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Equipment"));
+    }
+    Ok(())
+}
+
+async fn get_contract_or_404(pool: &SqlitePool, equipment_id: &str, contract_id: &str) -> ApiResult<ServiceContract> {
+    sqlx::query_as("SELECT * FROM service_contracts WHERE id = ? AND equipment_id = ?")
+        .bind(contract_id)
+        .bind(equipment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Service contract"))
+}
+
+fn map_unique_violation(e: sqlx::Error) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.message().contains("UNIQUE") => {
+            ApiError::bad_request("A contract with this vendor and number already exists for this equipment")
+        }
+        other => other.into(),
+    }
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_expiring_coverage(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ExpiringCoverageQuery>,
+) -> ApiResult<HttpResponse> {
+    let days = query.days.map(|d| d.clamp(0, 3650));
+    let items = load_expiring_coverage(&app_state.db_pool, days).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
+}
+
+pub async fn get_service_contracts(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+
+    let contracts: Vec<ServiceContract> = sqlx::query_as(
+        "SELECT * FROM service_contracts WHERE equipment_id = ? ORDER BY end_date DESC"
+    )
+        .bind(&equipment_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(contracts)))
+}
+
+pub async fn create_service_contract(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CreateServiceContractRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let equipment_id = path.into_inner();
+    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+
+    if body.end_date < body.start_date {
+        return Err(ApiError::bad_request("Contract end date must not be earlier than its start date"));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO service_contracts
+           (id, equipment_id, vendor, contract_number, coverage, start_date, end_date, cost,
+            contact, alert_days, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&equipment_id)
+        .bind(body.vendor.trim())
+        .bind(body.contract_number.trim())
+        .bind(&body.coverage)
+        .bind(body.start_date)
+        .bind(body.end_date)
+        .bind(body.cost)
+        .bind(&body.contact)
+        .bind(body.alert_days.unwrap_or(WARRANTY_ALERT_DAYS))
+        .bind(&body.notes)
+        .bind(&user_id)
+        .bind(now)
+        .bind(now)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+    let contract = get_contract_or_404(&app_state.db_pool, &equipment_id, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(contract)))
+}
+
+pub async fn update_service_contract(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<UpdateServiceContractRequest>,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let (equipment_id, contract_id) = path.into_inner();
+    let existing = get_contract_or_404(&app_state.db_pool, &equipment_id, &contract_id).await?;
+
+    let start = body.start_date.unwrap_or(existing.start_date);
+    let end = body.end_date.unwrap_or(existing.end_date);
+    if end < start {
+        return Err(ApiError::bad_request("Contract end date must not be earlier than its start date"));
+    }
+
+    sqlx::query(
+        r#"UPDATE service_contracts SET
+               vendor = COALESCE(?, vendor),
+               contract_number = COALESCE(?, contract_number),
+               coverage = COALESCE(?, coverage),
+               start_date = ?,
+               end_date = ?,
+               cost = COALESCE(?, cost),
+               contact = COALESCE(?, contact),
+               alert_days = COALESCE(?, alert_days),
+               notes = COALESCE(?, notes),
+               updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(body.vendor.as_deref().map(str::trim))
+        .bind(body.contract_number.as_deref().map(str::trim))
+        .bind(&body.coverage)
+        .bind(start)
+        .bind(end)
+        .bind(body.cost)
+        .bind(&body.contact)
+        .bind(body.alert_days)
+        .bind(&body.notes)
+        .bind(Utc::now())
+        .bind(&contract_id)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+    let contract = get_contract_or_404(&app_state.db_pool, &equipment_id, &contract_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(contract)))
+}
+
+/// Удаление договора; его документы остаются в файлах оборудования
+pub async fn delete_service_contract(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, contract_id) = path.into_inner();
+    get_contract_or_404(&app_state.db_pool, &equipment_id, &contract_id).await?;
+
+    sqlx::query("DELETE FROM service_contracts WHERE id = ?")
+        .bind(&contract_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Service contract deleted successfully".to_string(),
+    )))
+}
+
+pub async fn get_contract_files(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, contract_id) = path.into_inner();
+    get_contract_or_404(&app_state.db_pool, &equipment_id, &contract_id).await?;
+
+    let files: Vec<EquipmentFile> = sqlx::query_as(
+        "SELECT * FROM equipment_files WHERE equipment_id = ? AND contract_id = ? ORDER BY created_at DESC"
+    )
+        .bind(&equipment_id)
+        .bind(&contract_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(files)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage(kind: &str, days_left: i64) -> ExpiringCoverage {
+        ExpiringCoverage {
+            kind: kind.to_string(),
+            equipment_id: "eq-1".to_string(),
+            equipment_name: "HPLC".to_string(),
+            contract_id: (kind == "contract").then(|| "c-1".to_string()),
+            vendor: Some("Agilent".to_string()),
+            contract_number: (kind == "contract").then(|| "SC-42".to_string()),
+            end_date: crate::models::IsoDate::parse("2025-06-30").unwrap(),
+            days_left,
+        }
+    }
+
+    #[test]
+    fn test_expiry_stage_and_label() {
+        assert_eq!(expiry_stage(10), ("upcoming", "warning"));
+        assert_eq!(expiry_stage(0), ("upcoming", "warning"));
+        assert_eq!(expiry_stage(-1), ("expired", "critical"));
+
+        assert_eq!(coverage_label(&coverage("contract", 5)), "Service contract SC-42 (Agilent)");
+        assert_eq!(coverage_label(&coverage("warranty", 5)), "Warranty");
+    }
+}
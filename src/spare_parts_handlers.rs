@@ -0,0 +1,238 @@
+// src/spare_parts_handlers.rs
+//! Склад запасных частей оборудования.
+//!
+//! Запчасти списываются записями обслуживания (`parts_used`), каждое
+//! списание пишется в журнал `equipment_part_usage`. Остаток ниже
+//! `min_quantity` попадает в отчёт о дефиците и на дашборд, а
+//! `next_replacement` — в напоминания о плановой замене.
+//!
+//! Endpoints:
+//!   GET /api/v1/equipment/parts/low-stock
+//!   GET /api/v1/equipment/parts/replacement-due?days=30
+//!   GET /api/v1/equipment/{id}/parts/usage?part_id=&limit=
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::maintenance_handlers::parse_day;
+use crate::models::{
+    EquipmentPart, EquipmentPartUsage, LowStockPart, PartReplacementDue, PartReplacementQuery,
+    PartUsageInput, PartUsageQuery,
+};
+
+/// Условие дефицита для `equipment_parts p` (min_quantity = 0 — не отслеживается)
+pub const LOW_STOCK_CONDITION: &str = "p.min_quantity > 0 AND p.quantity <= p.min_quantity";
+
+const DEFAULT_REPLACEMENT_HORIZON_DAYS: i32 = 30;
+const DEFAULT_USAGE_LIMIT: i64 = 100;
+
+/// Списать запчасти в рамках записи обслуживания.
+/// Возвращает краткую сводку для `parts_replaced` ("Фильтр ×2, Лампа ×1").
+pub async fn consume_parts(
+    conn: &mut SqliteConnection,
+    equipment_id: &str,
+    maintenance_id: Option<&str>,
+    parts: &[PartUsageInput],
+    used_date: &str,
+    user_id: &str,
+) -> ApiResult<Option<String>> {
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    let day = parse_day(used_date)?;
+    let now = Utc::now();
+    let mut consumed = Vec::with_capacity(parts.len());
+
+    for usage in parts {
+        let part: EquipmentPart = sqlx::query_as(
+            "SELECT * FROM equipment_parts WHERE id = ? AND equipment_id = ?"
+        )
+            .bind(&usage.part_id)
+            .bind(equipment_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Equipment part"))?;
+
+        if part.quantity < usage.quantity {
+            return Err(ApiError::bad_request(&format!(
+                "Insufficient stock of part '{}'. Available: {}, Requested: {}",
+                part.name, part.quantity, usage.quantity
+            )));
+        }
+
+        let next_replacement = part.replacement_interval_days
+            .map(|days| (day + Duration::days(days as i64)).format("%Y-%m-%d").to_string());
+        let quantity_after = part.quantity - usage.quantity;
+
+        sqlx::query(
+            r#"UPDATE equipment_parts SET
+                   quantity = ?,
+                   last_replaced = ?,
+                   next_replacement = COALESCE(?, next_replacement),
+                   status = CASE WHEN status IN ('needs_attention', 'needs_replacement', 'replaced')
+                                 THEN 'good' ELSE status END,
+                   updated_at = ?
+               WHERE id = ?"#
+        )
+            .bind(quantity_after)
+            .bind(used_date)
+            .bind(&next_replacement)
+            .bind(now)
+            .bind(&part.id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query(
+            r#"INSERT INTO equipment_part_usage
+               (id, part_id, equipment_id, maintenance_id, quantity, quantity_after, used_date, used_by, created_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&part.id)
+            .bind(equipment_id)
+            .bind(maintenance_id)
+            .bind(usage.quantity)
+            .bind(quantity_after)
+            .bind(used_date)
+            .bind(user_id)
+            .bind(now)
+            .execute(&mut *conn)
+            .await?;
+
+        if part.min_quantity > 0 && quantity_after <= part.min_quantity {
+            log::warn!(
+                "Spare part '{}' of equipment {} is low on stock: {} left (min {})",
+                part.name, equipment_id, quantity_after, part.min_quantity
+            );
+        }
+        consumed.push((part.name, usage.quantity));
+    }
+
+    Ok(Some(usage_summary(&consumed)))
+}
+
+fn usage_summary(consumed: &[(String, i32)]) -> String {
+    consumed.iter()
+        .map(|(name, qty)| format!("{} ×{}", name, qty))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Перевести запчасти с наступившим сроком замены в 'needs_replacement'
+pub async fn apply_part_replacement_statuses(pool: &SqlitePool) -> ApiResult<u64> {
+    let result = sqlx::query(
+        r#"UPDATE equipment_parts
+           SET status = 'needs_replacement', updated_at = ?
+           WHERE next_replacement IS NOT NULL
+             AND date(next_replacement) <= date('now')
+             AND status IN ('good', 'needs_attention')"#
+    )
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// GET /equipment/parts/low-stock
+pub async fn get_low_stock_parts(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let sql = format!(r#"
+        SELECT p.id, p.name, p.part_number, p.manufacturer, p.equipment_id, e.name as equipment_name,
+               p.quantity, p.min_quantity, (p.min_quantity - p.quantity) as shortfall
+        FROM equipment_parts p
+        JOIN equipment e ON p.equipment_id = e.id
+        WHERE e.status != 'retired' AND {}
+        ORDER BY shortfall DESC, p.name ASC
+    "#, LOW_STOCK_CONDITION);
+
+    let parts: Vec<LowStockPart> = sqlx::query_as(&sql)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(parts)))
+}
+
+/// GET /equipment/parts/replacement-due?days= — просроченные и ближайшие замены
+pub async fn get_parts_replacement_due(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<PartReplacementQuery>,
+) -> ApiResult<HttpResponse> {
+    let days = query.days.unwrap_or(DEFAULT_REPLACEMENT_HORIZON_DAYS).clamp(0, 365);
+
+    let parts: Vec<PartReplacementDue> = sqlx::query_as(r#"
+        SELECT p.id, p.name, p.part_number, p.equipment_id, e.name as equipment_name,
+               p.status, p.quantity, p.last_replaced, p.next_replacement,
+               CAST(julianday(date(p.next_replacement)) - julianday(date('now')) AS INTEGER) as days_until_due,
+               date(p.next_replacement) < date('now') as is_overdue
+        FROM equipment_parts p
+        JOIN equipment e ON p.equipment_id = e.id
+        WHERE e.status != 'retired'
+          AND p.next_replacement IS NOT NULL
+          AND p.status NOT IN ('replaced', 'missing')
+          AND date(p.next_replacement) <= date('now', '+' || ? || ' days')
+        ORDER BY date(p.next_replacement) ASC, p.name ASC
+    "#)
+        .bind(days)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(parts)))
+}
+
+/// GET /equipment/{id}/parts/usage — журнал списаний, новые сверху
+pub async fn get_part_usage_history(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<PartUsageQuery>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)")
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Equipment"));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_USAGE_LIMIT).clamp(1, 1000);
+    let usage: Vec<EquipmentPartUsage> = sqlx::query_as(r#"
+        SELECT u.id, u.part_id, p.name as part_name, p.part_number, u.equipment_id,
+               u.maintenance_id, m.maintenance_type, u.quantity, u.quantity_after,
+               u.used_date, u.used_by, u.created_at
+        FROM equipment_part_usage u
+        JOIN equipment_parts p ON u.part_id = p.id
+        LEFT JOIN equipment_maintenance m ON u.maintenance_id = m.id
+        WHERE u.equipment_id = ? AND (? IS NULL OR u.part_id = ?)
+        ORDER BY u.used_date DESC, u.created_at DESC
+        LIMIT ?
+    "#)
+        .bind(&equipment_id)
+        .bind(&query.part_id)
+        .bind(&query.part_id)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(usage)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_summary() {
+        assert_eq!(
+            usage_summary(&[("HEPA filter".to_string(), 2), ("UV lamp".to_string(), 1)]),
+            "HEPA filter ×2, UV lamp ×1"
+        );
+        assert_eq!(usage_summary(&[]), "");
+    }
+}
@@ -0,0 +1,1026 @@
+// src/stocktake.rs
+//! Инвентаризация (stocktake / cycle count) по комнате или зоне.
+//!
+//! Сессия проходит статусы `counting` → `review` → `approved` (или `cancelled`).
+//! Во время пересчёта сотрудники отмечают каждый найденный контейнер (скан этикетки
+//! или вручную) с позицией и фактическим количеством. Расхождения вычисляются
+//! по текущему состоянию системы:
+//! - `missing` — числится в комнате/зоне, но не найден;
+//! - `unexpected` — найден, но числится в другом месте, не размещён или утилизирован;
+//! - `wrong_position` — найден в области пересчёта, но не на своей позиции/ячейке;
+//! - `quantity` — фактическое количество отличается от учётного.
+//!
+//! Утверждение (только администратор) применяет корректировки в одной транзакции:
+//! недостача списывается через `usage_logs`, перемещения пишутся в `container_movements`,
+//! каждое действие фиксируется в `stocktake_adjustments` с причиной утверждения.
+//!
+//! Endpoints:
+//!   GET    /api/v1/stocktakes?status=&room_id=
+//!   POST   /api/v1/stocktakes
+//!   GET    /api/v1/stocktakes/{id}
+//!   GET    /api/v1/stocktakes/{id}/counts
+//!   POST   /api/v1/stocktakes/{id}/counts              — отметить контейнер (повторно — перезаписать)
+//!   DELETE /api/v1/stocktakes/{id}/counts/{count_id}
+//!   GET    /api/v1/stocktakes/{id}/variances
+//!   POST   /api/v1/stocktakes/{id}/submit              — закончить пересчёт
+//!   POST   /api/v1/stocktakes/{id}/reopen              — вернуть на пересчёт
+//!   POST   /api/v1/stocktakes/{id}/approve             — применить корректировки
+//!   POST   /api/v1/stocktakes/{id}/cancel
+//!   GET    /api/v1/stocktakes/{id}/adjustments
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::auth::{get_current_user, UserRole};
+use crate::barcodes::{self, ScanEntity};
+use crate::container_handlers::{compute_container_status, current_slot, record_movement, reserve_slot, Slot};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::storage_handlers::{grid_cell_label, parse_grid_cell, refresh_position_occupancy};
+
+/// Расхождение количества меньше этого порога не считается
+const QUANTITY_TOLERANCE: f64 = 0.001;
+
+const STOCKTAKE_STATUSES: [&str; 4] = ["counting", "review", "approved", "cancelled"];
+
+// ==================== MODELS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct StocktakeSession {
+    pub id: String,
+    pub name: String,
+    pub room_id: String,
+    pub zone_id: Option<String>,
+    pub status: String,
+    pub notes: Option<String>,
+    pub started_by: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub submitted_by: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub approval_reason: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct StocktakeCount {
+    pub id: String,
+    pub session_id: String,
+    pub container_id: String,
+    pub position_id: Option<String>,
+    #[sqlx(default)]
+    pub position_name: Option<String>,
+    pub cell: Option<String>,
+    pub observed_quantity: Option<f64>,
+    pub method: String,
+    pub notes: Option<String>,
+    pub counted_by: Option<String>,
+    pub counted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StocktakeAdjustment {
+    pub id: String,
+    pub session_id: String,
+    pub container_id: String,
+    pub variance_type: String,
+    pub action: String,
+    pub from_position_id: Option<String>,
+    pub to_position_id: Option<String>,
+    pub system_quantity: Option<f64>,
+    pub observed_quantity: Option<f64>,
+    pub usage_log_id: Option<String>,
+    pub reason: String,
+    pub applied_by: Option<String>,
+    pub applied_at: DateTime<Utc>,
+}
+
+/// Учётное состояние контейнера: всё, что числится в области пересчёта, плюс найденные
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SystemContainer {
+    pub container_id: String,
+    pub short_code: Option<String>,
+    pub sequence_number: i64,
+    pub batch_id: String,
+    pub batch_number: String,
+    pub reagent_name: String,
+    pub unit: String,
+    pub quantity: f64,
+    pub status: String,
+    pub position_id: Option<String>,
+    pub position_name: Option<String>,
+    pub cell: Option<String>,
+    /// Размещён в комнате/зоне сессии и не утилизирован
+    pub in_scope: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarianceType {
+    Missing,
+    Unexpected,
+    WrongPosition,
+    Quantity,
+}
+
+impl VarianceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VarianceType::Missing => "missing",
+            VarianceType::Unexpected => "unexpected",
+            VarianceType::WrongPosition => "wrong_position",
+            VarianceType::Quantity => "quantity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Variance {
+    pub variance_type: VarianceType,
+    pub container_id: String,
+    pub short_code: Option<String>,
+    pub reagent_name: String,
+    pub batch_number: String,
+    pub sequence_number: i64,
+    pub unit: String,
+    pub container_status: String,
+    pub system_position_id: Option<String>,
+    pub system_position_name: Option<String>,
+    pub system_cell: Option<String>,
+    pub observed_position_id: Option<String>,
+    pub observed_position_name: Option<String>,
+    pub observed_cell: Option<String>,
+    pub system_quantity: f64,
+    pub observed_quantity: Option<f64>,
+    /// Факт − учёт
+    pub difference: Option<f64>,
+    /// Можно применить при утверждении; иначе нужна ручная обработка
+    pub adjustable: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct VarianceSummary {
+    pub expected: usize,
+    pub counted: usize,
+    pub missing: usize,
+    pub unexpected: usize,
+    pub wrong_position: usize,
+    pub quantity: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VarianceReport {
+    pub session: StocktakeSession,
+    pub summary: VarianceSummary,
+    pub variances: Vec<Variance>,
+}
+
+// ==================== REQUESTS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct StocktakeListQuery {
+    pub status: Option<String>,
+    pub room_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateStocktakeRequest {
+    #[validate(length(min = 1, max = 200, message = "Name must be 1-200 characters"))]
+    pub name: String,
+    pub room_id: String,
+    /// Не задана — вся комната
+    pub zone_id: Option<String>,
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RecordCountRequest {
+    /// Короткий код с этикетки (C000123) или id контейнера
+    pub container: String,
+    /// Где найден: id позиции или её короткий код (P000007)
+    pub position: Option<String>,
+    pub cell: Option<String>,
+    #[validate(range(min = 0.0, message = "Observed quantity cannot be negative"))]
+    pub observed_quantity: Option<f64>,
+    /// scan | manual
+    pub method: Option<String>,
+    #[validate(length(max = 500, message = "Notes max 500 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ApproveStocktakeRequest {
+    #[validate(length(min = 1, max = 500, message = "Reason must be 1-500 characters"))]
+    pub reason: String,
+    /// Списать остаток не найденных контейнеров (по умолчанию — только снять с позиции)
+    #[serde(default)]
+    pub write_off_missing: bool,
+    /// Контейнеры, расхождения по которым не применять
+    #[serde(default)]
+    pub skip_container_ids: Vec<String>,
+}
+
+// ==================== VARIANCES ====================
+
+fn variance(kind: VarianceType, system: &SystemContainer, count: Option<&StocktakeCount>) -> Variance {
+    let observed_quantity = count.and_then(|c| c.observed_quantity);
+    Variance {
+        variance_type: kind,
+        container_id: system.container_id.clone(),
+        short_code: system.short_code.clone(),
+        reagent_name: system.reagent_name.clone(),
+        batch_number: system.batch_number.clone(),
+        sequence_number: system.sequence_number,
+        unit: system.unit.clone(),
+        container_status: system.status.clone(),
+        system_position_id: system.position_id.clone(),
+        system_position_name: system.position_name.clone(),
+        system_cell: system.cell.clone(),
+        observed_position_id: count.and_then(|c| c.position_id.clone()),
+        observed_position_name: count.and_then(|c| c.position_name.clone()),
+        observed_cell: count.and_then(|c| c.cell.clone()),
+        system_quantity: system.quantity,
+        observed_quantity,
+        difference: observed_quantity.map(|q| q - system.quantity),
+        adjustable: system.status != "disposed"
+            && (kind == VarianceType::Missing || kind == VarianceType::Quantity
+                || count.and_then(|c| c.position_id.as_ref()).is_some()),
+    }
+}
+
+/// Сверка пересчёта с учётом
+pub fn compute_variances(system: &[SystemContainer], counts: &[StocktakeCount]) -> (VarianceSummary, Vec<Variance>) {
+    let counted: HashMap<&str, &StocktakeCount> = counts.iter().map(|c| (c.container_id.as_str(), c)).collect();
+    let mut summary = VarianceSummary {
+        expected: system.iter().filter(|s| s.in_scope).count(),
+        counted: counts.len(),
+        ..Default::default()
+    };
+    let mut variances = Vec::new();
+
+    for s in system.iter().filter(|s| s.in_scope && !counted.contains_key(s.container_id.as_str())) {
+        variances.push(variance(VarianceType::Missing, s, None));
+    }
+
+    let by_id: HashMap<&str, &SystemContainer> = system.iter().map(|s| (s.container_id.as_str(), s)).collect();
+    for count in counts {
+        let Some(s) = by_id.get(count.container_id.as_str()) else { continue };
+        if !s.in_scope {
+            variances.push(variance(VarianceType::Unexpected, s, Some(count)));
+        } else if let Some(ref position_id) = count.position_id {
+            let moved = s.position_id.as_deref() != Some(position_id.as_str())
+                || (count.cell.is_some() && count.cell != s.cell);
+            if moved {
+                variances.push(variance(VarianceType::WrongPosition, s, Some(count)));
+            }
+        }
+        if let Some(observed) = count.observed_quantity {
+            if (observed - s.quantity).abs() > QUANTITY_TOLERANCE {
+                variances.push(variance(VarianceType::Quantity, s, Some(count)));
+            }
+        }
+    }
+
+    for v in &variances {
+        match v.variance_type {
+            VarianceType::Missing => summary.missing += 1,
+            VarianceType::Unexpected => summary.unexpected += 1,
+            VarianceType::WrongPosition => summary.wrong_position += 1,
+            VarianceType::Quantity => summary.quantity += 1,
+        }
+    }
+    (summary, variances)
+}
+
+// ==================== HELPERS ====================
+
+async fn get_session(conn: &mut SqliteConnection, session_id: &str) -> ApiResult<StocktakeSession> {
+    sqlx::query_as("SELECT * FROM stocktake_sessions WHERE id = ?")
+        .bind(session_id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Stocktake session"))
+}
+
+fn ensure_status(session: &StocktakeSession, expected: &str) -> ApiResult<()> {
+    if session.status != expected {
+        return Err(ApiError::bad_request(&format!(
+            "Stocktake session is '{}', expected '{}'", session.status, expected
+        )));
+    }
+    Ok(())
+}
+
+async fn load_counts(conn: &mut SqliteConnection, session_id: &str) -> ApiResult<Vec<StocktakeCount>> {
+    let counts = sqlx::query_as(
+        r#"SELECT sc.*, sp.name AS position_name
+           FROM stocktake_counts sc
+           LEFT JOIN storage_positions sp ON sp.id = sc.position_id
+           WHERE sc.session_id = ?
+           ORDER BY sc.counted_at"#
+    )
+        .bind(session_id)
+        .fetch_all(conn)
+        .await?;
+    Ok(counts)
+}
+
+/// Контейнеры в области сессии и все найденные при пересчёте
+async fn load_system_state(conn: &mut SqliteConnection, session: &StocktakeSession) -> ApiResult<Vec<SystemContainer>> {
+    let rows = sqlx::query_as(
+        r#"SELECT * FROM (
+               SELECT bc.id AS container_id, bc.short_code, bc.sequence_number,
+                      b.id AS batch_id, b.batch_number, r.name AS reagent_name, b.unit,
+                      bc.quantity, bc.status, bp.position_id, sp.name AS position_name, bp.cell,
+                      (sz.room_id = ?1 AND (?2 IS NULL OR sp.zone_id = ?2) AND bc.status != 'disposed') AS in_scope
+               FROM batch_containers bc
+               JOIN batches b ON b.id = bc.batch_id
+               JOIN reagents r ON r.id = b.reagent_id
+               LEFT JOIN batch_placements bp ON bp.container_id = bc.id
+               LEFT JOIN storage_positions sp ON sp.id = bp.position_id
+               LEFT JOIN storage_zones sz ON sz.id = sp.zone_id
+               WHERE b.deleted_at IS NULL
+           )
+           WHERE in_scope OR container_id IN (SELECT container_id FROM stocktake_counts WHERE session_id = ?3)
+           ORDER BY position_name, reagent_name, batch_number, sequence_number"#
+    )
+        .bind(&session.room_id)
+        .bind(&session.zone_id)
+        .bind(&session.id)
+        .fetch_all(conn)
+        .await?;
+    Ok(rows)
+}
+
+/// Позиция по id или короткому коду; должна входить в область сессии
+async fn resolve_position(pool: &sqlx::SqlitePool, session: &StocktakeSession, reference: &str) -> ApiResult<StoragePosition> {
+    let column = match barcodes::parse_short_code(reference) {
+        Some((ScanEntity::Position, _)) => "short_code",
+        _ => "id",
+    };
+    let value = if column == "short_code" { reference.trim().to_ascii_uppercase() } else { reference.trim().to_string() };
+    let position: StoragePosition = sqlx::query_as(&format!("SELECT * FROM storage_positions WHERE {} = ?", column))
+        .bind(&value)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Storage position"))?;
+    let room_id: Option<String> = sqlx::query_scalar("SELECT room_id FROM storage_zones WHERE id = ?")
+        .bind(&position.zone_id)
+        .fetch_optional(pool)
+        .await?;
+    let in_scope = room_id.as_deref() == Some(session.room_id.as_str())
+        && session.zone_id.as_ref().is_none_or(|z| *z == position.zone_id);
+    if !in_scope {
+        return Err(ApiError::bad_request(&format!(
+            "Position {} is outside the scope of this stocktake", position.name
+        )));
+    }
+    Ok(position)
+}
+
+/// Изменение количества контейнера и партии; недостача пишется в usage_logs
+async fn adjust_quantity(
+    conn: &mut SqliteConnection,
+    system: &SystemContainer,
+    observed: f64,
+    user_id: &str,
+    note: &str,
+    now: DateTime<Utc>,
+) -> ApiResult<Option<String>> {
+    let difference = observed - system.quantity;
+    let (reagent_id, original_quantity): (String, f64) = sqlx::query_as(
+        "SELECT b.reagent_id, bc.original_quantity FROM batch_containers bc JOIN batches b ON b.id = bc.batch_id WHERE bc.id = ?"
+    )
+        .bind(&system.container_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE batch_containers SET quantity = ?, status = ?, updated_at = ? WHERE id = ?")
+        .bind(observed)
+        .bind(compute_container_status(observed, original_quantity))
+        .bind(now)
+        .bind(&system.container_id)
+        .execute(&mut *conn)
+        .await?;
+
+    // Статус партии пересчитывается только для складских статусов (expired и т.п. не трогаем)
+    sqlx::query(
+        r#"UPDATE batches
+           SET quantity = MAX(quantity + ?1, 0),
+               status = CASE
+                   WHEN status NOT IN ('available', 'low_stock', 'depleted') THEN status
+                   WHEN quantity + ?1 <= 0 THEN 'depleted'
+                   WHEN pack_size IS NOT NULL AND quantity + ?1 <= pack_size THEN 'low_stock'
+                   ELSE 'available'
+               END,
+               updated_by = ?2, updated_at = ?3
+           WHERE id = ?4"#
+    )
+        .bind(difference)
+        .bind(user_id)
+        .bind(now)
+        .bind(&system.batch_id)
+        .execute(&mut *conn)
+        .await?;
+
+    if difference >= 0.0 {
+        return Ok(None);
+    }
+    let usage_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, purpose, notes, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, 'Stocktake adjustment', ?, ?)"#
+    )
+        .bind(&usage_id)
+        .bind(&reagent_id)
+        .bind(&system.batch_id)
+        .bind(user_id)
+        .bind(-difference)
+        .bind(&system.unit)
+        .bind(note)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    Ok(Some(usage_id))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn record_adjustment(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+    v: &Variance,
+    action: &str,
+    to_position_id: Option<&str>,
+    usage_log_id: Option<&str>,
+    reason: &str,
+    user_id: &str,
+    now: DateTime<Utc>,
+) -> ApiResult<()> {
+    sqlx::query(
+        r#"INSERT INTO stocktake_adjustments
+           (id, session_id, container_id, variance_type, action, from_position_id, to_position_id,
+            system_quantity, observed_quantity, usage_log_id, reason, applied_by, applied_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(Uuid::new_v4().to_string())
+        .bind(session_id)
+        .bind(&v.container_id)
+        .bind(v.variance_type.as_str())
+        .bind(action)
+        .bind(&v.system_position_id)
+        .bind(to_position_id)
+        .bind(v.system_quantity)
+        .bind(v.observed_quantity)
+        .bind(usage_log_id)
+        .bind(reason)
+        .bind(user_id)
+        .bind(now)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// ==================== SESSIONS ====================
+
+pub async fn list_stocktakes(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<StocktakeListQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(ref status) = query.status {
+        if !STOCKTAKE_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Unknown status '{}'", status)));
+        }
+    }
+    let sessions: Vec<StocktakeSession> = sqlx::query_as(
+        r#"SELECT * FROM stocktake_sessions
+           WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR room_id = ?2)
+           ORDER BY started_at DESC"#
+    )
+        .bind(&query.status)
+        .bind(&query.room_id)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(sessions)))
+}
+
+pub async fn create_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateStocktakeRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    sqlx::query_as::<_, Room>("SELECT * FROM rooms WHERE id = ?")
+        .bind(&body.room_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Room"))?;
+    if let Some(ref zone_id) = body.zone_id {
+        let zone_room: Option<String> = sqlx::query_scalar("SELECT room_id FROM storage_zones WHERE id = ?")
+            .bind(zone_id)
+            .fetch_optional(pool)
+            .await?;
+        match zone_room {
+            None => return Err(ApiError::not_found("Storage zone")),
+            Some(room_id) if room_id != body.room_id => {
+                return Err(ApiError::bad_request("Zone does not belong to the room"));
+            }
+            _ => {}
+        }
+    }
+
+    // Пересекающиеся открытые сессии дали бы противоречивые корректировки
+    let overlapping: Option<String> = sqlx::query_scalar(
+        r#"SELECT name FROM stocktake_sessions
+           WHERE room_id = ? AND status IN ('counting', 'review')
+             AND (zone_id IS NULL OR ? IS NULL OR zone_id = ?)
+           LIMIT 1"#
+    )
+        .bind(&body.room_id)
+        .bind(&body.zone_id)
+        .bind(&body.zone_id)
+        .fetch_optional(pool)
+        .await?;
+    if let Some(name) = overlapping {
+        return Err(ApiError::bad_request(&format!("Stocktake '{}' is already open for this area", name)));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO stocktake_sessions (id, name, room_id, zone_id, status, notes, started_by, started_at, updated_at)
+           VALUES (?, ?, ?, ?, 'counting', ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(body.name.trim())
+        .bind(&body.room_id)
+        .bind(&body.zone_id)
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &claims.sub, "create", "stocktake", &id, &format!("Stocktake '{}' started", body.name.trim()), &http_request).await;
+    info!("📋 Stocktake '{}' started", body.name.trim());
+
+    let mut conn = pool.acquire().await?;
+    let session = get_session(&mut conn, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(session)))
+}
+
+pub async fn get_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let mut conn = app_state.db_pool.acquire().await?;
+    let session = get_session(&mut conn, &path.into_inner()).await?;
+    let counts = load_counts(&mut conn, &session.id).await?;
+    let system = load_system_state(&mut conn, &session).await?;
+    let (summary, _) = compute_variances(&system, &counts);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "session": session,
+        "summary": summary,
+    }))))
+}
+
+async fn set_status(
+    app_state: &AppState,
+    session_id: &str,
+    from: &str,
+    to: &str,
+    http_request: &HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(http_request)?;
+    let pool = &app_state.db_pool;
+    let mut conn = pool.acquire().await?;
+    let session = get_session(&mut conn, session_id).await?;
+    if !(from == "*" && matches!(session.status.as_str(), "counting" | "review")) {
+        ensure_status(&session, from)?;
+    }
+
+    let now = Utc::now();
+    let submitted = to == "review";
+    sqlx::query(
+        r#"UPDATE stocktake_sessions
+           SET status = ?, updated_at = ?,
+               submitted_by = CASE WHEN ? THEN ? ELSE submitted_by END,
+               submitted_at = CASE WHEN ? THEN ? ELSE submitted_at END
+           WHERE id = ?"#
+    )
+        .bind(to)
+        .bind(now)
+        .bind(submitted)
+        .bind(&claims.sub)
+        .bind(submitted)
+        .bind(now)
+        .bind(session_id)
+        .execute(&mut *conn)
+        .await?;
+
+    crate::audit::audit(pool, &claims.sub, "update", "stocktake", session_id,
+        &format!("Stocktake '{}': {} -> {}", session.name, session.status, to), http_request).await;
+
+    let session = get_session(&mut conn, session_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(session)))
+}
+
+pub async fn submit_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    set_status(&app_state, &path.into_inner(), "counting", "review", &http_request).await
+}
+
+pub async fn reopen_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    set_status(&app_state, &path.into_inner(), "review", "counting", &http_request).await
+}
+
+pub async fn cancel_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    set_status(&app_state, &path.into_inner(), "*", "cancelled", &http_request).await
+}
+
+// ==================== COUNTS ====================
+
+pub async fn get_counts(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let mut conn = app_state.db_pool.acquire().await?;
+    let session = get_session(&mut conn, &path.into_inner()).await?;
+    let counts = load_counts(&mut conn, &session.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(counts)))
+}
+
+pub async fn record_count(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<RecordCountRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let session_id = path.into_inner();
+
+    let mut conn = pool.acquire().await?;
+    let session = get_session(&mut conn, &session_id).await?;
+    ensure_status(&session, "counting")?;
+
+    let method = body.method.as_deref().unwrap_or("manual");
+    if !matches!(method, "scan" | "manual") {
+        return Err(ApiError::bad_request("method must be 'scan' or 'manual'"));
+    }
+
+    let reference = body.container.trim();
+    let container: BatchContainer = match barcodes::parse_short_code(reference) {
+        Some((ScanEntity::Container, code)) => sqlx::query_as("SELECT * FROM batch_containers WHERE short_code = ?")
+            .bind(code)
+            .fetch_optional(&mut *conn)
+            .await?,
+        Some(_) => return Err(ApiError::bad_request("Scanned code is not a container label")),
+        None => sqlx::query_as("SELECT * FROM batch_containers WHERE id = ?")
+            .bind(reference)
+            .fetch_optional(&mut *conn)
+            .await?,
+    }
+    .ok_or_else(|| ApiError::not_found("Container"))?;
+
+    let (position_id, cell) = match body.position.as_deref().filter(|p| !p.trim().is_empty()) {
+        Some(reference) => {
+            let position = resolve_position(pool, &session, reference).await?;
+            let cell = match (body.cell.as_deref(), position.grid_rows, position.grid_columns) {
+                (Some(cell), Some(rows), Some(columns)) => {
+                    let (row, column) = parse_grid_cell(cell, rows, columns).ok_or_else(|| {
+                        ApiError::bad_request(&format!("Cell '{}' is not in the grid of {}", cell, position.name))
+                    })?;
+                    Some(grid_cell_label(row, column))
+                }
+                _ => None,
+            };
+            (Some(position.id), cell)
+        }
+        None => (None, None),
+    };
+
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO stocktake_counts
+           (id, session_id, container_id, position_id, cell, observed_quantity, method, notes, counted_by, counted_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+           ON CONFLICT(session_id, container_id) DO UPDATE SET
+               position_id = excluded.position_id, cell = excluded.cell,
+               observed_quantity = excluded.observed_quantity, method = excluded.method,
+               notes = excluded.notes, counted_by = excluded.counted_by, counted_at = excluded.counted_at"#
+    )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&session.id)
+        .bind(&container.id)
+        .bind(&position_id)
+        .bind(&cell)
+        .bind(body.observed_quantity)
+        .bind(method)
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+
+    let count: StocktakeCount = sqlx::query_as(
+        r#"SELECT sc.*, sp.name AS position_name
+           FROM stocktake_counts sc
+           LEFT JOIN storage_positions sp ON sp.id = sc.position_id
+           WHERE sc.session_id = ? AND sc.container_id = ?"#
+    )
+        .bind(&session.id)
+        .bind(&container.id)
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(count)))
+}
+
+pub async fn delete_count(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (session_id, count_id) = path.into_inner();
+    let mut conn = app_state.db_pool.acquire().await?;
+    let session = get_session(&mut conn, &session_id).await?;
+    ensure_status(&session, "counting")?;
+
+    let result = sqlx::query("DELETE FROM stocktake_counts WHERE id = ? AND session_id = ?")
+        .bind(&count_id)
+        .bind(&session_id)
+        .execute(&mut *conn)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Stocktake count"));
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), "Count removed".to_string())))
+}
+
+// ==================== RECONCILIATION ====================
+
+pub async fn get_variances(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let mut conn = app_state.db_pool.acquire().await?;
+    let session = get_session(&mut conn, &path.into_inner()).await?;
+    let counts = load_counts(&mut conn, &session.id).await?;
+    let system = load_system_state(&mut conn, &session).await?;
+    let (summary, variances) = compute_variances(&system, &counts);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(VarianceReport { session, summary, variances })))
+}
+
+pub async fn approve_stocktake(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<ApproveStocktakeRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    if claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Only a supervisor (admin) can approve stocktake adjustments".to_string()));
+    }
+    let pool = &app_state.db_pool;
+    let session_id = path.into_inner();
+    let reason = body.reason.trim();
+    let now = Utc::now();
+
+    let mut tx = pool.begin().await?;
+    let session = get_session(&mut tx, &session_id).await?;
+    ensure_status(&session, "review")?;
+    let counts = load_counts(&mut tx, &session.id).await?;
+    let system = load_system_state(&mut tx, &session).await?;
+    let (_, variances) = compute_variances(&system, &counts);
+
+    let skip: HashSet<&str> = body.skip_container_ids.iter().map(|s| s.as_str()).collect();
+    let system_by_id: HashMap<&str, &SystemContainer> = system.iter().map(|s| (s.container_id.as_str(), s)).collect();
+    let note = format!("Stocktake '{}': {}", session.name, reason);
+    let movement_note: String = note.chars().take(500).collect();
+    let applicable: Vec<&Variance> = variances.iter()
+        .filter(|v| v.adjustable && !skip.contains(v.container_id.as_str()))
+        .collect();
+    let mut touched_positions: HashSet<String> = HashSet::new();
+    let mut applied = 0usize;
+
+    // 1. Не найденные: списать остаток (по запросу) и снять с позиции
+    for v in applicable.iter().filter(|v| v.variance_type == VarianceType::Missing) {
+        let s = system_by_id[v.container_id.as_str()];
+        let mut usage_id = None;
+        let mut action = "unplaced";
+        if body.write_off_missing && s.quantity > QUANTITY_TOLERANCE {
+            usage_id = adjust_quantity(&mut tx, s, 0.0, &claims.sub, &note, now).await?;
+            action = "written_off";
+        }
+        let from = current_slot(&mut tx, &v.container_id).await?;
+        if let Some(ref from) = from {
+            sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
+                .bind(&v.container_id)
+                .execute(&mut *tx)
+                .await?;
+            record_movement(&mut tx, &v.container_id, "unplace", Some(from), None, &claims.sub, Some(&movement_note)).await?;
+            touched_positions.insert(from.position_id.clone());
+        }
+        record_adjustment(&mut tx, &session.id, v, action, None, usage_id.as_deref(), reason, &claims.sub, now).await?;
+        applied += 1;
+    }
+
+    // 2. Найденные не на месте: сначала освобождаем старые места (обмен ячейками), затем размещаем
+    let relocations: Vec<&Variance> = applicable.iter()
+        .filter(|v| matches!(v.variance_type, VarianceType::WrongPosition | VarianceType::Unexpected))
+        .copied()
+        .collect();
+    let mut previous: HashMap<&str, Option<Slot>> = HashMap::new();
+    for v in &relocations {
+        let from = current_slot(&mut tx, &v.container_id).await?;
+        if let Some(ref from) = from {
+            sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
+                .bind(&v.container_id)
+                .execute(&mut *tx)
+                .await?;
+            touched_positions.insert(from.position_id.clone());
+        }
+        previous.insert(v.container_id.as_str(), from);
+    }
+    for v in &relocations {
+        let Some(ref position_id) = v.observed_position_id else { continue };
+        let to = reserve_slot(&mut tx, position_id, v.observed_cell.as_deref(), &v.container_id).await?;
+        sqlx::query(
+            r#"INSERT INTO batch_placements (id, container_id, position_id, placed_by, placed_at, notes, cell)
+               VALUES (?, ?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&v.container_id)
+            .bind(&to.position_id)
+            .bind(&claims.sub)
+            .bind(now)
+            .bind(&movement_note)
+            .bind(&to.cell)
+            .execute(&mut *tx)
+            .await?;
+        let from = previous.get(v.container_id.as_str()).and_then(|f| f.as_ref());
+        let movement = if from.is_some() { "move" } else { "place" };
+        record_movement(&mut tx, &v.container_id, movement, from, Some(&to), &claims.sub, Some(&movement_note)).await?;
+        touched_positions.insert(to.position_id.clone());
+        let action = if from.is_some() { "moved" } else { "placed" };
+        record_adjustment(&mut tx, &session.id, v, action, Some(&to.position_id), None, reason, &claims.sub, now).await?;
+        applied += 1;
+    }
+
+    // 3. Количество
+    for v in applicable.iter().filter(|v| v.variance_type == VarianceType::Quantity) {
+        let s = system_by_id[v.container_id.as_str()];
+        let Some(observed) = v.observed_quantity else { continue };
+        let usage_id = adjust_quantity(&mut tx, s, observed, &claims.sub, &note, now).await?;
+        record_adjustment(&mut tx, &session.id, v, "quantity_adjusted", None, usage_id.as_deref(), reason, &claims.sub, now).await?;
+        applied += 1;
+    }
+
+    for position_id in &touched_positions {
+        refresh_position_occupancy(&mut tx, position_id).await?;
+    }
+
+    sqlx::query(
+        r#"UPDATE stocktake_sessions
+           SET status = 'approved', approved_by = ?, approved_at = ?, approval_reason = ?, updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(reason)
+        .bind(now)
+        .bind(&session.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let skipped = variances.len() - applied;
+    crate::audit::audit(
+        pool, &claims.sub, "approve", "stocktake", &session.id,
+        &format!("Stocktake '{}' approved: {} adjustments applied, {} left for manual handling. Reason: {}",
+            session.name, applied, skipped, reason),
+        &http_request,
+    ).await;
+    info!("📋 Stocktake '{}' approved: {} adjustments", session.name, applied);
+
+    let adjustments: Vec<StocktakeAdjustment> = sqlx::query_as(
+        "SELECT * FROM stocktake_adjustments WHERE session_id = ? ORDER BY applied_at, variance_type"
+    )
+        .bind(&session.id)
+        .fetch_all(pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        adjustments,
+        format!("{} adjustments applied, {} skipped", applied, skipped),
+    )))
+}
+
+pub async fn get_adjustments(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let session_id = path.into_inner();
+    let mut conn = app_state.db_pool.acquire().await?;
+    get_session(&mut conn, &session_id).await?;
+    let adjustments: Vec<StocktakeAdjustment> = sqlx::query_as(
+        "SELECT * FROM stocktake_adjustments WHERE session_id = ? ORDER BY applied_at, variance_type"
+    )
+        .bind(&session_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(adjustments)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(id: &str, position: Option<&str>, quantity: f64, in_scope: bool) -> SystemContainer {
+        SystemContainer {
+            container_id: id.to_string(),
+            short_code: None,
+            sequence_number: 1,
+            batch_id: "b1".to_string(),
+            batch_number: "B-1".to_string(),
+            reagent_name: "Ethanol".to_string(),
+            unit: "ml".to_string(),
+            quantity,
+            status: "full".to_string(),
+            position_id: position.map(String::from),
+            position_name: None,
+            cell: None,
+            in_scope,
+        }
+    }
+
+    fn count(id: &str, position: Option<&str>, observed: Option<f64>) -> StocktakeCount {
+        StocktakeCount {
+            id: format!("count-{}", id),
+            session_id: "s1".to_string(),
+            container_id: id.to_string(),
+            position_id: position.map(String::from),
+            position_name: None,
+            cell: None,
+            observed_quantity: observed,
+            method: "scan".to_string(),
+            notes: None,
+            counted_by: None,
+            counted_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_compute_variances() {
+        let system = vec![
+            system("ok", Some("p1"), 500.0, true),
+            system("missing", Some("p1"), 500.0, true),
+            system("moved", Some("p1"), 500.0, true),
+            system("elsewhere", Some("p9"), 250.0, false),
+        ];
+        let counts = vec![
+            count("ok", Some("p1"), Some(500.0005)),
+            count("moved", Some("p2"), Some(420.0)),
+            count("elsewhere", None, None),
+        ];
+        let (summary, variances) = compute_variances(&system, &counts);
+        let kinds: Vec<(&str, VarianceType)> = variances.iter()
+            .map(|v| (v.container_id.as_str(), v.variance_type))
+            .collect();
+        assert_eq!(kinds, vec![
+            ("missing", VarianceType::Missing),
+            ("moved", VarianceType::WrongPosition),
+            ("moved", VarianceType::Quantity),
+            ("elsewhere", VarianceType::Unexpected),
+        ]);
+        assert_eq!((summary.expected, summary.counted, summary.missing), (3, 3, 1));
+        assert_eq!(variances[2].difference, Some(-80.0));
+        // Найден без позиции — переместить автоматически нельзя
+        assert!(!variances[3].adjustable);
+    }
+}
@@ -0,0 +1,69 @@
+// src/storage/local.rs
+//! Бэкенд на локальной файловой системе.
+
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+use super::FileStorage;
+use crate::error::{ApiError, ApiResult};
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Self {
+        Self { root: PathBuf::from(root) }
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.root.join(Path::new(key))
+    }
+}
+
+fn io_error(action: &str, key: &str, e: std::io::Error) -> ApiError {
+    ApiError::InternalServerError(format!("Failed to {} {}: {}", action, key, e))
+}
+
+#[async_trait]
+impl FileStorage for LocalStorage {
+    fn backend_name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, bytes: &[u8]) -> ApiResult<()> {
+        let path = self.path_for(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| io_error("create directory for", key, e))?;
+        }
+        // Пишем во временный файл и переименовываем, чтобы читатели
+        // никогда не видели частично записанное содержимое
+        let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&tmp, bytes).await.map_err(|e| io_error("write", key, e))?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(io_error("store", key, e));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> ApiResult<Vec<u8>> {
+        match tokio::fs::read(self.path_for(key)).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ApiError::not_found("Stored file")),
+            Err(e) => Err(io_error("read", key, e)),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> ApiResult<bool> {
+        tokio::fs::try_exists(self.path_for(key)).await.map_err(|e| io_error("stat", key, e))
+    }
+
+    async fn delete(&self, key: &str) -> ApiResult<()> {
+        match tokio::fs::remove_file(self.path_for(key)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error("delete", key, e)),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+// src/storage/mod.rs
+//! Хранилище загружаемых файлов.
+//!
+//! `FileStorage` — минимальный интерфейс бэкенда (локальная ФС или
+//! S3-совместимое хранилище). Поверх него `BlobStore` адресует файлы по
+//! содержимому: ключ — SHA-256 файла, поэтому одинаковые загрузки хранятся
+//! один раз, а при чтении содержимое сверяется с хешем.
+//!
+//! Переименование оборудования больше не влияет на расположение файлов,
+//! а несколько экземпляров сервера могут работать с общим бакетом.
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqliteExecutor};
+use std::sync::Arc;
+
+use crate::config::StorageConfig;
+use crate::error::{ApiError, ApiResult};
+
+pub mod local;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+#[async_trait]
+pub trait FileStorage: Send + Sync {
+    /// Имя бэкенда для логов и диагностики
+    fn backend_name(&self) -> &'static str;
+
+    async fn put(&self, key: &str, bytes: &[u8]) -> ApiResult<()>;
+
+    async fn get(&self, key: &str) -> ApiResult<Vec<u8>>;
+
+    async fn exists(&self, key: &str) -> ApiResult<bool>;
+
+    /// Удаление отсутствующего ключа не считается ошибкой
+    async fn delete(&self, key: &str) -> ApiResult<()>;
+}
+
+/// Результат сохранения файла
+#[derive(Debug, Clone)]
+pub struct StoredBlob {
+    pub key: String,
+    pub sha256: String,
+    pub size: i64,
+    /// Такое содержимое уже было в хранилище — повторно не записывалось
+    pub deduplicated: bool,
+}
+
+/// Ключ по содержимому: `sha256/ab/cd/abcd…` (шардирование по префиксу)
+pub fn content_key(sha256: &str) -> String {
+    format!("sha256/{}/{}/{}", &sha256[..2], &sha256[2..4], sha256)
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Проверка ключа до обращения к бэкенду: только наши content-ключи
+pub fn is_valid_key(key: &str) -> bool {
+    match key.strip_prefix("sha256/").map(|rest| rest.split('/').collect::<Vec<_>>()) {
+        Some(parts) if parts.len() == 3 => {
+            let hash = parts[2];
+            hash.len() == 64
+                && hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+                && parts[0] == &hash[..2]
+                && parts[1] == &hash[2..4]
+        }
+        _ => false,
+    }
+}
+
+/// Все колонки, хранящие ключи `BlobStore`. Одинаковое содержимое разных
+/// загрузок делит один ключ, поэтому содержимое удаляется только когда
+/// ссылок не осталось ни в одной из них.
+pub const STORAGE_KEY_COLUMNS: [(&str, &str); 4] = [
+    ("equipment_files", "storage_key"),
+    ("equipment_file_thumbnails", "storage_key"),
+    ("experiment_documents", "storage_key"),
+    ("goods_receipts", "coa_storage_key"),
+];
+
+/// Число ссылок на ключ по всем `STORAGE_KEY_COLUMNS`
+pub async fn count_references(executor: impl SqliteExecutor<'_>, key: &str) -> ApiResult<i64> {
+    let sql = STORAGE_KEY_COLUMNS
+        .iter()
+        .map(|(table, column)| format!("(SELECT COUNT(*) FROM {} WHERE {} = ?1)", table, column))
+        .collect::<Vec<_>>()
+        .join(" + ");
+    let references: i64 = sqlx::query_scalar(&format!("SELECT {}", sql))
+        .bind(key)
+        .fetch_one(executor)
+        .await?;
+    Ok(references)
+}
+
+/// Контентно-адресуемое хранилище поверх любого бэкенда
+pub struct BlobStore {
+    backend: Arc<dyn FileStorage>,
+}
+
+impl BlobStore {
+    pub fn new(backend: Arc<dyn FileStorage>) -> Self {
+        Self { backend }
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.backend_name()
+    }
+
+    /// Записать содержимое до вставки строки-владельца; после её фиксации
+    /// вызывающий обязан вызвать `confirm`
+    pub async fn store(&self, bytes: &[u8]) -> ApiResult<StoredBlob> {
+        let sha256 = sha256_hex(bytes);
+        let key = content_key(&sha256);
+        let deduplicated = self.backend.exists(&key).await?;
+        if !deduplicated {
+            self.backend.put(&key, bytes).await?;
+        }
+        Ok(StoredBlob { key, sha256, size: bytes.len() as i64, deduplicated })
+    }
+
+    /// Вызывается после фиксации строки-владельца. Параллельный `release` того же
+    /// содержимого мог удалить его между `store` и вставкой строки — тогда
+    /// записываем заново. Позже `release` уже увидит новую ссылку.
+    pub async fn confirm(&self, blob: &StoredBlob, bytes: &[u8]) -> ApiResult<()> {
+        if !self.backend.exists(&blob.key).await? {
+            log::warn!("Stored file {} was released concurrently; writing it again", blob.key);
+            self.backend.put(&blob.key, bytes).await?;
+        }
+        Ok(())
+    }
+
+    /// Чтение с проверкой целостности: содержимое должно совпасть с хешем
+    pub async fn load(&self, key: &str, expected_sha256: &str) -> ApiResult<Vec<u8>> {
+        if !is_valid_key(key) {
+            return Err(ApiError::InternalServerError(format!("Invalid storage key: {}", key)));
+        }
+        let bytes = self.backend.get(key).await?;
+        let actual = sha256_hex(&bytes);
+        if actual != expected_sha256 {
+            log::error!(
+                "Integrity check failed for {} ({}): expected {}, got {}",
+                key, self.backend_name(), expected_sha256, actual
+            );
+            return Err(ApiError::InternalServerError("Stored file is corrupted".to_string()));
+        }
+        Ok(bytes)
+    }
+
+    /// Удалить содержимое; вызывающий отвечает за то, что на ключ больше нет ссылок
+    pub async fn remove(&self, key: &str) -> ApiResult<()> {
+        if !is_valid_key(key) {
+            return Err(ApiError::InternalServerError(format!("Invalid storage key: {}", key)));
+        }
+        self.backend.delete(key).await
+    }
+
+    /// Удалить содержимое, если на ключ больше никто не ссылается.
+    /// Вызывается до фиксации транзакции, удалившей строку-владельца: она держит
+    /// блокировку записи, и вставка новой ссылки дождётся конца подсчёта и удаления.
+    /// Ошибки бэкенда только логируются.
+    pub async fn release(&self, conn: &mut SqliteConnection, key: &str) -> ApiResult<()> {
+        if count_references(&mut *conn, key).await? > 0 {
+            return Ok(());
+        }
+        if let Err(e) = self.remove(key).await {
+            log::error!("Failed to remove stored file {}: {}", key, e);
+        }
+        Ok(())
+    }
+}
+
+/// Создать хранилище по конфигурации (`STORAGE_BACKEND=local|s3`)
+pub fn from_config(config: &StorageConfig) -> anyhow::Result<BlobStore> {
+    let backend: Arc<dyn FileStorage> = match config.backend.as_str() {
+        "local" => Arc::new(LocalStorage::new(&config.local_root)),
+        "s3" => Arc::new(S3Storage::from_config(config)?),
+        other => anyhow::bail!("Unknown storage backend '{}'; expected 'local' or 's3'", other),
+    };
+    log::info!("File storage backend: {}", backend.backend_name());
+    Ok(BlobStore::new(backend))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_blob_store_dedupes_and_verifies() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(Arc::new(LocalStorage::new(dir.path().to_str().unwrap())));
+
+        let first = store.store(b"certificate").await.unwrap();
+        let second = store.store(b"certificate").await.unwrap();
+        assert!(!first.deduplicated);
+        assert!(second.deduplicated);
+        assert_eq!(first.key, second.key);
+        assert!(is_valid_key(&first.key));
+
+        assert_eq!(store.load(&first.key, &first.sha256).await.unwrap(), b"certificate");
+
+        // Повреждённое содержимое не должно отдаваться
+        std::fs::write(dir.path().join(&first.key), b"tampered").unwrap();
+        assert!(store.load(&first.key, &first.sha256).await.is_err());
+
+        assert!(!is_valid_key("../etc/passwd"));
+        assert!(!is_valid_key("sha256/00/00/short"));
+    }
+
+    #[actix_rt::test]
+    async fn test_storage_key_columns_cover_schema() {
+        let pool = crate::db::test_pool().await;
+        let columns: Vec<(String, String)> = sqlx::query_as(r#"
+            SELECT m.name, c.name FROM sqlite_master m, pragma_table_info(m.name) c
+            WHERE m.type = 'table' AND c.name LIKE '%storage_key'
+            ORDER BY m.name, c.name
+        "#)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+
+        let mut known: Vec<(String, String)> = STORAGE_KEY_COLUMNS
+            .iter()
+            .map(|(t, c)| (t.to_string(), c.to_string()))
+            .collect();
+        known.sort();
+        assert_eq!(columns, known);
+    }
+
+    #[actix_rt::test]
+    async fn test_release_keeps_blob_shared_with_coa() {
+        let pool = crate::db::test_pool().await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(Arc::new(LocalStorage::new(dir.path().to_str().unwrap())));
+        let blob = store.store(b"certificate of analysis").await.unwrap();
+
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', datetime('now'), datetime('now'))")
+            .execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, received_date, created_at, updated_at)
+            VALUES ('b1', 'r1', 'B-1', 1, 1, 'l', datetime('now'), datetime('now'), datetime('now'))
+        "#).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO goods_receipts (id, batch_id, supplier, delivery_note, received_quantity, unit,
+                                        coa_storage_key, coa_sha256, received_at, updated_at)
+            VALUES ('g1', 'b1', 'Acme', 'DN-1', 1, 'l', ?, ?, datetime('now'), datetime('now'))
+        "#).bind(&blob.key).bind(&blob.sha256).execute(&pool).await.unwrap();
+
+        // Файл оборудования с тем же содержимым удалён — CoA должен остаться
+        let mut conn = pool.acquire().await.unwrap();
+        store.release(&mut conn, &blob.key).await.unwrap();
+        assert!(store.load(&blob.key, &blob.sha256).await.is_ok());
+
+        sqlx::query("DELETE FROM goods_receipts").execute(&mut *conn).await.unwrap();
+        store.release(&mut conn, &blob.key).await.unwrap();
+        assert!(store.load(&blob.key, &blob.sha256).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_dedup_upload_survives_concurrent_release() {
+        let pool = crate::db::test_pool().await;
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(Arc::new(LocalStorage::new(dir.path().to_str().unwrap())));
+        let bytes = b"protocol scan";
+
+        sqlx::query("INSERT INTO experiments (id, title, experiment_date, status, created_at, updated_at) VALUES ('e1', 'Titration', datetime('now'), 'planned', datetime('now'), datetime('now'))")
+            .execute(&pool).await.unwrap();
+        let insert_document = |id: &'static str, blob: StoredBlob| {
+            sqlx::query(r#"
+                INSERT INTO experiment_documents (id, experiment_id, original_filename, mime_type, file_size, storage_key, sha256, created_at)
+                VALUES (?, 'e1', 'scan.pdf', 'application/pdf', ?, ?, ?, datetime('now'))
+            "#).bind(id).bind(blob.size).bind(blob.key).bind(blob.sha256)
+        };
+        let first = store.store(bytes).await.unwrap();
+        insert_document("d1", first.clone()).execute(&pool).await.unwrap();
+
+        // Вторая загрузка того же содержимого: дедупликация, put пропущен
+        let second = store.store(bytes).await.unwrap();
+        assert!(second.deduplicated);
+
+        // Тем временем удаляют первый документ: ссылок ноль, содержимое удалено
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query("DELETE FROM experiment_documents WHERE id = 'd1'").execute(&mut *tx).await.unwrap();
+        store.release(&mut tx, &first.key).await.unwrap();
+        tx.commit().await.unwrap();
+
+        // Строка второй загрузки фиксируется после удаления — confirm восстанавливает содержимое
+        insert_document("d2", second.clone()).execute(&pool).await.unwrap();
+        store.confirm(&second, bytes).await.unwrap();
+        assert_eq!(store.load(&second.key, &second.sha256).await.unwrap(), bytes);
+    }
+}
@@ -1,6 +1,8 @@
 // src/storage_handlers.rs
 //! Обработчики для управления зонами и позициями хранения
 //! Иерархия: Room → StorageZone (шкаф/холодильник) → StoragePosition (полка/ящик)
+//! Позиция может иметь сетку rows × columns (коробка, штатив): ячейки A1…Z99,
+//! вместимость = rows × columns, статус full/available поддерживается автоматически.
 
 use actix_web::{web, HttpResponse};
 use std::sync::Arc;
@@ -12,7 +14,8 @@ use chrono::Utc;
 use uuid::Uuid;
 use validator::Validate;
 use log::info;
-use sqlx::SqlitePool;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashSet;
 
 // ============================================================
 //                    STORAGE ZONES CRUD
@@ -273,18 +276,19 @@ pub async fn create_storage_position(
 
     if dup.is_some() { return Err(ApiError::bad_request("A position with this name already exists in this zone")); }
 
+    let max_capacity = grid_capacity(data.grid_rows, data.grid_columns, data.max_capacity)?;
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     let sort_order = data.sort_order.unwrap_or(0);
 
     sqlx::query(
         r#"INSERT INTO storage_positions 
-           (id, zone_id, name, position_label, max_capacity, current_count,
+           (id, zone_id, name, position_label, max_capacity, current_count, grid_rows, grid_columns,
             sort_order, description, status, created_by, updated_by, created_at, updated_at)
-           VALUES (?, ?, ?, ?, ?, 0, ?, ?, 'available', ?, ?, ?, ?)"#
+           VALUES (?, ?, ?, ?, ?, 0, ?, ?, ?, ?, 'available', ?, ?, ?, ?)"#
     )
-    .bind(&id).bind(&data.zone_id).bind(&data.name).bind(&data.position_label).bind(&data.max_capacity)
-    .bind(sort_order).bind(&data.description).bind(&user_id).bind(&user_id).bind(&now).bind(&now)
+    .bind(&id).bind(&data.zone_id).bind(&data.name).bind(&data.position_label).bind(max_capacity)
+    .bind(data.grid_rows).bind(data.grid_columns).bind(sort_order).bind(&data.description).bind(&user_id).bind(&user_id).bind(now).bind(now)
     .execute(&app_state.db_pool).await?;
 
     let created: StoragePosition = sqlx::query_as("SELECT * FROM storage_positions WHERE id = ?")
@@ -317,22 +321,68 @@ pub async fn update_storage_position(
         }
     }
 
+    if let Some(ref status) = data.status {
+        if !POSITION_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Invalid status. Allowed: {}", POSITION_STATUSES.join(", "))));
+        }
+    }
+
     let now = Utc::now();
     let name = data.name.as_ref().unwrap_or(&existing.name);
     let label = data.position_label.clone().or(existing.position_label);
-    let max_cap = data.max_capacity.or(existing.max_capacity);
+    let grid_rows = data.grid_rows.or(existing.grid_rows);
+    let grid_columns = data.grid_columns.or(existing.grid_columns);
+    let max_cap = grid_capacity(grid_rows, grid_columns, data.max_capacity)?.or(existing.max_capacity);
     let sort_order = data.sort_order.unwrap_or(existing.sort_order);
     let description = data.description.clone().or(existing.description);
     let status = data.status.as_ref().unwrap_or(&existing.status);
 
+    let mut tx = app_state.db_pool.begin().await?;
+    let placed: Vec<(String, Option<String>)> = sqlx::query_as(
+        "SELECT id, cell FROM batch_placements WHERE position_id = ? ORDER BY placed_at, id"
+    ).bind(&pos_id).fetch_all(&mut *tx).await?;
+
+    if let Some(cap) = max_cap {
+        if placed.len() as i64 > cap as i64 {
+            return Err(ApiError::bad_request(&format!(
+                "{} containers are placed here; capacity cannot be lower than that", placed.len()
+            )));
+        }
+    }
+
     sqlx::query(
         r#"UPDATE storage_positions 
-           SET name = ?, position_label = ?, max_capacity = ?, sort_order = ?, description = ?, status = ?,
+           SET name = ?, position_label = ?, max_capacity = ?, grid_rows = ?, grid_columns = ?,
+               sort_order = ?, description = ?, status = ?,
                updated_by = ?, updated_at = ? WHERE id = ?"#
     )
-    .bind(name).bind(&label).bind(&max_cap).bind(sort_order).bind(&description)
+    .bind(name).bind(&label).bind(max_cap).bind(grid_rows).bind(grid_columns)
+    .bind(sort_order).bind(&description)
     .bind(status).bind(&user_id).bind(&now).bind(&pos_id)
-    .execute(&app_state.db_pool).await?;
+    .execute(&mut *tx).await?;
+
+    // Контейнеры, размещённые до появления сетки, получают первые свободные ячейки
+    if let (Some(rows), Some(columns)) = (grid_rows, grid_columns) {
+        let mut taken: HashSet<String> = HashSet::new();
+        for cell in placed.iter().filter_map(|(_, cell)| cell.as_deref()) {
+            match parse_grid_cell(cell, rows, columns) {
+                Some((row, column)) => { taken.insert(grid_cell_label(row, column)); }
+                None => return Err(ApiError::bad_request(&format!(
+                    "Cell {} is occupied and lies outside a {}×{} grid", cell, rows, columns
+                ))),
+            }
+        }
+        let mut free = free_grid_cells(rows, columns, &taken);
+        for (placement_id, _) in placed.iter().filter(|(_, cell)| cell.is_none()) {
+            let cell = free.next().ok_or_else(|| ApiError::bad_request("Not enough free cells for placed containers"))?;
+            sqlx::query("UPDATE batch_placements SET cell = ? WHERE id = ?")
+                .bind(&cell).bind(placement_id)
+                .execute(&mut *tx).await?;
+        }
+    }
+
+    refresh_position_occupancy(&mut tx, &pos_id).await?;
+    tx.commit().await?;
 
     let updated: StoragePosition = sqlx::query_as("SELECT * FROM storage_positions WHERE id = ?")
         .bind(&pos_id).fetch_one(&app_state.db_pool).await?;
@@ -364,73 +414,75 @@ pub async fn delete_storage_position(
 }
 
 // ============================================================
-//              HIERARCHY & LOCATION QUERIES
+//              GRID CELLS & CAPACITY
 // ============================================================
 
-pub async fn get_storage_hierarchy(
-    app_state: web::Data<Arc<AppState>>,
-) -> ApiResult<HttpResponse> {
-    let rooms: Vec<crate::models::Room> = sqlx::query_as("SELECT * FROM rooms ORDER BY name ASC").fetch_all(&app_state.db_pool).await?;
-    let zones: Vec<StorageZone> = sqlx::query_as("SELECT * FROM storage_zones ORDER BY sort_order ASC, name ASC").fetch_all(&app_state.db_pool).await?;
-    let positions: Vec<StoragePosition> = sqlx::query_as("SELECT * FROM storage_positions ORDER BY sort_order ASC, name ASC").fetch_all(&app_state.db_pool).await?;
-
-    // ИСПРАВЛЕНИЕ: COUNT(DISTINCT bc.batch_id) через JOIN
-    let position_counts: Vec<(String, i64)> = sqlx::query_as(
-        r#"SELECT bp.position_id, COUNT(DISTINCT bc.batch_id) 
-           FROM batch_placements bp
-           JOIN batch_containers bc ON bp.container_id = bc.id
-           GROUP BY bp.position_id"#
-    ).fetch_all(&app_state.db_pool).await?;
+const POSITION_STATUSES: &[&str] = &["available", "full", "maintenance", "unavailable"];
+
+/// Вместимость позиции: для сетки — rows × columns, иначе max_capacity как есть
+pub fn grid_capacity(rows: Option<i32>, columns: Option<i32>, max_capacity: Option<i32>) -> ApiResult<Option<i32>> {
+    match (rows, columns) {
+        (Some(rows), Some(columns)) => {
+            let cells = rows * columns;
+            match max_capacity {
+                Some(cap) if cap != cells => Err(ApiError::bad_request(&format!(
+                    "A {}×{} grid holds exactly {} containers; max_capacity must match or be omitted", rows, columns, cells
+                ))),
+                _ => Ok(Some(cells)),
+            }
+        }
+        (None, None) => Ok(max_capacity),
+        _ => Err(ApiError::bad_request("grid_rows and grid_columns must be set together")),
+    }
+}
 
-    let count_map: std::collections::HashMap<String, i64> = position_counts.into_iter().collect();
-    let mut result: Vec<RoomWithStorage> = Vec::new();
+/// (1, 1) → "A1", (9, 9) → "I9"
+pub fn grid_cell_label(row: i32, column: i32) -> String {
+    format!("{}{}", (b'A' + (row - 1) as u8) as char, column)
+}
 
-    for room in rooms {
-        let room_zones: Vec<StorageZoneWithPositions> = zones.iter().filter(|z| z.room_id == room.id).map(|zone| {
-            let zone_positions: Vec<StoragePosition> = positions.iter().filter(|p| p.zone_id == zone.id).cloned().collect();
-            let items_count: i64 = zone_positions.iter().map(|p| count_map.get(&p.id).copied().unwrap_or(0)).sum();
+/// "b7" / "B07" → (2, 7); None, если адрес не распознан или вне сетки
+pub fn parse_grid_cell(cell: &str, rows: i32, columns: i32) -> Option<(i32, i32)> {
+    let cell = cell.trim().to_ascii_uppercase();
+    let letter = *cell.as_bytes().first()?;
+    if !letter.is_ascii_uppercase() {
+        return None;
+    }
+    let digits = &cell[1..];
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let row = (letter - b'A') as i32 + 1;
+    let column: i32 = digits.parse().ok()?;
+    (row <= rows && (1..=columns).contains(&column)).then_some((row, column))
+}
 
-            StorageZoneWithPositions { zone: zone.clone(), positions: zone_positions, items_count }
-        }).collect();
+/// Свободные ячейки построчно: A1, A2, …, B1, …
+pub fn free_grid_cells(rows: i32, columns: i32, taken: &HashSet<String>) -> impl Iterator<Item = String> + '_ {
+    (1..=rows)
+        .flat_map(move |row| (1..=columns).map(move |column| grid_cell_label(row, column)))
+        .filter(move |cell| !taken.contains(cell))
+}
 
-        let total_items: i64 = room_zones.iter().map(|z| z.items_count).sum();
-        result.push(RoomWithStorage {
-            id: room.id, name: room.name, description: room.description, capacity: room.capacity,
-            color: room.color, status: room.status, zones: room_zones, total_items,
-        });
-    }
+// ============================================================
+//              HIERARCHY & LOCATION QUERIES
+// ============================================================
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(result)))
+/// Всё дерево мест хранения любой глубины, упорядоченное по уровням (parent_id для сборки)
+pub async fn get_storage_hierarchy(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let nodes = crate::storage_locations::load_location_forest(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(nodes)))
 }
 
+/// Путь к позиции (или любому узлу дерева) от корня
 pub async fn get_location_path(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
 ) -> ApiResult<HttpResponse> {
-    let position_id = path.into_inner();
-    let location: Option<StorageLocationPath> = sqlx::query_as::<_, (
-        String, String, String, String, String, String, String, Option<String>,
-    )>(
-        r#"SELECT r.id, r.name, sz.id, sz.name, sz.zone_type, 
-                  sp.id, sp.name, sp.position_label
-           FROM storage_positions sp
-           JOIN storage_zones sz ON sp.zone_id = sz.id
-           JOIN rooms r ON sz.room_id = r.id
-           WHERE sp.id = ?"#
-    ).bind(&position_id).fetch_optional(&app_state.db_pool).await?
-    .map(|(room_id, room_name, zone_id, zone_name, zone_type, pos_id, pos_name, pos_label)| {
-        let full_path = format!("{} → {} → {}", room_name, zone_name, pos_name);
-        StorageLocationPath {
-            room_id, room_name, zone_id: Some(zone_id), zone_name: Some(zone_name),
-            zone_type: Some(zone_type), position_id: Some(pos_id), position_name: Some(pos_name),
-            position_label: pos_label, full_path,
-        }
-    });
-
-    match location {
-        Some(loc) => Ok(HttpResponse::Ok().json(ApiResponse::success(loc))),
-        None => Err(ApiError::not_found("Storage position")),
-    }
+    let location = crate::storage_locations::load_location_path(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(location)))
 }
 
 pub async fn get_position_items(
@@ -454,6 +506,7 @@ pub async fn get_position_items(
             bc.status as container_status,
             sp.id as position_id,
             sp.name as position_name,
+            bp.cell,
             b.id as batch_id,
             b.batch_number,
             b.lot_number,
@@ -481,6 +534,63 @@ pub async fn get_position_items(
     Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
 }
 
+/// GET /api/storage/positions/{id}/grid
+/// Карта занятости коробки/штатива по ячейкам
+pub async fn get_position_grid(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pos_id = path.into_inner();
+    let position: StoragePosition = sqlx::query_as("SELECT * FROM storage_positions WHERE id = ?")
+        .bind(&pos_id).fetch_optional(&app_state.db_pool).await?
+        .ok_or_else(|| ApiError::not_found("Storage position"))?;
+
+    let (rows, columns) = match (position.grid_rows, position.grid_columns) {
+        (Some(rows), Some(columns)) => (rows, columns),
+        _ => return Err(ApiError::bad_request("This position has no grid")),
+    };
+
+    let occupants: Vec<(String, String, i64, String, String, String, String)> = sqlx::query_as(
+        r#"SELECT bp.cell, bc.id, bc.sequence_number, bc.status, b.id, b.batch_number, rg.name
+           FROM batch_placements bp
+           JOIN batch_containers bc ON bp.container_id = bc.id
+           JOIN batches b ON bc.batch_id = b.id
+           JOIN reagents rg ON b.reagent_id = rg.id
+           WHERE bp.position_id = ? AND bp.cell IS NOT NULL"#
+    ).bind(&pos_id).fetch_all(&app_state.db_pool).await?;
+
+    let mut by_cell: std::collections::HashMap<String, _> = occupants.into_iter()
+        .map(|(cell, container_id, seq, status, batch_id, batch_number, reagent)| {
+            (cell, (container_id, seq, status, batch_id, batch_number, reagent))
+        })
+        .collect();
+    let occupied = by_cell.len() as i64;
+
+    let cells: Vec<Vec<GridCell>> = (1..=rows).map(|row| {
+        (1..=columns).map(|column| {
+            let cell = grid_cell_label(row, column);
+            let occupant = by_cell.remove(&cell);
+            let (container_id, sequence_number, container_status, batch_id, batch_number, reagent_name) = match occupant {
+                Some((c, seq, st, b, bn, r)) => (Some(c), Some(seq), Some(st), Some(b), Some(bn), Some(r)),
+                None => (None, None, None, None, None, None),
+            };
+            GridCell { cell, row, column, container_id, sequence_number, container_status, batch_id, batch_number, reagent_name }
+        }).collect()
+    }).collect();
+
+    let capacity = (rows * columns) as i64;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PositionGrid {
+        position_id: position.id,
+        position_name: position.name,
+        rows,
+        columns,
+        capacity,
+        occupied,
+        free: capacity - occupied,
+        cells,
+    })))
+}
+
 /// GET /api/storage/zones/{id}/items
 /// Get all batches stored in all positions of a given zone
 pub async fn get_zone_items(
@@ -504,6 +614,7 @@ pub async fn get_zone_items(
             bc.status as container_status,
             sp.id as position_id,
             sp.name as position_name,
+            bp.cell,
             b.id as batch_id,
             b.batch_number,
             b.lot_number,
@@ -531,20 +642,20 @@ pub async fn get_zone_items(
     Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
 }
 
-/// Utility for updating position count cache
-#[allow(dead_code)]
-pub async fn update_position_count(pool: &SqlitePool, position_id: &str) -> ApiResult<()> {
-    // ИСПРАВЛЕНИЕ: Подсчет уникальных батчей через batch_containers
+/// Пересчёт кэша current_count (число контейнеров) и статуса available/full.
+/// Статусы maintenance/unavailable выставляются вручную и не перезаписываются.
+pub async fn refresh_position_occupancy(conn: &mut SqliteConnection, position_id: &str) -> ApiResult<()> {
     sqlx::query(
         r#"UPDATE storage_positions 
-           SET current_count = (
-               SELECT COUNT(DISTINCT bc.batch_id) 
-               FROM batch_placements bp 
-               JOIN batch_containers bc ON bp.container_id = bc.id 
-               WHERE bp.position_id = ?
-           )
+           SET current_count = (SELECT COUNT(*) FROM batch_placements WHERE position_id = ?),
+               status = CASE
+                   WHEN status NOT IN ('available', 'full') THEN status
+                   WHEN max_capacity IS NOT NULL
+                        AND (SELECT COUNT(*) FROM batch_placements WHERE position_id = ?) >= max_capacity THEN 'full'
+                   ELSE 'available'
+               END
            WHERE id = ?"#
-    ).bind(position_id).bind(position_id).execute(pool).await?;
+    ).bind(position_id).bind(position_id).bind(position_id).execute(conn).await?;
     Ok(())
 }
 
@@ -555,22 +666,34 @@ pub async fn search_storage_locations(
     let search = query.get("q").map(|s| s.as_str()).unwrap_or("");
     if search.is_empty() { return Err(ApiError::bad_request("Search query 'q' is required")); }
 
-    let pattern = format!("%{}%", search);
+    let results = find_storage_locations(&app_state.db_pool, search, 20).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(results)))
+}
+
+/// Поиск позиций хранения по названию позиции, зоны или комнаты (также для глобального поиска)
+pub async fn find_storage_locations(pool: &SqlitePool, search: &str, limit: i64) -> ApiResult<Vec<serde_json::Value>> {
+    let pattern = format!("%{}%", crate::query_builders::utils::escape_like_value(search));
     let results: Vec<serde_json::Value> = sqlx::query_as::<_, (
-        String, String, Option<String>, String, String, String, String, String,
+        String, String, Option<String>, String, String, String, String, String, Option<String>,
     )>(
         r#"SELECT sp.id, sp.name, sp.position_label, sz.name, sz.zone_type,
-                  r.name, r.id, sz.id
+                  r.name, r.id, sz.id,
+                  (SELECT group_concat(name, ' → ') FROM (
+                       SELECT a.name FROM storage_locations n
+                       JOIN storage_locations a ON instr(n.path, '/' || a.id || '/') > 0
+                       WHERE n.id = sp.id ORDER BY a.depth
+                   )) as tree_path
            FROM storage_positions sp
            JOIN storage_zones sz ON sp.zone_id = sz.id
            JOIN rooms r ON sz.room_id = r.id
-           WHERE sp.name LIKE ? OR sp.position_label LIKE ? OR sz.name LIKE ? OR r.name LIKE ?
-           ORDER BY r.name, sz.sort_order, sp.sort_order LIMIT 20"#
+           WHERE sp.name LIKE ? ESCAPE '\' OR sp.position_label LIKE ? ESCAPE '\'
+              OR sz.name LIKE ? ESCAPE '\' OR r.name LIKE ? ESCAPE '\'
+           ORDER BY r.name, sz.sort_order, sp.sort_order LIMIT ?"#
     )
-    .bind(&pattern).bind(&pattern).bind(&pattern).bind(&pattern)
-    .fetch_all(&app_state.db_pool).await?.into_iter()
-    .map(|(pos_id, pos_name, pos_label, zone_name, zone_type, room_name, room_id, zone_id)| {
-        let full_path = format!("{} → {} → {}", room_name, zone_name, pos_name);
+    .bind(&pattern).bind(&pattern).bind(&pattern).bind(&pattern).bind(limit)
+    .fetch_all(pool).await?.into_iter()
+    .map(|(pos_id, pos_name, pos_label, zone_name, zone_type, room_name, room_id, zone_id, tree_path)| {
+        let full_path = tree_path.unwrap_or_else(|| format!("{} → {} → {}", room_name, zone_name, pos_name));
         serde_json::json!({
             "position_id": pos_id, "position_name": pos_name, "position_label": pos_label,
             "zone_id": zone_id, "zone_name": zone_name, "zone_type": zone_type,
@@ -578,5 +701,33 @@ pub async fn search_storage_locations(
         })
     }).collect();
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(results)))
-}
\ No newline at end of file
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_cells() {
+        assert_eq!(grid_cell_label(1, 1), "A1");
+        assert_eq!(grid_cell_label(9, 9), "I9");
+        assert_eq!(parse_grid_cell("b07", 9, 9), Some((2, 7)));
+        assert_eq!(parse_grid_cell(" I9 ", 9, 9), Some((9, 9)));
+        assert_eq!(parse_grid_cell("J1", 9, 9), None);
+        assert_eq!(parse_grid_cell("A10", 9, 9), None);
+        assert_eq!(parse_grid_cell("A0", 9, 9), None);
+        assert_eq!(parse_grid_cell("A+1", 9, 9), None);
+        assert_eq!(parse_grid_cell("", 9, 9), None);
+        assert_eq!(parse_grid_cell("Б1", 9, 9), None);
+
+        let taken: HashSet<String> = ["A1", "A2"].iter().map(|c| c.to_string()).collect();
+        let free: Vec<String> = free_grid_cells(2, 2, &taken).collect();
+        assert_eq!(free, vec!["B1", "B2"]);
+
+        assert_eq!(grid_capacity(Some(9), Some(9), None).unwrap(), Some(81));
+        assert!(grid_capacity(Some(9), Some(9), Some(50)).is_err());
+        assert!(grid_capacity(Some(9), None, None).is_err());
+        assert_eq!(grid_capacity(None, None, Some(12)).unwrap(), Some(12));
+    }
+}
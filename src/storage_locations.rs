@@ -0,0 +1,720 @@
+// src/storage_locations.rs
+//! Дерево мест хранения произвольной глубины (список смежности + материализованный путь).
+//!
+//! Комнаты, зоны и позиции — узлы с тем же id, что и запись в своей таблице;
+//! триггеры в БД создают/переименовывают/удаляют их узлы. Через дерево можно
+//! добавлять промежуточные уровни (здание, этаж, стеллаж, коробка) и переносить
+//! поддеревья целиком. Ограничения прежней модели сохраняются:
+//! позиция всегда внутри зоны, зона — внутри комнаты, комнаты не вкладываются друг в друга.
+//!
+//! Endpoints:
+//!   GET    /api/v1/storage/locations?parent_id=        — дочерние узлы (без parent_id — корни)
+//!   POST   /api/v1/storage/locations                   — создать узел
+//!   GET    /api/v1/storage/locations/{id}              — узел
+//!   PUT    /api/v1/storage/locations/{id}              — переименовать / изменить тип
+//!   DELETE /api/v1/storage/locations/{id}              — удалить пустой структурный узел
+//!   POST   /api/v1/storage/locations/{id}/move         — перенести поддерево
+//!   GET    /api/v1/storage/locations/{id}/tree         — поддерево с количеством контейнеров
+//!   GET    /api/v1/storage/locations/{id}/inventory    — контейнеры во всём поддереве
+//!   GET    /api/v1/storage/locations/{id}/path         — путь от корня
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::storage_handlers::grid_capacity;
+
+/// Поддерево узла (включая сам узел) по parent_id
+const SUBTREE_CTE: &str = r#"
+    WITH RECURSIVE subtree AS (
+        SELECT * FROM storage_locations WHERE id = ?
+        UNION ALL
+        SELECT c.* FROM storage_locations c JOIN subtree s ON c.parent_id = s.id
+    )
+"#;
+
+/// Цепочка предков узла (включая сам узел) по parent_id
+const ANCESTORS_CTE: &str = r#"
+    WITH RECURSIVE ancestors AS (
+        SELECT * FROM storage_locations WHERE id = ?
+        UNION ALL
+        SELECT p.* FROM storage_locations p JOIN ancestors a ON p.id = a.parent_id
+    )
+"#;
+
+// ==================== HELPERS ====================
+
+async fn get_location_or_404(conn: &mut SqliteConnection, id: &str) -> ApiResult<StorageLocation> {
+    sqlx::query_as("SELECT * FROM storage_locations WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Storage location"))
+}
+
+/// Путь и глубина нового дочернего узла
+fn child_path(parent: Option<&StorageLocation>, id: &str) -> (String, i32) {
+    match parent {
+        Some(p) => (format!("{}{}/", p.path, id), p.depth + 1),
+        None => (format!("/{}/", id), 0),
+    }
+}
+
+/// id узлов материализованного пути от корня: "/a/b/c/" → [a, b, c]
+fn path_ids(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// Ближайший предок (или сам узел пути) с данным linked_entity
+async fn nearest_linked(conn: &mut SqliteConnection, path: Option<&str>, entity: &str) -> ApiResult<Option<String>> {
+    let Some(path) = path else { return Ok(None) };
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"SELECT id FROM storage_locations
+           WHERE linked_entity = ? AND substr(?, 1, length(path)) = path
+           ORDER BY depth DESC LIMIT 1"#
+    )
+    .bind(entity)
+    .bind(path)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(|r| r.0))
+}
+
+fn name_conflict(err: sqlx::Error) -> ApiError {
+    match &err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            ApiError::bad_request("A location with this name already exists at this level")
+        }
+        _ => err.into(),
+    }
+}
+
+/// Количество размещённых контейнеров по позициям
+async fn placement_counts(pool: &SqlitePool) -> ApiResult<HashMap<String, i64>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT position_id, COUNT(*) FROM batch_placements GROUP BY position_id"
+    ).fetch_all(pool).await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Узлы с количеством контейнеров в самом узле и во всём поддереве
+pub fn build_location_nodes(locations: Vec<StorageLocation>, counts: &HashMap<String, i64>) -> Vec<StorageLocationNode> {
+    let mut totals: HashMap<&str, i64> = HashMap::new();
+    for loc in &locations {
+        if let Some(&count) = counts.get(&loc.id) {
+            for ancestor in path_ids(&loc.path) {
+                *totals.entry(ancestor).or_insert(0) += count;
+            }
+        }
+    }
+    let totals: HashMap<String, i64> = totals.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+    locations.into_iter().map(|location| {
+        let items_count = counts.get(&location.id).copied().unwrap_or(0);
+        let total_items = totals.get(&location.id).copied().unwrap_or(0);
+        StorageLocationNode { location, items_count, total_items }
+    }).collect()
+}
+
+/// Всё дерево, упорядоченное по глубине (для GET /storage/hierarchy)
+pub async fn load_location_forest(pool: &SqlitePool) -> ApiResult<Vec<StorageLocationNode>> {
+    let locations: Vec<StorageLocation> = sqlx::query_as(
+        "SELECT * FROM storage_locations ORDER BY depth, sort_order, name"
+    ).fetch_all(pool).await?;
+    let counts = placement_counts(pool).await?;
+    Ok(build_location_nodes(locations, &counts))
+}
+
+/// Путь от корня до узла любой глубины
+pub async fn load_location_path(pool: &SqlitePool, id: &str) -> ApiResult<StorageLocationPath> {
+    let sql = format!("{} SELECT * FROM ancestors ORDER BY depth", ANCESTORS_CTE);
+    let nodes: Vec<StorageLocation> = sqlx::query_as(&sql).bind(id).fetch_all(pool).await?;
+    if nodes.is_empty() {
+        return Err(ApiError::not_found("Storage location"));
+    }
+
+    let linked = |entity: &str| nodes.iter().rev().find(|n| n.linked_entity.as_deref() == Some(entity));
+    let room = linked("room");
+    let zone = linked("zone");
+    let position = linked("position");
+    let position_label: Option<String> = match position {
+        Some(p) => sqlx::query_scalar("SELECT position_label FROM storage_positions WHERE id = ?")
+            .bind(&p.id).fetch_optional(pool).await?.flatten(),
+        None => None,
+    };
+
+    Ok(StorageLocationPath {
+        room_id: room.map(|n| n.id.clone()),
+        room_name: room.map(|n| n.name.clone()),
+        zone_id: zone.map(|n| n.id.clone()),
+        zone_name: zone.map(|n| n.name.clone()),
+        zone_type: zone.map(|n| n.location_type.clone()),
+        position_id: position.map(|n| n.id.clone()),
+        position_name: position.map(|n| n.name.clone()),
+        position_label,
+        full_path: nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>().join(" → "),
+        nodes,
+    })
+}
+
+// ==================== READ ====================
+
+pub async fn get_location_children(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<LocationChildrenQuery>,
+) -> ApiResult<HttpResponse> {
+    let locations: Vec<StorageLocation> = match query.parent_id {
+        Some(ref parent_id) => sqlx::query_as(
+            "SELECT * FROM storage_locations WHERE parent_id = ? ORDER BY sort_order, name"
+        ).bind(parent_id).fetch_all(&app_state.db_pool).await?,
+        None => sqlx::query_as(
+            "SELECT * FROM storage_locations WHERE parent_id IS NULL ORDER BY sort_order, name"
+        ).fetch_all(&app_state.db_pool).await?,
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success(locations)))
+}
+
+pub async fn get_storage_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let mut conn = app_state.db_pool.acquire().await?;
+    let location = get_location_or_404(&mut conn, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(location)))
+}
+
+pub async fn get_location_tree(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let sql = format!("{} SELECT * FROM subtree ORDER BY depth, sort_order, name", SUBTREE_CTE);
+    let locations: Vec<StorageLocation> = sqlx::query_as(&sql)
+        .bind(path.into_inner())
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    if locations.is_empty() {
+        return Err(ApiError::not_found("Storage location"));
+    }
+    let counts = placement_counts(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(build_location_nodes(locations, &counts))))
+}
+
+pub async fn get_location_inventory(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let location_id = path.into_inner();
+    let mut conn = app_state.db_pool.acquire().await?;
+    get_location_or_404(&mut conn, &location_id).await?;
+
+    let sql = format!(r#"{}
+        SELECT
+            bc.id as container_id,
+            bc.sequence_number,
+            bc.quantity as container_quantity,
+            bc.is_opened,
+            bc.status as container_status,
+            sp.id as position_id,
+            sp.name as position_name,
+            bp.cell,
+            b.id as batch_id,
+            b.batch_number,
+            b.lot_number,
+            b.unit,
+            b.quantity as total_quantity,
+            b.expiry_date,
+            b.status as batch_status,
+            rg.id as reagent_id,
+            rg.name as reagent_name,
+            rg.formula,
+            rg.cas_number,
+            rg.hazard_pictograms
+        FROM subtree st
+        JOIN batch_placements bp ON bp.position_id = st.id
+        JOIN batch_containers bc ON bp.container_id = bc.id
+        JOIN storage_positions sp ON bp.position_id = sp.id
+        JOIN batches b ON bc.batch_id = b.id
+        JOIN reagents rg ON b.reagent_id = rg.id
+        WHERE b.deleted_at IS NULL AND bc.status != 'disposed'
+        ORDER BY st.path, rg.name, b.batch_number, bc.sequence_number"#, SUBTREE_CTE);
+    let items: Vec<PositionInventoryItem> = sqlx::query_as(&sql)
+        .bind(&location_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
+}
+
+pub async fn get_storage_location_path(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let location = load_location_path(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(location)))
+}
+
+// ==================== CREATE ====================
+
+/// Тип узла определяет, что создаётся:
+/// room — комната; placeable (или заданы вместимость/сетка) — позиция внутри зоны;
+/// тип зоны хранения внутри комнаты (вне зоны) — зона; остальное — структурный узел.
+pub async fn create_storage_location(
+    app_state: web::Data<Arc<AppState>>,
+    data: web::Json<CreateStorageLocationRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    data.validate()?;
+    if !LOCATION_TYPES.contains(&data.location_type.as_str()) {
+        return Err(ApiError::bad_request(&format!("Invalid location type. Allowed: {}", LOCATION_TYPES.join(", "))));
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let parent = match data.parent_id {
+        Some(ref parent_id) => Some(get_location_or_404(&mut tx, parent_id).await?),
+        None => None,
+    };
+    if parent.as_ref().and_then(|p| p.linked_entity.as_deref()) == Some("position") {
+        return Err(ApiError::bad_request("Containers are placed at positions; a position cannot have child locations"));
+    }
+
+    let parent_path = parent.as_ref().map(|p| p.path.as_str());
+    let room_id = nearest_linked(&mut tx, parent_path, "room").await?;
+    let zone_id = nearest_linked(&mut tx, parent_path, "zone").await?;
+    let placeable = data.placeable.unwrap_or(
+        data.max_capacity.is_some() || data.grid_rows.is_some() || data.grid_columns.is_some()
+    );
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let sort_order = data.sort_order.unwrap_or(0);
+    let (path, depth) = child_path(parent.as_ref(), &id);
+
+    let linked_entity = if data.location_type == "room" {
+        if placeable {
+            return Err(ApiError::bad_request("A room cannot hold containers directly; add a position inside it"));
+        }
+        if room_id.is_some() || zone_id.is_some() {
+            return Err(ApiError::bad_request("Rooms cannot be nested inside rooms or storage zones"));
+        }
+        let dup: Option<(String,)> = sqlx::query_as("SELECT id FROM rooms WHERE LOWER(name) = LOWER(?)")
+            .bind(&data.name).fetch_optional(&mut *tx).await?;
+        if dup.is_some() {
+            return Err(ApiError::bad_request("Room with this name already exists"));
+        }
+        sqlx::query(
+            r#"INSERT INTO rooms (id, name, description, color, status, created_by, updated_by, created_at, updated_at)
+               VALUES (?, ?, ?, '#667eea', 'available', ?, ?, ?, ?)"#
+        )
+        .bind(&id).bind(&data.name).bind(&data.description)
+        .bind(&user_id).bind(&user_id).bind(now).bind(now)
+        .execute(&mut *tx).await?;
+        Some("room")
+    } else if placeable {
+        let zone_id = zone_id.ok_or_else(|| ApiError::bad_request("A placeable location must be inside a storage zone"))?;
+        let dup: Option<(String,)> = sqlx::query_as(
+            "SELECT id FROM storage_positions WHERE zone_id = ? AND LOWER(name) = LOWER(?)"
+        ).bind(&zone_id).bind(&data.name).fetch_optional(&mut *tx).await?;
+        if dup.is_some() {
+            return Err(ApiError::bad_request("A position with this name already exists in this zone"));
+        }
+        let max_capacity = grid_capacity(data.grid_rows, data.grid_columns, data.max_capacity)?;
+        sqlx::query(
+            r#"INSERT INTO storage_positions
+               (id, zone_id, name, position_label, max_capacity, current_count, grid_rows, grid_columns,
+                sort_order, description, status, created_by, updated_by, created_at, updated_at)
+               VALUES (?, ?, ?, ?, ?, 0, ?, ?, ?, ?, 'available', ?, ?, ?, ?)"#
+        )
+        .bind(&id).bind(&zone_id).bind(&data.name).bind(&data.position_label).bind(max_capacity)
+        .bind(data.grid_rows).bind(data.grid_columns).bind(sort_order).bind(&data.description)
+        .bind(&user_id).bind(&user_id).bind(now).bind(now)
+        .execute(&mut *tx).await?;
+        Some("position")
+    } else if let (true, Some(room_id), None) = (StorageZoneType::is_valid(&data.location_type), &room_id, &zone_id) {
+        let dup: Option<(String,)> = sqlx::query_as(
+            "SELECT id FROM storage_zones WHERE room_id = ? AND LOWER(name) = LOWER(?)"
+        ).bind(room_id).bind(&data.name).fetch_optional(&mut *tx).await?;
+        if dup.is_some() {
+            return Err(ApiError::bad_request("A storage zone with this name already exists in this room"));
+        }
+        sqlx::query(
+            r#"INSERT INTO storage_zones
+               (id, room_id, name, zone_type, description, sort_order, status,
+                created_by, updated_by, created_at, updated_at)
+               VALUES (?, ?, ?, ?, ?, ?, 'available', ?, ?, ?, ?)"#
+        )
+        .bind(&id).bind(room_id).bind(&data.name).bind(&data.location_type)
+        .bind(&data.description).bind(sort_order)
+        .bind(&user_id).bind(&user_id).bind(now).bind(now)
+        .execute(&mut *tx).await?;
+        Some("zone")
+    } else {
+        sqlx::query(
+            r#"INSERT INTO storage_locations
+               (id, parent_id, location_type, name, linked_entity, path, depth, sort_order, description,
+                created_by, created_at, updated_at)
+               VALUES (?, ?, ?, ?, NULL, ?, ?, ?, ?, ?, ?, ?)"#
+        )
+        .bind(&id).bind(&data.parent_id).bind(&data.location_type).bind(&data.name)
+        .bind(&path).bind(depth).bind(sort_order).bind(&data.description)
+        .bind(&user_id).bind(now).bind(now)
+        .execute(&mut *tx).await?;
+        None
+    };
+
+    // Триггер поставил узел под комнату/зону; переносим его под запрошенного родителя
+    if linked_entity.is_some() {
+        sqlx::query(
+            r#"UPDATE storage_locations
+               SET parent_id = ?, path = ?, depth = ?, location_type = ?, sort_order = ?, description = ?, created_by = ?
+               WHERE id = ?"#
+        )
+        .bind(&data.parent_id).bind(&path).bind(depth).bind(&data.location_type)
+        .bind(sort_order).bind(&data.description).bind(&user_id).bind(&id)
+        .execute(&mut *tx).await?;
+    }
+
+    let created = get_location_or_404(&mut tx, &id).await?;
+    tx.commit().await?;
+
+    info!("🗄️ Created storage location: {} ({}) at depth {}", created.name, created.location_type, created.depth);
+    Ok(HttpResponse::Created().json(ApiResponse::success(created)))
+}
+
+// ==================== UPDATE / DELETE ====================
+
+pub async fn update_storage_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    data: web::Json<UpdateStorageLocationRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    data.validate()?;
+    let location_id = path.into_inner();
+    let mut tx = app_state.db_pool.begin().await?;
+    let existing = get_location_or_404(&mut tx, &location_id).await?;
+
+    let location_type = data.location_type.as_ref().unwrap_or(&existing.location_type);
+    if !LOCATION_TYPES.contains(&location_type.as_str()) {
+        return Err(ApiError::bad_request(&format!("Invalid location type. Allowed: {}", LOCATION_TYPES.join(", "))));
+    }
+    match existing.linked_entity.as_deref() {
+        Some("room") if location_type != "room" => {
+            return Err(ApiError::bad_request("A room keeps the 'room' type"));
+        }
+        Some("zone") if !StorageZoneType::is_valid(location_type) => {
+            return Err(ApiError::bad_request("A storage zone must have a storage zone type"));
+        }
+        Some("room") => {}
+        _ if location_type == "room" => {
+            return Err(ApiError::bad_request("Only rooms can have the 'room' type"));
+        }
+        _ => {}
+    }
+
+    let now = Utc::now();
+    let name = data.name.as_ref().unwrap_or(&existing.name);
+    let description = data.description.clone().or(existing.description);
+    let sort_order = data.sort_order.unwrap_or(existing.sort_order);
+
+    // Связанная запись обновляется первой; триггер синхронизирует имя узла
+    match existing.linked_entity.as_deref() {
+        Some("room") => {
+            sqlx::query("UPDATE rooms SET name = ?, description = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+                .bind(name).bind(&description).bind(&user_id).bind(now).bind(&location_id)
+                .execute(&mut *tx).await.map_err(name_conflict)?;
+        }
+        Some("zone") => {
+            sqlx::query(
+                r#"UPDATE storage_zones SET name = ?, zone_type = ?, description = ?, sort_order = ?,
+                       updated_by = ?, updated_at = ? WHERE id = ?"#
+            )
+            .bind(name).bind(location_type).bind(&description).bind(sort_order)
+            .bind(&user_id).bind(now).bind(&location_id)
+            .execute(&mut *tx).await.map_err(name_conflict)?;
+        }
+        Some("position") => {
+            sqlx::query(
+                r#"UPDATE storage_positions SET name = ?, description = ?, sort_order = ?,
+                       updated_by = ?, updated_at = ? WHERE id = ?"#
+            )
+            .bind(name).bind(&description).bind(sort_order)
+            .bind(&user_id).bind(now).bind(&location_id)
+            .execute(&mut *tx).await.map_err(name_conflict)?;
+        }
+        _ => {}
+    }
+
+    sqlx::query(
+        r#"UPDATE storage_locations SET name = ?, location_type = ?, description = ?, sort_order = ?, updated_at = ?
+           WHERE id = ?"#
+    )
+    .bind(name).bind(location_type).bind(&description).bind(sort_order).bind(now).bind(&location_id)
+    .execute(&mut *tx).await?;
+
+    let updated = get_location_or_404(&mut tx, &location_id).await?;
+    tx.commit().await?;
+
+    info!("🗄️ Updated storage location: {} ({})", updated.name, location_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+}
+
+/// Удаляются только пустые структурные узлы; комнаты, зоны и позиции — через свои endpoints
+pub async fn delete_storage_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let location_id = path.into_inner();
+    let mut tx = app_state.db_pool.begin().await?;
+    let existing = get_location_or_404(&mut tx, &location_id).await?;
+
+    if let Some(ref entity) = existing.linked_entity {
+        return Err(ApiError::bad_request(&format!("This location is a {}; delete it through the {} endpoint", entity, entity)));
+    }
+    let children: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM storage_locations WHERE parent_id = ?")
+        .bind(&location_id).fetch_one(&mut *tx).await?;
+    if children.0 > 0 {
+        return Err(ApiError::bad_request(&format!("Cannot delete: {} child locations. Move or delete them first.", children.0)));
+    }
+
+    sqlx::query("DELETE FROM storage_locations WHERE id = ?").bind(&location_id).execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    info!("🗄️ Deleted storage location: {}", location_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message((), "Storage location deleted successfully".to_string())))
+}
+
+// ==================== MOVE SUBTREE ====================
+
+/// Первый узел поддерева, нарушающий вложенность комнат/зон/позиций после переноса
+const SUBTREE_VIOLATION_QUERY: &str = r#"
+    SELECT n.linked_entity, n.name FROM storage_locations n
+    WHERE substr(n.path, 1, length(?1)) = ?1 AND (
+        (n.linked_entity = 'position' AND NOT EXISTS (
+            SELECT 1 FROM storage_locations a
+            WHERE a.linked_entity = 'zone' AND a.id != n.id AND substr(n.path, 1, length(a.path)) = a.path))
+        OR (n.linked_entity = 'zone' AND (
+            NOT EXISTS (
+                SELECT 1 FROM storage_locations a
+                WHERE a.linked_entity = 'room' AND substr(n.path, 1, length(a.path)) = a.path)
+            OR EXISTS (
+                SELECT 1 FROM storage_locations a
+                WHERE a.linked_entity = 'zone' AND a.id != n.id AND substr(n.path, 1, length(a.path)) = a.path)))
+        OR (n.linked_entity = 'room' AND EXISTS (
+            SELECT 1 FROM storage_locations a
+            WHERE a.linked_entity IN ('room', 'zone') AND a.id != n.id AND substr(n.path, 1, length(a.path)) = a.path))
+    )
+    LIMIT 1
+"#;
+
+pub async fn move_storage_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    data: web::Json<MoveStorageLocationRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let location_id = path.into_inner();
+    let mut tx = app_state.db_pool.begin().await?;
+    let node = get_location_or_404(&mut tx, &location_id).await?;
+
+    if node.parent_id == data.new_parent_id {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(node)));
+    }
+
+    let new_parent = match data.new_parent_id {
+        Some(ref parent_id) => Some(get_location_or_404(&mut tx, parent_id).await?),
+        None => None,
+    };
+    if let Some(ref parent) = new_parent {
+        if parent.path.starts_with(&node.path) {
+            return Err(ApiError::bad_request("Cannot move a location into itself or its own subtree"));
+        }
+        if parent.linked_entity.as_deref() == Some("position") {
+            return Err(ApiError::bad_request("Containers are placed at positions; a position cannot have child locations"));
+        }
+    }
+
+    let (new_path, new_depth) = child_path(new_parent.as_ref(), &node.id);
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"UPDATE storage_locations
+           SET path = ? || substr(path, ? + 1), depth = depth + ?, updated_at = ?
+           WHERE substr(path, 1, ?) = ?"#
+    )
+    .bind(&new_path)
+    .bind(node.path.len() as i64)
+    .bind(new_depth - node.depth)
+    .bind(now)
+    .bind(node.path.len() as i64)
+    .bind(&node.path)
+    .execute(&mut *tx).await?;
+    sqlx::query("UPDATE storage_locations SET parent_id = ? WHERE id = ?")
+        .bind(&data.new_parent_id).bind(&node.id)
+        .execute(&mut *tx).await?;
+
+    let violation: Option<(String, String)> = sqlx::query_as(SUBTREE_VIOLATION_QUERY)
+        .bind(&new_path)
+        .fetch_optional(&mut *tx).await?;
+    if let Some((entity, name)) = violation {
+        let rule = match entity.as_str() {
+            "position" => "positions must stay inside a storage zone",
+            "zone" => "storage zones must stay inside a room and not inside another zone",
+            _ => "rooms cannot be nested inside rooms or storage zones",
+        };
+        return Err(ApiError::bad_request(&format!("Cannot move here: '{}' — {}", name, rule)));
+    }
+
+    // Размещённые в поддереве контейнеры: если комната сменится, история должна это видеть
+    let placed: Vec<(String, String, Option<String>, String)> = sqlx::query_as(
+        r#"SELECT bp.container_id, bp.position_id, bp.cell, sz.room_id
+           FROM batch_placements bp
+           JOIN storage_positions sp ON sp.id = bp.position_id
+           JOIN storage_zones sz ON sz.id = sp.zone_id
+           WHERE bp.position_id IN (
+               SELECT id FROM storage_locations WHERE linked_entity = 'position' AND substr(path, 1, length(?1)) = ?1)"#
+    )
+    .bind(&new_path)
+    .fetch_all(&mut *tx).await?;
+
+    // Прежняя модель: room_id зон и zone_id позиций — ближайшие предки в дереве
+    sqlx::query(
+        r#"UPDATE storage_zones SET room_id = (
+               SELECT a.id FROM storage_locations n
+               JOIN storage_locations a ON a.linked_entity = 'room' AND substr(n.path, 1, length(a.path)) = a.path
+               WHERE n.id = storage_zones.id ORDER BY a.depth DESC LIMIT 1
+           ), updated_at = ?2
+           WHERE id IN (SELECT id FROM storage_locations WHERE linked_entity = 'zone' AND substr(path, 1, length(?1)) = ?1)"#
+    )
+    .bind(&new_path).bind(now)
+    .execute(&mut *tx).await.map_err(name_conflict)?;
+    sqlx::query(
+        r#"UPDATE storage_positions SET zone_id = (
+               SELECT a.id FROM storage_locations n
+               JOIN storage_locations a ON a.linked_entity = 'zone' AND a.id != n.id
+                    AND substr(n.path, 1, length(a.path)) = a.path
+               WHERE n.id = storage_positions.id ORDER BY a.depth DESC LIMIT 1
+           ), updated_at = ?2
+           WHERE id IN (SELECT id FROM storage_locations WHERE linked_entity = 'position' AND substr(path, 1, length(?1)) = ?1)"#
+    )
+    .bind(&new_path).bind(now)
+    .execute(&mut *tx).await.map_err(name_conflict)?;
+
+    // Позиция и ячейка те же, меняется только комната
+    for (container_id, position_id, cell, from_room_id) in &placed {
+        sqlx::query(
+            r#"INSERT INTO container_movements
+               (id, container_id, batch_id, action, from_position_id, from_room_id,
+                to_position_id, to_room_id, from_cell, to_cell, moved_by, moved_at, notes)
+               SELECT ?1, bc.id, bc.batch_id, 'move', ?2, ?3, ?2, sz.room_id, ?4, ?4, ?5, ?6, ?7
+               FROM batch_containers bc
+               JOIN storage_positions sp ON sp.id = ?2
+               JOIN storage_zones sz ON sz.id = sp.zone_id
+               WHERE bc.id = ?8 AND sz.room_id != ?3"#
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(position_id)
+        .bind(from_room_id)
+        .bind(cell)
+        .bind(&user_id)
+        .bind(now)
+        .bind(format!("Storage location '{}' moved", node.name))
+        .bind(container_id)
+        .execute(&mut *tx).await?;
+    }
+
+    let moved = get_location_or_404(&mut tx, &node.id).await?;
+    tx.commit().await?;
+
+    info!("🗄️ Moved storage location {} to {}", moved.name, data.new_parent_id.as_deref().unwrap_or("root"));
+    Ok(HttpResponse::Ok().json(ApiResponse::success(moved)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(id: &str, path: &str) -> StorageLocation {
+        StorageLocation {
+            id: id.to_string(),
+            parent_id: None,
+            location_type: "other".to_string(),
+            name: id.to_string(),
+            linked_entity: None,
+            path: path.to_string(),
+            depth: path_ids(path).count() as i32 - 1,
+            sort_order: 0,
+            description: None,
+            created_by: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_child_path_and_subtree_totals() {
+        let root = loc("b", "/b/");
+        assert_eq!(child_path(Some(&root), "f"), ("/b/f/".to_string(), 1));
+        assert_eq!(child_path(None, "b"), ("/b/".to_string(), 0));
+
+        let locations = vec![loc("b", "/b/"), loc("f", "/b/f/"), loc("x", "/b/f/x/"), loc("y", "/b/y/")];
+        let counts: HashMap<String, i64> = [("x".to_string(), 3), ("y".to_string(), 2)].into_iter().collect();
+        let nodes = build_location_nodes(locations, &counts);
+        let totals: Vec<(i64, i64)> = nodes.iter().map(|n| (n.items_count, n.total_items)).collect();
+        assert_eq!(totals, vec![(0, 5), (0, 3), (3, 3), (2, 2)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_moving_zone_records_room_change_for_placed_containers() {
+        let pool = crate::db::test_pool().await;
+        let now = Utc::now();
+
+        for room in ["ra", "rb"] {
+            sqlx::query("INSERT INTO rooms (id, name, created_at, updated_at) VALUES (?, ?, ?, ?)")
+                .bind(room).bind(room).bind(now).bind(now).execute(&pool).await.unwrap();
+        }
+        sqlx::query("INSERT INTO storage_zones (id, room_id, name, created_at, updated_at) VALUES ('z1', 'ra', 'Cabinet', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO storage_positions (id, zone_id, name, created_at, updated_at) VALUES ('p1', 'z1', 'Shelf 1', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Ethanol', ?, ?)")
+            .bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, received_date, created_at, updated_at)
+            VALUES ('b1', 'r1', 'B-1', 100, 100, 'ml', ?, ?, ?)
+        "#).bind(now).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query(r#"
+            INSERT INTO batch_containers (id, batch_id, sequence_number, quantity, original_quantity, created_at, updated_at)
+            VALUES ('c1', 'b1', 1, 100, 100, ?, ?)
+        "#).bind(now).bind(now).execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO batch_placements (id, container_id, position_id, cell, placed_at) VALUES ('pl1', 'c1', 'p1', 'A1', ?)")
+            .bind(now).execute(&pool).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            storage: Arc::new(crate::storage::BlobStore::new(Arc::new(
+                crate::storage::LocalStorage::new(dir.path().to_str().unwrap()),
+            ))),
+        }));
+
+        let request = MoveStorageLocationRequest { new_parent_id: Some("rb".to_string()) };
+        move_storage_location(app_state, web::Path::from("z1".to_string()), web::Json(request), "u1".to_string())
+            .await
+            .unwrap();
+
+        let moves: Vec<(String, String, String, String, String)> = sqlx::query_as(
+            "SELECT action, from_position_id, to_position_id, from_room_id, to_room_id FROM container_movements WHERE container_id = 'c1'"
+        ).fetch_all(&pool).await.unwrap();
+        let moves: Vec<[&str; 5]> = moves.iter().map(|m| [&*m.0, &*m.1, &*m.2, &*m.3, &*m.4]).collect();
+        assert_eq!(moves, vec![["move", "p1", "p1", "ra", "rb"]]);
+    }
+}
@@ -0,0 +1,178 @@
+// src/thumbnails.rs
+//! Обработка фотографий оборудования при загрузке.
+//!
+//! - EXIF (включая GPS и серийные номера камер) удаляется перекодированием;
+//!   поворот из EXIF при этом применяется к пикселям, чтобы фото не «легло набок».
+//! - Размеры и ориентация сохраняются в `equipment_files`.
+//! - Превью нескольких размеров кладутся в хранилище и отдаются через
+//!   `GET .../files/{file_id}?size=thumb|small|medium`.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::Deserialize;
+use std::io::Cursor;
+
+use crate::error::{ApiError, ApiResult};
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailSize {
+    Thumb,
+    Small,
+    Medium,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 3] = [ThumbnailSize::Thumb, ThumbnailSize::Small, ThumbnailSize::Medium];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThumbnailSize::Thumb => "thumb",
+            ThumbnailSize::Small => "small",
+            ThumbnailSize::Medium => "medium",
+        }
+    }
+
+    /// Максимальная сторона превью в пикселях
+    pub fn max_edge(&self) -> u32 {
+        match self {
+            ThumbnailSize::Thumb => 160,
+            ThumbnailSize::Small => 480,
+            ThumbnailSize::Medium => 1280,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Thumbnail {
+    pub size: ThumbnailSize,
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub mime_type: &'static str,
+}
+
+#[derive(Debug)]
+pub struct ProcessedPhoto {
+    /// Оригинал без метаданных (None — формат не несёт EXIF, храним как есть)
+    pub stripped: Option<Vec<u8>>,
+    /// Размеры с учётом поворота из EXIF
+    pub width: u32,
+    pub height: u32,
+    pub exif_orientation: u8,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+impl ProcessedPhoto {
+    pub fn orientation(&self) -> &'static str {
+        orientation_label(self.width, self.height)
+    }
+}
+
+pub fn orientation_label(width: u32, height: u32) -> &'static str {
+    match width.cmp(&height) {
+        std::cmp::Ordering::Greater => "landscape",
+        std::cmp::Ordering::Less => "portrait",
+        std::cmp::Ordering::Equal => "square",
+    }
+}
+
+fn image_error(e: image::ImageError) -> ApiError {
+    ApiError::bad_request(&format!("Invalid image: {}", e))
+}
+
+fn decode_oriented(bytes: &[u8]) -> ApiResult<(DynamicImage, ImageFormat, u8)> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| ApiError::bad_request(&format!("Invalid image: {}", e)))?;
+    let format = reader.format().ok_or_else(|| ApiError::bad_request("Unrecognized image format"))?;
+    let mut decoder = reader.into_decoder().map_err(image_error)?;
+    let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder).map_err(image_error)?;
+    img.apply_orientation(orientation);
+    Ok((img, format, orientation.to_exif()))
+}
+
+/// Превью — JPEG, либо PNG, если у изображения есть прозрачность
+fn encode_preview(img: &DynamicImage) -> ApiResult<(Vec<u8>, &'static str)> {
+    let mut out = Vec::new();
+    if img.color().has_alpha() {
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png).map_err(image_error)?;
+        Ok((out, "image/png"))
+    } else {
+        let encoder = JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+        img.to_rgb8().write_with_encoder(encoder).map_err(image_error)?;
+        Ok((out, "image/jpeg"))
+    }
+}
+
+/// Перекодировать оригинал в исходном формате — энкодеры не пишут EXIF
+fn strip_metadata(img: &DynamicImage, format: ImageFormat) -> ApiResult<Option<Vec<u8>>> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut out, 92);
+            img.to_rgb8().write_with_encoder(encoder).map_err(image_error)?;
+        }
+        ImageFormat::Png | ImageFormat::WebP => {
+            img.write_to(&mut Cursor::new(&mut out), format).map_err(image_error)?;
+        }
+        // GIF не содержит EXIF, а перекодирование потеряло бы анимацию
+        _ => return Ok(None),
+    }
+    Ok(Some(out))
+}
+
+pub fn generate_thumbnail(img: &DynamicImage, size: ThumbnailSize) -> ApiResult<Thumbnail> {
+    let edge = size.max_edge();
+    let resized = if img.width() > edge || img.height() > edge {
+        img.thumbnail(edge, edge)
+    } else {
+        img.clone()
+    };
+    let (bytes, mime_type) = encode_preview(&resized)?;
+    Ok(Thumbnail { size, bytes, width: resized.width(), height: resized.height(), mime_type })
+}
+
+/// Полная обработка загруженного фото (CPU-ёмкая — вызывать через spawn_blocking)
+pub fn process_photo(bytes: &[u8]) -> ApiResult<ProcessedPhoto> {
+    let (img, format, exif_orientation) = decode_oriented(bytes)?;
+    let stripped = strip_metadata(&img, format)?;
+    let thumbnails = ThumbnailSize::ALL.iter()
+        .map(|&size| generate_thumbnail(&img, size))
+        .collect::<ApiResult<Vec<_>>>()?;
+    Ok(ProcessedPhoto { stripped, width: img.width(), height: img.height(), exif_orientation, thumbnails })
+}
+
+/// Одно превью для фото, загруженного до появления превью
+pub fn thumbnail_from_bytes(bytes: &[u8], size: ThumbnailSize) -> ApiResult<Thumbnail> {
+    let (img, _, _) = decode_oriented(bytes)?;
+    generate_thumbnail(&img, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_process_photo_generates_bounded_thumbnails() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2000, 1000, Rgb([10, 120, 200])));
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+
+        let photo = process_photo(&png).unwrap();
+        assert_eq!((photo.width, photo.height), (2000, 1000));
+        assert_eq!(photo.orientation(), "landscape");
+        assert_eq!(photo.exif_orientation, 1);
+        assert!(photo.stripped.is_some());
+
+        let thumb = &photo.thumbnails[0];
+        assert_eq!(thumb.size, ThumbnailSize::Thumb);
+        assert_eq!((thumb.width, thumb.height), (160, 80));
+        assert_eq!(thumb.mime_type, "image/jpeg");
+        assert!(process_photo(b"not an image").is_err());
+    }
+}
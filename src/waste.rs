@@ -0,0 +1,973 @@
+// src/waste.rs
+//! Учёт опасных отходов: от списания контейнера до вывоза по манифесту.
+//!
+//! Просроченный или загрязнённый реагент списывается из контейнера (в том числе
+//! непустого) в ёмкость накопления отходов соответствующего потока (категории).
+//! Остаток контейнера и партии уменьшается, списание пишется в `usage_logs`
+//! и `waste_entries`; опустевший контейнер утилизируется и снимается с позиции.
+//!
+//! Ёмкость накопления проходит статусы `open` → `full` → `sealed` → `picked_up`.
+//! Срок накопления отсчитывается от первого поступления отходов
+//! (`accumulation_started_at`) и ограничен `accumulation_limit_days`
+//! (по умолчанию 90 дней); о приближении и нарушении срока почасовая задача
+//! создаёт уведомления. Вывоз оформляется манифестом перевозчика
+//! (`draft` → `shipped`) на одну или несколько запечатанных ёмкостей.
+//!
+//! Endpoints:
+//!   GET  /api/v1/waste/categories
+//!   GET  /api/v1/waste/containers?status=&waste_category=&overdue=
+//!   POST /api/v1/waste/containers
+//!   GET  /api/v1/waste/containers/{id}                 — ёмкость и её поступления
+//!   POST /api/v1/waste/containers/{id}/seal
+//!   POST /api/v1/containers/{container_id}/waste       — списать контейнер (или часть) в отходы
+//!   GET  /api/v1/waste/manifests?status=
+//!   POST /api/v1/waste/manifests
+//!   GET  /api/v1/waste/manifests/{id}
+//!   POST /api/v1/waste/manifests/{id}/ship             — вывоз: ёмкости → picked_up
+//!   GET  /api/v1/waste/report?from=&to=&period=none|month|quarter|year
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteExecutor, SqlitePool};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+use log::info;
+
+use crate::AppState;
+use crate::auth::get_current_user;
+use crate::container_handlers::{compute_container_status, current_slot, record_movement};
+use crate::error::{validate_unit, ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::notifications::{notify, NewNotification};
+use crate::storage_handlers::refresh_position_occupancy;
+use crate::validator::UnitConverter;
+
+/// (код, название) потоков отходов
+pub const WASTE_CATEGORIES: [(&str, &str); 11] = [
+    ("halogenated_solvent", "Halogenated solvents"),
+    ("non_halogenated_solvent", "Non-halogenated solvents"),
+    ("heavy_metal", "Heavy metals"),
+    ("acid", "Acids"),
+    ("base", "Bases"),
+    ("oxidizer", "Oxidizers"),
+    ("toxic", "Toxic organics"),
+    ("reactive", "Reactive / water-reactive"),
+    ("aqueous", "Aqueous waste"),
+    ("solid", "Contaminated solids"),
+    ("other", "Other"),
+];
+
+const WASTE_REASONS: [&str; 6] = ["expired", "contaminated", "degraded", "surplus", "spill", "other"];
+
+const WASTE_CONTAINER_STATUSES: [&str; 4] = ["open", "full", "sealed", "picked_up"];
+
+const DEFAULT_ACCUMULATION_LIMIT_DAYS: i64 = 90;
+
+/// За сколько дней до предельного срока накопления предупреждать
+const ACCUMULATION_WARNING_DAYS: i64 = 14;
+
+const QUANTITY_TOLERANCE: f64 = 0.001;
+
+// ==================== MODELS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct WasteContainer {
+    pub id: String,
+    pub label: String,
+    pub waste_category: String,
+    pub room_id: Option<String>,
+    #[sqlx(default)]
+    pub room_name: Option<String>,
+    pub capacity: f64,
+    pub unit: String,
+    pub current_quantity: f64,
+    pub status: String,
+    pub accumulation_limit_days: i64,
+    pub accumulation_started_at: Option<DateTime<Utc>>,
+    pub sealed_by: Option<String>,
+    pub sealed_at: Option<DateTime<Utc>>,
+    pub manifest_id: Option<String>,
+    pub picked_up_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Ёмкость с заполнением и сроком накопления
+#[derive(Debug, Serialize)]
+pub struct WasteContainerView {
+    #[serde(flatten)]
+    pub container: WasteContainer,
+    pub fill_percent: f64,
+    pub accumulation_deadline: Option<DateTime<Utc>>,
+    pub days_remaining: Option<i64>,
+    pub overdue: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WasteEntry {
+    pub id: String,
+    pub waste_container_id: String,
+    pub source_container_id: Option<String>,
+    pub batch_id: Option<String>,
+    #[sqlx(default)]
+    pub batch_number: Option<String>,
+    pub reagent_id: Option<String>,
+    #[sqlx(default)]
+    pub reagent_name: Option<String>,
+    pub quantity: f64,
+    pub unit: String,
+    pub waste_quantity: f64,
+    pub reason: String,
+    pub usage_log_id: Option<String>,
+    pub notes: Option<String>,
+    pub disposed_by: Option<String>,
+    pub disposed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WasteManifest {
+    pub id: String,
+    pub manifest_number: String,
+    pub transporter: String,
+    pub disposal_facility: Option<String>,
+    pub status: String,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub shipped_by: Option<String>,
+    pub shipped_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WasteCategory {
+    pub code: &'static str,
+    pub label: &'static str,
+}
+
+/// Строка отчёта: поток отходов за период
+#[derive(Debug, Serialize, PartialEq)]
+pub struct WasteStreamRow {
+    pub period: String,
+    pub waste_category: String,
+    pub unit: String,
+    pub entries: i64,
+    pub disposed_quantity: f64,
+    pub containers_picked_up: i64,
+    pub picked_up_quantity: f64,
+}
+
+/// Агрегат по дню из БД: (категория, единица, день YYYY-MM-DD, количество записей, количество)
+#[derive(Debug, sqlx::FromRow)]
+pub struct WasteDayTotal {
+    pub waste_category: String,
+    pub unit: String,
+    pub day: String,
+    pub count: i64,
+    pub quantity: f64,
+}
+
+// ==================== REQUESTS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct WasteContainerQuery {
+    pub status: Option<String>,
+    pub waste_category: Option<String>,
+    /// true — только с нарушенным сроком накопления
+    pub overdue: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateWasteContainerRequest {
+    #[validate(length(min = 1, max = 100, message = "Label must be 1-100 characters"))]
+    pub label: String,
+    pub waste_category: String,
+    /// Зона накопления
+    pub room_id: Option<String>,
+    #[validate(range(min = 0.001, message = "Capacity must be positive"))]
+    pub capacity: f64,
+    pub unit: String,
+    #[validate(range(min = 1, max = 365, message = "Accumulation limit must be 1-365 days"))]
+    pub accumulation_limit_days: Option<i64>,
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DisposeToWasteRequest {
+    pub waste_container_id: String,
+    /// В единицах партии; не задано — весь остаток контейнера
+    #[validate(range(min = 0.001, message = "Quantity must be positive"))]
+    pub quantity: Option<f64>,
+    /// В единицах ёмкости отходов (например, взвешенная масса); по умолчанию — пересчёт quantity
+    #[validate(range(min = 0.001, message = "Waste quantity must be positive"))]
+    pub waste_quantity: Option<f64>,
+    /// expired | contaminated | degraded | surplus | spill | other
+    pub reason: String,
+    #[validate(length(max = 500, message = "Notes max 500 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateManifestRequest {
+    #[validate(length(min = 1, max = 100, message = "Manifest number must be 1-100 characters"))]
+    pub manifest_number: String,
+    #[validate(length(min = 1, max = 200, message = "Transporter must be 1-200 characters"))]
+    pub transporter: String,
+    #[validate(length(max = 200, message = "Disposal facility max 200 characters"))]
+    pub disposal_facility: Option<String>,
+    /// Запечатанные ёмкости, ещё не включённые в манифест
+    #[validate(length(min = 1, max = 200, message = "Manifest must list 1-200 waste containers"))]
+    pub waste_container_ids: Vec<String>,
+    #[validate(length(max = 1000, message = "Notes max 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WasteReportQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// none | month | quarter | year
+    pub period: Option<String>,
+}
+
+// ==================== HELPERS ====================
+
+/// Предельная дата накопления
+pub fn accumulation_deadline(started_at: Option<DateTime<Utc>>, limit_days: i64) -> Option<DateTime<Utc>> {
+    started_at.map(|s| s + Duration::days(limit_days))
+}
+
+fn container_view(container: WasteContainer, now: DateTime<Utc>) -> WasteContainerView {
+    let fill_percent = (container.current_quantity / container.capacity * 1000.0).round() / 10.0;
+    // Вывезенная ёмкость срок больше не нарушает
+    let deadline = if container.status == "picked_up" {
+        None
+    } else {
+        accumulation_deadline(container.accumulation_started_at, container.accumulation_limit_days)
+    };
+    let days_remaining = deadline.map(|d| (d - now).num_days());
+    WasteContainerView {
+        overdue: deadline.is_some_and(|d| d < now),
+        container,
+        fill_percent,
+        accumulation_deadline: deadline,
+        days_remaining,
+    }
+}
+
+/// Сводит дневные итоги списаний и вывозов в строки по периодам и потокам
+pub fn aggregate_waste_streams(
+    periods: &[(String, NaiveDate, NaiveDate)],
+    disposed: &[WasteDayTotal],
+    picked_up: &[WasteDayTotal],
+) -> Vec<WasteStreamRow> {
+    let mut rows: BTreeMap<(usize, String, String), WasteStreamRow> = BTreeMap::new();
+    let period_of = |day: &str| {
+        let day = NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?;
+        periods.iter().position(|(_, start, end)| *start <= day && day <= *end)
+    };
+    for (totals, is_pickup) in [(disposed, false), (picked_up, true)] {
+        for t in totals {
+            let Some(index) = period_of(&t.day) else { continue };
+            let row = rows
+                .entry((index, t.waste_category.clone(), t.unit.clone()))
+                .or_insert_with(|| WasteStreamRow {
+                    period: periods[index].0.clone(),
+                    waste_category: t.waste_category.clone(),
+                    unit: t.unit.clone(),
+                    entries: 0,
+                    disposed_quantity: 0.0,
+                    containers_picked_up: 0,
+                    picked_up_quantity: 0.0,
+                });
+            if is_pickup {
+                row.containers_picked_up += t.count;
+                row.picked_up_quantity += t.quantity;
+            } else {
+                row.entries += t.count;
+                row.disposed_quantity += t.quantity;
+            }
+        }
+    }
+    rows.into_values().collect()
+}
+
+const WASTE_CONTAINER_SELECT: &str = r#"
+    SELECT wc.*, rm.name AS room_name
+    FROM waste_containers wc
+    LEFT JOIN rooms rm ON rm.id = wc.room_id
+"#;
+
+async fn get_waste_container_or_404(executor: impl SqliteExecutor<'_>, id: &str) -> ApiResult<WasteContainer> {
+    sqlx::query_as(&format!("{} WHERE wc.id = ?", WASTE_CONTAINER_SELECT))
+        .bind(id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Waste container"))
+}
+
+async fn get_manifest_or_404(executor: impl SqliteExecutor<'_>, id: &str) -> ApiResult<WasteManifest> {
+    sqlx::query_as("SELECT * FROM waste_manifests WHERE id = ?")
+        .bind(id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Waste manifest"))
+}
+
+fn map_unique_violation(e: sqlx::Error, message: &str) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.message().contains("UNIQUE") => ApiError::bad_request(message),
+        other => other.into(),
+    }
+}
+
+/// Уведомления о приближении и нарушении предельного срока накопления.
+/// Ключ включает дату начала накопления — новая ёмкость получает свои уведомления.
+pub async fn generate_accumulation_notifications(pool: &SqlitePool) -> ApiResult<u64> {
+    let now = Utc::now();
+    let containers: Vec<WasteContainer> = sqlx::query_as(&format!(
+        "{} WHERE wc.status IN ('open', 'full', 'sealed') AND wc.accumulation_started_at IS NOT NULL",
+        WASTE_CONTAINER_SELECT,
+    ))
+        .fetch_all(pool)
+        .await?;
+
+    let mut created = 0u64;
+    for view in containers.into_iter().map(|c| container_view(c, now)) {
+        let (Some(deadline), Some(days_left)) = (view.accumulation_deadline, view.days_remaining) else { continue };
+        if days_left > ACCUMULATION_WARNING_DAYS {
+            continue;
+        }
+        let c = &view.container;
+        let (stage, severity, message) = if view.overdue {
+            ("overdue", "critical", format!(
+                "Waste container '{}' ({}) exceeded its {}-day accumulation limit on {}",
+                c.label, c.waste_category, c.accumulation_limit_days, deadline.date_naive()
+            ))
+        } else {
+            ("due", "warning", format!(
+                "Waste container '{}' ({}) must be picked up by {} (in {} days)",
+                c.label, c.waste_category, deadline.date_naive(), days_left
+            ))
+        };
+        let started = c.accumulation_started_at.map(|s| s.to_rfc3339()).unwrap_or_default();
+        let inserted = notify(pool, &NewNotification {
+            category: "waste_accumulation",
+            severity,
+            title: format!("Waste accumulation limit {}", if view.overdue { "exceeded" } else { "approaching" }),
+            message,
+            entity_type: Some("waste_container"),
+            entity_id: Some(&c.id),
+            dedupe_key: format!("waste_accumulation:{}:{}:{}", c.id, started, stage),
+        }).await?;
+        if inserted {
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+// ==================== WASTE CONTAINERS ====================
+
+pub async fn get_waste_categories() -> ApiResult<HttpResponse> {
+    let categories: Vec<WasteCategory> = WASTE_CATEGORIES.iter()
+        .map(|&(code, label)| WasteCategory { code, label })
+        .collect();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(categories)))
+}
+
+pub async fn list_waste_containers(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<WasteContainerQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(ref status) = query.status {
+        if !WASTE_CONTAINER_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::bad_request(&format!("Unknown status '{}'", status)));
+        }
+    }
+    let containers: Vec<WasteContainer> = sqlx::query_as(&format!(
+        r#"{} WHERE (?1 IS NULL OR wc.status = ?1) AND (?2 IS NULL OR wc.waste_category = ?2)
+           ORDER BY wc.status = 'picked_up', wc.accumulation_started_at IS NULL, wc.accumulation_started_at, wc.label"#,
+        WASTE_CONTAINER_SELECT,
+    ))
+        .bind(&query.status)
+        .bind(&query.waste_category)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    let now = Utc::now();
+    let views: Vec<WasteContainerView> = containers.into_iter()
+        .map(|c| container_view(c, now))
+        .filter(|v| query.overdue.is_none_or(|overdue| v.overdue == overdue))
+        .collect();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(views)))
+}
+
+pub async fn create_waste_container(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateWasteContainerRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    if !WASTE_CATEGORIES.iter().any(|(code, _)| *code == body.waste_category) {
+        return Err(ApiError::bad_request(&format!("Unknown waste category '{}'", body.waste_category)));
+    }
+    validate_unit(&body.unit)?;
+    if let Some(ref room_id) = body.room_id {
+        sqlx::query_as::<_, Room>("SELECT * FROM rooms WHERE id = ?")
+            .bind(room_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Room"))?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let label = body.label.trim();
+    sqlx::query(
+        r#"INSERT INTO waste_containers
+           (id, label, waste_category, room_id, capacity, unit, current_quantity, status,
+            accumulation_limit_days, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, 0, 'open', ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(label)
+        .bind(&body.waste_category)
+        .bind(&body.room_id)
+        .bind(body.capacity)
+        .bind(&body.unit)
+        .bind(body.accumulation_limit_days.unwrap_or(DEFAULT_ACCUMULATION_LIMIT_DAYS))
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(|e| map_unique_violation(e, "A waste container with this label already exists"))?;
+
+    crate::audit::audit(pool, &claims.sub, "create", "waste_container", &id,
+        &format!("Waste container '{}' ({}) created", label, body.waste_category), &http_request).await;
+
+    let container = get_waste_container_or_404(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(container_view(container, now))))
+}
+
+pub async fn get_waste_container(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let container = get_waste_container_or_404(pool, &path.into_inner()).await?;
+    let entries: Vec<WasteEntry> = sqlx::query_as(
+        r#"SELECT we.*, b.batch_number, r.name AS reagent_name
+           FROM waste_entries we
+           LEFT JOIN batches b ON b.id = we.batch_id
+           LEFT JOIN reagents r ON r.id = we.reagent_id
+           WHERE we.waste_container_id = ?
+           ORDER BY we.disposed_at DESC"#
+    )
+        .bind(&container.id)
+        .fetch_all(pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "container": container_view(container, Utc::now()),
+        "entries": entries,
+    }))))
+}
+
+pub async fn seal_waste_container(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let container = get_waste_container_or_404(pool, &path.into_inner()).await?;
+    if !matches!(container.status.as_str(), "open" | "full") {
+        return Err(ApiError::bad_request(&format!("Waste container is already {}", container.status)));
+    }
+    if container.current_quantity <= QUANTITY_TOLERANCE {
+        return Err(ApiError::bad_request("Cannot seal an empty waste container"));
+    }
+
+    let now = Utc::now();
+    sqlx::query("UPDATE waste_containers SET status = 'sealed', sealed_by = ?, sealed_at = ?, updated_at = ? WHERE id = ?")
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .bind(&container.id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(pool, &claims.sub, "update", "waste_container", &container.id,
+        &format!("Waste container '{}' sealed", container.label), &http_request).await;
+
+    let container = get_waste_container_or_404(pool, &container.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(container_view(container, now))))
+}
+
+// ==================== DISPOSAL ====================
+
+/// Списание контейнера реагента (целиком или частично) в ёмкость отходов
+pub async fn dispose_to_waste(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<DisposeToWasteRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let container_id = path.into_inner();
+
+    if !WASTE_REASONS.contains(&body.reason.as_str()) {
+        return Err(ApiError::bad_request(&format!(
+            "reason must be one of: {}", WASTE_REASONS.join(", ")
+        )));
+    }
+
+    // Чтения и записи в одной транзакции; UPDATE ниже ещё раз проверяют остаток и место
+    let mut tx = pool.begin().await?;
+    let container: BatchContainer = sqlx::query_as("SELECT * FROM batch_containers WHERE id = ?")
+        .bind(&container_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Container"))?;
+    if container.status == "disposed" {
+        return Err(ApiError::bad_request("Container is already disposed"));
+    }
+    let (reagent_id, unit, batch_number): (String, String, String) = sqlx::query_as(
+        "SELECT reagent_id, unit, batch_number FROM batches WHERE id = ? AND deleted_at IS NULL"
+    )
+        .bind(&container.batch_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Batch"))?;
+
+    let quantity = body.quantity.unwrap_or(container.quantity);
+    if quantity <= QUANTITY_TOLERANCE {
+        return Err(ApiError::bad_request("Container is empty; dispose of it without a waste entry"));
+    }
+    if quantity > container.quantity + QUANTITY_TOLERANCE {
+        return Err(ApiError::bad_request(&format!(
+            "Container #{} holds only {:.3} {}", container.sequence_number, container.quantity, unit
+        )));
+    }
+
+    let waste = get_waste_container_or_404(&mut *tx, &body.waste_container_id).await?;
+    if waste.status != "open" {
+        return Err(ApiError::bad_request(&format!("Waste container '{}' is {}", waste.label, waste.status)));
+    }
+    let waste_quantity = match body.waste_quantity {
+        Some(q) => q,
+        None if unit == waste.unit => quantity,
+        None => UnitConverter::new().convert(quantity, &unit, &waste.unit).map_err(|e| ApiError::bad_request(&format!(
+            "{}; specify waste_quantity in {}", e, waste.unit
+        )))?,
+    };
+    let free_capacity = waste.capacity - waste.current_quantity;
+    if waste_quantity > free_capacity + QUANTITY_TOLERANCE {
+        return Err(ApiError::bad_request(&format!(
+            "Waste container '{}' has room for only {:.3} {}", waste.label, free_capacity.max(0.0), waste.unit
+        )));
+    }
+
+    let now = Utc::now();
+    let note = match body.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        Some(notes) => format!("{} → {}: {}", body.reason, waste.label, notes),
+        None => format!("{} → {}", body.reason, waste.label),
+    };
+
+    // 1. Контейнер: остаток; опустевший утилизируется и снимается с позиции
+    let remaining = container.quantity - quantity;
+    let emptied = remaining <= QUANTITY_TOLERANCE;
+    let status = if emptied {
+        "disposed"
+    } else if container.status == "expired" {
+        "expired"
+    } else {
+        compute_container_status(remaining, container.original_quantity)
+    };
+    let drawn = sqlx::query(
+        r#"UPDATE batch_containers
+           SET quantity = CASE WHEN ?3 THEN 0 ELSE MAX(quantity - ?1, 0) END, status = ?4, updated_at = ?5
+           WHERE id = ?6 AND status != 'disposed' AND quantity >= ?1 - ?2"#
+    )
+        .bind(quantity)
+        .bind(QUANTITY_TOLERANCE)
+        .bind(emptied)
+        .bind(status)
+        .bind(now)
+        .bind(&container_id)
+        .execute(&mut *tx)
+        .await?;
+    if drawn.rows_affected() == 0 {
+        return Err(ApiError::bad_request(&format!(
+            "Container #{} changed while disposing; reload and try again", container.sequence_number
+        )));
+    }
+    if emptied {
+        let from = current_slot(&mut tx, &container_id).await?;
+        sqlx::query("DELETE FROM batch_placements WHERE container_id = ?")
+            .bind(&container_id)
+            .execute(&mut *tx)
+            .await?;
+        record_movement(&mut tx, &container_id, "dispose", from.as_ref(), None, &claims.sub, Some(&note)).await?;
+        if let Some(ref from) = from {
+            refresh_position_occupancy(&mut tx, &from.position_id).await?;
+        }
+    }
+
+    // 2. Партия (expired и прочие нескладские статусы не трогаем)
+    sqlx::query(
+        r#"UPDATE batches
+           SET quantity = MAX(quantity - ?1, 0),
+               status = CASE
+                   WHEN status NOT IN ('available', 'low_stock', 'depleted') THEN status
+                   WHEN quantity - ?1 <= 0 THEN 'depleted'
+                   WHEN pack_size IS NOT NULL AND quantity - ?1 <= pack_size THEN 'low_stock'
+                   ELSE 'available'
+               END,
+               updated_by = ?2, updated_at = ?3
+           WHERE id = ?4"#
+    )
+        .bind(quantity)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&container.batch_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // 3. Списание
+    let usage_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, purpose, notes, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, 'Waste disposal', ?, ?)"#
+    )
+        .bind(&usage_id)
+        .bind(&reagent_id)
+        .bind(&container.batch_id)
+        .bind(&claims.sub)
+        .bind(quantity)
+        .bind(&unit)
+        .bind(&note)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    let entry_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO waste_entries
+           (id, waste_container_id, source_container_id, batch_id, reagent_id, quantity, unit,
+            waste_quantity, reason, usage_log_id, notes, disposed_by, disposed_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&entry_id)
+        .bind(&waste.id)
+        .bind(&container_id)
+        .bind(&container.batch_id)
+        .bind(&reagent_id)
+        .bind(quantity)
+        .bind(&unit)
+        .bind(waste_quantity)
+        .bind(&body.reason)
+        .bind(&usage_id)
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    // 4. Ёмкость отходов: заполнение и начало срока накопления
+    let filled = sqlx::query(
+        r#"UPDATE waste_containers
+           SET current_quantity = current_quantity + ?1,
+               status = CASE WHEN current_quantity + ?1 >= capacity - ?2 THEN 'full' ELSE status END,
+               accumulation_started_at = COALESCE(accumulation_started_at, ?3),
+               updated_at = ?3
+           WHERE id = ?4 AND status = 'open' AND current_quantity + ?1 <= capacity + ?2"#
+    )
+        .bind(waste_quantity)
+        .bind(QUANTITY_TOLERANCE)
+        .bind(now)
+        .bind(&waste.id)
+        .execute(&mut *tx)
+        .await?;
+    if filled.rows_affected() == 0 {
+        return Err(ApiError::bad_request(&format!(
+            "Waste container '{}' changed while disposing; reload and try again", waste.label
+        )));
+    }
+
+    tx.commit().await?;
+
+    crate::audit::audit(pool, &claims.sub, "dispose", "batch_container", &container_id,
+        &format!("{:.3} {} of batch {} (container #{}) disposed as waste: {}",
+            quantity, unit, batch_number, container.sequence_number, note), &http_request).await;
+    info!("☣️ {:.3} {} from container #{} (batch {}) → waste '{}'",
+        quantity, unit, container.sequence_number, batch_number, waste.label);
+
+    let waste = get_waste_container_or_404(pool, &waste.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        serde_json::json!({
+            "entry_id": entry_id,
+            "usage_id": usage_id,
+            "container_id": container_id,
+            "container_remaining": if emptied { 0.0 } else { remaining },
+            "container_status": status,
+            "waste_container": container_view(waste, now),
+        }),
+        format!("Disposed {:.3} {} as {} waste", quantity, unit, body.reason),
+    )))
+}
+
+// ==================== MANIFESTS ====================
+
+pub async fn list_manifests(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ManifestQuery>,
+) -> ApiResult<HttpResponse> {
+    let manifests: Vec<WasteManifest> = sqlx::query_as(
+        "SELECT * FROM waste_manifests WHERE (?1 IS NULL OR status = ?1) ORDER BY created_at DESC"
+    )
+        .bind(&query.status)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(manifests)))
+}
+
+async fn manifest_details(pool: &SqlitePool, manifest: WasteManifest) -> ApiResult<serde_json::Value> {
+    let containers: Vec<WasteContainer> = sqlx::query_as(&format!(
+        "{} WHERE wc.manifest_id = ? ORDER BY wc.waste_category, wc.label", WASTE_CONTAINER_SELECT
+    ))
+        .bind(&manifest.id)
+        .fetch_all(pool)
+        .await?;
+    let now = Utc::now();
+    let containers: Vec<WasteContainerView> = containers.into_iter().map(|c| container_view(c, now)).collect();
+    Ok(serde_json::json!({ "manifest": manifest, "containers": containers }))
+}
+
+pub async fn get_manifest(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let manifest = get_manifest_or_404(pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(manifest_details(pool, manifest).await?)))
+}
+
+pub async fn create_manifest(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateManifestRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    let mut tx = pool.begin().await?;
+    for waste_id in &body.waste_container_ids {
+        let container = get_waste_container_or_404(&mut *tx, waste_id).await?;
+        if container.status != "sealed" {
+            return Err(ApiError::bad_request(&format!(
+                "Waste container '{}' must be sealed before pickup (status: {})", container.label, container.status
+            )));
+        }
+        if container.manifest_id.is_some() {
+            return Err(ApiError::bad_request(&format!(
+                "Waste container '{}' is already on a manifest", container.label
+            )));
+        }
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let manifest_number = body.manifest_number.trim();
+    sqlx::query(
+        r#"INSERT INTO waste_manifests
+           (id, manifest_number, transporter, disposal_facility, status, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, 'draft', ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(manifest_number)
+        .bind(body.transporter.trim())
+        .bind(&body.disposal_facility)
+        .bind(&body.notes)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| map_unique_violation(e, "A manifest with this number already exists"))?;
+    for waste_id in &body.waste_container_ids {
+        let claimed = sqlx::query(
+            "UPDATE waste_containers SET manifest_id = ?, updated_at = ? WHERE id = ? AND status = 'sealed' AND manifest_id IS NULL"
+        )
+            .bind(&id)
+            .bind(now)
+            .bind(waste_id)
+            .execute(&mut *tx)
+            .await?;
+        if claimed.rows_affected() != 1 {
+            return Err(ApiError::bad_request(&format!(
+                "Waste container {} was claimed by another manifest", waste_id
+            )));
+        }
+    }
+    tx.commit().await?;
+
+    crate::audit::audit(pool, &claims.sub, "create", "waste_manifest", &id,
+        &format!("Waste manifest {} for {} containers", manifest_number, body.waste_container_ids.len()), &http_request).await;
+
+    let manifest = get_manifest_or_404(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(manifest_details(pool, manifest).await?)))
+}
+
+/// Вывоз по манифесту: ёмкости переходят в picked_up
+pub async fn ship_manifest(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let mut tx = pool.begin().await?;
+    let manifest = get_manifest_or_404(&mut *tx, &path.into_inner()).await?;
+    if manifest.status != "draft" {
+        return Err(ApiError::bad_request(&format!("Manifest is already {}", manifest.status)));
+    }
+
+    let now = Utc::now();
+    let shipped = sqlx::query(
+        "UPDATE waste_manifests SET status = 'shipped', shipped_by = ?, shipped_at = ?, updated_at = ? WHERE id = ? AND status = 'draft'"
+    )
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .bind(&manifest.id)
+        .execute(&mut *tx)
+        .await?;
+    if shipped.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Manifest has already been shipped"));
+    }
+    let picked_up = sqlx::query(
+        "UPDATE waste_containers SET status = 'picked_up', picked_up_at = ?, updated_at = ? WHERE manifest_id = ?"
+    )
+        .bind(now)
+        .bind(now)
+        .bind(&manifest.id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+
+    crate::audit::audit(pool, &claims.sub, "update", "waste_manifest", &manifest.id,
+        &format!("Waste manifest {} shipped ({} containers)", manifest.manifest_number, picked_up), &http_request).await;
+    info!("🚚 Waste manifest {} shipped with {} containers", manifest.manifest_number, picked_up);
+
+    let manifest = get_manifest_or_404(pool, &manifest.id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(manifest_details(pool, manifest).await?)))
+}
+
+// ==================== REPORT ====================
+
+/// Отчёт по потокам отходов: списано в ёмкости и вывезено, по категориям и периодам.
+/// Количества — в единицах ёмкостей отходов.
+pub async fn get_waste_report(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<WasteReportQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let period = query.period.as_deref().unwrap_or("none");
+    let (from, to) = crate::equipment_usage::period_bounds(query.from.as_deref(), query.to.as_deref(), 365)?;
+    let periods = crate::report_handlers::cost_periods(from, to, period)
+        .ok_or_else(|| ApiError::bad_request("period must be one of: none, month, quarter, year"))?;
+    let (from_day, to_day) = (from.to_string(), to.to_string());
+
+    let disposed: Vec<WasteDayTotal> = sqlx::query_as(
+        r#"SELECT wc.waste_category, wc.unit, substr(we.disposed_at, 1, 10) AS day,
+                  COUNT(*) AS count, SUM(we.waste_quantity) AS quantity
+           FROM waste_entries we
+           JOIN waste_containers wc ON wc.id = we.waste_container_id
+           WHERE substr(we.disposed_at, 1, 10) BETWEEN ? AND ?
+           GROUP BY wc.waste_category, wc.unit, day"#
+    )
+        .bind(&from_day)
+        .bind(&to_day)
+        .fetch_all(pool)
+        .await?;
+    let picked_up: Vec<WasteDayTotal> = sqlx::query_as(
+        r#"SELECT waste_category, unit, substr(picked_up_at, 1, 10) AS day,
+                  COUNT(*) AS count, SUM(current_quantity) AS quantity
+           FROM waste_containers
+           WHERE status = 'picked_up' AND substr(picked_up_at, 1, 10) BETWEEN ? AND ?
+           GROUP BY waste_category, unit, day"#
+    )
+        .bind(&from_day)
+        .bind(&to_day)
+        .fetch_all(pool)
+        .await?;
+
+    let rows = aggregate_waste_streams(&periods, &disposed, &picked_up);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "from": from,
+        "to": to,
+        "period": period,
+        "rows": rows,
+    }))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day_total(category: &str, day: &str, count: i64, quantity: f64) -> WasteDayTotal {
+        WasteDayTotal {
+            waste_category: category.to_string(),
+            unit: "L".to_string(),
+            day: day.to_string(),
+            count,
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_waste_streams() {
+        let from = NaiveDate::from_ymd_opt(2025, 1, 10).unwrap();
+        let to = NaiveDate::from_ymd_opt(2025, 3, 5).unwrap();
+        let periods = crate::report_handlers::cost_periods(from, to, "month").unwrap();
+        let disposed = [
+            day_total("heavy_metal", "2025-01-12", 2, 1.5),
+            day_total("heavy_metal", "2025-01-30", 1, 0.5),
+            day_total("acid", "2025-02-01", 1, 2.0),
+            day_total("acid", "2025-04-01", 1, 9.0), // вне периода
+        ];
+        let picked_up = [day_total("heavy_metal", "2025-03-01", 1, 20.0)];
+
+        let rows = aggregate_waste_streams(&periods, &disposed, &picked_up);
+        assert_eq!(rows.len(), 3);
+        assert_eq!((rows[0].period.as_str(), rows[0].waste_category.as_str()), ("2025-01", "heavy_metal"));
+        assert_eq!((rows[0].entries, rows[0].disposed_quantity), (3, 2.0));
+        assert_eq!((rows[1].period.as_str(), rows[1].waste_category.as_str()), ("2025-02", "acid"));
+        assert_eq!((rows[2].containers_picked_up, rows[2].picked_up_quantity, rows[2].entries), (1, 20.0, 0));
+
+        let started = DateTime::parse_from_rfc3339("2025-01-12T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(accumulation_deadline(Some(started), 90).unwrap().date_naive().to_string(), "2025-04-12");
+    }
+}